@@ -0,0 +1,160 @@
+use std::io::{Result, Error, ErrorKind};
+use tokio::{net::TcpStream, io::{AsyncReadExt, AsyncWriteExt}};
+use crate::{Cursor, QueryResponse, QUERY_FLAG, CURSOR_FLAG, PING_FLAG, PONG, decode_query_response, decode_next_response};
+
+
+///Async version of Connection for tokio applications. It speaks the same protocol, but only
+///over plain TCP
+pub struct AsyncConnection {
+    stream : TcpStream,
+    warnings : Vec<String>,
+    message : Option<String>,
+    affected : Option<u64>,
+    inserted_id : Option<i64>,
+}
+
+
+impl AsyncConnection {
+
+    pub async fn new(address : String, database : String, key : String) -> Result<Self> {
+        let stream = TcpStream::connect(&address).await?;
+        let mut connection = AsyncConnection{stream, warnings: vec![], message: None, affected: None, inserted_id: None};
+        let bytes = format!("{}.{}", database, key).into_bytes();
+        match connection.request(&bytes).await?[..] {
+            [0] => (),
+            [1] => return Err(Error::new(ErrorKind::PermissionDenied, "wrong key")),
+            _ => return Err(Error::other("unexpected response")),
+        }
+        Ok(connection)
+    }
+
+    ///Sends message as one frame and waits until the whole response of the server arrived.
+    ///Frames start with the length of their message as u32
+    async fn request(&mut self, message : &[u8]) -> Result<Vec<u8>> {
+        let len = u32::try_from(message.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "message is too long for a frame"))?;
+        let mut frame : Vec<u8> = Vec::with_capacity(4 + message.len());
+        frame.extend(len.to_le_bytes());
+        frame.extend(message);
+        self.stream.write_all(&frame).await?;
+        let len = self.stream.read_u32_le().await?;
+        let mut buffer = vec![0; len as usize];
+        self.stream.read_exact(&mut buffer).await?;
+        Ok(buffer)
+    }
+
+    pub async fn query(&mut self, query : String) -> Result<Option<Cursor>> {
+        let mut message : Vec<u8> = vec![];
+        message.push(QUERY_FLAG);
+        message.extend(query.as_bytes());
+        let buffer = self.request(&message).await?;
+        self.warnings.clear();
+        self.message = None;
+        self.affected = None;
+        self.inserted_id = None;
+        match decode_query_response(buffer)? {
+            QueryResponse::Rows(warnings, cursor) => {
+                self.warnings = warnings;
+                Ok(Some(cursor))
+            },
+            QueryResponse::Done(warnings, affected, inserted_id, message) => {
+                self.warnings = warnings;
+                self.affected = affected;
+                self.inserted_id = inserted_id;
+                self.message = message;
+                Ok(None)
+            },
+        }
+    }
+
+    ///Returns the warnings the server attached to the response of the last successful query
+    pub fn warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    ///Returns the text the server reported for the last successful query without rows
+    pub fn message(&self) -> Option<&String> {
+        self.message.as_ref()
+    }
+
+    ///Returns the number of rows the last successful insert or delete changed
+    pub fn affected(&self) -> Option<u64> {
+        self.affected
+    }
+
+    ///Returns the value the auto increment col got by the last successful insert
+    pub fn inserted_id(&self) -> Option<i64> {
+        self.inserted_id
+    }
+
+    pub async fn next(&mut self, cursor : &mut Cursor) -> Result<bool> {
+        let mut message : Vec<u8> = vec![];
+        message.push(CURSOR_FLAG);
+        message.extend(cursor.hash.clone());
+        let buffer = self.request(&message).await?;
+        match decode_next_response(buffer)? {
+            Some(row) => {
+                cursor.row = row;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    ///Checks that the server still answers on this connection
+    pub async fn ping(&mut self) -> Result<()> {
+        let buffer = self.request(&[PING_FLAG]).await?;
+        if buffer != PONG {
+            return Err(Error::new(ErrorKind::InvalidData, "server did not answer the ping"));
+        }
+        Ok(())
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.shutdown().await;
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::tests::{encode_row, fake_server};
+
+
+    #[tokio::test]
+    async fn async_query_test() {
+        let mut first : Vec<u8> = vec![0];
+        first.extend(0u64.to_le_bytes());
+        first.extend(0u64.to_le_bytes());
+        first.extend([7u8; 16]);
+        first.extend(encode_row(vec![1]));
+        let mut second : Vec<u8> = vec![0];
+        second.extend(encode_row(vec![2]));
+        let address = fake_server(vec![first, second, vec![1]]);
+        let mut connection = AsyncConnection::new(address, "db".to_string(), "key".to_string()).await.unwrap();
+        let mut cursor = connection.query("SELECT * FROM t;".to_string()).await.unwrap().unwrap();
+        assert_eq!(cursor.get(0).unwrap().as_i64(), Some(1));
+        assert!(connection.next(&mut cursor).await.unwrap());
+        assert_eq!(cursor.get(0).unwrap().as_i64(), Some(2));
+        assert!(!connection.next(&mut cursor).await.unwrap());
+        connection.close().await;
+    }
+
+
+    #[tokio::test]
+    async fn async_large_message_test() {
+        let mut first : Vec<u8> = vec![0];
+        first.extend(0u64.to_le_bytes());
+        first.extend(0u64.to_le_bytes());
+        first.extend([7u8; 16]);
+        first.extend(2000u64.to_le_bytes());
+        first.extend(1u64.to_le_bytes());
+        first.extend([b'a'; 2000]);
+        let address = fake_server(vec![first]);
+        let mut connection = AsyncConnection::new(address, "db".to_string(), "key".to_string()).await.unwrap();
+        let cursor = connection.query(format!("SELECT * FROM t WHERE a == {};", "a".repeat(2000))).await.unwrap().unwrap();
+        assert_eq!(cursor.get(0).unwrap().as_str().map(str::len), Some(2000));
+    }
+
+}
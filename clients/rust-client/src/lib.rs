@@ -1,25 +1,120 @@
-use std::{net::TcpStream, io::{Result, Error, ErrorKind, Write, Read}};
+use std::{net::TcpStream, os::unix::net::UnixStream, io::{Result, Error, ErrorKind, Write, Read, BufReader}, fs::File, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned, pki_types::ServerName};
 
 const QUERY_FLAG : u8 = 0x00;
 const CURSOR_FLAG : u8 = 0x01;
+const PREPARE_FLAG : u8 = 0x06;
+const EXECUTE_FLAG : u8 = 0x07;
+const CLOSE_CURSOR_FLAG : u8 = 0x08;
+const PING_FLAG : u8 = 0x0B;
+
+//Answer of the server to a ping
+const PONG : &[u8] = b"pong";
+
+///Writes message as one frame. A frame starts with the length of the message as u32, so the
+///other side knows when the whole message arrived no matter how it is split on the way
+pub fn write_frame(mut writer : impl Write, message : &[u8]) -> Result<()> {
+    let len = u32::try_from(message.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "message is too long for a frame"))?;
+    let mut frame : Vec<u8> = Vec::with_capacity(4 + message.len());
+    frame.extend(len.to_le_bytes());
+    frame.extend(message);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+///Reads until a whole frame arrived and returns the message in it
+pub fn read_frame(mut reader : impl Read) -> Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let mut message = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut message)?;
+    Ok(message)
+}
+
+mod pool;
+pub use pool::{Pool, PooledConnection};
+
+#[cfg(feature = "async")]
+mod async_connection;
+#[cfg(feature = "async")]
+pub use async_connection::AsyncConnection;
 
 
 #[derive(Debug)]
 pub enum Value {
     Text(String),
-    Number(u64),
+    Number(i64),
+
+    //Blobs are sent as params of prepared statements, inside a query they are written as hex
+    Blob(Vec<u8>),
 }
 
 impl Value {
 
-    fn new_number(bytes : Vec<u8>) -> Self {
-        return Self::Number(u64::from_le_bytes(bytes.try_into().expect("expected 8 bytes")));
+    fn new_number(bytes : Vec<u8>) -> Result<Self> {
+        let bytes : [u8; 8] = bytes.try_into().map_err(|_| Error::new(ErrorKind::InvalidData, "expected 8 bytes for a number"))?;
+        Ok(Self::Number(i64::from_le_bytes(bytes)))
     }
 
     fn new_text(bytes : Vec<u8>) -> Self {
         return Self::Text(String::from_utf8_lossy(&bytes).to_string());
     }
 
+    ///Returns the number if the value is one
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Number(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    ///Returns the text if the value is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Text(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    ///Returns the bytes if the value is a blob
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Blob(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    ///Returns the value as JSON, numbers as numbers and text as strings. Blobs are strings of
+    ///their hex like in queries
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::Number(val) => val.to_string(),
+            _ => json_string(&self.to_string()),
+        }
+    }
+
+}
+
+impl TryInto<String> for Value {
+    type Error = std::io::Error;
+
+    fn try_into(self) -> std::result::Result<String, Self::Error> {
+        match self {
+            Self::Text(val) => Ok(val),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "could not convert value to String")),
+        }
+    }
+}
+
+impl TryInto<i64> for Value {
+    type Error = std::io::Error;
+
+    fn try_into(self) -> std::result::Result<i64, Self::Error> {
+        match self {
+            Self::Number(val) => Ok(val),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "could not convert value to i64")),
+        }
+    }
 }
 
 impl TryFrom<(u64, Vec<u8>)> for Value {
@@ -27,8 +122,9 @@ impl TryFrom<(u64, Vec<u8>)> for Value {
 
     fn try_from((type_id, bytes) : (u64, Vec<u8>)) -> std::result::Result<Self, Self::Error> {
         match type_id {
-            0 => Ok(Value::new_number(bytes)),
+            0 => Value::new_number(bytes),
             1 => Ok(Value::new_text(bytes)),
+            2 => Ok(Value::Blob(bytes)),
             _ => Err(Error::new(ErrorKind::InvalidInput, "type id did not correspond to any type")),
         }
     }
@@ -40,37 +136,271 @@ impl ToString for Value {
         match self {
             Self::Text(val) => val.clone(),
             Self::Number(val) => val.to_string(),
+            Self::Blob(val) => val.iter().map(|byte| format!("{:02x}", byte)).collect(),
         }
     }
 }
 
 
+///Category of an error the server reported. The io::Error returned for it has a matching kind,
+///ErrorCode::of tells the categories with the same kind apart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    Internal,
+    Parse,
+    TableNotFound,
+    TypeMismatch,
+    ConstraintViolation,
+    InvalidInput,
+    Busy,
+    Timeout,
+    PermissionDenied,
+}
+
+impl ErrorCode {
+
+    fn from_byte(byte : u8) -> Self {
+        match byte {
+            1 => Self::Parse,
+            2 => Self::TableNotFound,
+            3 => Self::TypeMismatch,
+            4 => Self::ConstraintViolation,
+            5 => Self::InvalidInput,
+            6 => Self::Busy,
+            7 => Self::Timeout,
+            8 => Self::PermissionDenied,
+            _ => Self::Internal,
+        }
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parse | Self::InvalidInput | Self::ConstraintViolation => ErrorKind::InvalidInput,
+            Self::TableNotFound => ErrorKind::NotFound,
+            Self::TypeMismatch => ErrorKind::InvalidData,
+            Self::Busy => ErrorKind::WouldBlock,
+            Self::Timeout => ErrorKind::TimedOut,
+            Self::PermissionDenied => ErrorKind::PermissionDenied,
+            Self::Internal => ErrorKind::Other,
+        }
+    }
+
+    ///Returns the code of an error the server reported, None for other errors like a lost
+    ///connection
+    pub fn of(error : &Error) -> Option<Self> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<ServerError>()).map(|server_error| server_error.code)
+    }
+
+}
+
+///Error the server reported, it is the inner error of the io::Error returned for it
+#[derive(Debug)]
+pub struct ServerError {
+    pub code : ErrorCode,
+    pub message : String,
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+///Decodes an error response without its status byte, the code is followed by the message
+fn decode_error(bytes : &[u8]) -> Error {
+    let code = ErrorCode::from_byte(bytes.first().copied().unwrap_or(0));
+    let message = String::from_utf8_lossy(bytes.get(1..).unwrap_or(&[])).to_string();
+    Error::new(code.kind(), ServerError{code, message})
+}
+
 #[derive(Debug)]
 pub struct Cursor {
     pub row : Vec<Value>,
+
+    //Names of the columns of the rows in the same order as the values
+    pub column_names : Vec<String>,
     hash : Vec<u8>,
+
+    //Number of reconnects of the connection when the cursor was opened
+    generation : u64,
+
+    //Where the hash is put when the cursor is dropped while it is still open on the server, so
+    //the connection can close it. None once the server no longer holds the cursor
+    dropped : Option<Arc<Mutex<Vec<Vec<u8>>>>>,
+}
+
+impl Cursor {
+
+    ///Returns the value in the column at index of the current row
+    pub fn get(&self, index : usize) -> Option<&Value> {
+        self.row.get(index)
+    }
+
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        let Some(dropped) = self.dropped.take() else {
+            return;
+        };
+        let Ok(mut dropped) = dropped.lock() else {
+            return;
+        };
+        dropped.push(std::mem::take(&mut self.hash));
+    }
 }
 
 impl TryFrom<Vec<u8>> for Cursor {
     type Error = std::io::Error;
 
+    ///Decodes the column names followed by the hash and the first row
     fn try_from(value: Vec<u8>) -> std::result::Result<Self, Self::Error> {
-        let hash : Vec<u8> = value[0..16].to_vec();
-        let row : Vec<Value> = decode_row(value[16..].to_vec())?;
-        return Ok(Cursor {row, hash});
+        let (column_names, len) = decode_strings(&value)?;
+        let hash : Vec<u8> = value.get(len..(len + 16)).ok_or_else(|| Error::new(ErrorKind::InvalidData, "response was too short for a cursor"))?.to_vec();
+        let row : Vec<Value> = decode_row(value[(len + 16)..].to_vec())?;
+        Ok(Cursor {row, column_names, hash, generation: 0, dropped: None})
+    }
+
+}
+
+///Transport of a connection, either plain TCP, TLS on top of it or a unix socket
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+    Unix(UnixStream),
+}
+
+impl Stream {
+
+    ///Connects to address. If a certificate path is given the connection is encrypted with TLS and
+    ///the server has to present a certificate that is signed by the certificate in the file
+    pub fn connect(address : &str, cert_path : Option<&Path>) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let cert_path = match cert_path {
+            Some(cert_path) => cert_path,
+            None => return Ok(Stream::Plain(stream)),
+        };
+
+        //Only the given certificate is trusted, so self signed certificates can be used
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?)) {
+            roots.add(cert?).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        }
+        let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+
+        //The host part of the address has to match the certificate of the server
+        let host = address.rsplit_once(':').map(|(host, _)| host).unwrap_or(address).trim_matches(['[', ']']);
+        let server_name = ServerName::try_from(host.to_string()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let connection = ClientConnection::new(Arc::new(config), server_name).map_err(Error::other)?;
+        let mut stream = StreamOwned::new(connection, stream);
+
+        //The handshake is completed before any credentials are sent
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+
+    ///Connects to the unix socket at path, these connections are never encrypted
+    pub fn connect_unix(path : &Path) -> Result<Self> {
+        Ok(Stream::Unix(UnixStream::connect(path)?))
+    }
+
+    ///Sends message to the server as one frame
+    pub fn send(&mut self, message : &[u8]) -> Result<()> {
+        write_frame(self, message)
+    }
+
+    ///Waits for the next message of the server and returns it once it arrived completely
+    pub fn receive(&mut self) -> Result<Vec<u8>> {
+        read_frame(self)
+    }
+
+    ///Ends the connection, TLS connections notify the server before
+    pub fn shutdown(&mut self) -> Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.shutdown(std::net::Shutdown::Both),
+            Stream::Tls(stream) => {
+                stream.conn.send_close_notify();
+                let _ = stream.conn.complete_io(&mut stream.sock);
+                stream.sock.shutdown(std::net::Shutdown::Both)
+            },
+            Stream::Unix(stream) => stream.shutdown(std::net::Shutdown::Both),
+        }
     }
 
 }
 
+impl Read for Stream {
+    fn read(&mut self, buf : &mut [u8]) -> Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf : &[u8]) -> Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+///A query that was prepared on the server and can be executed with params
+#[derive(Debug)]
+pub struct Statement {
+    hash : Vec<u8>,
+}
+
+///Encodes params like the columns of a row: length, type id and bytes of every value
+fn encode_params(params : Vec<Value>) -> Vec<u8> {
+    let mut bytes : Vec<u8> = vec![];
+    for param in params {
+        let (type_id, param_bytes) : (u64, Vec<u8>) = match param {
+            Value::Number(val) => (0, val.to_le_bytes().to_vec()),
+            Value::Text(val) => (1, val.into_bytes()),
+            Value::Blob(val) => (2, val),
+        };
+        bytes.extend((param_bytes.len() as u64).to_le_bytes());
+        bytes.extend(type_id.to_le_bytes());
+        bytes.extend(param_bytes);
+    }
+    bytes
+}
+
+///Reads the little endian u64 at index, what names the value in the error if bytes are too short
+fn read_u64(bytes : &[u8], index : usize, what : &str) -> Result<u64> {
+    let too_short = || Error::new(ErrorKind::UnexpectedEof, format!("response was too short for {}", what));
+    let number_bytes : [u8; 8] = bytes.get(index..index.checked_add(8).ok_or_else(too_short)?).ok_or_else(too_short)?.try_into().map_err(|_| too_short())?;
+    Ok(u64::from_le_bytes(number_bytes))
+}
+
 fn decode_row(bytes : Vec<u8>) -> Result<Vec<Value>> {
     let mut row : Vec<Value> = vec![];
     let mut index = 0;
     while index < bytes.len() {
-        let len = u64::from_le_bytes(bytes[index..(index+8)].try_into().expect("unexpected error")) as usize; 
+        let len = read_u64(&bytes, index, "a row")? as usize;
         index += 8;
-        let type_id = u64::from_le_bytes(bytes[index..(index+8)].try_into().expect("unexpected error"));
+        let type_id = read_u64(&bytes, index, "a row")?;
         index += 8;
-        let val = Value::try_from((type_id, bytes[index..(index+len)].try_into().expect("unexpected")))?;
+        let value_bytes = index.checked_add(len).and_then(|end| bytes.get(index..end)).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "response was too short for a row"))?;
+        let val = Value::try_from((type_id, value_bytes.to_vec()))?;
         index += len;
         row.push(val);
     }
@@ -78,21 +408,200 @@ fn decode_row(bytes : Vec<u8>) -> Result<Vec<Value>> {
     return Ok(row);
 }
 
+///Decodes the strings at the start of bytes, like the warnings of a response or the column names
+///of a cursor, and returns them along with the number of bytes they took up
+fn decode_strings(bytes : &[u8]) -> Result<(Vec<String>, usize)> {
+    let count = read_u64(bytes, 0, "its strings")?;
+    let mut index = 8;
+    let mut strings : Vec<String> = vec![];
+    for _ in 0..count {
+        let len = read_u64(bytes, index, "its strings")? as usize;
+        index += 8;
+        let string_bytes = index.checked_add(len).and_then(|end| bytes.get(index..end)).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "response was too short for its strings"))?;
+        strings.push(String::from_utf8_lossy(string_bytes).to_string());
+        index += len;
+    }
+    Ok((strings, index))
+}
+
+///Quotes text as a JSON string
+fn json_string(text : &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+///Decodes the optional number of affected rows and id of an inserted row that start at index and
+///returns them along with the index of the first byte after them
+fn decode_affected(bytes : &[u8], index : usize) -> Result<(Option<u64>, Option<i64>, usize)> {
+    let flag = bytes.get(index).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "response was too short for the affected rows"))?;
+    match flag {
+        0 => Ok((None, None, index + 1)),
+        1 => Ok((Some(read_u64(bytes, index + 1, "the affected rows")?), None, index + 9)),
+        2 => Ok((Some(read_u64(bytes, index + 1, "the affected rows")?), Some(read_u64(bytes, index + 9, "the affected rows")? as i64), index + 17)),
+        _ => Err(Error::new(ErrorKind::InvalidData, "response had an invalid affected rows flag")),
+    }
+}
+
+///Decoded response of the server to a query, shared by the sync and async connection
+enum QueryResponse {
+    Rows(Vec<String>, Cursor),
+    Done(Vec<String>, Option<u64>, Option<i64>, Option<String>),
+}
+
+///Decodes the response to a query or an executed statement, buffer includes the status byte
+fn decode_query_response(mut buffer : Vec<u8>) -> Result<QueryResponse> {
+    if buffer.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "response was empty"));
+    }
+    match buffer.remove(0) {
+        0 => {
+            let (warnings, len) = decode_strings(&buffer)?;
+            let cursor = Cursor::try_from(buffer[len..].to_vec())?;
+            Ok(QueryResponse::Rows(warnings, cursor))
+        },
+        1 => {
+            let (warnings, len) = decode_strings(&buffer)?;
+            let (affected, inserted_id, len) = decode_affected(&buffer, len)?;
+            let mut message = None;
+            if len < buffer.len() {
+                message = Some(String::from_utf8_lossy(&buffer[len..]).to_string());
+            }
+            Ok(QueryResponse::Done(warnings, affected, inserted_id, message))
+        },
+        2 => Err(decode_error(&buffer)),
+        _ => Err(Error::new(ErrorKind::InvalidData, "response had invalid status code")),
+    }
+}
+
+///Decodes the response to a cursor request, returns None if the cursor was exhausted
+fn decode_next_response(mut buffer : Vec<u8>) -> Result<Option<Vec<Value>>> {
+    if buffer.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "response was empty"));
+    }
+    match buffer.remove(0) {
+        0 => Ok(Some(decode_row(buffer)?)),
+        1 => Ok(None),
+        2 => Err(decode_error(&buffer)),
+        _ => Err(Error::new(ErrorKind::InvalidData, "response had invalid status code")),
+    }
+}
+
+///Iterator over the rows of a cursor, see Connection::rows
+pub struct Rows<'a> {
+    connection : &'a mut Connection,
+    cursor : Cursor,
+
+    //The first row arrives with the query, so it is returned without asking the server
+    first : bool,
+    done : bool,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.first {
+            self.first = false;
+            return Some(Ok(std::mem::take(&mut self.cursor.row)));
+        }
+        match self.connection.next(&mut self.cursor) {
+            Ok(true) => Some(Ok(std::mem::take(&mut self.cursor.row))),
+            Ok(false) => {
+                self.done = true;
+                None
+            },
+
+            //The cursor can not be trusted after an error, so iteration stops
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+///Where a connection is opened to, it is connected to again when the connection is lost
+enum Endpoint {
+    Tcp(String, Option<PathBuf>),
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    fn connect(&self) -> Result<Stream> {
+        match self {
+            Endpoint::Tcp(address, cert_path) => Stream::connect(address, cert_path.as_deref()),
+            Endpoint::Unix(path) => Stream::connect_unix(path),
+        }
+    }
+}
+
 pub struct Connection {
-    stream : TcpStream,
+    stream : Stream,
+    endpoint : Endpoint,
+    database : String,
+    key : String,
+    warnings : Vec<String>,
+    message : Option<String>,
+    affected : Option<u64>,
+    inserted_id : Option<i64>,
+
+    //Hashes of the cursors that were not read till the end, they are closed with the connection
+    cursors : Vec<Vec<u8>>,
+
+    //Hashes of the cursors that were dropped before they were read till the end, they are closed
+    //before the next query or row is requested
+    dropped_cursors : Arc<Mutex<Vec<Vec<u8>>>>,
+
+    //Set when reading or writing the stream failed and reconnecting did not work either
+    broken : bool,
+
+    //Number of reconnects, cursors of older generations are invalid
+    generation : u64,
 }
 
 
 impl Connection {
 
     pub fn new(address : String, database : String, key : String) -> Result<Self> {
-        let mut stream = TcpStream::connect(&address)?;
+        Self::create(Endpoint::Tcp(address, None), database, key)
+    }
+
+    ///Like new but encrypts the connection with TLS. The server has to present a certificate that
+    ///is signed by the certificate at cert_path
+    pub fn with_tls(address : String, database : String, key : String, cert_path : &Path) -> Result<Self> {
+        Self::create(Endpoint::Tcp(address, Some(cert_path.to_path_buf())), database, key)
+    }
+
+    ///Like new but connects to the unix socket at path, which the server opens if UNIX_SOCKET_PATH
+    ///is set
+    pub fn new_unix(path : &Path, database : String, key : String) -> Result<Self> {
+        Self::create(Endpoint::Unix(path.to_path_buf()), database, key)
+    }
+
+    fn create(endpoint : Endpoint, database : String, key : String) -> Result<Self> {
+        let stream = Self::authenticate(endpoint.connect()?, &database, &key)?;
+        Ok(Connection{stream, endpoint, database, key, warnings: vec![], message: None, affected: None, inserted_id: None, cursors: vec![], dropped_cursors: Arc::new(Mutex::new(vec![])), broken: false, generation: 0})
+    }
+
+    fn authenticate(mut stream : Stream, database : &str, key : &str) -> Result<Stream> {
         let bytes = format!("{}.{}", database, key).into_bytes();
-        stream.write_all(&bytes)?;
-        stream.flush()?;
-        let mut buffer = [0u8; 512];
-        let len = stream.read(&mut buffer)?;
-        match buffer[..len] {
+        stream.send(&bytes)?;
+        match stream.receive()?[..] {
             [0] => (),
             [1] => {
                 drop(stream);
@@ -100,63 +609,608 @@ impl Connection {
             },
             _ => {return Err(Error::new(ErrorKind::Other, "unexpected response"))},
         }
-        return Ok(Connection{stream});
+        Ok(stream)
     }
 
     pub fn query(&mut self, query : String) -> Result<Option<Cursor>> {
         let mut message : Vec<u8> = vec![];
         message.push(QUERY_FLAG);
         message.extend(query.as_bytes());
-        self.stream.write_all(&message)?;
-        let mut buffer = vec![0; 1024];
-        let len = self.stream.read(&mut buffer)?;
-        buffer.truncate(len);
-        if len < 1 {
-            return Err(Error::new(ErrorKind::InvalidData, "response was empty"));
-        }
+        self.send_query(message)
+    }
+
+    ///Parses the query on the server so it can be executed repeatedly. Values can be replaced by
+    ///?, which are filled with params when the statement is executed
+    pub fn prepare(&mut self, query : String) -> Result<Statement> {
+        let mut message : Vec<u8> = vec![];
+        message.push(PREPARE_FLAG);
+        message.extend(query.as_bytes());
+        let mut buffer = self.request(&message)?;
         match buffer.remove(0) {
-            0 => Ok(Some(Cursor::try_from(buffer)?)),
-            1 => Ok(None),
-            2 => Err(Error::new(ErrorKind::Other, String::from_utf8_lossy(&buffer))),
+            0 => Ok(Statement{hash: buffer}),
+            2 => Err(decode_error(&buffer)),
             _ => Err(Error::new(ErrorKind::InvalidData, "response had invalid status code")),
         }
     }
 
+    ///Executes a prepared statement with params in the order of the placeholders
+    pub fn execute(&mut self, statement : &Statement, params : Vec<Value>) -> Result<Option<Cursor>> {
+        let mut message : Vec<u8> = vec![];
+        message.push(EXECUTE_FLAG);
+        message.extend(statement.hash.clone());
+        message.extend(encode_params(params));
+        self.send_query(message)
+    }
+
+    ///Sends a message and returns the response of the server. A failing stream or a closed
+    ///connection marks the connection as broken
+    fn request(&mut self, message : &[u8]) -> Result<Vec<u8>> {
+        let result = self.stream.send(message).and_then(|_| self.stream.receive());
+        match result {
+            Ok(buffer) => Ok(buffer),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                self.broken = true;
+                Err(Error::new(ErrorKind::ConnectionAborted, "connection was closed by the server"))
+            },
+            Err(e) => {
+                self.broken = true;
+                Err(e)
+            },
+        }
+    }
+
+    ///Checks that the server still answers on this connection. A failed ping marks the
+    ///connection as broken, it is not reconnected
+    pub fn ping(&mut self) -> Result<()> {
+        let buffer = self.request(&[PING_FLAG])?;
+        if buffer != PONG {
+            return Err(Error::new(ErrorKind::InvalidData, "server did not answer the ping"));
+        }
+        Ok(())
+    }
+
+    ///Returns true if the connection to the server was lost
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    ///Opens a new socket to the server and authenticates again. All cursors of the old
+    ///connection are invalid afterwards
+    fn reconnect(&mut self) -> Result<()> {
+        let stream = self.endpoint.connect()?;
+        self.stream = Self::authenticate(stream, &self.database, &self.key)?;
+        self.broken = false;
+        self.cursors.clear();
+        self.generation += 1;
+        Ok(())
+    }
 
+    ///Like request, but if the connection was lost it reconnects and sends the message again once
+    fn request_with_retry(&mut self, message : &[u8]) -> Result<Vec<u8>> {
+        match self.request(message) {
+            Err(_) if self.broken => {
+                self.reconnect()?;
+                self.request(message)
+            },
+            result => result,
+        }
+    }
+
+    fn send_query(&mut self, message : Vec<u8>) -> Result<Option<Cursor>> {
+        self.close_dropped_cursors();
+        let buffer = self.request_with_retry(&message)?;
+        self.warnings.clear();
+        self.message = None;
+        self.affected = None;
+        self.inserted_id = None;
+        match decode_query_response(buffer)? {
+            QueryResponse::Rows(warnings, mut cursor) => {
+                self.warnings = warnings;
+                cursor.generation = self.generation;
+                cursor.dropped = Some(self.dropped_cursors.clone());
+                self.cursors.push(cursor.hash.clone());
+                Ok(Some(cursor))
+            },
+            QueryResponse::Done(warnings, affected, inserted_id, message) => {
+                self.warnings = warnings;
+                self.affected = affected;
+                self.inserted_id = inserted_id;
+                self.message = message;
+                Ok(None)
+            },
+        }
+    }
+
+    ///Returns the warnings the server attached to the response of the last successful query
+    pub fn warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    ///Returns the text the server reported for the last successful query without rows, e.g. the
+    ///statistics computed by analyze
+    pub fn message(&self) -> Option<&String> {
+        self.message.as_ref()
+    }
+
+    ///Returns the number of rows the last successful insert or delete changed
+    pub fn affected(&self) -> Option<u64> {
+        self.affected
+    }
+
+    ///Returns the value the auto increment col got by the last successful insert
+    pub fn inserted_id(&self) -> Option<i64> {
+        self.inserted_id
+    }
+
+
+    ///Moves the cursor to the next row, returns false if there are no rows left. Fails if the
+    ///connection was reestablished since the cursor was opened, as the server dropped the cursor
     pub fn next(&mut self, cursor : &mut Cursor) -> Result<bool> {
+        if cursor.generation != self.generation {
+            cursor.dropped = None;
+            return Err(Error::new(ErrorKind::NotConnected, "cursor invalidated by a reconnect"));
+        }
+        self.close_dropped_cursors();
         let mut message : Vec<u8> = vec![];
         message.push(CURSOR_FLAG);
         message.extend(cursor.hash.clone());
-        self.stream.write_all(&message)?;
-        let mut buffer = vec![0; 1024];
-        let len = self.stream.read(&mut buffer)?;
-        buffer.truncate(len);
-        if len < 1 {
-            return Err(Error::new(ErrorKind::InvalidData, "response was empty"));
+        let buffer = match self.request(&message) {
+            Err(_) if self.broken => {
+                cursor.dropped = None;
+                self.reconnect()?;
+                return Err(Error::new(ErrorKind::NotConnected, "cursor invalidated by a reconnect"));
+            },
+            result => result?,
+        };
+        match decode_next_response(buffer) {
+            Ok(Some(row)) => {
+                cursor.row = row;
+                Ok(true)
+            },
+
+            //The server removes exhausted and failed cursors by itself
+            result => {
+                cursor.dropped = None;
+                self.cursors.retain(|hash| *hash != cursor.hash);
+                result.map(|_| false)
+            },
+        }
+    }
+
+    ///Returns an iterator over all rows of a cursor starting with the row it currently holds
+    pub fn rows(&mut self, cursor : Cursor) -> Rows<'_> {
+        Rows{connection: self, cursor, first: true, done: false}
+    }
+
+    ///Reads all rows of a cursor starting with the row it currently holds and returns them as a
+    ///JSON array with one object per row, keyed by the column names
+    pub fn to_json(&mut self, cursor : Cursor) -> Result<String> {
+        let keys : Vec<String> = cursor.column_names.iter().map(|name| json_string(name)).collect();
+        let mut objects : Vec<String> = vec![];
+        for row in self.rows(cursor) {
+            let fields : Vec<String> = keys.iter().zip(row?.iter()).map(|(key, value)| format!("{}:{}", key, value.to_json())).collect();
+            objects.push(format!("{{{}}}", fields.join(",")));
         }
+        Ok(format!("[{}]", objects.join(",")))
+    }
+
+    ///Closes a cursor that is not needed anymore, so the server can free it. Returns the number
+    ///of cursors that are still open on the server. Dropping a cursor closes it as well, but
+    ///only with the next request
+    pub fn close_cursor(&mut self, mut cursor : Cursor) -> Result<u64> {
+        cursor.dropped = None;
+        self.close_hash(std::mem::take(&mut cursor.hash))
+    }
+
+    fn close_hash(&mut self, hash : Vec<u8>) -> Result<u64> {
+        self.cursors.retain(|h| *h != hash);
+        let mut message : Vec<u8> = vec![];
+        message.push(CLOSE_CURSOR_FLAG);
+        message.extend(hash);
+        let mut buffer = self.request(&message)?;
         match buffer.remove(0) {
-            0 => {
-                cursor.row = decode_row(buffer)?;
-                Ok(true)
+            1 => {
+                read_u64(&buffer, 0, "the cursor count")
             },
-            1 => Ok(false),
-            2 => Err(Error::new(ErrorKind::Other, String::from_utf8_lossy(&buffer))),
+            2 => Err(decode_error(&buffer)),
             _ => Err(Error::new(ErrorKind::InvalidData, "response had invalid status code")),
         }
     }
 
+    ///Closes the cursors that were dropped before they were read till the end. Cursors of an
+    ///older connection are skipped, the server dropped them already
+    fn close_dropped_cursors(&mut self) {
+        let dropped : Vec<Vec<u8>> = match self.dropped_cursors.lock() {
+            Ok(mut dropped) => std::mem::take(&mut *dropped),
+            Err(_) => return,
+        };
+        for hash in dropped {
+            if self.cursors.contains(&hash) {
+                let _ = self.close_hash(hash);
+            }
+        }
+    }
+
+    ///Closes the cursors that were not read till the end and ends the connection, the same
+    ///happens when the connection is dropped
     pub fn close(self) {
-        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        drop(self);
     }
 
 }
 
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.broken {
+            return;
+        }
+        for hash in std::mem::take(&mut self.cursors) {
+            let _ = self.close_hash(hash);
+        }
+        let _ = self.stream.shutdown();
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+    #[test]
+    fn decode_warnings_test() {
+        let mut bytes : Vec<u8> = vec![];
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(15u64.to_le_bytes());
+        bytes.extend(b"value truncated");
+        bytes.extend(b"rest");
+        let (warnings, len) = decode_strings(&bytes).unwrap();
+        assert_eq!(warnings, vec!["value truncated".to_string()]);
+        assert_eq!(&bytes[len..], b"rest");
+        assert!(decode_strings(&bytes[..20]).is_err(), "truncated warnings should return an error");
+    }
 
 
+    #[test]
+    fn truncated_bytes_test() {
+        let row = encode_row(vec![1, 2]);
+        let decoded : Vec<String> = decode_row(row.clone()).unwrap().iter().map(|value| value.to_string()).collect();
+        assert_eq!(decoded, vec!["2".to_string(), "1".to_string()]);
+        for len in [3, 8, 20, 30] {
+            assert!(decode_row(row[..len].to_vec()).is_err(), "a row truncated to {} bytes should return an error", len);
+        }
+        let mut huge_len : Vec<u8> = vec![];
+        huge_len.extend(u64::MAX.to_le_bytes());
+        huge_len.extend(1u64.to_le_bytes());
+        assert!(decode_row(huge_len).is_err(), "a length past the end should return an error");
+        assert!(Value::try_from((0, vec![1, 2, 3])).is_err(), "a number needs eight bytes");
+        let mut cursor : Vec<u8> = vec![];
+        cursor.extend(1u64.to_le_bytes());
+        cursor.extend(1u64.to_le_bytes());
+        cursor.extend(b"a");
+        cursor.extend([0u8; 16]);
+        cursor.extend(encode_row(vec![7]));
+        assert_eq!(Cursor::try_from(cursor.clone()).unwrap().row[0].to_string(), "7");
+        for len in [4, 17, 30, 40] {
+            assert!(Cursor::try_from(cursor[..len].to_vec()).is_err(), "a cursor truncated to {} bytes should return an error", len);
+        }
+    }
+
+    ///Encodes a row like the server, each column as length, type id and bytes
+    pub(crate) fn encode_row(row : Vec<i64>) -> Vec<u8> {
+        let mut bytes : Vec<u8> = vec![];
+        for val in row {
+            bytes.extend(8u64.to_le_bytes());
+            bytes.extend(0u64.to_le_bytes());
+            bytes.extend(val.to_le_bytes());
+        }
+        bytes
+    }
+
+    ///Starts a server that accepts one connection and answers every request after the
+    ///authentication with the next of the given responses
+    pub(crate) fn fake_server(responses : Vec<Vec<u8>>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_frame(&mut stream).unwrap();
+            write_frame(&mut stream, &[0]).unwrap();
+            for response in responses {
+                if read_frame(&mut stream).is_err() {
+                    return;
+                }
+                write_frame(&mut stream, &response).unwrap();
+            }
+        });
+        address
+    }
+
+    ///Starts a server that accepts limit connections one after another, answers one request on
+    ///each with response and closes it. Returns the address and the number of accepted connections
+    pub(crate) fn closing_server(response : Vec<u8>, limit : usize) -> (String, Arc<AtomicUsize>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(limit) {
+                let mut stream = stream.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                read_frame(&mut stream).unwrap();
+                write_frame(&mut stream, &[0]).unwrap();
+                if read_frame(&mut stream).is_ok() {
+                    write_frame(&mut stream, &response).unwrap();
+                }
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+        });
+        (address, accepted)
+    }
+
+    #[test]
+    fn reconnect_test() {
+        let mut response : Vec<u8> = vec![0];
+        response.extend(0u64.to_le_bytes());
+        response.extend(0u64.to_le_bytes());
+        response.extend([7u8; 16]);
+        response.extend(encode_row(vec![1]));
+        let (address, accepted) = closing_server(response, 3);
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        let mut first = connection.query("SELECT * FROM t;".to_string()).unwrap().unwrap();
+
+        //The server closed the connection, the cursor is lost with it
+        let result = connection.next(&mut first);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotConnected);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        assert!(!connection.is_broken());
+        let second = connection.query("SELECT * FROM t;".to_string()).unwrap();
+        assert!(second.is_some());
+        assert_eq!(connection.next(&mut first).unwrap_err().kind(), ErrorKind::NotConnected);
+
+        //The query is sent again on a new connection
+        assert!(connection.query("SELECT * FROM t;".to_string()).unwrap().is_some());
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn rows_test() {
+        let mut first : Vec<u8> = vec![0];
+        first.extend(0u64.to_le_bytes());
+        first.extend(0u64.to_le_bytes());
+        first.extend([7u8; 16]);
+        first.extend(encode_row(vec![1]));
+        let mut second : Vec<u8> = vec![0];
+        second.extend(encode_row(vec![2]));
+        let address = fake_server(vec![first, second, vec![1], vec![2]]);
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        let cursor = connection.query("SELECT * FROM t;".to_string()).unwrap().unwrap();
+        let rows : Vec<i64> = connection.rows(cursor).map(|row| row.unwrap()[0].as_i64().unwrap()).collect();
+        assert_eq!(rows, vec![1, 2]);
+    }
+
+    #[test]
+    fn rows_error_test() {
+        let mut first : Vec<u8> = vec![0];
+        first.extend(0u64.to_le_bytes());
+        first.extend(0u64.to_le_bytes());
+        first.extend([7u8; 16]);
+        first.extend(encode_row(vec![1]));
+        let mut error : Vec<u8> = vec![2, 5];
+        error.extend(b"hash is invalid");
+        let address = fake_server(vec![first, error]);
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        let cursor = connection.query("SELECT * FROM t;".to_string()).unwrap().unwrap();
+        let rows : Vec<Result<Vec<Value>>> = connection.rows(cursor).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].is_ok());
+        assert!(rows[1].is_err(), "errors should be returned as items and end the iteration");
+    }
+
+    #[test]
+    fn drop_cursor_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_frame(&mut stream).unwrap();
+            write_frame(&mut stream, &[0]).unwrap();
+            loop {
+                let request = match read_frame(&mut stream) {
+                    Ok(request) => request,
+                    Err(_) => return,
+                };
+
+                //Queries open a cursor named after the number of requests so far, closes report
+                //that no cursors are left
+                let mut response : Vec<u8> = vec![];
+                if request[0] == QUERY_FLAG {
+                    response.push(0);
+                    response.extend(0u64.to_le_bytes());
+                    response.extend(0u64.to_le_bytes());
+                    response.extend([request.len() as u8; 16]);
+                    response.extend(encode_row(vec![1]));
+                } else {
+                    response.push(1);
+                    response.extend(0u64.to_le_bytes());
+                }
+                sender.send(request).unwrap();
+                write_frame(&mut stream, &response).unwrap();
+            }
+        });
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+
+        //A dropped cursor is closed before the next query
+        let cursor = connection.query("a".to_string()).unwrap().unwrap();
+        drop(cursor);
+        let _cursor = connection.query("ab".to_string()).unwrap().unwrap();
+        let mut close_first : Vec<u8> = vec![CLOSE_CURSOR_FLAG];
+        close_first.extend([2u8; 16]);
+        assert_eq!(receiver.recv().unwrap(), b"\x00a".to_vec());
+        assert_eq!(receiver.recv().unwrap(), close_first);
+        assert_eq!(receiver.recv().unwrap(), b"\x00ab".to_vec());
+
+        //Cursors that are still open are closed with the connection
+        drop(connection);
+        let mut close_second : Vec<u8> = vec![CLOSE_CURSOR_FLAG];
+        close_second.extend([3u8; 16]);
+        assert_eq!(receiver.recv().unwrap(), close_second);
+    }
+
+    #[test]
+    fn large_message_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_frame(&mut stream).unwrap();
+            write_frame(&mut stream, &[0]).unwrap();
+
+            //The query arrives as a whole, the answer is written in small pieces
+            let request = read_frame(&mut stream).unwrap();
+            let mut response : Vec<u8> = vec![0];
+            response.extend(0u64.to_le_bytes());
+            response.extend(0u64.to_le_bytes());
+            response.extend([7u8; 16]);
+            response.extend(((request.len() - 1) as u64).to_le_bytes());
+            response.extend(1u64.to_le_bytes());
+            response.extend(&request[1..]);
+            let mut frame : Vec<u8> = vec![];
+            write_frame(&mut frame, &response).unwrap();
+            for chunk in frame.chunks(100) {
+                stream.write_all(chunk).unwrap();
+                stream.flush().unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        });
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        let query = format!("SELECT * FROM t WHERE a == {};", "x".repeat(3000));
+        let cursor = connection.query(query.clone()).unwrap().unwrap();
+        assert_eq!(cursor.get(0).and_then(|value| value.as_str()), Some(query.as_str()));
+    }
+
+    #[test]
+    fn decode_error_test() {
+        let mut bytes : Vec<u8> = vec![2];
+        bytes.extend(b"table does not exist");
+        let error = decode_error(&bytes);
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(ErrorCode::of(&error), Some(ErrorCode::TableNotFound));
+        assert_eq!(error.to_string(), "table does not exist");
+        assert_eq!(ErrorCode::of(&decode_error(&[4])), Some(ErrorCode::ConstraintViolation));
+        assert_eq!(ErrorCode::of(&decode_error(&[])), Some(ErrorCode::Internal), "unknown codes should be internal");
+        assert_eq!(ErrorCode::of(&Error::other("x")), None);
+    }
+
+    #[test]
+    fn unix_test() {
+        let dir = std::env::temp_dir().join(format!("rust-client-unix-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("d-bee.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                assert_eq!(read_frame(&mut stream).unwrap(), b"db.key");
+                write_frame(&mut stream, &[0]).unwrap();
+                if read_frame(&mut stream).is_ok() {
+                    write_frame(&mut stream, PONG).unwrap();
+                }
+            }
+        });
+        let mut connection = Connection::new_unix(&path, "db".to_string(), "key".to_string()).unwrap();
+        assert!(connection.ping().is_ok());
+
+        //Lost connections are opened on the same socket again
+        connection.reconnect().unwrap();
+        assert!(connection.ping().is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ping_test() {
+        let address = fake_server(vec![PONG.to_vec(), vec![2]]);
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        assert!(connection.ping().is_ok());
+        assert!(connection.ping().is_err(), "other answers than pong should fail the ping");
+        assert!(connection.ping().is_err(), "a closed connection should fail the ping");
+        assert!(connection.is_broken());
+    }
+
+    #[test]
+    fn to_json_test() {
+        let mut first : Vec<u8> = vec![0];
+        first.extend(0u64.to_le_bytes());
+        first.extend(2u64.to_le_bytes());
+        for name in ["name", "age"] {
+            first.extend((name.len() as u64).to_le_bytes());
+            first.extend(name.as_bytes());
+        }
+        first.extend([7u8; 16]);
+
+        //Rows arrive reversed
+        first.extend(encode_row(vec![30]));
+        first.extend(5u64.to_le_bytes());
+        first.extend(1u64.to_le_bytes());
+        first.extend(b"a \"b\"");
+        let address = fake_server(vec![first, vec![1]]);
+        let mut connection = Connection::new(address, "db".to_string(), "key".to_string()).unwrap();
+        let cursor = connection.query("SELECT * FROM t;".to_string()).unwrap().unwrap();
+        assert_eq!(cursor.column_names, vec!["name".to_string(), "age".to_string()]);
+        assert_eq!(connection.to_json(cursor).unwrap(), r#"[{"name":"a \"b\"","age":30}]"#);
+    }
+
+    #[test]
+    fn value_accessors_test() {
+        let number = Value::Number(3);
+        let text = Value::Text("a".to_string());
+        assert_eq!((number.as_i64(), number.as_str()), (Some(3), None));
+        assert_eq!((text.as_i64(), text.as_str()), (None, Some("a")));
+        assert_eq!(TryInto::<i64>::try_into(number).unwrap(), 3);
+        assert!(TryInto::<i64>::try_into(Value::Text("3".to_string())).is_err());
+        assert_eq!(TryInto::<String>::try_into(text).unwrap(), "a");
+        let cursor = Cursor{row: vec![Value::Number(1)], column_names: vec!["a".to_string()], hash: vec![], generation: 0, dropped: None};
+        assert_eq!(cursor.get(0).and_then(|value| value.as_i64()), Some(1));
+        assert!(cursor.get(1).is_none());
+        let blob = Value::Blob(vec![1, 171]);
+        assert_eq!((blob.as_bytes(), blob.as_i64()), (Some(&[1u8, 171][..]), None));
+        assert_eq!(blob.to_string(), "01ab");
+    }
+
+    #[test]
+    fn decode_affected_test() {
+        let mut bytes : Vec<u8> = vec![9, 1];
+        bytes.extend(3u64.to_le_bytes());
+        bytes.push(0);
+        bytes.push(2);
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend((7i64).to_le_bytes());
+        assert_eq!(decode_affected(&bytes, 1).unwrap(), (Some(3), None, 10));
+        assert_eq!(decode_affected(&bytes, 10).unwrap(), (None, None, 11));
+        assert_eq!(decode_affected(&bytes, 11).unwrap(), (Some(1), Some(7), 28));
+        assert!(decode_affected(&bytes[..5], 1).is_err(), "truncated counts should return an error");
+        assert!(decode_affected(&bytes[..20], 11).is_err(), "truncated ids should return an error");
+    }
+
+    #[test]
+    fn encode_params_test() {
+        let bytes = encode_params(vec![Value::Number(-3), Value::Text("a b".to_string()), Value::Blob(vec![0, 255])]);
+        let mut expected : Vec<u8> = vec![];
+        expected.extend(8u64.to_le_bytes());
+        expected.extend(0u64.to_le_bytes());
+        expected.extend((-3i64).to_le_bytes());
+        expected.extend(3u64.to_le_bytes());
+        expected.extend(1u64.to_le_bytes());
+        expected.extend(b"a b");
+        expected.extend(2u64.to_le_bytes());
+        expected.extend(2u64.to_le_bytes());
+        expected.extend([0, 255]);
+        assert_eq!(bytes, expected);
+    }
+
     #[test]
     fn o() {
         let mut connection = Connection::new("127.0.0.1:4321".to_string(),"standard".to_string(), "4321".to_string()).expect("couldnt connect");
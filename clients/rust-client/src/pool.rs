@@ -0,0 +1,157 @@
+use std::{io::{Result, Error, ErrorKind}, ops::{Deref, DerefMut}, path::PathBuf, sync::{Mutex, Condvar}};
+use crate::Connection;
+
+
+struct PoolState {
+    idle : Vec<Connection>,
+
+    //Number of connections that are idle or handed out
+    open : usize,
+}
+
+///Keeps up to size authenticated connections to one database and hands them out with get, so
+///they can be reused instead of connecting for every request
+pub struct Pool {
+    address : String,
+    database : String,
+    key : String,
+    cert_path : Option<PathBuf>,
+    size : usize,
+    state : Mutex<PoolState>,
+    available : Condvar,
+}
+
+
+impl Pool {
+
+    ///Connections are opened when they are needed, only the first one is opened right away to
+    ///make sure the address and key are valid
+    pub fn new(address : String, database : String, key : String, size : usize) -> Result<Self> {
+        Self::create(address, database, key, None, size)
+    }
+
+    ///Like new but all connections are encrypted with TLS, see Connection::with_tls
+    pub fn with_tls(address : String, database : String, key : String, cert_path : PathBuf, size : usize) -> Result<Self> {
+        Self::create(address, database, key, Some(cert_path), size)
+    }
+
+    fn create(address : String, database : String, key : String, cert_path : Option<PathBuf>, size : usize) -> Result<Self> {
+        if size == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "pool size has to be at least 1"));
+        }
+        let pool = Pool{address, database, key, cert_path, size, state: Mutex::new(PoolState{idle: vec![], open: 0}), available: Condvar::new()};
+        let connection = pool.connect()?;
+        let mut state = pool.state.lock().map_err(|_| Error::other("pool lock was poisoned"))?;
+        state.idle.push(connection);
+        state.open = 1;
+        drop(state);
+        Ok(pool)
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        match &self.cert_path {
+            Some(cert_path) => Connection::with_tls(self.address.clone(), self.database.clone(), self.key.clone(), cert_path),
+            None => Connection::new(self.address.clone(), self.database.clone(), self.key.clone()),
+        }
+    }
+
+    ///Returns an idle connection or opens a new one if less than size are open. Blocks until a
+    ///connection is returned otherwise
+    pub fn get(&self) -> Result<PooledConnection<'_>> {
+        let mut state = self.state.lock().map_err(|_| Error::other("pool lock was poisoned"))?;
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection{pool: self, connection: Some(connection)});
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match self.connect() {
+                    Ok(connection) => Ok(PooledConnection{pool: self, connection: Some(connection)}),
+                    Err(e) => {
+                        self.release(None);
+                        Err(e)
+                    },
+                };
+            }
+            state = self.available.wait(state).map_err(|_| Error::other("pool lock was poisoned"))?;
+        }
+    }
+
+    ///Puts a connection back or frees its slot if it is broken, so the next get reconnects
+    fn release(&self, connection : Option<Connection>) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        match connection {
+            Some(connection) if !connection.is_broken() => state.idle.push(connection),
+            _ => state.open -= 1,
+        }
+        self.available.notify_one();
+    }
+
+}
+
+///Connection borrowed from a pool, it is returned to the pool when dropped
+pub struct PooledConnection<'a> {
+    pool : &'a Pool,
+    connection : Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().expect("connection was already returned")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection.as_mut().expect("connection was already returned")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        self.pool.release(self.connection.take());
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::tests::closing_server;
+    use std::sync::atomic::Ordering;
+
+
+    #[test]
+    fn pool_test() {
+        let mut response : Vec<u8> = vec![1];
+        response.extend(0u64.to_le_bytes());
+        response.push(0);
+        let (address, accepted) = closing_server(response, 2);
+        let pool = Pool::new(address, "db".to_string(), "key".to_string(), 1).unwrap();
+        {
+            let mut connection = pool.get().unwrap();
+            assert!(connection.query("CREATE TABLE t (a number);".to_string()).is_ok());
+        }
+
+        //The server closed the connection after the first query, so the connection reconnects
+        {
+            let mut connection = pool.get().unwrap();
+            assert!(connection.query("CREATE TABLE t (a number);".to_string()).is_ok());
+            assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        }
+
+        //The server is gone now, the broken connection has to be dropped by the pool
+        {
+            let mut connection = pool.get().unwrap();
+            assert!(connection.query("CREATE TABLE t (a number);".to_string()).is_err());
+            assert!(connection.is_broken());
+        }
+        assert!(pool.get().is_err(), "the pool should try to open a new connection");
+    }
+
+}
@@ -3,11 +3,16 @@
 
 
 use std::fmt::{self, Display, Formatter};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 
 pub struct Bubble {
    content : String, 
    width : Vec<usize>,
+
+   //Lines of a dynamic bubble where None stands for a divider. They are only rendered once the
+   //widths of all columns are known
+   lines : Option<Vec<Option<Vec<String>>>>,
 }
 
 
@@ -28,15 +33,22 @@ impl Bubble {
     }
 
 
-    //Splits up the content into columns of fixed width
+    //Splits up the content into columns of fixed width. Widths are measured in terminal columns,
+    //so wide characters take two and cells are cut between characters
     pub fn format_line(&self, content : Vec<String>) -> String {
         let mut result : String = String::new();
         for i in 0..self.width.len() {
             result.push_str("|"); 
-            let mut line = String::from(content[i].clone());
-            line.truncate(self.width[i]);
-            result.push_str(&line);
-            for _ in content[i].len()..self.width[i] {
+            let mut used = 0;
+            for c in content[i].chars() {
+                let width = c.width().unwrap_or(0);
+                if used + width > self.width[i] {
+                    break;
+                }
+                result.push(c);
+                used += width;
+            }
+            for _ in used..self.width[i] {
                 result.push_str(" ");
             }
         }
@@ -48,18 +60,53 @@ impl Bubble {
 
 
     pub fn new(width : Vec<usize>) -> Bubble {
-        let mut bubble = Bubble{width, content: String::new()};
+        let mut bubble = Bubble{width, content: String::new(), lines: None};
         bubble.add_divider(); 
         return bubble;
     }
 
+
+    //Creates a bubble that sizes each column to its widest cell so nothing is truncated. Lines
+    //are buffered and rendered when the bubble is displayed
+    pub fn dynamic() -> Bubble {
+        Bubble{width: vec![], content: String::new(), lines: Some(vec![])}
+    }
+
     pub fn add_divider(&mut self) {
+        if let Some(lines) = self.lines.as_mut() {
+            lines.push(None);
+            return;
+        }
         self.content.push_str(&self.get_divider());
         self.content.push_str("\n");
 
     }
 
+    //Renders the lines of a dynamic bubble as a GitHub flavored markdown table. The first line is
+    //the header, dividers are left out and pipes inside cells are escaped
+    pub fn to_markdown(&self) -> String {
+        let lines : Vec<&Vec<String>> = self.lines.iter().flatten().flatten().collect();
+        let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        let format = |line : &Vec<String>| -> String {
+            let mut cells : Vec<String> = line.iter().map(|cell| cell.replace('|', "\\|").replace('\n', "<br>")).collect();
+            cells.resize(columns, String::new());
+            format!("| {} |\n", cells.join(" | "))
+        };
+        let mut result = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            result.push_str(&format(line));
+            if i == 0 {
+                result.push_str(&format(&vec!["---".to_string(); columns]));
+            }
+        }
+        result
+    }
+
     pub fn add_line(&mut self, content : Vec<String>) {
+        if let Some(lines) = self.lines.as_mut() {
+            lines.push(Some(content));
+            return;
+        }
         self.content.push_str(&self.format_line(content));
         self.content.push_str("|\n");
     }
@@ -67,6 +114,34 @@ impl Bubble {
 
 impl Display for Bubble {
     fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        if let Some(lines) = &self.lines {
+
+            //Each column is as wide as its widest cell, missing cells of short lines stay empty
+            let mut width : Vec<usize> = vec![];
+            for line in lines.iter().flatten() {
+                width.resize(width.len().max(line.len()), 0);
+                for (i, cell) in line.iter().enumerate() {
+                    width[i] = width[i].max(cell.width());
+                }
+            }
+            let fixed = Bubble{width, content: String::new(), lines: None};
+            let mut content = fixed.get_divider();
+            content.push('\n');
+            for line in lines {
+                match line {
+                    Some(line) => {
+                        let mut line = line.clone();
+                        line.resize(fixed.width.len(), String::new());
+                        content.push_str(&fixed.format_line(line));
+                    },
+                    None => content.push_str(&fixed.get_divider()),
+                }
+                content.push('\n');
+            }
+            content.push_str(&fixed.get_divider());
+            content.push('\n');
+            return write!(f, "{}", content);
+        }
         let mut content = self.content.clone();
         for j in 0..self.width.len() {
             content.push_str("+");
@@ -80,3 +155,48 @@ impl Display for Bubble {
 write!(f, "{}", content)
     }
 }
+
+
+
+#[cfg(test)]
+mod test {
+
+
+    use super::*;
+
+
+    #[test]
+    fn dynamic_test() {
+        let mut bubble = Bubble::dynamic();
+        bubble.add_line(vec!["id".to_string(), "name".to_string()]);
+        bubble.add_divider();
+        bubble.add_line(vec!["1".to_string(), "a rather long name".to_string()]);
+        bubble.add_line(vec!["22".to_string()]);
+        let expected = "+--+------------------+\n|id|name              |\n+--+------------------+\n|1 |a rather long name|\n|22|                  |\n+--+------------------+\n";
+        assert_eq!(bubble.to_string(), expected);
+    }
+
+
+    #[test]
+    fn unicode_width_test() {
+        let bubble = Bubble::new(vec![4, 3]);
+        assert_eq!(bubble.format_line(vec!["äöü".to_string(), "日本語".to_string()]), "|äöü |日 |", "wide characters should not be split");
+        let mut bubble = Bubble::dynamic();
+        bubble.add_line(vec!["日本".to_string()]);
+        bubble.add_line(vec!["ab".to_string()]);
+        assert_eq!(bubble.to_string(), "+----+\n|日本|\n|ab  |\n+----+\n");
+    }
+
+
+    #[test]
+    fn markdown_test() {
+        let mut bubble = Bubble::dynamic();
+        bubble.add_line(vec!["id".to_string(), "name".to_string()]);
+        bubble.add_divider();
+        bubble.add_line(vec!["1".to_string(), "a|b".to_string()]);
+        bubble.add_line(vec!["2".to_string()]);
+        assert_eq!(bubble.to_markdown(), "| id | name |\n| --- | --- |\n| 1 | a\\|b |\n| 2 |  |\n");
+    }
+
+
+}
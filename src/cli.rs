@@ -1,21 +1,85 @@
 use rust_client::*;
 use std::io::{self, Write};
 use std::thread;
-use std::time::Duration;
-use std::net::TcpStream;
-use std::io::Read;
-use crate::{bubble::*, storage::file_management::get_base_path};
-use std::env;
+use std::time::{Duration, Instant};
+use crate::{bubble::*, executor::Executor, query::parsing::split_statements, storage::file_management::get_base_path};
+use std::{env, net::SocketAddr, path::{Path, PathBuf}};
+use rustyline::{DefaultEditor, error::ReadlineError};
+use unicode_width::UnicodeWidthStr;
 
 
 const NEW_DATABASE_FLAG : u8 = 0x02;
-const GET_KEY_FLAG : u8 = 0x03;
 const TERMINATE_FLAG : u8 = 0x04;
 const DELETE_DATABASE_FLAG : u8 = 0x05;
-
-
-
-pub fn start_cli() {
+const LIST_DATABASES_FLAG : u8 = 0x09;
+const ROTATE_ADMIN_KEY_FLAG : u8 = 0x0A;
+const CHANGE_KEY_FLAG : u8 = 0x0C;
+const ADD_ADMIN_FLAG : u8 = 0x0D;
+const REVOKE_ADMIN_FLAG : u8 = 0x0E;
+const LIST_ADMINS_FLAG : u8 = 0x0F;
+const SET_READ_KEY_FLAG : u8 = 0x10;
+const REMOVE_READ_KEY_FLAG : u8 = 0x11;
+const STATS_FLAG : u8 = 0x12;
+const LIST_CONNECTIONS_FLAG : u8 = 0x13;
+const KILL_CONNECTION_FLAG : u8 = 0x14;
+const STATUS_FLAG : u8 = 0x15;
+
+//Commands available while not connected to a database along with their description
+const ROOT_COMMANDS : [(&str, &str); 17] = [
+    ("connect <database name> [key]", "connect to a database to run queries in it, with a client key if one is given"),
+    ("new <database name> [page size]", "create a database and print its key, it can not be shown again"),
+    ("delete <database name>", "delete a database and all of its tables"),
+    ("change_key <database name>", "replace the key of a database and print the new one"),
+    ("read_key <database name>", "replace the read only key of a database and print the new one"),
+    ("remove_read_key <database name>", "stop accepting the read only key of a database"),
+    ("list", "print the names of all databases"),
+    ("stats", "print the size, tables and row counts of all databases"),
+    ("status", "print the uptime and the number of databases, connections and open cursors"),
+    ("connections", "print the open connections with their database, type and idle time"),
+    ("kill <token>", "end the connection with the token and close its cursors"),
+    ("rotate", "replace the admin key and print the new one"),
+    ("add_admin <label> [full|read_only]", "add an admin and print its key, read only admins can only list databases, their statistics and the server status"),
+    ("revoke_admin <label>", "stop accepting the key of an added admin"),
+    ("admins", "print the labels and roles of all added admins"),
+    ("help", "print this list"),
+    ("exit", "shut down the server and exit"),
+];
+
+//Statements available while connected to a database along with their description
+const DATABASE_COMMANDS : [(&str, &str); 27] = [
+    ("CREATE TABLE <table> (<col> <TEXT|NUMBER|BLOB|NUMBER AUTO_INCREMENT>, ...);", "create a table"),
+    ("CREATE TABLE <table> AS SELECT [DISTINCT] <*|col, ...> FROM <table> [WHERE <col> <op> <value>];", "create a table from the rows of a select"),
+    ("DROP TABLE <table>;", "delete a table"),
+    ("RENAME TABLE <table> TO <new table>;", "rename a table"),
+    ("ALTER TABLE <table> DROP COLUMN <col>;", "remove a column from a table"),
+    ("INSERT INTO <table> [(<col>, ...)] VALUES (<value>, ...);", "insert a row"),
+    ("SELECT [DISTINCT] <*|col, ...> FROM <table> [WHERE <col> <op> <value>];", "print matching rows"),
+    ("SELECT COUNT(*) FROM <table> [WHERE <col> <op> <value>];", "print the number of matching rows"),
+    ("SELECT EXISTS FROM <table> [WHERE <col> <op> <value>];", "print 1 if any row matches, 0 otherwise"),
+    ("DELETE FROM <table> [WHERE <col> <op> <value>];", "delete matching rows"),
+    ("... WHERE <col> ==* '<text>'", "match text ignoring its case"),
+    ("... WHERE <col> <IN|op> (SELECT <col> FROM <table> [WHERE ...])", "compare with the values of a subquery, an op needs exactly one"),
+    ("SELECT <UPPER|LOWER|LENGTH>(<col>), ... FROM ...;", "apply a function to a text column, the columns keep the order of the query"),
+    ("SELECT ... ORDER BY <col> [ASC|DESC], ...;", "sort the rows by the selected cols, the first decides and the others break ties"),
+    ("DESCRIBE <table>;", "print the columns of a table"),
+    ("EXPLAIN <statement>;", "print how a statement would be executed without running it"),
+    ("ANALYZE <table>;", "recompute the statistics of a table"),
+    ("VACUUM <table>;", "free unused pages of a table"),
+    ("EXPORT <table> TO '<path>';", "write all rows of a table to a csv file"),
+    ("IMPORT '<path>' INTO <table> [SKIP INVALID];", "insert the rows of a csv file into a table"),
+    ("timing <on|off>", "print how long each query took"),
+    ("format <table|markdown>", "print rows as a bubble or as a markdown table"),
+    ("source <path> [continue]", "run the statements of a file, continue runs past failed ones"),
+    ("export <table> <path>", "write all rows of a table to a csv file on this machine"),
+    ("reconnect", "connect to the database again with the same key, e.g. after the server restarted"),
+    ("help", "print this list"),
+    ("exit", "disconnect from the database, disconnect does the same"),
+];
+
+
+
+///Runs the cli, the admin key is the session key of the server running in this process
+pub fn start_cli(admin_key : String, address : SocketAddr, admin_address : SocketAddr) {
 
     //Sleep till server has started.
     thread::sleep(Duration::from_millis(100));
@@ -23,83 +87,169 @@ pub fn start_cli() {
     let path = get_base_path().expect("couldnt get base path").join(".env");
     dotenv::from_path(path).expect("couldnt load env");
 
-    let admin_key = env::var("ADMIN_KEY").expect("couldnt find the admin key");
+    //If the server uses TLS its certificate is trusted for all connections. It has to be an end
+    //entity certificate (CA:FALSE) issued for 127.0.0.1
+    let cert_path : Option<PathBuf> = env::var("TLS_CERT_PATH").ok().map(PathBuf::from);
 
     //Try to connect to server on the port designated for admins. Otherwise print error.
-    if let Ok(mut connection) = TcpStream::connect("127.0.0.1:4322") {
+    if let Ok(mut connection) = Stream::connect(&admin_address.to_string(), cert_path.as_deref()) {
 
         //Authenticate as admin
-        if !connection.write_all(admin_key.as_bytes()).is_ok() {
+        if connection.send(admin_key.as_bytes()).is_err() {
             println!("not ok");
             return;
         }
 
         //Check response and exit if authentication failed.
-        let mut buffer = [0u8; 512];
-        let len = connection.read(&mut buffer).expect("failed to read from connection");
-        match buffer[..len] {
+        match connection.receive().expect("failed to read from connection")[..] {
             [0] => (),
             _ => {
                 println!("failed to connect to server");
                 return;},
         }
 
-        //Database is used for connection to one database.
-        let mut database : Option<(String, Connection)> = None;
+        //Database is used for connection to one database. Its name and key are kept to reconnect
+        let mut database : Option<(String, String, Connection)> = None;
 
         //Disconnect is used to exit the connection to one database. This has to be done since a
         //reference to database is held while exit is called.
         let mut disconnect : bool = false;
 
+        //If timing is on the duration of each query is printed after its result
+        let mut timing : bool = false;
+
+        //Rows are printed in this format, format markdown switches to markdown tables
+        let mut format = OutputFormat::Table;
+
+        //Lines of a statement that does not end with a semicolon yet
+        let mut pending = String::new();
+
+        //The editor allows recalling previous commands, which are kept in the history file
+        //between sessions
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(e) => {
+                println!("failed to start the line editor: {}", e);
+                return;
+            },
+        };
+        let history_path = get_base_path().expect("couldnt get base path").join("history");
+        let _ = editor.load_history(&history_path);
+
         //Continuously print path to the terminal and wait for new inputs.
         'outer:
         loop {
-            if let Some((ref db, _)) = database {
-                print!("<d-bee/{}>: ", db);
+            let prompt = if !pending.is_empty() {
+                "...> ".to_string()
+            }else if let Some((ref db, _, _)) = database {
+                format!("<d-bee/{}>: ", db)
             }else{
-                print!("<d-bee>: ");
+                "<d-bee>: ".to_string()
+            };
+            let mut command = match editor.readline(&prompt) {
+                Ok(command) => command,
+
+                //Ctrl-C discards the current statement, Ctrl-D acts like exit
+                Err(ReadlineError::Interrupted) => {
+                    pending.clear();
+                    continue;
+                },
+                Err(ReadlineError::Eof) => {
+                    pending.clear();
+                    "exit".to_string()
+                },
+                Err(e) => {
+                    println!("failed to read line: {}", e);
+                    return;
+                },
+            };
+
+            //Statements can span several lines, they are sent once a line ends with a semicolon.
+            //The commands of the CLI itself are single line
+            if database.is_some() && (!pending.is_empty() || !is_cli_command(command.trim())) {
+                if pending.is_empty() && command.trim().is_empty() {
+                    continue;
+                }
+                if !pending.is_empty() {
+                    pending.push('\n');
+                }
+                pending.push_str(&command);
+                if !pending.trim_end().ends_with(';') {
+                    continue;
+                }
+                command = std::mem::take(&mut pending);
+            }
+            if !command.trim().is_empty() {
+                let _ = editor.add_history_entry(command.as_str());
+                let _ = editor.save_history(&history_path);
             }
-            let mut command = String::new();
-            io::stdout().flush().unwrap(); // Ensure the prompt is displayed before input
-            io::stdin().read_line(&mut command).expect("Failed to read line");
-            
-            //remove <\n> character
-            command.truncate(command.len() - 1);
 
             //Check if the CLI is currently connected to a specific database and than treat the
             //command accordingly.
-            if let Some((_, ref mut database_connection)) = database.as_mut() {
+            if let Some((ref database_name, ref key, ref mut database_connection)) = database.as_mut() {
 
                 //When connected to a specific database check for the exit command, otherwise
                 //forward the input to the server via the rust client.
                 match command.trim() {
-                    "exit" => {
+                    "exit" | "disconnect" => {
                         disconnect = true;
                     },
-                    _ => {
-                        match database_connection.query(command) {
-                            
-                            //Print result as a bubble if there is one
-                            Ok(Some(mut res)) => {
-                                let bubble = Bubble::new(vec![10; res.row.len()].to_vec());
-                                println!("{}", bubble.get_divider());
-                                loop {
-                                    println!("{}", bubble.format_line(res.row.iter().map(|value| value.to_string()).collect()));
-                                    if !match database_connection.next(&mut res) {
-                                        Ok(val) => val,
-                                        _ => false,
-                                    } {
-                                        break;
-                                    }
-                                }
-                                println!("{}", bubble.get_divider());
+                    "reconnect" => {
+                        match connect_database(address, database_name, key, cert_path.as_deref()) {
+                            Ok(new_connection) => {
+                                *database_connection = new_connection;
+                                print_green(&format!("reconnected to {}", database_name));
                             },
-
-                            //If the result is empty print success so the user is not confused
-                            Ok(None) => print_green("success"),
+                            Err(e) => println!("failed to reconnect: {}", e),
+                        }
+                    },
+                    "help" => {
+                        print_help(&DATABASE_COMMANDS);
+                    },
+                    "timing on" => {
+                        timing = true;
+                        println!("timing is on");
+                    },
+                    "timing off" => {
+                        timing = false;
+                        println!("timing is off");
+                    },
+                    "format table" => {
+                        format = OutputFormat::Table;
+                    },
+                    "format markdown" => {
+                        format = OutputFormat::Markdown;
+                    },
+                    source if source.split_whitespace().next() == Some("source") => {
+                        let tokens : Vec<&str> = source.split_whitespace().collect();
+                        match tokens[..] {
+                            [_, path] => run_script(database_connection, path, false, format),
+                            [_, path, "continue"] => run_script(database_connection, path, true, format),
+                            _ => println!("wrong usage of source. Use it like this: source <path> [continue]"),
+                        }
+                    },
+                    export if is_client_export(export) => {
+                        let tokens : Vec<&str> = export.split_whitespace().collect();
+                        if tokens.len() != 3 {
+                            println!("wrong usage of export. Use it like this: export <table> <path>");
+                            continue;
+                        }
+                        match export_table(database_connection, tokens[1], tokens[2]) {
+                            Ok(1) => print_green(&format!("wrote 1 row to {}", tokens[2])),
+                            Ok(written) => print_green(&format!("wrote {} rows to {}", written, tokens[2])),
                             Err(e) => println!("{}", e),
                         }
                     },
+                    _ => {
+
+                        //The duration includes fetching all rows of the result
+                        let start = Instant::now();
+                        let result = database_connection.query(command);
+                        print_result(database_connection, result, format);
+                        if timing {
+                            println!("({:.1} ms)", start.elapsed().as_secs_f64() * 1000.0);
+                        }
+                    },
                 }
             }else{
 
@@ -108,50 +258,26 @@ pub fn start_cli() {
                 let tokens : Vec<&str> = command.split(" ").collect();
                 match tokens[0] {
                     "connect" => {
-                        //Valid length for a connection attempt is 2
-                        if tokens.len() != 2 {
-                            println!("wrong usage of connect. Use it like this: connect <database name>");
+                        //Valid length for a connection attempt is 2 or 3 if a key is given
+                        if tokens.len() != 2 && tokens.len() != 3 {
+                            println!("wrong usage of connect. Use it like this: connect <database name> [key]");
                             continue;
                         }
 
-                        //The right key for the database is requested with admin privilege
+                        //A given key authenticates like a client would, which allows testing
+                        //client keys. Database keys are only stored hashed, so without one the
+                        //admin key is used to open the database instead
                         let database_name = tokens[1];
-                        let mut message : Vec<u8> = vec![];
-                        message.push(GET_KEY_FLAG);
-                        message.extend(database_name.as_bytes());
-                        if !connection.write_all(&message).is_ok() {
-                            println!("failed to send request");
-                            continue;
-                        };
-                        let mut buffer = vec![0; 1024];
-                        if let Ok(len) = connection.read(&mut buffer) {
-                            buffer.truncate(len);
-                            //The key is constructed from the servers response or errors are
-                            //ignored and input is skipped
-                            if len < 1 {
-                                println!("response from server was empty");
-                                continue;
-                            }
-                            match buffer.remove(0) {
-                                0 => {
-                                    let key = String::from_utf8_lossy(&buffer);
-
-                                    //Tries to set database to a rust client connection with the
-                                    //requested key
-                                    match Connection::new("127.0.0.1:4321".to_string(), database_name.to_string(), key.to_string()) { 
-                                        Ok(database_connection) => database = Some((database_name.to_string(), database_connection)),
-                                        Err(e) => println!("{}", e),
-                                    }
-                                },
-                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
-                                _ => {println!("invalid status code returned from server");},
-                            }
+                        let key = tokens.get(2).map_or(admin_key.clone(), |key| key.to_string());
+                        match connect_database(address, database_name, &key, cert_path.as_deref()) {
+                            Ok(database_connection) => database = Some((database_name.to_string(), key, database_connection)),
+                            Err(e) => println!("{}", e),
                         }
                     },
                     "new" => {
-                        //Valid length for new is 2
-                        if tokens.len() != 2 {
-                            println!("wrong usgae of new. Use it like this: new <database name>");
+                        //Valid length for new is 2 or 3 if a page size is given
+                        if tokens.len() != 2 && tokens.len() != 3 {
+                            println!("wrong usgae of new. Use it like this: new <database name> [page size]");
                             continue;
                         }
 
@@ -160,16 +286,17 @@ pub fn start_cli() {
                         let mut message : Vec<u8> = vec![];
                         message.push(NEW_DATABASE_FLAG);
                         message.extend(database_name.as_bytes());
-                        if !connection.write_all(&message).is_ok() {
+                        if let Some(page_size) = tokens.get(2) {
+                            message.extend(format!(" {}", page_size).as_bytes());
+                        }
+                        if connection.send(&message).is_err() {
                             println!("failed to send request");
                             continue;
                         };
 
                         //Response is handled
-                        let mut buffer = vec![0; 1024];
-                        if let Ok(len) = connection.read(&mut buffer) {
-                            buffer.truncate(len);
-                            if len < 1 {
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
                                 println!("response from server was empty");
                                 continue;
                             }
@@ -207,16 +334,14 @@ pub fn start_cli() {
                         let mut message : Vec<u8> = vec![];
                         message.push(DELETE_DATABASE_FLAG);
                         message.extend(database_name.as_bytes());
-                        if !connection.write_all(&message).is_ok() {
+                        if connection.send(&message).is_err() {
                             println!("failed to send request");
                             continue;
                         };
 
                         //Response is handled
-                        let mut buffer = vec![0; 1024];
-                        if let Ok(len) = connection.read(&mut buffer) {
-                            buffer.truncate(len);
-                            if len < 1 {
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
                                 println!("response from server was empty");
                                 continue;
                             }
@@ -228,47 +353,346 @@ pub fn start_cli() {
                         }
 
                     },
-                    "key" => {
+                    "change_key" => {
 
-                        //Valid length for new is 2
+                        //Valid length for change_key is 2
                         if tokens.len() != 2 {
-                            println!("wrong usgae of key. Use it like this: key <database name>");
+                            println!("wrong usage of change_key. Use it like this: change_key <database name>");
                             continue;
                         }
 
-                        //The right key for the database is requested with admin privilege
-                        let database_name = tokens[1];
-                        let mut message : Vec<u8> = vec![];
-                        message.push(GET_KEY_FLAG);
-                        message.extend(database_name.as_bytes());
-                        if !connection.write_all(&message).is_ok() {
+                        //Connected clients keep working, only new connections need the new key
+                        let mut message : Vec<u8> = vec![CHANGE_KEY_FLAG];
+                        message.extend(tokens[1].as_bytes());
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("new key: {}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "read_key" => {
+
+                        //Valid length for read_key is 2
+                        if tokens.len() != 2 {
+                            println!("wrong usage of read_key. Use it like this: read_key <database name>");
+                            continue;
+                        }
+
+                        //Clients using the key can only run queries that do not change the database
+                        let mut message : Vec<u8> = vec![SET_READ_KEY_FLAG];
+                        message.extend(tokens[1].as_bytes());
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("read only key: {}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "remove_read_key" => {
+
+                        //Valid length for remove_read_key is 2
+                        if tokens.len() != 2 {
+                            println!("wrong usage of remove_read_key. Use it like this: remove_read_key <database name>");
+                            continue;
+                        }
+
+                        //Connections that use the key stay open, only new ones are refused
+                        let mut message : Vec<u8> = vec![REMOVE_READ_KEY_FLAG];
+                        message.extend(tokens[1].as_bytes());
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("removed the read only key of {}", tokens[1]);},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "list" => {
+
+                        //Valid length for list is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of list. Use it like this: list");
+                            continue;
+                        }
+
+                        //The names of all databases are requested with admin privilege
+                        if connection.send(&[LIST_DATABASES_FLAG; 1]).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {
+                                    //Names are separated by newlines and printed as a bubble
+                                    let names = String::from_utf8_lossy(&buffer).to_string();
+                                    let names : Vec<&str> = names.split('\n').filter(|name| !name.is_empty()).collect();
+                                    if names.is_empty() {
+                                        println!("there are no databases");
+                                        continue;
+                                    }
+                                    let width = names.iter().map(|name| name.width()).max().unwrap_or(0).max(10);
+                                    let bubble = Bubble::new(vec![width]);
+                                    println!("{}", bubble.get_divider());
+                                    for name in names {
+                                        println!("{}", bubble.format_line(vec![name.to_string()]));
+                                    }
+                                    println!("{}", bubble.get_divider());
+                                },
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "stats" => {
+
+                        //Valid length for stats is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of stats. Use it like this: stats");
+                            continue;
+                        }
+
+                        //The server renders the statistics as a bubble
+                        if connection.send(&[STATS_FLAG; 1]).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {print!("{}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "status" => {
+
+                        //Valid length for status is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of status. Use it like this: status");
+                            continue;
+                        }
+                        if connection.send(&[STATUS_FLAG; 1]).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {print!("{}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "connections" => {
+
+                        //Valid length for connections is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of connections. Use it like this: connections");
+                            continue;
+                        }
+
+                        //The server renders the connections as a bubble
+                        if connection.send(&[LIST_CONNECTIONS_FLAG; 1]).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {print!("{}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "kill" => {
+
+                        //Valid length for kill is 2
+                        if tokens.len() != 2 {
+                            println!("wrong usage of kill. Use it like this: kill <token>");
+                            continue;
+                        }
+
+                        //The token is the one printed by connections
+                        let mut message : Vec<u8> = vec![KILL_CONNECTION_FLAG];
+                        message.extend(tokens[1].as_bytes());
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("ended connection {}", tokens[1]);},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "rotate" => {
+
+                        //Valid length for rotate is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of rotate. Use it like this: rotate");
+                            continue;
+                        }
+
+                        //The new key is only shown once, the server stores it in the env file
+                        if connection.send(&[ROTATE_ADMIN_KEY_FLAG; 1]).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("new admin key: {}", String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "add_admin" => {
+
+                        //Valid length for add_admin is 2 or 3 if a role is given
+                        if tokens.len() != 2 && tokens.len() != 3 {
+                            println!("wrong usage of add_admin. Use it like this: add_admin <label> [full|read_only]");
+                            continue;
+                        }
+
+                        //The key of the new admin is only shown once
+                        let mut message : Vec<u8> = vec![ADD_ADMIN_FLAG];
+                        message.extend(tokens[1..].join(" ").as_bytes());
+                        if connection.send(&message).is_err() {
                             println!("failed to send request");
                             continue;
                         };
-                        let mut buffer = vec![0; 1024];
-                        if let Ok(len) = connection.read(&mut buffer) {
-                            buffer.truncate(len);
-                            //The key is constructed from the servers response or errors are
-                            //ignored and input is skipped
-                            if len < 1 {
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("key of {}: {}", tokens[1], String::from_utf8_lossy(&buffer));},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "revoke_admin" => {
+
+                        //Valid length for revoke_admin is 2
+                        if tokens.len() != 2 {
+                            println!("wrong usage of revoke_admin. Use it like this: revoke_admin <label>");
+                            continue;
+                        }
+
+                        //Connections of the admin stay open, only new ones are refused
+                        let mut message : Vec<u8> = vec![REVOKE_ADMIN_FLAG];
+                        message.extend(tokens[1].as_bytes());
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
+                                println!("response from server was empty");
+                                continue;
+                            }
+                            match buffer.remove(0) {
+                                0 => {println!("revoked {}", tokens[1]);},
+                                1 => {println!("{}", String::from_utf8_lossy(&buffer));},
+                                _ => {println!("invalid status code returned from server");},
+                            }
+                        }
+                    },
+                    "admins" => {
+
+                        //Valid length for admins is 1
+                        if tokens.len() != 1 {
+                            println!("wrong usage of admins. Use it like this: admins");
+                            continue;
+                        }
+
+                        //The labels and roles of all added admins are requested
+                        let message : Vec<u8> = vec![LIST_ADMINS_FLAG];
+                        if connection.send(&message).is_err() {
+                            println!("failed to send request");
+                            continue;
+                        };
+                        if let Ok(mut buffer) = connection.receive() {
+                            if buffer.is_empty() {
                                 println!("response from server was empty");
                                 continue;
                             }
                             match buffer.remove(0) {
                                 0 => {
-                                    let key = String::from_utf8_lossy(&buffer);
-                                    println!("{}", key);
-                                }
+                                    let admins = String::from_utf8_lossy(&buffer).to_string();
+                                    if admins.is_empty() {
+                                        println!("there are no added admins");
+                                        continue;
+                                    }
+                                    println!("{}", admins);
+                                },
                                 1 => {println!("{}", String::from_utf8_lossy(&buffer));},
                                 _ => {println!("invalid status code returned from server");},
                             }
                         }
                     },
+                    "help" => {
+                        print_help(&ROOT_COMMANDS);
+                    },
                     "exit" => {
 
                         //The server is notified about exit command and handles shutdown gracefully
-                        let _ = connection.write_all(&[TERMINATE_FLAG; 1]);
-                        let _ = connection.flush();
+                        let _ = connection.send(&[TERMINATE_FLAG; 1]);
 
                         //CLI stops printing any newlines
                         return;
@@ -276,7 +700,7 @@ pub fn start_cli() {
                     _ => {
 
                         //Any invalid tokens are handled by notifying the user
-                        println!("Invalid Token");
+                        println!("Invalid Token, type help for commands");
                         continue;
                     },
                 }
@@ -286,7 +710,7 @@ pub fn start_cli() {
             if disconnect {
 
                 //Should always be some
-                if let Some((_, database_connection)) = database {
+                if let Some((_, _, database_connection)) = database {
                     disconnect = false;
 
                     database_connection.close();
@@ -301,6 +725,212 @@ pub fn start_cli() {
 }
 
 
+///Formats in which the CLI prints rows
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Table,
+    Markdown,
+}
+
+
+///Prints the result of a query. Rows are printed in the format with the column names as their
+///header, otherwise success is printed along with the affected rows, the inserted id and the message
+fn print_result(database_connection : &mut Connection, result : io::Result<Option<Cursor>>, format : OutputFormat) {
+    match result {
+
+        //Print result as a bubble if there is one
+        Ok(Some(mut res)) => {
+
+            //All rows are fetched before printing so the columns fit the widest values
+            let mut bubble = Bubble::dynamic();
+
+            //The column names the server sent with the first row form the header
+            if res.column_names.len() == res.row.len() {
+                bubble.add_line(res.column_names.clone());
+                bubble.add_divider();
+            }
+            loop {
+                bubble.add_line(res.row.iter().map(|value| value.to_string()).collect());
+                if !database_connection.next(&mut res).unwrap_or_default() {
+                    break;
+                }
+            }
+            match format {
+                OutputFormat::Table => print!("{}", bubble),
+                OutputFormat::Markdown => print!("{}", bubble.to_markdown()),
+            }
+            for warning in database_connection.warnings() {
+                println!("warning: {}", warning);
+            }
+        },
+
+        //If the result is empty print success so the user is not confused
+        Ok(None) => {
+            print_success(database_connection.warnings());
+            match database_connection.affected() {
+                Some(1) => println!("1 row affected"),
+                Some(n) => println!("{} rows affected", n),
+                None => (),
+            }
+            if let Some(id) = database_connection.inserted_id() {
+                println!("inserted id {}", id);
+            }
+            if let Some(message) = database_connection.message() {
+                println!("{}", message);
+            }
+        },
+        Err(e) => print_error(database_connection, &e),
+    }
+}
+
+
+///Opens a connection to a database of the server at address, encrypted if there is a certificate
+fn connect_database(address : SocketAddr, database_name : &str, key : &str, cert_path : Option<&Path>) -> io::Result<Connection> {
+    match cert_path {
+        Some(cert_path) => Connection::with_tls(address.to_string(), database_name.to_string(), key.to_string(), cert_path),
+        None => Connection::new(address.to_string(), database_name.to_string(), key.to_string()),
+    }
+}
+
+
+///Prints an error of a query and suggests reconnecting if the connection to the server was lost
+fn print_error(database_connection : &Connection, e : &io::Error) {
+    println!("{}", e);
+    if database_connection.is_broken() || is_connection_lost(e) {
+        println!("the connection to the server was lost, use reconnect once the server is back");
+    }
+}
+
+
+///Returns true if the error means the server can not be reached over the connection anymore
+fn is_connection_lost(e : &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected | io::ErrorKind::UnexpectedEof)
+}
+
+
+///Runs the statements of a script file one after another and prints the result of each. Running
+///stops at the first statement that fails unless keep_going is set
+fn run_script(database_connection : &mut Connection, path : &str, keep_going : bool, format : OutputFormat) {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            println!("failed to read {}: {}", path, e);
+            return;
+        },
+    };
+    let statements = split_statements(&script);
+    let mut failed = 0;
+    for (index, statement) in statements.iter().enumerate() {
+        match database_connection.query(statement.clone()) {
+            Err(e) => {
+                println!("statement {} failed: {}", index + 1, e);
+                failed += 1;
+                if !keep_going {
+                    println!("stopped after statement {} of {}", index + 1, statements.len());
+                    return;
+                }
+            },
+            Ok(None) => {
+                print!("statement {}: ", index + 1);
+                print_result(database_connection, Ok(None), format);
+            },
+            result => {
+                println!("statement {}:", index + 1);
+                print_result(database_connection, result, format);
+            },
+        }
+    }
+    println!("ran {} statements, {} failed", statements.len(), failed);
+}
+
+
+///Returns true for the commands the CLI handles itself while connected to a database
+fn is_cli_command(command : &str) -> bool {
+    (matches!(command, "exit" | "disconnect" | "reconnect" | "help" | "timing on" | "timing off" | "format table" | "format markdown") || command.split_whitespace().next() == Some("source") || is_client_export(command))
+}
+
+
+///Returns true if the command is the export of the CLI. Statements end with a semicolon, which
+///tells it apart from the EXPORT statement that writes the file on the server
+fn is_client_export(command : &str) -> bool {
+    command.split_whitespace().next() == Some("export") && !command.ends_with(';')
+}
+
+
+///Writes all rows of a table to a csv file on this machine and returns how many were written. The
+///rows are fetched over the client protocol and the column names sent with them form the header
+fn export_table(database_connection : &mut Connection, table : &str, path : &str) -> io::Result<u64> {
+    let result = database_connection.query(format!("SELECT * FROM {};", table))?;
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+    let mut written = 0;
+    if let Some(mut cursor) = result {
+        let header : Vec<String> = cursor.column_names.iter().map(|name| Executor::csv_field(name)).collect();
+        writeln!(file, "{}", header.join(","))?;
+        loop {
+            let fields : Vec<String> = cursor.row.iter().map(|value| Executor::csv_field(&value.to_string())).collect();
+            writeln!(file, "{}", fields.join(","))?;
+            written += 1;
+            if !database_connection.next(&mut cursor)? {
+                break;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(written)
+}
+
+
+///Prints success along with the warnings of an operation, e.g. success (1 warning: value truncated)
+fn print_success(warnings : &[String]) {
+    match warnings.len() {
+        0 => print_green("success"),
+        1 => print_green(&format!("success (1 warning: {})", warnings[0])),
+        n => print_green(&format!("success ({} warnings: {})", n, warnings.join("; "))),
+    }
+}
+
+
+///Prints commands and their descriptions as a bubble
+fn print_help(commands : &[(&str, &str)]) {
+    let command_width = commands.iter().map(|(command, _)| command.len()).max().unwrap_or(0);
+    let description_width = commands.iter().map(|(_, description)| description.len()).max().unwrap_or(0);
+    let bubble = Bubble::new(vec![command_width, description_width]);
+    println!("{}", bubble.get_divider());
+    for (command, description) in commands {
+        println!("{}", bubble.format_line(vec![command.to_string(), description.to_string()]));
+    }
+    println!("{}", bubble.get_divider());
+}
+
+
 fn print_green(s : &str) {
     println!("\x1B[1;32m{}\x1b[0m", s);
 }
+
+
+
+#[cfg(test)]
+mod test {
+
+
+    use super::*;
+
+
+    #[test]
+    fn client_export_test() {
+        assert!(is_client_export("export t out.csv"));
+        assert!(!is_client_export("export t to 'out.csv';"), "the export statement should be sent to the server");
+        assert!(is_cli_command("export t out.csv"));
+    }
+
+
+    #[test]
+    fn connection_lost_test() {
+        assert!(is_connection_lost(&io::Error::new(io::ErrorKind::ConnectionRefused, "refused")));
+        assert!(is_connection_lost(&io::Error::new(io::ErrorKind::ConnectionAborted, "connection was closed by the server")));
+        assert!(!is_connection_lost(&io::Error::new(io::ErrorKind::InvalidInput, "table does not exist")));
+        assert!(is_cli_command("reconnect") && is_cli_command("disconnect"));
+    }
+
+
+}
@@ -0,0 +1,127 @@
+use std::{fmt::{self, Display, Formatter}, io::{Error, ErrorKind}, sync::LockResult};
+use log::warn;
+
+
+
+///Category of an error. It is sent to clients along with the message, so they can react to errors
+///without matching their text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    Internal = 0,
+    Parse = 1,
+    TableNotFound = 2,
+    TypeMismatch = 3,
+    ConstraintViolation = 4,
+    InvalidInput = 5,
+    Busy = 6,
+    Timeout = 7,
+    PermissionDenied = 8,
+}
+
+
+
+///Message of an io::Error that was created with a code
+#[derive(Debug)]
+struct CodedError {
+    code : ErrorCode,
+    message : String,
+}
+
+
+
+impl Display for CodedError {
+
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+
+
+}
+
+
+
+impl std::error::Error for CodedError {}
+
+
+
+impl ErrorCode {
+
+
+    ///Creates an io::Error of kind that carries this code. Its message is displayed like the one
+    ///of any other io::Error
+    pub fn error(self, kind : ErrorKind, message : impl Into<String>) -> Error {
+        Error::new(kind, CodedError{code: self, message: message.into()})
+    }
+
+
+    ///Returns the code of an error. Errors that were created without a code are categorized by
+    ///their kind, which leaves invalid input, busy resources, timeouts and denied permissions,
+    ///everything else is internal
+    pub fn of(error : &Error) -> ErrorCode {
+        if let Some(coded) = error.get_ref().and_then(|inner| inner.downcast_ref::<CodedError>()) {
+            return coded.code;
+        }
+        match error.kind() {
+            ErrorKind::InvalidInput => ErrorCode::InvalidInput,
+            ErrorKind::WouldBlock => ErrorCode::Busy,
+            ErrorKind::TimedOut => ErrorCode::Timeout,
+            ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            _ => ErrorCode::Internal,
+        }
+    }
+
+
+}
+
+
+
+///Returns the guard of a lock even if a thread panicked while holding it, so one failed operation
+///does not make the lock unusable for good. Only used for data that stays valid when an
+///operation stops midway
+pub fn recover<G>(result : LockResult<G>, name : &str) -> G {
+    result.unwrap_or_else(|poisoned| {
+        warn!("recovered the {} lock after a thread panicked while holding it", name);
+        poisoned.into_inner()
+    })
+}
+
+
+
+#[cfg(test)]
+mod test {
+
+
+    use super::*;
+
+
+    #[test]
+    fn error_code_test() {
+        let error = ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist");
+        assert_eq!(ErrorCode::of(&error), ErrorCode::TableNotFound);
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "table does not exist", "the code should not change the message");
+        assert_eq!(ErrorCode::of(&Error::new(ErrorKind::InvalidInput, "x")), ErrorCode::InvalidInput);
+        assert_eq!(ErrorCode::of(&Error::new(ErrorKind::WouldBlock, "x")), ErrorCode::Busy);
+        assert_eq!(ErrorCode::of(&Error::new(ErrorKind::TimedOut, "x")), ErrorCode::Timeout);
+        assert_eq!(ErrorCode::of(&Error::new(ErrorKind::PermissionDenied, "x")), ErrorCode::PermissionDenied);
+        assert_eq!(ErrorCode::of(&Error::new(ErrorKind::InvalidData, "x")), ErrorCode::Internal);
+    }
+
+
+    #[test]
+    fn recover_test() {
+        let lock = std::sync::Arc::new(std::sync::Mutex::new(vec![1]));
+        let poisoner = lock.clone();
+        let _ = std::thread::spawn(move || {
+            let mut data = poisoner.lock().unwrap();
+            data.push(2);
+            panic!("poison the lock");
+        }).join();
+        assert!(lock.is_poisoned());
+        recover(lock.lock(), "test").push(3);
+        assert_eq!(*recover(lock.lock(), "test"), vec![1, 2, 3]);
+    }
+
+
+}
@@ -1,19 +1,90 @@
 
 
 
-    use crate::{schema::TableSchemaHandler, query::parsing::*, storage::{table_management::{Cursor, Operator, Predicate, Row, Type, TableHandler, simple::SimpleTableHandler}, file_management::delete_file}};
-    use std::{io::{Result, Error, ErrorKind}, path::PathBuf, collections::hash_map::HashMap, sync::{RwLock, Mutex}};
+    use crate::{error::{recover, ErrorCode}, schema::{TableSchemaHandler, StatisticsHandler, AutoIncrementHandler, RowCountHandler, CheckHandler, Reference, ReferenceHandler}, query::parsing::*, storage::{page_management::{DEFAULT_PAGE_SIZE, simple::SimplePageHandler}, table_management::{check_value_count, Cursor, Operator, Predicate, Row, Type, TableHandler, TableStatistics, Value, simple::SimpleTableHandler}, file_management::{delete_file, get_size, rename, Storage}}};
+    use std::{io::{Result, Error, ErrorKind, BufWriter, Write}, fs::File, path::{Path, PathBuf}, collections::{hash_map::HashMap, HashSet, VecDeque}, sync::{RwLock, Mutex}, time::{Duration, Instant}, cmp::Ordering};
     use rand::RngCore;
+    use log::{debug, warn};
+
+
+
+    ///Result of an executed query
+    pub struct QueryResult {
+
+        ///Hash that points to the cursor of a select along with the first row
+        pub cursor : Option<(Vec<u8>, Row)>,
+
+        ///Caveats of an operation that succeeded nevertheless
+        pub warnings : Vec<String>,
+
+        ///Text an operation reports besides rows, e.g. the statistics computed by analyze
+        pub message : Option<String>,
+
+        ///Number of rows an insert or delete changed
+        pub affected : Option<u64>,
+
+        ///Value of the auto increment col of an inserted row
+        pub inserted_id : Option<i64>,
+
+        ///Names of the cols of the rows of cursor in the order clients receive them
+        pub columns : Vec<String>,
+    }
+
+
+
+    ///Result a hash points to. Selects read their rows lazily from the table, other commands like
+    ///describe hold their remaining rows in memory
+    enum OpenCursor {
+        Table(String, Cursor),
+        Rows(VecDeque<Row>),
+
+        //Select distinct, also reads lazily but keeps the bytes of every row it returned to skip
+        //duplicates. The set grows with the number of unique rows, so its memory is bound by the
+        //size of the projected cols of the whole table rather than by a single page
+        Distinct(String, Cursor, HashSet<Vec<u8>>),
+
+        //Cursor that was not used within the ttl. Only its hash is kept for a while, so clients get
+        //a clear error instead of an invalid hash
+        Expired,
+    }
 
 
 
     pub struct Executor {
         db_path : PathBuf,
+
+        //Where the tables are kept, databases in memory only use db_path to name their files
+        storage : Storage,
         schema : TableSchemaHandler,
         tables : RwLock<Vec<(String, Box<dyn TableHandler>)>>,
 
-        //Map that maps a hash to a cursor so requests can access a cursor via the hash
-        cursors : Mutex<HashMap<Vec<u8>, (String, Cursor)>>,
+        //Map that maps a hash to a cursor so requests can access a cursor via the hash. Each
+        //cursor is stored with the time it was last used, so abandoned cursors can expire
+        cursors : Mutex<HashMap<Vec<u8>, (Instant, OpenCursor)>>,
+
+        //Statistics of the tables. Inserts keep row count, min and max up to date, deletes leave
+        //them stale until the table is analyzed again
+        statistics_handler : StatisticsHandler,
+        statistics : Mutex<HashMap<String, TableStatistics>>,
+
+        //Parsed queries with placeholders that can be executed via a hash like cursors
+        statements : Mutex<HashMap<Vec<u8>, Query>>,
+
+        //Counters of the auto increment cols of the tables
+        auto_increment : AutoIncrementHandler,
+
+        //Exact row counts of the tables, so counting all rows does not need a scan
+        row_counts : RowCountHandler,
+
+        //Persisted check constraints, the table handlers evaluate them on insert
+        checks : CheckHandler,
+
+        //Foreign keys of the tables. Deletes of referenced rows are restricted, there is no cascade
+        references : ReferenceHandler,
+
+        //Serializes inserts and deletes that have to look at foreign keys, so a referenced row can
+        //not be deleted while a row that references it is inserted
+        constraints : Mutex<()>,
     }
 
 
@@ -22,32 +93,68 @@
 
 
         pub fn new(db_path: PathBuf) -> Result<Self> {
-            let schema : TableSchemaHandler = TableSchemaHandler::new(&db_path)?;
+            Executor::with_page_size(db_path, DEFAULT_PAGE_SIZE)
+        }
+
+
+        ///Works like new but a new database uses pages of page_size bytes for all of its tables.
+        ///Existing databases keep the page size they were created with.
+        pub fn with_page_size(db_path: PathBuf, page_size : usize) -> Result<Self> {
+            Executor::with_storage(db_path, page_size, Storage::Disk)
+        }
+
+
+        ///Works like with_page_size but keeps all tables where storage decides. A database in
+        ///memory always starts empty and is lost when the executor is dropped
+        pub fn with_storage(db_path: PathBuf, page_size : usize, storage : Storage) -> Result<Self> {
+            let schema : TableSchemaHandler = TableSchemaHandler::with_storage(&db_path, page_size, storage)?;
 
             //Fill tables with Table Handlers constructed with data from the schema
             let mut tables : Vec<(String, Box<dyn TableHandler>)> = vec![];
             let table_data = schema.get_table_data()?;
             for table_id in table_data.keys() {
-                tables.push((table_id.clone(), Box::new(SimpleTableHandler::new(db_path.join(format!("{}.hive", table_id)), table_data.get(table_id).ok_or_else(|| Error::new(ErrorKind::Other, "unexpected error when creating new Executor"))?.clone())?)));
+                tables.push((table_id.clone(), Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(db_path.join(format!("{}.hive", table_id)), schema.page_size(), storage)?), table_data.get(table_id).ok_or_else(|| Error::other("unexpected error when creating new Executor"))?.clone())?)));
+            }
+
+            //Hand the persisted checks to the table handlers
+            let checks = CheckHandler::new(&db_path, schema.page_size(), storage)?;
+            for (table_id, handler) in tables.iter() {
+                for (col_name, operator, value) in checks.get_checks(table_id.clone())? {
+                    let value = handler.create_value(col_name.clone(), value)?;
+                    handler.add_check(Predicate{column: col_name, operator, value})?;
+                }
+                for col_name in schema.get_not_null(table_id.clone())? {
+                    handler.add_not_null(col_name)?;
+                }
+            }
+
+            //Load the statistics of all tables that were analyzed before
+            let statistics_handler = StatisticsHandler::new(&db_path, schema.page_size(), storage)?;
+            let mut statistics : HashMap<String, TableStatistics> = HashMap::new();
+            for (table_id, col_data) in table_data.iter() {
+                if let Some(table_statistics) = statistics_handler.get_statistics(table_id.clone(), col_data)? {
+                    statistics.insert(table_id.clone(), table_statistics);
+                }
             }
+            let auto_increment = AutoIncrementHandler::new(&db_path, schema.page_size(), storage)?;
+            let row_counts = RowCountHandler::new(&db_path, schema.page_size(), storage)?;
+            let references = ReferenceHandler::new(&db_path, schema.page_size(), storage)?;
             let cursors = Mutex::new(HashMap::new());
-            return Ok(Executor{db_path, schema, tables: RwLock::new(tables), cursors});
+            Ok(Executor{db_path, storage, schema, tables: RwLock::new(tables), cursors, statistics_handler, statistics: Mutex::new(statistics), statements: Mutex::new(HashMap::new()), auto_increment, row_counts, checks, references, constraints: Mutex::new(())})
         }
 
 
         ///Used to create a new table in the database
         fn create(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
 
-            //Extract table name from the args map
+            //Extract table name from the args map, a dot would make qualified names ambiguous and
+            //the file of a table called schema would be the schema of the database
             let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||{Error::new(ErrorKind::InvalidInput, "args did not contain a table name")})?.first().ok_or_else(||{Error::new(ErrorKind::InvalidInput, "args did not contain a table name")})?.clone();
-
-            //Check if table does exist
-            if let Ok(tables) = self.tables.write() {
-                if tables.iter().any(|(t, _)| *t == table_name) {
-                    return Err(Error::new(ErrorKind::InvalidInput, "table exists already"));
-                }
-            }else{
-                return Err(Error::new(ErrorKind::Other, "thread poisoned"));
+            if table_name.contains('.') {
+                return Err(Error::new(ErrorKind::InvalidInput, "table names can not contain a dot"));
+            }
+            if table_name == "schema" {
+                return Err(Error::new(ErrorKind::InvalidInput, "schema is reserved and can not be used as a table name"));
             }
 
             //Extract information about the tables columns
@@ -57,78 +164,466 @@
                 return Err(Error::new(ErrorKind::InvalidInput, "args col types and col names had different lengths"));
             }
 
-            //Combine column information
+            //Combine column information, auto increment cols are numbers
             let mut col_data : Vec<(Type, String)> = vec![];
+            let mut auto_increment_col : Option<String> = None;
             for i in 0..col_types.len() {
+                if col_types[i] == AUTO_INCREMENT {
+                    if auto_increment_col.is_some() {
+                        return Err(Error::new(ErrorKind::InvalidInput, "a table can only have one auto increment col"));
+                    }
+                    auto_increment_col = Some(col_names[i].clone());
+                    col_data.push((Type::Number, col_names[i].clone()));
+                    continue;
+                }
                 col_data.push((Type::try_from(col_types[i].clone())?, col_names[i].clone()));
             }
 
-            //Construct new TableHandler
-            let new_table = Box::new(SimpleTableHandler::new(self.db_path.join(format!("{}.hive", table_name)), col_data.clone())?);
+            //The write lock is held from the existence check until the new table is registered, so
+            //two concurrent creates of the same table can not both succeed
+            let mut tables = self.tables.write().map_err(|_| Error::other("thread poisoned"))?;
+            if tables.iter().any(|(t, _)| *t == table_name) {
+                return Err(Error::new(ErrorKind::InvalidInput, "table exists already"));
+            }
+
+            //Every col has a not null flag, which is empty if the col may be null
+            let not_null : Vec<String> = args.get(NOT_NULL).cloned().unwrap_or_default();
+            if not_null.len() != col_names.len() {
+                return Err(Error::new(ErrorKind::InvalidInput, "args contained incomplete not null flags"));
+            }
+
+            //Foreign keys have to point to an existing col of the same type, a table may reference
+            //itself
+            let reference_tables : Vec<String> = args.get(REFERENCE_TABLE).cloned().unwrap_or_default();
+            let mut reference_cols = args.get(REFERENCE_COL).cloned().unwrap_or_default().into_iter();
+            if reference_tables.len() != col_names.len() {
+                return Err(Error::new(ErrorKind::InvalidInput, "args contained incomplete references"));
+            }
+            let mut references : Vec<Reference> = vec![];
+            for (i, ref_table) in reference_tables.into_iter().enumerate() {
+                if ref_table.is_empty() {
+                    continue;
+                }
+                let ref_col = reference_cols.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args contained incomplete references"))?;
+                let ref_col_data = if ref_table == table_name {
+                    col_data.clone()
+                }else if tables.iter().any(|(t, _)| *t == ref_table) {
+                    self.schema.get_col_data(ref_table.clone())?
+                }else{
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("referenced table {} does not exist", ref_table)));
+                };
+                match ref_col_data.iter().find(|(_, n)| *n == ref_col) {
+                    Some((t, _)) if *t == col_data[i].0 => (),
+                    Some(_) => return Err(ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, format!("col {} has a different type than {}({})", col_names[i], ref_table, ref_col))),
+                    None => return Err(Error::new(ErrorKind::InvalidInput, format!("referenced col {}({}) does not exist", ref_table, ref_col))),
+                }
+                references.push(Reference{table: table_name.clone(), col: col_names[i].clone(), ref_table, ref_col});
+            }
+
+            //Construct new TableHandler, checks are validated against its cols before anything is
+            //added to the schema
+            let new_table = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(self.db_path.join(format!("{}.hive", table_name)), self.schema.page_size(), self.storage)?), col_data.clone())?);
+            let check_cols : Vec<String> = args.get(CHECK_COL).cloned().unwrap_or_default();
+            let check_operators : Vec<String> = args.get(OPERATOR_KEY).cloned().unwrap_or_default();
+            let check_values : Vec<String> = args.get(CHECK_VAL).cloned().unwrap_or_default();
+            if check_cols.len() != check_operators.len() || check_cols.len() != check_values.len() {
+                return Err(Error::new(ErrorKind::InvalidInput, "args contained incomplete checks"));
+            }
+            let add_checks = || -> Result<Vec<Predicate>> {
+                let mut checks : Vec<Predicate> = vec![];
+                for ((column, operator), value) in check_cols.into_iter().zip(check_operators).zip(check_values) {
+                    let value = new_table.create_value(column.clone(), value)?;
+                    let check = Predicate{column, operator: Operator::try_from(operator)?, value};
+                    new_table.add_check(check.clone())?;
+                    checks.push(check);
+                }
+                Ok(checks)
+            };
+
+            //The file of the new table is removed again if a check is invalid
+            let checks = match add_checks() {
+                Ok(checks) => checks,
+                Err(e) => {
+                    let _ = delete_file(&self.db_path.join(format!("{}.hive", table_name)));
+                    return Err(e);
+                },
+            };
+            for check in checks.iter() {
+                self.checks.add_check(table_name.clone(), check)?;
+            }
+            for reference in references {
+                self.references.add_reference(reference)?;
+            }
+            for col in col_data.clone() {
+                self.schema.add_col_data(table_name.clone(), col)?;
+            }
+            for (col_name, _) in col_names.into_iter().zip(not_null).filter(|(_, n)| !n.is_empty()) {
+                new_table.add_not_null(col_name.clone())?;
+                self.schema.add_not_null(table_name.clone(), col_name)?;
+            }
+            if let Some(col_name) = auto_increment_col {
+                self.auto_increment.add_column(table_name.clone(), col_name)?;
+            }
+            self.row_counts.set_count(&table_name, 0)?;
+
+            //A new table is empty so its statistics are exact from the start
+            if let Ok(mut statistics) = self.statistics.lock() {
+                statistics.insert(table_name.clone(), TableStatistics::new(col_data.into_iter().map(|(_, n)| n).collect()));
+            }
 
             //Insert new TableHandler into tables vec
-            if let Ok(mut tables) = self.tables.write() {
-                tables.push((table_name.clone(), new_table));
-                for col in col_data {
-                    self.schema.add_col_data(table_name.clone(), col)?;
+            tables.push((table_name, new_table));
+            Ok(())
+        }
+
+
+        ///Creates a table from the result of a select and returns the number of rows it was filled
+        ///with. The new cols are named like the selected ones, qualified names lose their table,
+        ///and take their types from the source table. Constraints, foreign keys and auto increment
+        ///are not copied. If the rows can not be inserted the new table is dropped again
+        fn create_as(&self, args : HashMap<String, Vec<String>>) -> Result<u64> {
+            let new_table_name : String = args.get(NEW_TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a new table name"))?.clone();
+
+            //All rows are read before the table is created, so a failing select leaves nothing
+            //behind. Rows store their cols in reverse order
+            let mut rows : Vec<Vec<String>> = vec![];
+            if let Some((hash, row)) = self.select(args.clone())? {
+                rows.push(row.cols.iter().rev().map(|value| value.to_string()).collect());
+                while let Some(row) = self.next(hash.clone())? {
+                    rows.push(row.cols.iter().rev().map(|value| value.to_string()).collect());
                 }
-                return Ok(());
-            }else {
-                return Err(Error::new(ErrorKind::Other, "thread poisoned"));
+            }
+
+            //The cols are created in the order a select returns them
+            let table_name : String = args.get(TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let source_cols = self.schema.get_col_data(table_name)?;
+            let col_names = self.result_columns(&args)?;
+            let mut col_types : Vec<String> = vec![];
+            for col_name in col_names.iter() {
+                let (col_type, _) = source_cols.iter().find(|(_, n)| n == col_name).ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("col {} does not exist", col_name)))?;
+                col_types.push(col_type.to_string());
+            }
+
+            //Parsed queries list their cols in reverse, create expects them like that
+            let create_args : HashMap<String, Vec<String>> = HashMap::from([
+                (TABLE_NAME_KEY.to_string(), vec![new_table_name.clone()]),
+                (COLUMN_NAME_KEY.to_string(), col_names.iter().rev().cloned().collect()),
+                (COLUMN_TYPE_KEY.to_string(), col_types.into_iter().rev().collect()),
+                (REFERENCE_TABLE.to_string(), vec![String::new(); col_names.len()]),
+                (NOT_NULL.to_string(), vec![String::new(); col_names.len()]),
+            ]);
+            self.create(create_args)?;
+            if rows.is_empty() {
+                return Ok(0);
+            }
+            match self.insert_rows(&new_table_name, Some(col_names), rows) {
+                Ok((count, _)) => Ok(count),
+                Err(e) => {
+                    let _ = self.drop(HashMap::from([(TABLE_NAME_KEY.to_string(), vec![new_table_name])]), &mut vec![]);
+                    Err(e)
+                },
             }
         }
 
 
         ///Used to delete a whole table
-        fn drop(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
+        fn drop(&self, args : HashMap<String, Vec<String>>, warnings : &mut Vec<String>) -> Result<()> {
 
             //Extract table name from args map
             let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||{Error::new(ErrorKind::InvalidInput, "args did not contain a table name")})?.first().ok_or_else(||{Error::new(ErrorKind::InvalidInput, "args did not contain a table name")})?.clone();
 
-            //Check if table exists
-            if let Ok(tables) = self.tables.read() {
-                if !tables.iter().any(|(t, _)|*t == table_name) {
-                    return Err(Error::new(ErrorKind::InvalidInput, "table does not exists"));
-                }
-            }else{
-                return Err(Error::new(ErrorKind::Other, "thread poisoned"));
+            //Check if table exists and remove it while holding the write lock, so the schema can not
+            //change in between
+            let mut tables = self.tables.write().map_err(|_| Error::other("thread poisoned"))?;
+            if !tables.iter().any(|(t, _)|*t == table_name) {
+                return Err(Error::new(ErrorKind::InvalidInput, "table does not exists"));
+            }
+
+            //Tables other tables point to can not be dropped, the rows referencing them would dangle
+            if let Some(reference) = self.references.get_referencing(&table_name)?.into_iter().find(|r| r.table != table_name) {
+                return Err(ErrorCode::ConstraintViolation.error(ErrorKind::InvalidInput, format!("table is referenced by {}({})", reference.table, reference.col)));
             }
 
             //Remove TableHandler from memory
             self.schema.remove_table_data(table_name.clone())?;
-            if let Ok(mut tables) = self.tables.write() {
-                tables.retain(|(n, _)| *n != table_name.clone()); 
+            self.schema.remove_not_null(table_name.clone())?;
+            tables.retain(|(n, _)| *n != table_name.clone()); 
+            if let Ok(mut cursors) = self.cursors.lock() {
+                cursors.retain(|_, (_, c)| !matches!(c, OpenCursor::Table(t, _) | OpenCursor::Distinct(t, _, _) if *t == table_name));
+            }
+            self.statistics_handler.remove_statistics(table_name.clone())?;
+            if let Ok(mut statistics) = self.statistics.lock() {
+                statistics.remove(&table_name);
+            }
+            self.auto_increment.remove_table(table_name.clone())?;
+            self.row_counts.remove_table(table_name.clone())?;
+            self.checks.remove_checks(table_name.clone())?;
+            self.references.remove_table(table_name.clone())?;
+
+            //Clean up used file, the table is gone either way so a failure is only reported
+            if self.storage == Storage::Disk {
+                if let Err(e) = delete_file(&self.db_path.join(format!("{}.hive", table_name))) {
+                    warnings.push(format!("table file could not be removed: {}", e));
+                }
+            }
+            return Ok(());
+        }
+
+
+        ///Gives a table a new name, its rows, checks, foreign keys, statistics and auto increment
+        ///counter are kept. Rejected while cursors of the table are open
+        fn rename(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
+
+            //Extract the old and the new name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let new_table_name : String = args.get(NEW_TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a new table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a new table name"))?.clone();
+            if new_table_name.contains('.') {
+                return Err(Error::new(ErrorKind::InvalidInput, "table names can not contain a dot"));
+            }
+            if new_table_name == "schema" {
+                return Err(Error::new(ErrorKind::InvalidInput, "schema is reserved and can not be used as a table name"));
+            }
+
+            //Both locks are held until the table is registered under its new name, so no request
+            //can open a cursor or reach the table in between
+            let (mut tables, cursors) = match (self.tables.write(), self.cursors.lock()) {
+                (Ok(tables), Ok(cursors)) => (tables, cursors),
+                _ => return Err(Error::other("thread poisoned")),
+            };
+            if !tables.iter().any(|(t, _)| *t == table_name) {
+                return Err(ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"));
+            }
+            if tables.iter().any(|(t, _)| *t == new_table_name) {
+                return Err(Error::new(ErrorKind::InvalidInput, "table exists already"));
+            }
+            if cursors.values().any(|(_, c)| matches!(c, OpenCursor::Table(t, _) | OpenCursor::Distinct(t, _, _) if *t == table_name)) {
+                return Err(Error::new(ErrorKind::WouldBlock, "table has open cursors"));
+            }
+
+            //The old handler of a file on disk is dropped before the file is moved and opened again
+            //under the new name, tables in memory keep their handler
+            let col_data = self.schema.get_col_data(table_name.clone())?;
+            let index = tables.iter().position(|(t, _)| *t == table_name).ok_or_else(|| ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?;
+            let (_, mut handler) = tables.remove(index);
+            let reopened = self.storage == Storage::Disk;
+            if reopened {
+                drop(handler);
+                rename(&self.db_path.join(format!("{}.hive", table_name)), &self.db_path.join(format!("{}.hive", new_table_name)))?;
+                handler = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(self.db_path.join(format!("{}.hive", new_table_name)), self.schema.page_size(), self.storage)?), col_data.clone())?);
+            }
+
+            //Rewrite every entry that is keyed by the table name
+            for (col_name, operator, value) in self.checks.get_checks(table_name.clone())? {
+                let value = handler.create_value(col_name.clone(), value)?;
+                let check = Predicate{column: col_name, operator, value};
+                if reopened {
+                    handler.add_check(check.clone())?;
+                }
+                self.checks.add_check(new_table_name.clone(), &check)?;
+            }
+            self.checks.remove_checks(table_name.clone())?;
+            for col_name in self.schema.get_not_null(table_name.clone())? {
+                if reopened {
+                    handler.add_not_null(col_name.clone())?;
+                }
+                self.schema.add_not_null(new_table_name.clone(), col_name)?;
+            }
+            self.schema.remove_not_null(table_name.clone())?;
+            self.schema.remove_table_data(table_name.clone())?;
+            for col in col_data.clone() {
+                self.schema.add_col_data(new_table_name.clone(), col)?;
+            }
+            if let Some(table_statistics) = self.statistics_handler.get_statistics(table_name.clone(), &col_data)? {
+                self.statistics_handler.set_statistics(new_table_name.clone(), &table_statistics)?;
+                self.statistics_handler.remove_statistics(table_name.clone())?;
+            }
+            if let Ok(mut statistics) = self.statistics.lock() {
+                if let Some(table_statistics) = statistics.remove(&table_name) {
+                    statistics.insert(new_table_name.clone(), table_statistics);
+                }
+            }
+            self.auto_increment.rename_table(&table_name, &new_table_name)?;
+            self.row_counts.rename_table(&table_name, &new_table_name)?;
+            self.references.rename_table(&table_name, &new_table_name)?;
+            tables.push((new_table_name, handler));
+            Ok(())
+        }
+
+
+        ///Removes a col from a table. Every row is rewritten without the col into a new file which
+        ///then replaces the table file. Rejected while cursors of the table are open
+        fn drop_column(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
+
+            //Extract table and col name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let col_name : String = args.get(COLUMN_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a col name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a col name"))?.clone();
+
+            //Both locks are held until the rewritten table is registered, like for vacuum
+            let (mut tables, cursors) = match (self.tables.write(), self.cursors.lock()) {
+                (Ok(tables), Ok(cursors)) => (tables, cursors),
+                _ => return Err(Error::other("thread poisoned")),
+            };
+            let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+            if cursors.values().any(|(_, c)| matches!(c, OpenCursor::Table(t, _) | OpenCursor::Distinct(t, _, _) if *t == table_name)) {
+                return Err(Error::new(ErrorKind::WouldBlock, "table has open cursors"));
+            }
+
+            //The col has to exist, may not be the last one and may not be part of a constraint
+            let col_data = self.schema.get_col_data(table_name.clone())?;
+            let index = col_data.iter().position(|(_, n)| *n == col_name).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "col does not exist in table"))?;
+            if col_data.len() == 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, "the last col of a table can not be dropped"));
+            }
+            let in_check = self.checks.get_checks(table_name.clone())?.iter().any(|(c, _, _)| *c == col_name);
+            let in_reference = self.references.get_references(&table_name)?.iter().any(|r| r.col == col_name) || self.references.get_referencing(&table_name)?.iter().any(|r| r.ref_col == col_name);
+            let is_auto_increment = self.auto_increment.get_column(&table_name)? == Some(col_name.clone());
+            if in_check || in_reference || is_auto_increment {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("col {} is used in a constraint", col_name)));
+            }
+
+            //Copy the rows without the col into a new file
+            let mut new_col_data = col_data.clone();
+            new_col_data.remove(index);
+            let table_path = self.db_path.join(format!("{}.hive", table_name));
+            let new_path = self.db_path.join(format!("{}.hive.tmp", table_name));
+            let _ = delete_file(&new_path);
+            let new_table = SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(new_path.clone(), handler.page_size(), self.storage)?), new_col_data.clone())?;
+            let mut next = handler.select_row(None, None)?;
+            while let Some((mut row, mut cursor)) = next {
+                row.cols.remove(index);
+                new_table.insert_row(row)?;
+                next = handler.next(&mut cursor)?.map(|r| (r, cursor));
+            }
+
+            //Replace the table file and open it with the reduced cols, tables in memory keep the
+            //handler of the copy
+            tables.retain(|(t, _)| *t != table_name);
+            let handler : Box<dyn TableHandler> = if self.storage == Storage::Disk {
+                drop(new_table);
+                rename(&new_path, &table_path)?;
+                Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(table_path, self.schema.page_size(), self.storage)?), new_col_data)?)
             }else{
-                return Err(Error::new(ErrorKind::Other, "thread poisoned"));
+                Box::new(new_table)
+            };
+            for (col_name, operator, value) in self.checks.get_checks(table_name.clone())? {
+                let value = handler.create_value(col_name.clone(), value)?;
+                handler.add_check(Predicate{column: col_name, operator, value})?;
+            }
+            self.schema.remove_col_data(table_name.clone(), &col_name)?;
+            for col_name in self.schema.get_not_null(table_name.clone())? {
+                handler.add_not_null(col_name)?;
             }
 
-            //Clean up used file
-            let _ = delete_file(&self.db_path.join(format!("{}.hive", table_name)));             
-            return Ok(());
+            //Statistics of the remaining cols stay valid
+            if let Some(mut table_statistics) = self.statistics_handler.get_statistics(table_name.clone(), &col_data)? {
+                table_statistics.columns.retain(|c| c.name != col_name);
+                self.statistics_handler.set_statistics(table_name.clone(), &table_statistics)?;
+            }
+            if let Ok(mut statistics) = self.statistics.lock() {
+                if let Some(table_statistics) = statistics.get_mut(&table_name) {
+                    table_statistics.columns.retain(|c| c.name != col_name);
+                }
+            }
+            tables.push((table_name, handler));
+            Ok(())
         }
 
 
-        ///Inserts a row into a table
-        fn insert(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
+        ///Inserts a row into a table and returns the number of inserted rows along with the value
+        ///of the auto increment col if the table has one
+        fn insert(&self, args : HashMap<String, Vec<String>>) -> Result<(u64, Option<i64>)> {
 
             //Extract table name from args map
             let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
 
             //Extract row data from args map
             let col_names_option : Option<Vec<String>> = args.get(COLUMN_NAME_KEY).cloned();
-            let col_values : Vec<String> = args.get(COLUMN_VALUE_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain col values"))?.clone();
-            if let Some(ref col_names) = col_names_option {
-                if col_names.len() != col_values.len() {
-                    return Err(Error::new(ErrorKind::InvalidInput, "amount of values and columns did not match"));
-                }
-            }
+            //An empty value list has no values in args, it fails the check of the value count
+            let col_values : Vec<String> = args.get(COLUMN_VALUE_KEY).cloned().unwrap_or_default();
+            self.insert_rows(&table_name, col_names_option, vec![col_values])
+        }
 
-            //Choose the table handler and use it to insert the row into the table
+
+        ///Inserts rows that all name the same cols into a table. The rows are written in one batch,
+        ///so either all of them pass the constraints or none is inserted. Returns the number of
+        ///inserted rows along with the value of the auto increment col of the last one
+        fn insert_rows(&self, table_name : &str, col_names_option : Option<Vec<String>>, rows_values : Vec<Vec<String>>) -> Result<(u64, Option<i64>)> {
+
+            //Choose the table handler and use it to insert the rows into the table
             if let Ok(tables) = self.tables.read() {
-                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||Error::new(ErrorKind::InvalidInput, "table does not exist"))?.1;
-                let row = handler.cols_to_row(col_names_option, col_values)?;
-                let _ = handler.insert_row(row);
-                return Ok(());
+                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+
+                //The value count of every row is checked before auto increment values are taken,
+                //so a failed insert does not write anything
+                let expected = col_names_option.as_ref().map_or(handler.col_count(), |col_names| col_names.len());
+                for col_values in &rows_values {
+                    check_value_count(expected, col_values.len())?;
+                }
+                let auto_increment_col = self.auto_increment.get_column(table_name)?;
+                let references = self.references.get_references(table_name)?;
+                let _constraints = if references.is_empty() {
+                    None
+                }else{
+                    Some(self.constraints.lock().map_err(|_| Error::other("thread poisoned"))?)
+                };
+                let mut rows : Vec<(Row, Option<i64>)> = vec![];
+                for mut col_values in rows_values {
+
+                    //The auto increment col gets the next value of its counter if the insert names
+                    //its cols and leaves it out
+                    let mut col_names_option = col_names_option.clone();
+                    let mut inserted_id : Option<i64> = None;
+                    if let (Some(col_name), Some(col_names)) = (&auto_increment_col, col_names_option.as_mut()) {
+                        if !col_names.contains(col_name) {
+                            let value = self.auto_increment.take_value(table_name)?;
+                            col_names.push(col_name.clone());
+                            col_values.push(value.to_string());
+                            inserted_id = Some(value);
+                        }
+                    }
+                    let row = handler.cols_to_row(col_names_option, col_values)?;
+
+                    //Every foreign key value has to exist in the referenced table. A row of a table
+                    //that references itself may also point to its own value or to a row inserted
+                    //before it in the same batch
+                    for reference in &references {
+                        let value = handler.get_col_from_row(row.clone(), &reference.col)?;
+                        if reference.ref_table == table_name && handler.get_col_from_row(row.clone(), &reference.ref_col)? == value {
+                            continue;
+                        }
+                        if reference.ref_table == table_name && rows.iter().any(|(r, _)| handler.get_col_from_row(r.clone(), &reference.ref_col).is_ok_and(|v| v == value)) {
+                            continue;
+                        }
+                        let ref_handler = &tables.iter().find(|(t, _)| *t == reference.ref_table).ok_or_else(|| Error::other("referenced table does not exist"))?.1;
+                        let predicate = Predicate{column: reference.ref_col.clone(), operator: Operator::Equal, value: value.clone()};
+                        if ref_handler.select_row(Some(predicate), Some(vec![reference.ref_col.clone()]))?.is_none() {
+                            return Err(ErrorCode::ConstraintViolation.error(ErrorKind::InvalidInput, format!("value {} of col {} does not exist in {}({})", value, reference.col, reference.ref_table, reference.ref_col)));
+                        }
+                    }
+                    rows.push((row, inserted_id));
+                }
+                handler.insert_rows(rows.iter().map(|(row, _)| row.clone()).collect())?;
+                self.row_counts.add_rows(table_name, rows.len() as i64)?;
+
+                //Explicit values move the counter past them, so they are not handed out again
+                let mut last_id : Option<i64> = None;
+                for (row, inserted_id) in &rows {
+                    last_id = *inserted_id;
+                    if let (Some(col_name), None) = (&auto_increment_col, inserted_id) {
+                        if let Value::Number(value) = handler.get_col_from_row(row.clone(), col_name)? {
+                            self.auto_increment.observe_value(table_name, value)?;
+                            last_id = Some(value);
+                        }
+                    }
+                }
+                if let Ok(mut statistics) = self.statistics.lock() {
+                    if let Some(table_statistics) = statistics.get_mut(table_name) {
+                        for (row, _) in &rows {
+                            table_statistics.add_row(row);
+                        }
+                    }
+                }
+                Ok((rows.len() as u64, last_id))
             }else{
                 return Err(Error::new(ErrorKind::Other, "thread poisoned"));
             }
@@ -141,59 +636,74 @@
             //Extract table name
             let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
 
-            //Extract the columns that should be returned
-            let col_names : Option<Vec<String>> = args.get(COLUMN_NAME_KEY).cloned();
+            //Extract the columns that should be returned. Functions are computed from whole rows
+            let functions = self.projection_functions(&args)?;
+            let col_names : Option<Vec<String>> = match functions {
+                Some(_) => None,
+                None => args.get(COLUMN_NAME_KEY).cloned(),
+            };
+
+            //The order cols are checked before anything is read
+            let order = self.order_keys(&args)?;
+
+            //A subquery runs before the lock is taken, it takes the lock itself
+            let subquery = self.run_subquery(&args)?;
             if let Ok(tables) = self.tables.read() {
 
                 //Check if table exists and get it if possible
-                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||Error::new(ErrorKind::InvalidInput, "table does not exist"))?.1;
+                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
 
                 //Construct predicate from args
-                let predicate : Option<Predicate> = match (
-                    args.get(PREDICATE_COL),
-                    args.get(OPERATOR_KEY),
-                    args.get(PREDICATE_VAL),
-                ) {
-                    (Some(column), Some(operator), Some(value)) => {
-                        match (
-                            column.first(),
-                            operator.first(),
-                            value.first(),
-                        ){
-                            (Some(column), Some(operator), Some(value)) => {
-                                let operator = Operator::try_from(operator.clone())?;
-                                let value = handler.create_value(column.clone(), value.clone())?;
-                                Some(Predicate{column : column.clone(), operator, value})
-                            },
-
-                            //If there is no predicate in args the query is executed without one
-                            _ => None,
+                let predicate = Executor::create_predicate(handler.as_ref(), &args, subquery)?;
+
+                //Sorting needs all rows, so they are read while the lock is held and returned from
+                //memory. The sort is stable, rows that are equal in every key keep the order of the
+                //table. Functions are computed the same way, so distinct and order by see their
+                //results
+                if !order.is_empty() || functions.is_some() {
+                    let mut rows : Vec<Row> = vec![];
+                    let mut seen : HashSet<Vec<u8>> = HashSet::new();
+                    if let Some((first, mut cursor)) = handler.select_row(predicate, col_names)? {
+                        let mut row = Some(first);
+                        while let Some(mut r) = row {
+                            if let Some(functions) = &functions {
+
+                                //Rows are built reversed like all rows
+                                r = Row{cols: functions.iter().rev().map(|(index, function)| Executor::apply_function(function, r.cols[*index].clone())).collect::<Result<Vec<Value>>>()?};
+                            }
+                            if !args.contains_key(DISTINCT) || seen.insert(r.clone().into()) {
+                                rows.push(r);
+                            }
+                            row = handler.next(&mut cursor)?;
                         }
-                    },
-                    _ => None,
-                };
+                    }
+                    rows.sort_by(|a, b| {
+                        for (index, descending) in &order {
+                            let ordering = a.cols[*index].partial_cmp(&b.cols[*index]).unwrap_or(Ordering::Equal);
+                            if ordering != Ordering::Equal {
+                                return if *descending { ordering.reverse() } else { ordering };
+                            }
+                        }
+                        Ordering::Equal
+                    });
+                    let mut rows : VecDeque<Row> = rows.into();
+                    return Ok(match rows.pop_front() {
+                        Some(row) => Some((self.open_cursor(OpenCursor::Rows(rows))?, row)),
+                        None => None,
+                    });
+                }
 
-                //Execute the query
+                //Execute the query, distinct compares the projected rows so it applies to the
+                //selected cols only
                 Ok(match handler.select_row(predicate, col_names)? {
+                    Some((r, c)) if args.contains_key(DISTINCT) => {
+                        let seen : HashSet<Vec<u8>> = HashSet::from([r.clone().into()]);
+                        Some((self.open_cursor(OpenCursor::Distinct(table_name, c, seen))?, r))
+                    },
                     Some((r, c)) => {
 
-                        //Store the cursor in the cursors map along with a randomly generated hash
-                        let mut hash = [0u8; 16];  
-                        loop {
-                            rand::thread_rng().fill_bytes(&mut hash);
-                            if let Ok(mut cursors) = self.cursors.lock() {
-                                if cursors.contains_key(&hash.to_vec()) {
-                                    continue;
-                                }
-                                cursors.insert(hash.to_vec(), (table_name, c));
-                                break;
-                            }else{
-                                return Err(Error::new(ErrorKind::Other, "thread poisoned"));
-                            }
-                        }
-
                         //Return the hash as a pointer to the cursor and the row
-                        Some((hash.to_vec(), r))
+                        Some((self.open_cursor(OpenCursor::Table(table_name, c))?, r))
                     },
                     None => None,
                 })
@@ -203,99 +713,1842 @@
         }
 
 
-        ///Used to delete rows from a table that match a certain predicate
-        fn delete(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
+        ///Constructs the predicate of a select or delete from args. If the predicate compares with a
+        ///subquery, subquery holds the values it returned. In matches any of them, the other
+        ///operators need exactly one
+        fn create_predicate(handler : &dyn TableHandler, args : &HashMap<String, Vec<String>>, subquery : Option<Vec<Value>>) -> Result<Option<Predicate>> {
+            let (column, operator) = match (args.get(PREDICATE_COL).and_then(|c| c.first()), args.get(OPERATOR_KEY).and_then(|o| o.first())) {
+                (Some(column), Some(operator)) => (column.clone(), operator.clone()),
+
+                //If there is no predicate in args the query is executed without one
+                _ => return Ok(None),
+            };
+            let value = match (args.get(PREDICATE_VAL).and_then(|v| v.first()), subquery) {
+                (Some(value), _) => handler.create_value(column.clone(), value.clone())?,
+                (None, Some(values)) if operator == IN => {
+
+                    //The values are converted to the type of the col, so a mismatch is an error
+                    //instead of a predicate that never matches
+                    let values = values.into_iter().map(|value| handler.create_value(column.clone(), value.to_string())).collect::<Result<Vec<Value>>>()?;
+                    return Ok(Some(Predicate{column, operator: Operator::In(values), value: Value::new_text(String::new())}));
+                },
+                (None, Some(values)) if values.len() == 1 => handler.create_value(column.clone(), values[0].to_string())?,
+                (None, Some(values)) => return Err(Error::new(ErrorKind::InvalidInput, format!("subquery returned {} rows but a comparison needs exactly one", values.len()))),
+                (None, None) => return Ok(None),
+            };
+            Ok(Some(Predicate{column, operator: Operator::try_from(operator)?, value}))
+        }
+
+
+        ///Runs the subquery of a predicate and returns the values of its col, or None if the query
+        ///has no subquery. It goes through select, so it sees the same rows a query of its table
+        ///would
+        fn run_subquery(&self, args : &HashMap<String, Vec<String>>) -> Result<Option<Vec<Value>>> {
+            let table_name = match args.get(SUBQUERY_TABLE) {
+                Some(table_name) => table_name.clone(),
+                None => return Ok(None),
+            };
+            let mut plan : HashMap<String, Vec<String>> = HashMap::from([(TABLE_NAME_KEY.to_string(), table_name)]);
+            for (key, select_key) in [(SUBQUERY_COL, COLUMN_NAME_KEY), (SUBQUERY_PREDICATE_COL, PREDICATE_COL), (SUBQUERY_OPERATOR, OPERATOR_KEY), (SUBQUERY_PREDICATE_VAL, PREDICATE_VAL)] {
+                if let Some(values) = args.get(key) {
+                    plan.insert(select_key.to_string(), values.clone());
+                }
+            }
+            let mut values : Vec<Value> = vec![];
+            let mut next = self.select(plan)?;
+            while let Some((hash, row)) = next {
+                values.push(row.cols.into_iter().next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "subquery did not return a col"))?);
+                next = self.next(hash.clone())?.map(|row| (hash, row));
+            }
+            Ok(Some(values))
+        }
+
+
+        ///Used to delete rows from a table that match a certain predicate. Returns the number of
+        ///deleted rows
+        fn delete(&self, args : HashMap<String, Vec<String>>) -> Result<u64> {
 
             //Extract table name from args
             let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
 
-            //Create predicate from args
+            //Create predicate from args, a subquery runs before the lock is taken
+            let subquery = self.run_subquery(&args)?;
             if let Ok(tables) = self.tables.read() {
-                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||Error::new(ErrorKind::InvalidInput, "table does not exist"))?.1;
-                let predicate : Option<Predicate> = match (
-                    args.get(PREDICATE_COL),
-                    args.get(OPERATOR_KEY),
-                    args.get(PREDICATE_VAL),
-                ) {
-                    (Some(column), Some(operator), Some(value)) => {
-                        match (
-                            column.first(),
-                            operator.first(),
-                            value.first(),
-                        ){
-                            (Some(column), Some(operator), Some(value)) => {
-                                let operator = Operator::try_from(operator.clone())?;
-                                let value = handler.create_value(column.clone(), value.clone())?;
-                                Some(Predicate{column : column.clone(), operator, value})
-                            },
-                            _ => None,
-                        }
-                    },
-                    _ => None,
+                let handler = &tables.iter().find(|(t, _)| *t== table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+                let predicate = Executor::create_predicate(handler.as_ref(), &args, subquery)?;
+
+                //Deletes are restricted, none of the rows may be referenced by a foreign key
+                let referencing = self.references.get_referencing(&table_name)?;
+                let _constraints = if referencing.is_empty() {
+                    None
+                }else{
+                    Some(self.constraints.lock().map_err(|_| Error::other("thread poisoned"))?)
                 };
+                for reference in referencing {
+                    let referencing_handler = &tables.iter().find(|(t, _)| *t == reference.table).ok_or_else(|| Error::other("referencing table does not exist"))?.1;
+                    let mut next = handler.select_row(predicate.clone(), None)?;
+                    while let Some((row, mut cursor)) = next {
+                        let value = handler.get_col_from_row(row, &reference.ref_col)?;
+                        let referencing_predicate = Predicate{column: reference.col.clone(), operator: Operator::Equal, value: value.clone()};
+                        if referencing_handler.select_row(Some(referencing_predicate), Some(vec![reference.col.clone()]))?.is_some() {
+                            return Err(ErrorCode::ConstraintViolation.error(ErrorKind::InvalidInput, format!("value {} of col {} is referenced by {}({})", value, reference.ref_col, reference.table, reference.col)));
+                        }
+                        next = handler.next(&mut cursor)?.map(|r| (r, cursor));
+                    }
+                }
 
                 //Delete rows
-                Ok(handler.delete_row(predicate)?)
+                let deleted = handler.delete_row(predicate)? as u64;
+                self.row_counts.add_rows(&table_name, -(deleted as i64))?;
+                Ok(deleted)
             }else{
                 return Err(Error::new(ErrorKind::Other, "thread poisoned"));
             }
         }
 
 
-        ///Like select but with a starting point
-        pub fn next(&self, hash : Vec<u8>) -> Result<Option<Row>> {
-            match (self.tables.read(), self.cursors.lock()) {
-                (Ok(tables), Ok(mut cursors)) => {
-
-                    //Get the cursor corresponding to the hash
-                    let (table_name, cursor) = cursors.get_mut(&hash).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "hash is invalid"))?;
+        ///Frees unused pages of a table and shrinks its file. Rejected while cursors of the table
+        ///are open since compaction moves the pages they point to. The rows are counted again
+        ///afterwards, which fixes a count that went wrong or was never kept
+        fn vacuum(&self, args : HashMap<String, Vec<String>>) -> Result<()> {
 
-                    //Try to access the table stored with the cursor
-                    let handler = &tables.iter().find(|(t, _)| *t==*table_name).ok_or_else(||Error::new(ErrorKind::InvalidInput, "table does not exist"))?.1;
+            //Extract table name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
 
-                    //Get next
-                    handler.next(cursor)},
-                _ => Err(Error::new(ErrorKind::Other, "thread poisoned")),
+            //The write lock keeps other requests from reading pages or opening cursors while the
+            //pages are moved
+            match (self.tables.write(), self.cursors.lock()) {
+                (Ok(tables), Ok(cursors)) => {
+                    let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+                    if cursors.values().any(|(_, c)| matches!(c, OpenCursor::Table(t, _) | OpenCursor::Distinct(t, _, _) if *t == table_name)) {
+                        return Err(Error::new(ErrorKind::WouldBlock, "table has open cursors"));
+                    }
+                    handler.compact()?;
+                    let count = handler.count_rows(None)?;
+                    match self.row_counts.get_count(&table_name)? {
+                        Some(kept) if kept != count => warn!("row count of {} was {} but the table has {} rows", table_name, kept, count),
+                        _ => (),
+                    }
+                    self.row_counts.set_count(&table_name, count)
+                },
+                _ => Err(Error::other("thread poisoned")),
             }
         }
 
 
-        pub fn execute(&self, query: Query) -> Result<Option<(Vec<u8>, Row)>>{
+        ///Counts the rows of a table that fulfill the predicate and returns them as a single row.
+        ///Without a predicate the kept row count is used, tables without one are scanned
+        fn count(&self, args : HashMap<String, Vec<String>>) -> Result<Option<(Vec<u8>, Row)>> {
 
-            //Extract the command token from the input
-            let command = query.plan.get(COMMAND_KEY).ok_or_else(||{Error::new(ErrorKind::InvalidInput, "query was not valid")})?.first().ok_or_else(||{Error::new(ErrorKind::InvalidInput, "command was empty")})?;
+            //Extract table name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
 
-            //Execute an action according to that token
-            Ok(match command.as_str() {
-                CREATE => {
-                    self.create(query.plan.clone())?;
-                    None
-                },
-                DROP => {
-                    self.drop(query.plan.clone())?;
-                    None
-                },
-                INSERT => {
-                    self.insert(query.plan.clone())?;
-                    None
-                },
-                SELECT => {
-                    self.select(query.plan.clone())?
-                },
-                DELETE => {
-                    self.delete(query.plan.clone())?;
-                    None
-                },
-                _ => return Err(Error::new(ErrorKind::InvalidInput, ""))
+            //A subquery runs before the lock is taken, it takes the lock itself
+            let subquery = self.run_subquery(&args)?;
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+            let predicate = Executor::create_predicate(handler.as_ref(), &args, subquery)?;
+            let count = match (&predicate, self.row_counts.get_count(&table_name)?) {
+                (None, Some(count)) => count,
+                _ => handler.count_rows(predicate)?,
+            };
+            let row = Row{cols: vec![Value::new_number(count as i64)]};
+            Ok(Some((self.open_cursor(OpenCursor::Rows(VecDeque::new()))?, row)))
+        }
+
+
+        ///Returns a single row that holds 1 if a row of the table fulfills the predicate and 0
+        ///otherwise. The scan stops at the first matching row
+        fn exists(&self, args : HashMap<String, Vec<String>>) -> Result<Option<(Vec<u8>, Row)>> {
 
+            //Extract table name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+
+            //A subquery runs before the lock is taken, it takes the lock itself
+            let subquery = self.run_subquery(&args)?;
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+            let predicate = Executor::create_predicate(handler.as_ref(), &args, subquery)?;
+            let exists = handler.select_row(predicate, None)?.is_some();
+            let row = Row{cols: vec![Value::new_number(exists as i64)]};
+            Ok(Some((self.open_cursor(OpenCursor::Rows(VecDeque::new()))?, row)))
+        }
+
+
+        ///Recomputes the exact statistics of a table with a full scan, persists them and returns
+        ///them
+        fn analyze(&self, args : HashMap<String, Vec<String>>) -> Result<TableStatistics> {
+
+            //Extract table name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+
+            //The write lock keeps inserts from changing the table between the scan and the update
+            //of the statistics
+            let tables = self.tables.write().map_err(|_| Error::other("thread poisoned"))?;
+            let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+            let table_statistics = handler.analyze()?;
+            self.statistics_handler.set_statistics(table_name.clone(), &table_statistics)?;
+            let mut statistics = self.statistics.lock().map_err(|_| Error::other("thread poisoned"))?;
+            statistics.insert(table_name, table_statistics.clone());
+            Ok(table_statistics)
+        }
+
+
+        ///Returns the name and type of every column of a table as rows, like a select. The type of
+        ///a not null column is followed by not null
+        fn describe(&self, args : HashMap<String, Vec<String>>) -> Result<Option<(Vec<u8>, Row)>> {
+
+            //Extract table name from args
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+
+            //The read lock keeps the table from being dropped while its columns are read
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            if !tables.iter().any(|(t, _)| *t == table_name) {
+                return Err(ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"));
+            }
+
+            //Tables store their columns in reverse order of the query and clients reverse the rows
+            //they receive, so the rows are built reversed as well
+            let not_null = self.schema.get_not_null(table_name.clone())?;
+            let mut rows : VecDeque<Row> = self.schema.get_col_data(table_name)?.into_iter().rev().map(|(col_type, col_name)| {
+                let col_type = if not_null.contains(&col_name) { format!("{} not null", col_type) } else { col_type.to_string() };
+                Row{cols: vec![Value::new_text(col_type), Value::new_text(col_name)]}
+            }).collect();
+            Ok(match rows.pop_front() {
+                Some(row) => Some((self.open_cursor(OpenCursor::Rows(rows))?, row)),
+                None => None,
             })
         }
 
-    }
+
+        ///Describes how the statement an explain wraps would be executed instead of running it and
+        ///returns the description as a single row. There are no indexes, so every statement that
+        ///reads rows scans its whole table. The estimated row count comes from the statistics of
+        ///the last analyze, or from the kept row count if the table was never analyzed
+        fn explain(&self, args : HashMap<String, Vec<String>>) -> Result<Option<(Vec<u8>, Row)>> {
+            let command = CommandKind::try_from(args.get(COMMAND_KEY).and_then(|c| c.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a command"))?.as_str())?;
+            let table_name : String = args.get(TABLE_NAME_KEY).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.first().ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let mut steps : Vec<String> = vec![];
+            if matches!(command, CommandKind::Select | CommandKind::Count | CommandKind::Exists | CommandKind::Delete | CommandKind::CreateAs | CommandKind::Export) {
+                let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+                if !tables.iter().any(|(t, _)| *t == table_name) {
+                    return Err(ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"));
+                }
+                let kept_count = self.row_counts.get_count(&table_name)?;
+
+                //Counting all rows uses the kept row count the same way count does
+                if command == CommandKind::Count && !args.contains_key(PREDICATE_COL) && kept_count.is_some() {
+                    steps.push(format!("row count of {}", table_name));
+                }else{
+                    steps.push(format!("full scan of {}", table_name));
+                }
+                if let (Some(col), Some(operator)) = (args.get(PREDICATE_COL).and_then(|c| c.first()), args.get(OPERATOR_KEY).and_then(|o| o.first())) {
+                    let value = match (args.get(PREDICATE_VAL).and_then(|v| v.first()), args.get(SUBQUERY_TABLE).and_then(|t| t.first())) {
+                        (Some(value), _) => value.clone(),
+                        (None, Some(subquery_table)) => {
+                            let subquery_col = args.get(SUBQUERY_COL).and_then(|c| c.first()).cloned().unwrap_or_default();
+                            let mut subquery = format!("(select {} from {}", subquery_col, subquery_table);
+                            if let (Some(col), Some(operator), Some(value)) = (args.get(SUBQUERY_PREDICATE_COL).and_then(|c| c.first()), args.get(SUBQUERY_OPERATOR).and_then(|o| o.first()), args.get(SUBQUERY_PREDICATE_VAL).and_then(|v| v.first())) {
+                                subquery.push_str(&format!(" where {} {} {}", col, Executor::operator_symbol(operator), value));
+                            }
+                            subquery.push(')');
+                            steps.push(format!("full scan of {} for the subquery", subquery_table));
+                            subquery
+                        },
+                        (None, None) => String::new(),
+                    };
+                    steps.push(format!("where {} {} {}", col, Executor::operator_symbol(operator), value));
+                }
+                if command == CommandKind::Exists {
+                    steps.push("stops at the first match".to_string());
+                }
+                if args.contains_key(DISTINCT) {
+                    steps.push("distinct".to_string());
+                }
+
+                //Like all lists the order keys are stored in reverse order of the query
+                if let (Some(cols), Some(directions)) = (args.get(ORDER_COL), args.get(ORDER_DIRECTION)) {
+                    let keys : Vec<String> = cols.iter().zip(directions.iter()).rev().map(|(col, direction)| format!("{} {}", col, direction)).collect();
+                    steps.push(format!("sort by {} in memory", keys.join(", ")));
+                }
+                let statistics = self.statistics.lock().map_err(|_| Error::other("thread poisoned"))?;
+                let estimate = match (statistics.get(&table_name), kept_count) {
+                    (Some(table_statistics), _) => table_statistics.row_count.to_string(),
+                    (None, Some(count)) => count.to_string(),
+                    (None, None) => "unknown".to_string(),
+                };
+                steps.push(format!("estimated rows {}", estimate));
+            }else{
+                steps.push(format!("{} of {} without a scan", args.get(COMMAND_KEY).and_then(|c| c.first()).cloned().unwrap_or_default(), table_name));
+            }
+            let row = Row{cols: vec![Value::new_text(steps.join(", "))]};
+            Ok(Some((self.open_cursor(OpenCursor::Rows(VecDeque::new()))?, row)))
+        }
+
+
+        ///Returns the operator of a plan the way it is written in a query
+        fn operator_symbol(operator : &str) -> &str {
+            match operator {
+                EQUAL => "==",
+                NOT_EQUAL => "!=",
+                EQUAL_IGNORE_CASE => "==*",
+                SMALLER => "<",
+                SMALLER_EQUAL => "<=",
+                BIGGER => ">",
+                BIGGER_EQUAL => ">=",
+                other => other,
+            }
+        }
+
+
+        ///Writes all rows of a table to a csv file at path, which is created or overwritten. The
+        ///first line holds the col names in the order the table was created with, every other
+        ///line one row. Text that contains a comma, a quote or a line break is quoted and blobs
+        ///are written as hex. Returns the number of exported rows
+        pub fn export(&self, table_name : &str, path : &Path) -> Result<u64> {
+
+            //The read lock keeps the table from being dropped or changed in structure during the
+            //scan
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+
+            //Tables store their cols in reverse order, so the header and every row are reversed
+            let col_names : Vec<String> = self.schema.get_col_data(table_name.to_string())?.into_iter().rev().map(|(_, col_name)| Executor::csv_field(&col_name)).collect();
+            let mut writer = BufWriter::new(File::create(path)?);
+            writeln!(writer, "{}", col_names.join(","))?;
+            let mut count : u64 = 0;
+            let mut next = handler.select_row(None, None)?;
+            while let Some((row, mut cursor)) = next {
+                let fields : Vec<String> = row.cols.iter().rev().map(|value| Executor::csv_field(&value.to_string())).collect();
+                writeln!(writer, "{}", fields.join(","))?;
+                count += 1;
+                next = handler.next(&mut cursor)?.map(|row| (row, cursor));
+            }
+            writer.flush()?;
+            Ok(count)
+        }
+
+
+        ///Inserts the rows of the csv file at path into a table. The first line names the cols the
+        ///fields belong to, it may leave out the auto increment col. Every row goes through the
+        ///same checks as an insert. If skip_invalid is not set the first invalid row aborts the
+        ///import with an error naming its line. Rows with a wrong number of fields or values that
+        ///can not be parsed are found before anything is inserted, rows violating a constraint
+        ///abort the import after the rows before them were inserted. If skip_invalid is set
+        ///invalid rows are skipped and their errors added to warnings. Returns the number of
+        ///imported rows
+        pub fn import(&self, path : &Path, table_name : &str, skip_invalid : bool, warnings : &mut Vec<String>) -> Result<u64> {
+            let mut records = Executor::parse_csv(&std::fs::read_to_string(path)?)?.into_iter();
+            let col_names : Vec<String> = match records.next() {
+                Some((_, header)) => header.into_iter().map(|col_name| col_name.trim().to_lowercase()).collect(),
+                None => return Err(Error::new(ErrorKind::InvalidInput, "csv file is empty")),
+            };
+
+            //Rows that do not fit the cols of the table are found before the first insert
+            let mut valid : Vec<(usize, Vec<String>)> = vec![];
+            {
+                let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+                let handler = &tables.iter().find(|(t, _)| *t == table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+                let col_data = self.schema.get_col_data(table_name.to_string())?;
+                if let Some(col_name) = col_names.iter().find(|col_name| !col_data.iter().any(|(_, n)| n == *col_name)) {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("col {} of the header does not exist in {}", col_name, table_name)));
+                }
+                for (line, fields) in records {
+                    let result = if fields.len() != col_names.len() {
+                        Err(Error::new(ErrorKind::InvalidInput, format!("expected {} fields but found {}", col_names.len(), fields.len())))
+                    } else {
+                        col_names.iter().zip(fields.iter()).try_for_each(|(col_name, field)| handler.create_value(col_name.clone(), field.clone()).map(|_| ()))
+                    };
+                    match result {
+                        Ok(()) => valid.push((line, fields)),
+                        Err(e) if skip_invalid => warnings.push(format!("skipped line {}: {}", line, e)),
+                        Err(e) => return Err(Error::new(e.kind(), format!("line {}: {}", line, e))),
+                    }
+                }
+            }
+
+            //Inserts take the locks themselves, so they run after the lock above was released. The
+            //rows are inserted in one batch, if it fails they are inserted one by one to find the
+            //lines that caused it
+            let rows : Vec<Vec<String>> = valid.iter().map(|(_, fields)| fields.clone()).collect();
+            if let Ok((count, _)) = self.insert_rows(table_name, Some(col_names.clone()), rows) {
+                return Ok(count);
+            }
+            let mut count : u64 = 0;
+            for (line, fields) in valid {
+                let args = HashMap::from([
+                    (TABLE_NAME_KEY.to_string(), vec![table_name.to_string()]),
+                    (COLUMN_NAME_KEY.to_string(), col_names.clone()),
+                    (COLUMN_VALUE_KEY.to_string(), fields),
+                ]);
+                match self.insert(args) {
+                    Ok((inserted, _)) => count += inserted,
+                    Err(e) if skip_invalid => warnings.push(format!("skipped line {}: {}", line, e)),
+                    Err(e) => return Err(Error::new(e.kind(), format!("line {}: {}", line, e))),
+                }
+            }
+            Ok(count)
+        }
+
+
+        ///Splits csv text into records along with the line they start at. Fields in quotes may
+        ///contain separators, line breaks and doubled quotes. Empty lines are ignored
+        fn parse_csv(content : &str) -> Result<Vec<(usize, Vec<String>)>> {
+            let mut records : Vec<(usize, Vec<String>)> = vec![];
+            let mut fields : Vec<String> = vec![];
+            let mut field = String::new();
+            let mut quoted = false;
+            let mut line = 1;
+            let mut start = 1;
+            let mut chars = content.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if quoted && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    },
+                    '"' if quoted => quoted = false,
+                    '"' if field.is_empty() => quoted = true,
+                    '\n' if !quoted => {
+                        fields.push(std::mem::take(&mut field));
+                        if fields.len() > 1 || !fields[0].is_empty() {
+                            records.push((start, std::mem::take(&mut fields)));
+                        }
+                        fields.clear();
+                        line += 1;
+                        start = line;
+                    },
+                    '\r' if !quoted && chars.peek() == Some(&'\n') => {},
+                    ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                    c => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.push(c);
+                    },
+                }
+            }
+            if quoted {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("line {}: quote was not closed", start)));
+            }
+            fields.push(field);
+            if fields.len() > 1 || !fields[0].is_empty() {
+                records.push((start, fields));
+            }
+            Ok(records)
+        }
+
+
+        ///Quotes a csv field if it contains a separator, a quote or a line break. Quotes inside
+        ///the field are doubled
+        pub fn csv_field(field : &str) -> String {
+            if field.contains([',', '"', '\n', '\r']) {
+                return format!("\"{}\"", field.replace('"', "\"\""));
+            }
+            field.to_string()
+        }
+
+
+        ///Like select but with a starting point. Exhausted cursors are removed, as are cursors that
+        ///failed, for example because their table was modified
+        pub fn next(&self, hash : Vec<u8>) -> Result<Option<Row>> {
+            let tables = recover(self.tables.read(), "tables");
+            let mut cursors = recover(self.cursors.lock(), "cursors");
+
+            //Get the cursor corresponding to the hash
+            let (used, cursor) = cursors.get_mut(&hash).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "hash is invalid"))?;
+            *used = Instant::now();
+            let row = (|| -> Result<Option<Row>> {
+                Ok(match cursor {
+                    OpenCursor::Table(table_name, cursor) => {
+
+                        //Try to access the table stored with the cursor
+                        let handler = &tables.iter().find(|(t, _)| *t==*table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+
+                        //Get next
+                        handler.next(cursor)?
+                    },
+                    OpenCursor::Rows(rows) => rows.pop_front(),
+                    OpenCursor::Distinct(table_name, cursor, seen) => {
+                        let handler = &tables.iter().find(|(t, _)| *t==*table_name).ok_or_else(||ErrorCode::TableNotFound.error(ErrorKind::InvalidInput, "table does not exist"))?.1;
+
+                        //Skip rows that were returned before
+                        let mut row = handler.next(cursor)?;
+                        while let Some(r) = row.clone() {
+                            if seen.insert(r.into()) {
+                                break;
+                            }
+                            row = handler.next(cursor)?;
+                        }
+                        row
+                    },
+                    OpenCursor::Expired => return Err(ErrorCode::Timeout.error(ErrorKind::TimedOut, "cursor expired")),
+                })
+            })();
+            if !matches!(row, Ok(Some(_))) {
+                cursors.remove(&hash);
+            }
+            row
+        }
+
+
+        ///Returns the names of the cols a select returns. Clients receive them in the order the
+        ///table was created with, no matter in which order the query names them. If functions are
+        ///applied the cols keep the order of the query instead, since a col may be used twice
+        fn result_columns(&self, args : &HashMap<String, Vec<String>>) -> Result<Vec<String>> {
+            let table_name : String = args.get(TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let projection : Option<&Vec<String>> = args.get(COLUMN_NAME_KEY);
+            if let (Some(cols), Some(functions)) = (projection, args.get(FUNCTION_KEY)) {
+                if functions.iter().any(|f| f != NO_FUNCTION) {
+                    return Ok(cols.iter().zip(functions.iter()).rev().map(|(col, function)| match function.as_str() {
+                        NO_FUNCTION => col.clone(),
+                        function => format!("{}({})", function, col),
+                    }).collect());
+                }
+            }
+            Ok(self.schema.get_col_data(table_name)?.into_iter().rev()
+                .map(|(_, col_name)| col_name)
+                .filter(|col_name| projection.is_none_or(|cols| cols.contains(col_name)))
+                .collect())
+        }
+
+
+        ///Returns the position in the rows of a table of every col a select applies a function to,
+        ///along with the function, in the order of the query. Returns None if no function is
+        ///applied. Text functions need text cols
+        fn projection_functions(&self, args : &HashMap<String, Vec<String>>) -> Result<Option<Vec<(usize, String)>>> {
+            let (cols, functions) = match (args.get(COLUMN_NAME_KEY), args.get(FUNCTION_KEY)) {
+                (Some(cols), Some(functions)) if functions.iter().any(|f| f != NO_FUNCTION) => (cols, functions),
+                _ => return Ok(None),
+            };
+            let table_name : String = args.get(TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(||Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?.clone();
+            let col_data = self.schema.get_col_data(table_name)?;
+            cols.iter().zip(functions.iter()).rev().map(|(col, function)| {
+                let index = col_data.iter().position(|(_, n)| n == col).ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("col {} does not exist", col)))?;
+                let col_type = &col_data[index].0;
+                if function != NO_FUNCTION && *col_type != Type::Text {
+                    return Err(ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, format!("{} needs a text col but {} is a {} col", function, col, col_type)));
+                }
+                Ok((index, function.clone()))
+            }).collect::<Result<Vec<(usize, String)>>>().map(Some)
+        }
+
+
+        ///Computes the function a select applies to a col
+        fn apply_function(function : &str, value : Value) -> Result<Value> {
+            Ok(match (function, value) {
+                (NO_FUNCTION, value) => value,
+                (UPPER, Value::Text(text)) => Value::new_text(text.to_uppercase()),
+                (LOWER, Value::Text(text)) => Value::new_text(text.to_lowercase()),
+                (LENGTH, Value::Text(text)) => Value::new_number(text.chars().count() as i64),
+                (function, value) => return Err(ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, format!("{} can not be applied to {}", function, value))),
+            })
+        }
+
+
+        ///Returns the position in the rows of a select of every col it is ordered by, along with
+        ///whether it is sorted descending. The first key decides, the following ones break ties
+        fn order_keys(&self, args : &HashMap<String, Vec<String>>) -> Result<Vec<(usize, bool)>> {
+            let order_cols : Vec<String> = args.get(ORDER_COL).cloned().unwrap_or_default();
+            if order_cols.is_empty() {
+                return Ok(vec![]);
+            }
+            let directions : Vec<String> = args.get(ORDER_DIRECTION).cloned().unwrap_or_default();
+            if directions.len() != order_cols.len() {
+                return Err(Error::new(ErrorKind::InvalidInput, "every order col needs a direction"));
+            }
+
+            //Rows hold their cols in reverse order of the result columns and the keys are stored
+            //in reverse order of the query
+            let columns = self.result_columns(args)?;
+            order_cols.iter().zip(directions.iter()).rev().map(|(col, direction)| {
+                let position = columns.iter().position(|c| c == col).ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("order col {} is not a col of the result", col)))?;
+                Ok((columns.len() - 1 - position, direction == DESCENDING))
+            }).collect()
+        }
+
+
+        ///Strips the table qualifier of col names, e.g. users.id becomes id, so the table handlers
+        ///can look them up by their plain name. A qualifier has to name the table of the query
+        fn resolve_qualifiers(mut plan : HashMap<String, Vec<String>>) -> Result<HashMap<String, Vec<String>>> {
+            let table_name = match plan.get(TABLE_NAME_KEY).and_then(|t| t.first()) {
+                Some(table_name) => table_name.clone(),
+                None => return Ok(plan),
+            };
+            //The cols of a subquery belong to its own table
+            let subquery_table_name = plan.get(SUBQUERY_TABLE).and_then(|t| t.first()).cloned().unwrap_or_default();
+            for (table_name, key) in [(&table_name, COLUMN_NAME_KEY), (&table_name, PREDICATE_COL), (&table_name, ORDER_COL), (&table_name, CHECK_COL), (&subquery_table_name, SUBQUERY_COL), (&subquery_table_name, SUBQUERY_PREDICATE_COL)] {
+                if let Some(cols) = plan.get_mut(key) {
+                    for col in cols.iter_mut() {
+                        if let Some((qualifier, name)) = col.split_once('.') {
+                            if qualifier != table_name {
+                                return Err(Error::new(ErrorKind::InvalidInput, format!("col {} does not belong to table {}", col, table_name)));
+                            }
+                            *col = name.to_string();
+                        }
+                    }
+                }
+            }
+            Ok(plan)
+        }
+
+
+        ///Stores a cursor in the cursors map along with a randomly generated hash and returns the
+        ///hash
+        fn open_cursor(&self, cursor : OpenCursor) -> Result<Vec<u8>> {
+            let mut cursors = recover(self.cursors.lock(), "cursors");
+            let mut hash = [0u8; 16];
+            loop {
+                rand::thread_rng().fill_bytes(&mut hash);
+                if !cursors.contains_key(&hash.to_vec()) {
+                    break;
+                }
+            }
+            cursors.insert(hash.to_vec(), (Instant::now(), cursor));
+            Ok(hash.to_vec())
+        }
+
+
+        ///Writes all tables to the storage device, used when the server shuts down
+        pub fn sync(&self) -> Result<()> {
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            for (_, table) in tables.iter() {
+                table.sync()?;
+            }
+            Ok(())
+        }
+
+
+        ///Removes the cursor the hash points to so it does not have to be read till the end
+        pub fn close_cursor(&self, hash : Vec<u8>) -> Result<()> {
+            let mut cursors = recover(self.cursors.lock(), "cursors");
+            cursors.remove(&hash).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "hash is invalid"))?;
+            Ok(())
+        }
+
+
+        ///Returns the size of the .hive files of the database in bytes along with the row count of
+        ///every table sorted by name. A database in memory has no files, so its size is 0
+        pub fn stats(&self) -> Result<(u64, Vec<(String, u64)>)> {
+            let mut size = 0;
+            if self.storage == Storage::Disk {
+                for entry in std::fs::read_dir(&self.db_path)? {
+                    let path = entry?.path();
+                    if path.extension().is_some_and(|extension| extension == "hive") {
+                        size += get_size(&path)?;
+                    }
+                }
+            }
+            let tables = self.tables.read().map_err(|_| Error::other("thread poisoned"))?;
+            let mut row_counts : Vec<(String, u64)> = vec![];
+            for (name, table) in tables.iter() {
+                row_counts.push((name.clone(), table.count_rows(None)?));
+            }
+            row_counts.sort();
+            Ok((size, row_counts))
+        }
+
+
+        ///Returns the number of open cursors
+        pub fn cursor_count(&self) -> Result<usize> {
+            let cursors = recover(self.cursors.lock(), "cursors");
+            Ok(cursors.values().filter(|(_, c)| !matches!(c, OpenCursor::Expired)).count())
+        }
+
+
+        ///Expires the cursors that were not used within the ttl and returns how many expired.
+        ///Their hashes are forgotten once they were expired for another ttl
+        pub fn expire_cursors(&self, ttl : Duration) -> usize {
+            let mut cursors = recover(self.cursors.lock(), "cursors");
+            cursors.retain(|_, (used, c)| !(matches!(c, OpenCursor::Expired) && used.elapsed() >= ttl));
+            let mut expired = 0;
+            for (used, cursor) in cursors.values_mut() {
+                if !matches!(cursor, OpenCursor::Expired) && used.elapsed() >= ttl {
+                    *used = Instant::now();
+                    *cursor = OpenCursor::Expired;
+                    expired += 1;
+                }
+            }
+            expired
+        }
+
+
+        ///Stores a parsed query so it can be executed with different params later. Returns the
+        ///hash that points to the query
+        pub fn prepare(&self, query : Query) -> Result<Vec<u8>> {
+            let mut statements = self.statements.lock().map_err(|_| Error::other("thread poisoned"))?;
+            let mut hash = [0u8; 16];
+            loop {
+                rand::thread_rng().fill_bytes(&mut hash);
+                if !statements.contains_key(&hash.to_vec()) {
+                    break;
+                }
+            }
+            statements.insert(hash.to_vec(), query);
+            Ok(hash.to_vec())
+        }
+
+
+        ///Returns the prepared query the hash points to, its placeholders are not replaced
+        pub fn get_prepared(&self, hash : &Vec<u8>) -> Result<Query> {
+            let statements = self.statements.lock().map_err(|_| Error::other("thread poisoned"))?;
+            statements.get(hash).cloned().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "hash is invalid"))
+        }
+
+
+        ///Executes a prepared query with its placeholders replaced by params
+        pub fn execute_prepared(&self, hash : Vec<u8>, params : Vec<String>) -> Result<QueryResult> {
+            let query = self.get_prepared(&hash)?.bind(params)?;
+            self.execute(query)
+        }
+
+
+        ///Executes the queries of a batch in order and returns the result of the last one along with
+        ///the warnings of all. Cursors opened before the last query are closed, the first error stops
+        ///the batch and names the query it belongs to
+        pub fn execute_batch(&self, queries : Vec<Query>) -> Result<QueryResult> {
+            let count = queries.len();
+            let mut warnings : Vec<String> = vec![];
+            let mut last : Option<QueryResult> = None;
+            for (i, query) in queries.into_iter().enumerate() {
+                let mut result = self.execute(query).map_err(|e| if count > 1 { in_statement(i, e) } else { e })?;
+                warnings.append(&mut result.warnings);
+                if let Some(previous) = last.replace(result) {
+                    if let Some((hash, _)) = previous.cursor {
+                        self.close_cursor(hash)?;
+                    }
+                }
+            }
+            let mut result = last.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "batch did not contain a query"))?;
+            result.warnings = warnings;
+            Ok(result)
+        }
+
+
+        pub fn execute(&self, query: Query) -> Result<QueryResult> {
+            let mut warnings : Vec<String> = vec![];
+            let mut message : Option<String> = None;
+            let mut affected : Option<u64> = None;
+            let mut inserted_id : Option<i64> = None;
+            let mut columns : Vec<String> = vec![];
+            if query.placeholder_count() > 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, "query contains placeholders, prepare it and pass params instead"));
+            }
+
+            //Execute an action according to the command of the query
+            let command = query.command()?;
+            debug!("executing {:?}", command);
+            let query = Query{plan: Executor::resolve_qualifiers(query.plan)?};
+            let cursor = match command {
+                CommandKind::Create => {
+                    self.create(query.plan)?;
+                    None
+                },
+                CommandKind::CreateAs => {
+                    affected = Some(self.create_as(query.plan)?);
+                    None
+                },
+                CommandKind::Drop => {
+                    self.drop(query.plan, &mut warnings)?;
+                    None
+                },
+                CommandKind::Rename => {
+                    self.rename(query.plan)?;
+                    None
+                },
+                CommandKind::DropColumn => {
+                    self.drop_column(query.plan)?;
+                    None
+                },
+                CommandKind::Insert => {
+                    let (count, id) = self.insert(query.plan)?;
+                    affected = Some(count);
+                    inserted_id = id;
+                    None
+                },
+                CommandKind::Select => {
+                    columns = self.result_columns(&query.plan)?;
+                    self.select(query.plan)?
+                },
+                CommandKind::Count => {
+                    columns = vec!["count".to_string()];
+                    self.count(query.plan)?
+                },
+                CommandKind::Exists => {
+                    columns = vec!["exists".to_string()];
+                    self.exists(query.plan)?
+                },
+                CommandKind::Delete => {
+                    affected = Some(self.delete(query.plan)?);
+                    None
+                },
+                CommandKind::Vacuum => {
+                    self.vacuum(query.plan)?;
+                    None
+                },
+                CommandKind::Analyze => {
+                    message = Some(self.analyze(query.plan)?.to_string());
+                    None
+                },
+                CommandKind::Describe => {
+                    columns = vec!["name".to_string(), "type".to_string()];
+                    self.describe(query.plan)?
+                },
+                CommandKind::Export => {
+                    let table_name = query.plan.get(TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?;
+                    let path = query.plan.get(FILE_PATH_KEY).and_then(|p| p.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a file path"))?;
+                    affected = Some(self.export(table_name, Path::new(path))?);
+                    None
+                },
+                CommandKind::Import => {
+                    let table_name = query.plan.get(TABLE_NAME_KEY).and_then(|t| t.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a table name"))?;
+                    let path = query.plan.get(FILE_PATH_KEY).and_then(|p| p.first()).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "args did not contain a file path"))?;
+                    affected = Some(self.import(Path::new(path), table_name, query.plan.contains_key(SKIP_INVALID), &mut warnings)?);
+                    None
+                },
+                CommandKind::Explain => {
+                    columns = vec!["plan".to_string()];
+                    self.explain(query.plan)?
+                },
+            };
+            debug!("executed {:?}, affected {:?}, warnings {:?}", command, affected, warnings);
+            Ok(QueryResult{cursor, warnings, message, affected, inserted_id, columns})
+        }
+
+    }
+
+
+    #[cfg(test)]
+    pub mod test {
+
+
+
+        use super::*;
+        use crate::storage::file_management::{get_test_path, create_dir, delete_dir};
+        use std::{sync::Arc, thread};
+
+
+
+        ///Creates an empty database directory for a test and returns an Executor working in it
+        fn test_executor(name : &str) -> Executor {
+            let path = get_test_path().unwrap().join(name);
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            Executor::new(path).unwrap()
+        }
+
+
+
+        #[test]
+        fn concurrent_create_test() {
+            for _ in 0..20 {
+                let executor = Arc::new(test_executor("concurrent_create"));
+                let handles : Vec<_> = (0..2).map(|_| {
+                    let executor = Arc::clone(&executor);
+                    thread::spawn(move || executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()))
+                }).collect();
+                let results : Vec<Result<QueryResult>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+                assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1, "exactly one create should succeed");
+                assert_eq!(executor.schema.get_col_data("t".to_string()).unwrap().len(), 1, "the schema should contain the columns only once");
+            }
+        }
+
+
+
+        #[test]
+        fn stats_test() {
+            let executor = test_executor("stats");
+            let (size, tables) = executor.stats().unwrap();
+            assert!(tables.is_empty());
+            executor.execute(Query::from("CREATE TABLE b (a NUMBER);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("CREATE TABLE a (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO b VALUES ({});", i)).unwrap()).unwrap();
+            }
+            executor.sync().unwrap();
+            let (new_size, tables) = executor.stats().unwrap();
+            assert_eq!(tables, vec![("a".to_string(), 0), ("b".to_string(), 3)]);
+            assert!(new_size > size, "the files of the new tables should be counted");
+        }
+
+
+
+        #[test]
+        fn vacuum_with_open_cursor_test() {
+            let executor = test_executor("vacuum_with_open_cursor");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            let cursor = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor;
+            assert!(cursor.is_some());
+            let result = executor.execute(Query::from("VACUUM t;".to_string()).unwrap());
+            assert!(result.is_err(), "vacuum should be rejected while a cursor is open");
+        }
+
+
+
+        #[test]
+        fn execute_batch_test() {
+            let executor = test_executor("execute_batch");
+            let queries = Query::batch("CREATE TABLE t (a NUMBER); INSERT INTO t VALUES (1); SELECT * FROM t; INSERT INTO t VALUES (2); SELECT * FROM t WHERE a > 1;".to_string()).unwrap();
+            let result = executor.execute_batch(queries).unwrap();
+            let (_, row) = result.cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::Number(2)]);
+            assert_eq!(executor.cursor_count().unwrap(), 1, "only the cursor of the last select should stay open");
+
+            //The first error stops the batch and names its statement
+            let queries = Query::batch("INSERT INTO t VALUES (3); INSERT INTO u VALUES (4); INSERT INTO t VALUES (5);".to_string()).unwrap();
+            match executor.execute_batch(queries) {
+                Err(error) => assert!(error.to_string().starts_with("statement 2: "), "{}", error),
+                Ok(_) => panic!("inserting into a missing table should fail"),
+            }
+            let (_, tables) = executor.stats().unwrap();
+            assert_eq!(tables, vec![("t".to_string(), 3)]);
+            assert!(executor.execute_batch(vec![]).is_err());
+        }
+
+
+        #[test]
+        fn close_cursor_test() {
+            let executor = test_executor("close_cursor");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+
+            //Cursors are removed when they are exhausted
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(executor.cursor_count().unwrap(), 1);
+            while executor.next(hash.clone()).unwrap().is_some() {}
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            assert!(executor.next(hash).is_err(), "exhausted cursors should be gone");
+
+            //Or when they are closed explicitly, which allows vacuum again
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            executor.close_cursor(hash.clone()).unwrap();
+            assert!(executor.close_cursor(hash).is_err(), "closing twice should return an error");
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            executor.execute(Query::from("VACUUM t;".to_string()).unwrap()).unwrap();
+
+            //Or when their table is dropped
+            executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("DROP TABLE t;".to_string()).unwrap()).unwrap();
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+
+            //Or when their table was modified while they were open
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 1..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            executor.execute(Query::from("DELETE FROM t WHERE a == 1;".to_string()).unwrap()).unwrap();
+            assert!(executor.next(hash).is_err(), "cursors should be invalid after their table was modified");
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+        }
+
+
+        #[test]
+        fn expire_cursors_test() {
+            let executor = test_executor("expire_cursors");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+
+            //Recently used cursors are kept
+            assert_eq!(executor.expire_cursors(Duration::from_secs(60)), 0);
+            assert!(executor.next(hash.clone()).unwrap().is_some());
+
+            //Expired cursors no longer count as open, reading them reports the expiry once
+            assert_eq!(executor.expire_cursors(Duration::ZERO), 1);
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            match executor.next(hash.clone()) {
+                Err(error) => assert_eq!(error.to_string(), "cursor expired"),
+                Ok(_) => panic!("expired cursors should not return rows"),
+            }
+            assert!(executor.next(hash).is_err());
+
+            //Hashes of expired cursors that are never read are forgotten by a later sweep
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(executor.expire_cursors(Duration::ZERO), 1);
+            assert_eq!(executor.expire_cursors(Duration::ZERO), 0);
+            match executor.next(hash) {
+                Err(error) => assert_eq!(error.to_string(), "hash is invalid"),
+                Ok(_) => panic!("forgotten cursors should not return rows"),
+            }
+        }
+
+
+
+        #[test]
+        fn describe_test() {
+            let executor = test_executor("describe");
+            executor.execute(Query::from("CREATE TABLE t (name TEXT, age NUMBER);".to_string()).unwrap()).unwrap();
+            let (hash, row) = executor.execute(Query::from("DESCRIBE t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("text".to_string()), Value::new_text("name".to_string())]);
+            let row = executor.next(hash.clone()).unwrap().unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("number".to_string()), Value::new_text("age".to_string())]);
+            assert!(executor.next(hash).unwrap().is_none());
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            assert!(executor.execute(Query::from("DESCRIBE u;".to_string()).unwrap()).is_err(), "describing a missing table should return an error");
+        }
+
+
+
+        #[test]
+        fn not_null_test() {
+            let path = get_test_path().unwrap().join("not_null");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER NOT NULL, b TEXT, c TEXT NOT NULL);".to_string()).unwrap()).unwrap();
+            let error = executor.execute(Query::from("INSERT INTO t (a, b) VALUES (1, x);".to_string()).unwrap()).err().unwrap();
+            assert!(error.to_string().contains("col c is not null"), "the error should name the not null col");
+            assert!(executor.execute(Query::from("INSERT INTO t (a, c) VALUES (1, x);".to_string()).unwrap()).is_err(), "null values are not supported");
+            drop(executor);
+
+            //The flags survive a restart, a rename and dropping another col
+            let executor = Executor::new(path).unwrap();
+            executor.execute(Query::from("RENAME TABLE t TO u;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("ALTER TABLE u DROP COLUMN a;".to_string()).unwrap()).unwrap();
+            let error = executor.execute(Query::from("INSERT INTO u (b) VALUES (x);".to_string()).unwrap()).err().unwrap();
+            assert_eq!(ErrorCode::of(&error), ErrorCode::ConstraintViolation);
+            let (hash, row) = executor.execute(Query::from("DESCRIBE u;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("text".to_string()), Value::new_text("b".to_string())]);
+            let row = executor.next(hash).unwrap().unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("text not null".to_string()), Value::new_text("c".to_string())]);
+        }
+
+
+
+        #[test]
+        fn result_columns_test() {
+            let executor = test_executor("result_columns");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER, b TEXT, c NUMBER);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO t VALUES (1, x, 3);".to_string()).unwrap()).unwrap();
+            let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.columns, vec!["a", "b", "c"]);
+
+            //Projected cols keep the order of the table and the rows are reversed like all rows
+            let result = executor.execute(Query::from("SELECT t.c, a FROM t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.columns, vec!["a", "c"]);
+            assert_eq!(result.cursor.unwrap().1.cols, vec![Value::new_number(3), Value::new_number(1)]);
+            let result = executor.execute(Query::from("DESCRIBE t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.columns, vec!["name", "type"]);
+            let result = executor.execute(Query::from("INSERT INTO t VALUES (1, x, 3);".to_string()).unwrap()).unwrap();
+            assert!(result.columns.is_empty());
+        }
+
+
+
+        #[test]
+        fn vacuum_test() {
+            let executor = test_executor("vacuum");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            executor.execute(Query::from("DELETE FROM t WHERE a < 2;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("VACUUM t;".to_string()).unwrap()).unwrap();
+            let (_, row) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_number(2)]);
+        }
+
+
+
+        #[test]
+        fn page_size_test() {
+            let path = get_test_path().unwrap().join("page_size");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::with_page_size(path.clone(), 8192).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            drop(executor);
+
+            //Tables created after reopening use the page size of the database as well
+            let executor = Executor::new(path).unwrap();
+            executor.execute(Query::from("CREATE TABLE u (a NUMBER);".to_string()).unwrap()).unwrap();
+            for (_, handler) in executor.tables.read().unwrap().iter() {
+                assert_eq!(handler.page_size(), 8192);
+            }
+        }
+
+
+
+        #[test]
+        fn drop_warning_test() {
+            let executor = test_executor("drop_warning");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            let result = executor.execute(Query::from("DROP TABLE t;".to_string()).unwrap()).unwrap();
+            assert!(result.warnings.is_empty());
+
+            //Remove the table file beforehand so dropping only succeeds with a warning
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            delete_file(&executor.db_path.join("t.hive")).unwrap();
+            let result = executor.execute(Query::from("DROP TABLE t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.warnings.len(), 1, "dropping a table without a file should warn");
+            assert!(result.cursor.is_none());
+        }
+
+
+
+        #[test]
+        fn prepared_statement_test() {
+            let executor = test_executor("prepared_statement");
+            executor.execute(Query::from("CREATE TABLE t (name TEXT, age NUMBER);".to_string()).unwrap()).unwrap();
+            let insert = executor.prepare(Query::from("INSERT INTO t (name, age) VALUES (?, ?);".to_string()).unwrap()).unwrap();
+            executor.execute_prepared(insert.clone(), vec!["Bob O'Neil; DROP".to_string(), "30".to_string()]).unwrap();
+            executor.execute_prepared(insert.clone(), vec!["alice".to_string(), "12".to_string()]).unwrap();
+            assert!(executor.execute_prepared(insert, vec!["carl".to_string()]).is_err(), "missing params should return an error");
+
+            //The params are stored as they were passed
+            let select = executor.prepare(Query::from("SELECT name FROM t WHERE age > ?;".to_string()).unwrap()).unwrap();
+            let (_, row) = executor.execute_prepared(select, vec!["20".to_string()]).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("Bob O'Neil; DROP".to_string())]);
+            assert!(executor.execute(Query::from("DELETE FROM t WHERE age > ?;".to_string()).unwrap()).is_err(), "placeholders have to be bound before execution");
+            assert!(executor.execute_prepared(vec![0; 16], vec![]).is_err(), "unknown hashes should return an error");
+        }
+
+
+
+        #[test]
+        fn affected_rows_test() {
+            let executor = test_executor("affected_rows");
+            executor.execute(Query::from("CREATE TABLE t (n NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..5 {
+                let result = executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+                assert_eq!(result.affected, Some(1));
+            }
+            let result = executor.execute(Query::from("DELETE FROM t WHERE n < 3;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(3));
+            let result = executor.execute(Query::from("DELETE FROM t WHERE n > 10;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(0));
+            let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, None);
+        }
+
+
+
+        #[test]
+        fn auto_increment_test() {
+            let path = get_test_path().unwrap().join("auto_increment");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER AUTO_INCREMENT, name TEXT);".to_string()).unwrap()).unwrap();
+            for (name, id) in [("a", 1), ("b", 2)] {
+                let result = executor.execute(Query::from(format!("INSERT INTO t (name) VALUES ({});", name)).unwrap()).unwrap();
+                assert_eq!(result.inserted_id, Some(id));
+            }
+            executor.execute(Query::from("DELETE FROM t WHERE id == 2;".to_string()).unwrap()).unwrap();
+            let result = executor.execute(Query::from("INSERT INTO t (id, name) VALUES (10, c);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.inserted_id, Some(10));
+            drop(executor);
+
+            //The counter survives a restart and does not reuse deleted or explicit values
+            let executor = Executor::new(path).unwrap();
+            let result = executor.execute(Query::from("INSERT INTO t (name) VALUES (d);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.inserted_id, Some(11));
+            let (_, row) = executor.execute(Query::from("SELECT id FROM t WHERE name == d;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_number(11)]);
+            assert!(executor.execute(Query::from("CREATE TABLE u (a NUMBER AUTO_INCREMENT, b NUMBER AUTO_INCREMENT);".to_string()).unwrap()).is_err());
+        }
+
+
+
+        #[test]
+        fn count_test() {
+            let path = get_test_path().unwrap().join("count");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            let count = |executor : &Executor, query : &str| -> i64 {
+                let result = executor.execute(Query::from(query.to_string()).unwrap()).unwrap();
+                assert_eq!(result.columns, vec!["count"]);
+                let (hash, row) = result.cursor.unwrap();
+                assert!(executor.next(hash).unwrap().is_none());
+                match row.cols[0] {
+                    Value::Number(count) => count,
+                    _ => panic!("count should be a number"),
+                }
+            };
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM t;"), 0);
+            for i in 0..5 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            executor.execute(Query::from("DELETE FROM t WHERE a < 2;".to_string()).unwrap()).unwrap();
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM t;"), 3);
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM t WHERE a > 2;"), 2);
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            assert!(executor.execute(Query::from("SELECT COUNT(*) FROM u;".to_string()).unwrap()).is_err());
+            drop(executor);
+
+            //The count survives a restart and a rename. Without a predicate it is answered from the
+            //kept count, which vacuum recomputes
+            let executor = Executor::new(path).unwrap();
+            executor.execute(Query::from("RENAME TABLE t TO u;".to_string()).unwrap()).unwrap();
+            assert_eq!(executor.row_counts.get_count("u").unwrap(), Some(3));
+            executor.row_counts.set_count("u", 10).unwrap();
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM u;"), 10);
+            executor.execute(Query::from("VACUUM u;".to_string()).unwrap()).unwrap();
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM u;"), 3);
+
+            //Tables without a count are scanned
+            executor.row_counts.remove_table("u".to_string()).unwrap();
+            executor.execute(Query::from("INSERT INTO u VALUES (7);".to_string()).unwrap()).unwrap();
+            assert_eq!(count(&executor, "SELECT COUNT(*) FROM u;"), 4);
+            assert_eq!(executor.row_counts.get_count("u").unwrap(), None);
+            executor.execute(Query::from("DROP TABLE u;".to_string()).unwrap()).unwrap();
+            assert_eq!(executor.row_counts.get_count("u").unwrap(), None);
+        }
+
+
+        #[test]
+        fn insert_value_count_test() {
+            let executor = test_executor("insert_value_count");
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER AUTO_INCREMENT, name TEXT, age NUMBER);".to_string()).unwrap()).unwrap();
+            for (query, message) in [
+                ("INSERT INTO t VALUES (1, a);", "expected 3 values but got 2"),
+                ("INSERT INTO t VALUES (1, a, 2, 3);", "expected 3 values but got 4"),
+                ("INSERT INTO t VALUES ();", "expected 3 values but got 0"),
+                ("INSERT INTO t (name, age) VALUES (a);", "expected 2 values but got 1"),
+                ("INSERT INTO t (name, age) VALUES (a, 2, 3);", "expected 2 values but got 3"),
+                ("INSERT INTO t (name, age) VALUES ();", "expected 2 values but got 0")] {
+                match executor.execute(Query::from(query.to_string()).unwrap()) {
+                    Err(error) => assert_eq!(error.to_string(), message, "{}", query),
+                    Ok(_) => panic!("{} should fail", query),
+                }
+            }
+
+            //Nothing was written, not even the auto increment counter
+            let result = executor.execute(Query::from("INSERT INTO t (name, age) VALUES (a, 2);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.inserted_id, Some(1));
+            let (_, tables) = executor.stats().unwrap();
+            assert_eq!(tables, vec![("t".to_string(), 1)]);
+        }
+
+
+        #[test]
+        fn projection_with_predicate_test() {
+            let executor = test_executor("projection_with_predicate");
+            executor.execute(Query::from("CREATE TABLE t (name TEXT, age NUMBER, city TEXT);".to_string()).unwrap()).unwrap();
+            for i in 0..300 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES (n{}, {}, c{});", i, i, i % 3)).unwrap()).unwrap();
+            }
+            let rows = |query : &str| -> Vec<Vec<Value>> {
+                let mut rows : Vec<Vec<Value>> = vec![];
+                if let Some((hash, row)) = executor.execute(Query::from(query.to_string()).unwrap()).unwrap().cursor {
+                    rows.push(row.cols);
+                    while let Some(row) = executor.next(hash.clone()).unwrap() {
+                        rows.push(row.cols);
+                    }
+                }
+                rows
+            };
+
+            //The predicate is evaluated against the whole row on every page the cursor moves to,
+            //while only the projected col is returned
+            let mut names = rows("SELECT name FROM t WHERE age >= 100;");
+            assert_eq!(names.len(), 200);
+            assert!(names.iter().all(|row| row.len() == 1));
+            let mut expected : Vec<Vec<Value>> = (100..300).map(|i| vec![Value::new_text(format!("n{}", i))]).collect();
+            names.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+            expected.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+            assert_eq!(names, expected);
+
+            //Several projected cols leave out the one that is filtered on
+            let projected = rows("SELECT city, name FROM t WHERE age < 6;");
+            assert_eq!(projected.len(), 6);
+            assert!(projected.iter().all(|row| row.len() == 2 && !row.contains(&Value::new_number(0))));
+
+            //Distinct compares the projected cols only
+            let cities = rows("SELECT DISTINCT city FROM t WHERE age >= 100;");
+            assert_eq!(cities.len(), 3);
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+        }
+
+
+        #[test]
+        fn equal_ignore_case_test() {
+            let executor = test_executor("equal_ignore_case");
+            executor.execute(Query::from("CREATE TABLE t (name TEXT, age NUMBER);".to_string()).unwrap()).unwrap();
+            for (name, age) in [("Bob", 1), ("BOB", 2), ("bob", 3), ("Bobby", 4), ("Alice", 5)] {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ('{}', {});", name, age)).unwrap()).unwrap();
+            }
+            let count = |query : &str| -> Value {
+                let (_, row) = executor.execute(Query::from(query.to_string()).unwrap()).unwrap().cursor.unwrap();
+                row.cols[0].clone()
+            };
+            assert_eq!(count("SELECT COUNT(*) FROM t WHERE name ==* 'bOb';"), Value::new_number(3));
+            assert_eq!(count("SELECT COUNT(*) FROM t WHERE name ==* bob;"), Value::new_number(3));
+            assert_eq!(count("SELECT COUNT(*) FROM t WHERE name == 'Bob';"), Value::new_number(1), "== should still respect the case");
+            assert_eq!(count("SELECT COUNT(*) FROM t WHERE name ==* 'ALICE';"), Value::new_number(1));
+
+            //Numbers have no case
+            match executor.execute(Query::from("SELECT * FROM t WHERE age ==* 1;".to_string()).unwrap()) {
+                Err(error) => assert_eq!(ErrorCode::of(&error), ErrorCode::TypeMismatch),
+                Ok(_) => panic!("numbers should not be compared ignoring case"),
+            }
+
+            //Deletes and checks use the same operator
+            executor.execute(Query::from("DELETE FROM t WHERE name ==* 'BOBBY';".to_string()).unwrap()).unwrap();
+            assert_eq!(count("SELECT COUNT(*) FROM t;"), Value::new_number(4));
+            executor.execute(Query::from("CREATE TABLE u (name TEXT CHECK (name ==* 'admin'));".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO u VALUES ('Admin');".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("INSERT INTO u VALUES ('user');".to_string()).unwrap()).is_err());
+        }
+
+
+        #[test]
+        fn exists_test() {
+            let executor = test_executor("exists");
+            let exists = |executor : &Executor, query : &str| -> bool {
+                let result = executor.execute(Query::from(query.to_string()).unwrap()).unwrap();
+                assert_eq!(result.columns, vec!["exists"]);
+                let (hash, row) = result.cursor.unwrap();
+                assert!(executor.next(hash).unwrap().is_none());
+                row.cols[0] == Value::new_number(1)
+            };
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            assert!(!exists(&executor, "SELECT EXISTS FROM t;"));
+            for i in 0..5 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            assert!(exists(&executor, "SELECT EXISTS FROM t;"));
+            assert!(exists(&executor, "SELECT EXISTS FROM t WHERE a == 4;"));
+            assert!(!exists(&executor, "SELECT EXISTS FROM t WHERE a > 4;"));
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+            assert!(executor.execute(Query::from("SELECT EXISTS FROM u;".to_string()).unwrap()).is_err());
+        }
+
+
+
+        #[test]
+        fn order_by_test() {
+            let executor = test_executor("order_by");
+            let select = |executor : &Executor, query : &str| -> Vec<Vec<Value>> {
+                let mut rows = vec![];
+                if let Some((hash, row)) = executor.execute(Query::from(query.to_string()).unwrap()).unwrap().cursor {
+                    rows.push(row.cols);
+                    while let Some(row) = executor.next(hash.clone()).unwrap() {
+                        rows.push(row.cols);
+                    }
+                }
+                rows
+            };
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER, b TEXT);".to_string()).unwrap()).unwrap();
+            assert!(select(&executor, "SELECT * FROM t ORDER BY a;").is_empty());
+            for (a, b) in [(1, "x"), (2, "y"), (1, "z"), (3, "x"), (1, "x")] {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({}, {});", a, b)).unwrap()).unwrap();
+            }
+            let row = |a : i64, b : &str| vec![Value::new_text(b.to_string()), Value::new_number(a)];
+
+            //Rows are reversed, so b comes first
+            assert_eq!(select(&executor, "SELECT * FROM t ORDER BY a DESC, b;"), vec![row(3, "x"), row(2, "y"), row(1, "x"), row(1, "x"), row(1, "z")]);
+            assert_eq!(select(&executor, "SELECT * FROM t ORDER BY b DESC, t.a ASC;"), vec![row(1, "z"), row(2, "y"), row(1, "x"), row(1, "x"), row(3, "x")]);
+            assert_eq!(select(&executor, "SELECT DISTINCT * FROM t WHERE a < 3 ORDER BY b DESC, a;"), vec![row(1, "z"), row(2, "y"), row(1, "x")]);
+
+            //Rows that are equal in every key keep the order of the table
+            assert_eq!(select(&executor, "SELECT * FROM t WHERE a == 1 ORDER BY a;"), vec![row(1, "x"), row(1, "z"), row(1, "x")]);
+
+            //Order cols have to be part of the result
+            assert!(executor.execute(Query::from("SELECT b FROM t ORDER BY c;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT b FROM t ORDER BY b, a;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT b FROM t ORDER BY u.b;".to_string()).unwrap()).is_err());
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+        }
+
+
+
+        #[test]
+        fn select_functions_test() {
+            let executor = test_executor("select_functions");
+            let select = |executor : &Executor, query : &str| -> (Vec<String>, Vec<Vec<Value>>) {
+                let result = executor.execute(Query::from(query.to_string()).unwrap()).unwrap();
+                let mut rows = vec![];
+                if let Some((hash, row)) = result.cursor {
+                    rows.push(row.cols);
+                    while let Some(row) = executor.next(hash.clone()).unwrap() {
+                        rows.push(row.cols);
+                    }
+                }
+                (result.columns, rows)
+            };
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER, name TEXT);".to_string()).unwrap()).unwrap();
+            for (id, name) in [(1, "Anna"), (2, "bob"), (3, "ANNA")] {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({}, '{}');", id, name)).unwrap()).unwrap();
+            }
+
+            //The cols keep the order of the query and the rows are reversed like all rows
+            let (columns, rows) = select(&executor, "SELECT upper(name), length(t.name), id FROM t WHERE id < 3;");
+            assert_eq!(columns, vec!["upper(name)", "length(name)", "id"]);
+            assert_eq!(rows, vec![
+                vec![Value::new_number(1), Value::new_number(4), Value::new_text("ANNA".to_string())],
+                vec![Value::new_number(2), Value::new_number(3), Value::new_text("BOB".to_string())]]);
+
+            //Distinct and order by apply to the results of the functions
+            let (columns, rows) = select(&executor, "SELECT DISTINCT lower(name) FROM t;");
+            assert_eq!(columns, vec!["lower(name)"]);
+            assert_eq!(rows, vec![vec![Value::new_text("anna".to_string())], vec![Value::new_text("bob".to_string())]]);
+            let (_, rows) = select(&executor, "SELECT length(name), id FROM t ORDER BY id DESC;");
+            assert_eq!(rows, vec![
+                vec![Value::new_number(3), Value::new_number(4)],
+                vec![Value::new_number(2), Value::new_number(3)],
+                vec![Value::new_number(1), Value::new_number(4)]]);
+
+            //Text functions need text cols
+            assert!(executor.execute(Query::from("SELECT upper(id) FROM t;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT length(id) FROM t;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT upper(age) FROM t;".to_string()).unwrap()).is_err());
+            assert_eq!(executor.cursor_count().unwrap(), 0);
+        }
+
+
+
+        #[test]
+        fn check_test() {
+            let path = get_test_path().unwrap().join("check");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            assert!(executor.execute(Query::from("CREATE TABLE u (a NUMBER CHECK (b > 0));".to_string()).unwrap()).is_err(), "checks on missing cols should be rejected");
+            assert!(executor.execute(Query::from("CREATE TABLE u (a NUMBER CHECK (a > x));".to_string()).unwrap()).is_err(), "checks with values of the wrong type should be rejected");
+            executor.execute(Query::from("CREATE TABLE t (age NUMBER CHECK (age >= 0), name TEXT);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO t (age, name) VALUES (3, a);".to_string()).unwrap()).unwrap();
+            drop(executor);
+
+            //Checks survive a restart
+            let executor = Executor::new(path).unwrap();
+            let error = executor.execute(Query::from("INSERT INTO t (age, name) VALUES (-1, b);".to_string()).unwrap()).err().unwrap();
+            assert!(error.to_string().contains("age >= 0"), "the error should name the failing check");
+            let result = executor.execute(Query::from("SELECT * FROM t WHERE age < 0;".to_string()).unwrap()).unwrap();
+            assert!(result.cursor.is_none(), "rows violating a check should not be written");
+            executor.execute(Query::from("DROP TABLE t;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (age NUMBER);".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("INSERT INTO t VALUES (-1);".to_string()).unwrap()).is_ok(), "checks of dropped tables should be removed");
+        }
+
+
+
+        #[test]
+        fn reference_test() {
+            let path = get_test_path().unwrap().join("reference");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER, name TEXT);".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("CREATE TABLE u (a NUMBER REFERENCES missing(id));".to_string()).unwrap()).is_err(), "references to missing tables should be rejected");
+            assert!(executor.execute(Query::from("CREATE TABLE u (a TEXT REFERENCES users(id));".to_string()).unwrap()).is_err(), "references to cols of another type should be rejected");
+            executor.execute(Query::from("CREATE TABLE pets (name TEXT, owner_id NUMBER REFERENCES users(id));".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO users VALUES (1, a);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO users VALUES (2, b);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO pets VALUES (rex, 1);".to_string()).unwrap()).unwrap();
+            drop(executor);
+
+            //References survive a restart
+            let executor = Executor::new(path).unwrap();
+            assert!(executor.execute(Query::from("INSERT INTO pets VALUES (tom, 3);".to_string()).unwrap()).is_err(), "values missing in the referenced table should be rejected");
+            assert!(executor.execute(Query::from("DELETE FROM users WHERE id == 1;".to_string()).unwrap()).is_err(), "referenced rows should not be deleted");
+            assert!(executor.execute(Query::from("DROP TABLE users;".to_string()).unwrap()).is_err(), "referenced tables should not be dropped");
+            let result = executor.execute(Query::from("DELETE FROM users WHERE id == 2;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(1));
+            executor.execute(Query::from("DROP TABLE pets;".to_string()).unwrap()).unwrap();
+            let result = executor.execute(Query::from("DELETE FROM users;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(1), "references of dropped tables should be removed");
+        }
+
+
+
+        #[test]
+        fn metadata_table_names_test() {
+            let path = get_test_path().unwrap().join("metadata_table_names");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            assert!(executor.execute(Query::from("CREATE TABLE schema (a NUMBER);".to_string()).unwrap()).is_err(), "the schema file should not be usable as a table");
+
+            //Tables may be named like the metadata of the database without sharing their files
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER AUTO_INCREMENT, age NUMBER CHECK (age > 0));".to_string()).unwrap()).unwrap();
+            for name in ["checks", "statistics", "auto_increment", "row_count", "not_null"] {
+                executor.execute(Query::from(format!("CREATE TABLE {} (a TEXT, b TEXT);", name)).unwrap()).unwrap();
+                executor.execute(Query::from(format!("INSERT INTO {} VALUES (x, y);", name)).unwrap()).unwrap();
+            }
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER REFERENCES users(id));".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("RENAME TABLE t TO references;".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("RENAME TABLE users TO schema;".to_string()).unwrap()).is_err(), "renaming to schema should be rejected");
+            executor.execute(Query::from("INSERT INTO users (age) VALUES (30);".to_string()).unwrap()).unwrap();
+            drop(executor);
+
+            let executor = Executor::new(path).unwrap();
+            for name in ["checks", "statistics", "auto_increment", "row_count", "not_null"] {
+                let (_, row) = executor.execute(Query::from(format!("SELECT * FROM {};", name)).unwrap()).unwrap().cursor.unwrap();
+                assert_eq!(row.cols.len(), 2, "table {} should keep its own cols", name);
+            }
+            let result = executor.execute(Query::from("INSERT INTO users (age) VALUES (5);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.inserted_id, Some(2));
+            assert!(executor.execute(Query::from("INSERT INTO users (age) VALUES (0);".to_string()).unwrap()).is_err(), "checks should survive next to a table called checks");
+            assert!(executor.execute(Query::from("INSERT INTO references VALUES (9);".to_string()).unwrap()).is_err(), "foreign keys should survive next to a table called references");
+        }
+
+
+
+        #[test]
+        fn distinct_test() {
+            let executor = test_executor("distinct");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER, b TEXT);".to_string()).unwrap()).unwrap();
+            for (a, b) in [(1, "x"), (1, "y"), (2, "x"), (1, "x")] {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({}, {});", a, b)).unwrap()).unwrap();
+            }
+
+            //Distinct applies to the selected cols, not to the whole row
+            let collect = |query : &str| -> Vec<Row> {
+                let (hash, row) = executor.execute(Query::from(query.to_string()).unwrap()).unwrap().cursor.unwrap();
+                let mut rows = vec![row];
+                while let Some(row) = executor.next(hash.clone()).unwrap() {
+                    rows.push(row);
+                }
+                rows
+            };
+            assert_eq!(collect("SELECT DISTINCT a FROM t;").len(), 2);
+            assert_eq!(collect("SELECT DISTINCT * FROM t;").len(), 3);
+            assert_eq!(collect("SELECT DISTINCT b FROM t WHERE a == 1;").len(), 2);
+            assert_eq!(collect("SELECT a FROM t;").len(), 4);
+        }
+
+
+
+        #[test]
+        fn qualified_names_test() {
+            let executor = test_executor("qualified_names");
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER, name TEXT);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO users (users.id, name) VALUES (1, a);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO users VALUES (2, b);".to_string()).unwrap()).unwrap();
+            let (_, row) = executor.execute(Query::from("SELECT users.name FROM users WHERE users.id == 2;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_text("b".to_string())]);
+            assert!(executor.execute(Query::from("SELECT pets.name FROM users;".to_string()).unwrap()).is_err(), "cols of other tables should be rejected");
+            assert!(executor.execute(Query::from("CREATE TABLE a.b (id NUMBER);".to_string()).unwrap()).is_err(), "table names with a dot should be rejected");
+            let result = executor.execute(Query::from("DELETE FROM users WHERE users.id == 1;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(1));
+        }
+
+
+
+        #[test]
+        fn rename_test() {
+            let path = get_test_path().unwrap().join("rename");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER AUTO_INCREMENT, age NUMBER CHECK (age > 0));".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("CREATE TABLE pets (owner_id NUMBER REFERENCES users(id));".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO users (age) VALUES (30);".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("RENAME TABLE users TO pets;".to_string()).unwrap()).is_err(), "renaming to an existing table should be rejected");
+            let (hash, _) = executor.execute(Query::from("SELECT * FROM users;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert!(executor.execute(Query::from("RENAME TABLE users TO people;".to_string()).unwrap()).is_err(), "tables with open cursors should not be renamed");
+            executor.close_cursor(hash).unwrap();
+            executor.execute(Query::from("RENAME TABLE users TO people;".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("SELECT * FROM users;".to_string()).unwrap()).is_err());
+            drop(executor);
+
+            //Rows, checks, counters and foreign keys move to the new name and survive a restart
+            let executor = Executor::new(path).unwrap();
+            let (_, row) = executor.execute(Query::from("SELECT age FROM people WHERE id == 1;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_number(30)]);
+            let result = executor.execute(Query::from("INSERT INTO people (age) VALUES (5);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.inserted_id, Some(2));
+            assert!(executor.execute(Query::from("INSERT INTO people (age) VALUES (0);".to_string()).unwrap()).is_err());
+            executor.execute(Query::from("INSERT INTO pets VALUES (2);".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("DELETE FROM people WHERE id == 2;".to_string()).unwrap()).is_err());
+        }
+
+
+
+        #[test]
+        fn create_as_test() {
+            let executor = test_executor("create_as");
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER AUTO_INCREMENT, name TEXT, data BLOB, age NUMBER CHECK (age > 0));".to_string()).unwrap()).unwrap();
+            for (name, age) in [("ann", 30), ("bob", 12), ("cid", 45), ("ann", 30)] {
+                executor.execute(Query::from(format!("INSERT INTO users (name, data, age) VALUES ({}, 0aff, {});", name, age)).unwrap()).unwrap();
+            }
+
+            //The cols keep their names, order and types, qualified names lose their table
+            let result = executor.execute(Query::from("CREATE TABLE adults AS SELECT DISTINCT users.name, data, age FROM users WHERE age >= 18;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(2));
+            let (_, row) = executor.execute(Query::from("SELECT * FROM adults WHERE name == cid;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols.iter().rev().map(|value| value.to_string()).collect::<Vec<String>>(), vec!["cid", "0aff", "45"]);
+            assert_eq!(executor.result_columns(&Query::from("SELECT * FROM adults;".to_string()).unwrap().plan).unwrap(), vec!["name", "data", "age"]);
+            assert_eq!(executor.stats().unwrap().1, vec![("adults".to_string(), 2), ("users".to_string(), 4)]);
+
+            //Constraints are not copied and an empty result creates an empty table
+            executor.execute(Query::from("INSERT INTO adults VALUES (dan, 00, 0);".to_string()).unwrap()).unwrap();
+            let result = executor.execute(Query::from("CREATE TABLE nobody AS SELECT id FROM users WHERE age > 100;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(0));
+            assert!(executor.execute(Query::from("SELECT * FROM nobody;".to_string()).unwrap()).unwrap().cursor.is_none());
+
+            //Existing tables, missing tables and unknown cols are rejected
+            assert!(executor.execute(Query::from("CREATE TABLE adults AS SELECT * FROM users;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("CREATE TABLE copy AS SELECT * FROM missing;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("CREATE TABLE copy AS SELECT foo FROM users;".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT * FROM copy;".to_string()).unwrap()).is_err(), "a failed create should not leave a table behind");
+        }
+
+
+
+        #[test]
+        fn subquery_test() {
+            let executor = test_executor("subquery");
+            executor.execute(Query::from("CREATE TABLE users (id NUMBER, name TEXT);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("CREATE TABLE orders (owner_id NUMBER, total NUMBER);".to_string()).unwrap()).unwrap();
+            for (id, name) in [(1, "ann"), (2, "bob"), (3, "cid")] {
+                executor.execute(Query::from(format!("INSERT INTO users VALUES ({}, {});", id, name)).unwrap()).unwrap();
+            }
+            for (owner_id, total) in [(1, 10), (3, 50), (3, 70)] {
+                executor.execute(Query::from(format!("INSERT INTO orders VALUES ({}, {});", owner_id, total)).unwrap()).unwrap();
+            }
+            let names = |query : &str| -> Vec<String> {
+                let mut names : Vec<String> = vec![];
+                let mut next = executor.execute(Query::from(query.to_string()).unwrap()).unwrap().cursor;
+                while let Some((hash, row)) = next {
+                    names.push(row.cols[0].to_string());
+                    next = executor.next(hash.clone()).unwrap().map(|row| (hash, row));
+                }
+                names.sort();
+                names
+            };
+
+            //In matches every value of the subquery, its own predicate and qualified names work
+            assert_eq!(names("SELECT name FROM users WHERE id IN (SELECT owner_id FROM orders);"), vec!["ann", "cid"]);
+            assert_eq!(names("SELECT name FROM users WHERE users.id IN (SELECT orders.owner_id FROM orders WHERE total > 20);"), vec!["cid"]);
+            assert!(names("SELECT name FROM users WHERE id IN (SELECT owner_id FROM orders WHERE total > 100);").is_empty());
+
+            //A comparison needs exactly one value
+            assert_eq!(names("SELECT name FROM users WHERE id < (SELECT owner_id FROM orders WHERE total == 50);"), vec!["ann", "bob"]);
+            assert!(executor.execute(Query::from("SELECT name FROM users WHERE id == (SELECT owner_id FROM orders);".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT name FROM users WHERE id == (SELECT owner_id FROM orders WHERE total > 100);".to_string()).unwrap()).is_err());
+            assert!(executor.execute(Query::from("SELECT name FROM users WHERE id IN (SELECT name FROM users);".to_string()).unwrap()).is_err(), "values of another type should be rejected");
+
+            //Deletes take subqueries as well, also on their own table
+            let result = executor.execute(Query::from("DELETE FROM users WHERE id IN (SELECT owner_id FROM orders WHERE total < 60);".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(2));
+            executor.execute(Query::from("DELETE FROM users WHERE id == (SELECT id FROM users WHERE name == bob);".to_string()).unwrap()).unwrap();
+            assert!(names("SELECT name FROM users;").is_empty());
+            assert_eq!(executor.cursor_count().unwrap(), 0, "subqueries should not leave cursors open");
+        }
+
+
+
+        #[test]
+        fn drop_column_test() {
+            let path = get_test_path().unwrap().join("drop_column");
+            let _ = delete_dir(&path);
+            create_dir(&path).unwrap();
+            let executor = Executor::new(path.clone()).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER AUTO_INCREMENT, name TEXT, age NUMBER CHECK (age > 0), note TEXT);".to_string()).unwrap()).unwrap();
+            for (name, age) in [("a", 1), ("b", 2), ("c", 3)] {
+                executor.execute(Query::from(format!("INSERT INTO t (name, age, note) VALUES ({}, {}, x);", name, age)).unwrap()).unwrap();
+            }
+            assert!(executor.execute(Query::from("ALTER TABLE t DROP COLUMN age;".to_string()).unwrap()).is_err(), "cols with checks should not be dropped");
+            assert!(executor.execute(Query::from("ALTER TABLE t DROP COLUMN id;".to_string()).unwrap()).is_err(), "auto increment cols should not be dropped");
+            assert!(executor.execute(Query::from("ALTER TABLE t DROP COLUMN missing;".to_string()).unwrap()).is_err());
+            executor.execute(Query::from("ALTER TABLE t DROP COLUMN note;".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("SELECT note FROM t;".to_string()).unwrap()).is_err());
+            drop(executor);
+
+            //The remaining cols keep their values after a restart and the checks still apply
+            let executor = Executor::new(path).unwrap();
+            let (hash, row) = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            let mut rows = vec![row];
+            while let Some(row) = executor.next(hash.clone()).unwrap() {
+                rows.push(row);
+            }
+            assert_eq!(rows.len(), 3);
+            for row in rows {
+                assert_eq!(row.cols.len(), 3);
+            }
+            let (_, row) = executor.execute(Query::from("SELECT id, name, age FROM t WHERE name == b;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert!(row.cols.contains(&Value::new_number(2)) && row.cols.contains(&Value::new_text("b".to_string())));
+            assert!(executor.execute(Query::from("INSERT INTO t (name, age) VALUES (d, 0);".to_string()).unwrap()).is_err());
+            executor.execute(Query::from("CREATE TABLE u (a NUMBER);".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("ALTER TABLE u DROP COLUMN a;".to_string()).unwrap()).is_err(), "the last col should not be dropped");
+        }
+
+
+
+        #[test]
+        fn in_memory_test() {
+            let executor = Executor::with_storage(PathBuf::from("memory"), DEFAULT_PAGE_SIZE, Storage::Memory).unwrap();
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER AUTO_INCREMENT, name TEXT, note TEXT CHECK (note != x));".to_string()).unwrap()).unwrap();
+            for name in ["a", "b", "c"] {
+                executor.execute(Query::from(format!("INSERT INTO t (name, note) VALUES ({}, y);", name)).unwrap()).unwrap();
+            }
+            assert!(executor.execute(Query::from("INSERT INTO t (name, note) VALUES (d, x);".to_string()).unwrap()).is_err());
+            executor.execute(Query::from("RENAME TABLE t TO u;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("DELETE FROM u WHERE id == 2;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("ALTER TABLE u DROP COLUMN name;".to_string()).unwrap()).unwrap();
+            assert!(executor.execute(Query::from("INSERT INTO u (note) VALUES (x);".to_string()).unwrap()).is_err(), "checks should survive renaming and dropping cols");
+            let (hash, row) = executor.execute(Query::from("SELECT id FROM u;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            let mut rows = vec![row];
+            while let Some(row) = executor.next(hash.clone()).unwrap() {
+                rows.push(row);
+            }
+            assert_eq!(rows.into_iter().map(|r| r.cols).collect::<Vec<_>>(), vec![vec![Value::new_number(1)], vec![Value::new_number(3)]]);
+            let result = executor.execute(Query::from("DROP TABLE u;".to_string()).unwrap()).unwrap();
+            assert!(result.warnings.is_empty(), "dropping a table in memory should not touch the disk");
+        }
+
+
+
+        #[test]
+        fn export_test() {
+            let executor = test_executor("export");
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER, name TEXT, data BLOB);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO t VALUES (1, 'Smith, J', 00ff);".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("INSERT INTO t VALUES (-2, b, 01);".to_string()).unwrap()).unwrap();
+            let path = get_test_path().unwrap().join("export.csv");
+            let result = executor.execute(Query::from(format!("EXPORT t TO '{}';", path.display())).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(2));
+            let csv = std::fs::read_to_string(&path).unwrap();
+            let mut lines : Vec<&str> = csv.lines().collect();
+            assert_eq!(lines.remove(0), "id,name,data", "the header should list the cols in the order they were created");
+            lines.sort();
+            assert_eq!(lines, vec!["-2,b,01", "1,\"Smith, J\",00ff"]);
+            assert!(executor.export("missing", &path).is_err(), "exporting a missing table should fail");
+            std::fs::remove_file(&path).unwrap();
+        }
+
+
+
+        #[test]
+        fn import_test() {
+            let executor = test_executor("import");
+            executor.execute(Query::from("CREATE TABLE t (id NUMBER AUTO_INCREMENT, name TEXT, age NUMBER CHECK (age >= 0));".to_string()).unwrap()).unwrap();
+            let path = get_test_path().unwrap().join("import.csv");
+
+            //Quoted fields keep separators and line breaks, the auto increment col is filled in
+            std::fs::write(&path, "name,age\r\n\"Smith, J\",30\r\n\"a\nb\",4\r\n\r\n").unwrap();
+            let result = executor.execute(Query::from(format!("IMPORT '{}' INTO t;", path.display())).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(2));
+            let result = executor.execute(Query::from("SELECT id FROM t WHERE name == 'Smith, J';".to_string()).unwrap()).unwrap();
+            assert_eq!(result.cursor.unwrap().1.cols, vec![Value::new_number(1)]);
+            assert!(executor.execute(Query::from("SELECT * FROM t WHERE name == 'a\nb';".to_string()).unwrap()).unwrap().cursor.is_some());
+
+            //Invalid rows abort the import before anything is inserted
+            std::fs::write(&path, "name,age\nc,1\nd\ne,x\n").unwrap();
+            let error = executor.import(&path, "t", false, &mut vec![]).err().unwrap();
+            assert!(error.to_string().contains("line 3"), "the error should name the line of the first invalid row");
+            assert!(executor.execute(Query::from("SELECT * FROM t WHERE name == c;".to_string()).unwrap()).unwrap().cursor.is_none());
+
+            //Or are skipped, including rows violating a check
+            std::fs::write(&path, "name,age\nc,1\nd\ne,x\nf,-1\n").unwrap();
+            let result = executor.execute(Query::from(format!("IMPORT '{}' INTO t SKIP INVALID;", path.display())).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(1));
+            assert_eq!(result.warnings.len(), 3);
+            assert!(result.warnings[2].starts_with("skipped line 5"));
+
+            //The header has to name cols of the table
+            std::fs::write(&path, "name,height\nc,1\n").unwrap();
+            assert!(executor.import(&path, "t", true, &mut vec![]).is_err(), "unknown cols in the header should be rejected");
+            std::fs::remove_file(&path).unwrap();
+        }
+
+
+
+        #[test]
+        fn blob_test() {
+            let executor = test_executor("blob");
+            executor.execute(Query::from("CREATE TABLE t (data BLOB);".to_string()).unwrap()).unwrap();
+            let hash = executor.prepare(Query::from("INSERT INTO t VALUES (?);".to_string()).unwrap()).unwrap();
+            executor.execute_prepared(hash, vec![hex::encode([0u8, 255, 10])]).unwrap();
+            let (_, row) = executor.execute(Query::from("SELECT * FROM t WHERE data == 00ff0a;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_blob(vec![0, 255, 10])]);
+            assert!(executor.execute(Query::from("SELECT * FROM t WHERE data < 00;".to_string()).unwrap()).is_err(), "blobs should only support equality");
+        }
+
+
+
+        #[test]
+        fn negative_number_test() {
+            let executor = test_executor("negative_number");
+            executor.execute(Query::from("CREATE TABLE t (n NUMBER);".to_string()).unwrap()).unwrap();
+            for i in [-5, 3, -1] {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            let result = executor.execute(Query::from("DELETE FROM t WHERE n < 0;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.affected, Some(2));
+            let (_, row) = executor.execute(Query::from("SELECT * FROM t WHERE n > -1;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols, vec![Value::new_number(3)]);
+        }
+
+
+
+        #[test]
+        fn analyze_test() {
+            let executor = test_executor("analyze");
+            executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES ({});", i)).unwrap()).unwrap();
+            }
+            assert_eq!(executor.statistics.lock().unwrap()["t"].row_count, 3);
+
+            //Deletes are not tracked so the row count is stale afterwards
+            executor.execute(Query::from("DELETE FROM t WHERE a < 2;".to_string()).unwrap()).unwrap();
+            assert_eq!(executor.statistics.lock().unwrap()["t"].row_count, 3);
+            let result = executor.execute(Query::from("ANALYZE t;".to_string()).unwrap()).unwrap();
+            assert_eq!(result.message, Some("rows: 1; a: min 2, max 2, distinct 1".to_string()));
+            assert_eq!(executor.statistics.lock().unwrap()["t"].row_count, 1);
+
+            //The analyzed statistics are persisted
+            let path = executor.db_path.clone();
+            drop(executor);
+            let executor = Executor::new(path).unwrap();
+            let statistics = executor.statistics.lock().unwrap()["t"].clone();
+            assert_eq!(statistics.row_count, 1);
+            assert_eq!(statistics.columns[0].min, Some(Value::new_number(2)));
+        }
+
+
+
+        #[test]
+        fn explain_test() {
+            let executor = test_executor("explain");
+            executor.execute(Query::from("CREATE TABLE t (name TEXT, age NUMBER);".to_string()).unwrap()).unwrap();
+            for i in 0..3 {
+                executor.execute(Query::from(format!("INSERT INTO t VALUES (n{}, {});", i, i)).unwrap()).unwrap();
+            }
+            let explain = |query : &str| -> String {
+                let result = executor.execute(Query::from(query.to_string()).unwrap()).unwrap();
+                assert_eq!(result.columns, vec!["plan".to_string()]);
+                let (hash, row) = result.cursor.unwrap();
+                assert!(executor.next(hash).unwrap().is_none(), "the plan should be a single row");
+                row.cols[0].to_string()
+            };
+            assert_eq!(explain("EXPLAIN SELECT name FROM t WHERE age > 1;"), "full scan of t, where age > 1, estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT name FROM t ORDER BY age DESC, name;"), "full scan of t, sort by age desc, name asc in memory, estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT DISTINCT name FROM t;"), "full scan of t, distinct, estimated rows 3");
+            assert_eq!(explain("EXPLAIN DELETE FROM t;"), "full scan of t, estimated rows 3");
+            assert_eq!(explain("EXPLAIN EXPORT t TO 't.csv';"), "full scan of t, estimated rows 3");
+            assert_eq!(explain("EXPLAIN CREATE TABLE u AS SELECT name FROM t WHERE age == 0;"), "full scan of t, where age == 0, estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT * FROM t WHERE name IN (SELECT name FROM t WHERE age == 0);"), "full scan of t, full scan of t for the subquery, where name in (select name from t where age == 0), estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT COUNT(*) FROM t;"), "row count of t, estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT * FROM t WHERE name ==* 'N1';"), "full scan of t, where name ==* N1, estimated rows 3");
+            assert_eq!(explain("EXPLAIN SELECT COUNT(*) FROM t WHERE age > 0;"), "full scan of t, where age > 0, estimated rows 3");
+            assert_eq!(explain("EXPLAIN INSERT INTO t VALUES (x, 9);"), "insert of t without a scan");
+
+            //Explained statements are not executed
+            let (_, row) = executor.execute(Query::from("SELECT COUNT(*) FROM t;".to_string()).unwrap()).unwrap().cursor.unwrap();
+            assert_eq!(row.cols[0], Value::new_number(3));
+
+            //The estimate comes from the statistics once the table was analyzed
+            executor.execute(Query::from("ANALYZE t;".to_string()).unwrap()).unwrap();
+            executor.execute(Query::from("DELETE FROM t WHERE age == 0;".to_string()).unwrap()).unwrap();
+            assert_eq!(explain("EXPLAIN SELECT EXISTS FROM t;"), "full scan of t, stops at the first match, estimated rows 3");
+            assert!(executor.execute(Query::from("EXPLAIN SELECT * FROM u;".to_string()).unwrap()).is_err());
+        }
 
 
-    #[cfg(test)]
-    pub mod test {
 
     }
 
@@ -5,14 +5,25 @@ mod executor;
 mod schema;
 mod server;
 mod cli;
+mod error;
 use std::thread;
 
 fn main() {
 
+    //Log output is filtered by RUST_LOG, errors and warnings are shown by default
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
     //Server is started first so the connection by the cli_thread can be accepted.
     let server = server::Server::new(); 
-    let cli_thread = thread::spawn(|| cli::start_cli());
-    server.start(10).expect("failed to start server");
+    let session_key = server.get_session_key();
+    let (address, admin_address) = server.cli_addresses();
+    let cli_thread = thread::spawn(move || cli::start_cli(session_key, address, admin_address));
+
+    //Start only returns once the server was shut down and its data was written
+    if let Err(e) = server.start(10) {
+        log::error!("server stopped with error: {}", e);
+        std::process::exit(1);
+    }
     let _ = cli_thread.join();
 }
 
@@ -4,9 +4,10 @@ pub mod parsing {
 
 
 
-    use crate::storage::table_management::Type;
-    use std::{io::{Result, ErrorKind, Error}, collections::hash_map::HashMap};
+    use crate::{error::{recover, ErrorCode}, storage::table_management::Type};
+    use std::{io::{Result, ErrorKind, Error}, collections::{hash_map::HashMap, VecDeque}, sync::Mutex};
     use regex::Regex;
+    use log::debug;
 
 
 
@@ -192,25 +193,67 @@ pub mod parsing {
 
     pub const COMMAND_KEY : &str = "command";
     pub const CREATE : &str = "create";
+    pub const CREATE_AS : &str = "create_as";
     pub const DROP : &str = "drop";
+    pub const RENAME : &str = "rename";
+    pub const DROP_COLUMN : &str = "drop_column";
     pub const INSERT : &str = "insert";
     pub const SELECT : &str = "select";
+    pub const COUNT : &str = "count";
+    pub const EXISTS : &str = "exists";
     pub const DELETE : &str = "delete";
+    pub const VACUUM : &str = "vacuum";
+    pub const ANALYZE : &str = "analyze";
+    pub const EXPLAIN : &str = "explain";
+    pub const DESCRIBE : &str = "describe";
+    pub const EXPORT : &str = "export";
+    pub const IMPORT : &str = "import";
+    pub const SKIP_INVALID : &str = "skip_invalid";
+    pub const DISTINCT : &str = "distinct";
     pub const TABLE_NAME_KEY : &str = "table_name";
+    pub const NEW_TABLE_NAME_KEY : &str = "new_table_name";
+    pub const FILE_PATH_KEY : &str = "file_path";
     pub const COLUMN_NAME_KEY : &str = "column_name";
     pub const COLUMN_TYPE_KEY : &str = "column_type";
     pub const COLUMN_VALUE_KEY : &str = "column_value";
     pub const NUMBER : &str = "number";
     pub const TEXT : &str = "text";
+    pub const BLOB : &str = "blob";
+    pub const AUTO_INCREMENT : &str = "auto_increment";
+    pub const CHECK_COL : &str = "check_col";
+    pub const CHECK_VAL : &str = "check_val";
+    pub const REFERENCE_TABLE : &str = "reference_table";
+    pub const REFERENCE_COL : &str = "reference_col";
+    pub const NOT_NULL : &str = "not_null";
     pub const OPERATOR_KEY : &str = "operator";
     pub const EQUAL : &str = "equal";
     pub const NOT_EQUAL : &str = "not_equal";
+    pub const EQUAL_IGNORE_CASE : &str = "equal_ignore_case";
     pub const SMALLER : &str = "less";
     pub const SMALLER_EQUAL : &str = "less_equal";
     pub const BIGGER : &str = "bigger";
     pub const BIGGER_EQUAL : &str = "bigger_equal";
+    pub const IN : &str = "in";
     pub const PREDICATE_COL : &str = "predicate_col";
     pub const PREDICATE_VAL : &str = "predicate_val";
+    pub const SUBQUERY_TABLE : &str = "subquery_table";
+    pub const SUBQUERY_COL : &str = "subquery_col";
+    pub const SUBQUERY_PREDICATE_COL : &str = "subquery_predicate_col";
+    pub const SUBQUERY_OPERATOR : &str = "subquery_operator";
+    pub const SUBQUERY_PREDICATE_VAL : &str = "subquery_predicate_val";
+    pub const FUNCTION_KEY : &str = "function";
+    pub const NO_FUNCTION : &str = "no_function";
+    pub const UPPER : &str = "upper";
+    pub const LOWER : &str = "lower";
+    pub const LENGTH : &str = "length";
+    pub const ORDER_COL : &str = "order_col";
+    pub const ORDER_DIRECTION : &str = "order_direction";
+    pub const ASCENDING : &str = "asc";
+    pub const DESCENDING : &str = "desc";
+    pub const PLACEHOLDER : &str = "?";
+
+    //Keys whose values may be replaced by placeholders
+    const VALUE_KEYS : [&str; 3] = [COLUMN_VALUE_KEY, PREDICATE_VAL, SUBQUERY_PREDICATE_VAL];
 
 
 
@@ -219,6 +262,111 @@ pub mod parsing {
 
 
 
+    ///Splits a script into statements at the semicolons that are not quoted. Each statement keeps its
+    ///semicolon, empty statements are left out
+    pub fn split_statements(script : &str) -> Vec<String> {
+        let mut statements : Vec<String> = vec![];
+        let mut current = String::new();
+        let mut quoted = false;
+        for c in script.chars() {
+            current.push(c);
+            match c {
+                '\'' => quoted = !quoted,
+                ';' if !quoted => {
+                    if current.trim() != ";" {
+                        statements.push(current.trim().to_string());
+                    }
+                    current.clear();
+                },
+                _ => (),
+            }
+        }
+
+        //A last statement without a semicolon is still kept so parsing it reports the error
+        if !current.trim().is_empty() {
+            statements.push(current.trim().to_string());
+        }
+        statements
+    }
+
+
+
+    ///Adds the position of a statement in a batch to its error, the code of the error is kept
+    pub fn in_statement(index : usize, error : Error) -> Error {
+        ErrorCode::of(&error).error(error.kind(), format!("statement {}: {}", index + 1, error))
+    }
+
+
+
+    ///The kind of command a query represents
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CommandKind {
+        Create,
+        CreateAs,
+        Drop,
+        Rename,
+        DropColumn,
+        Insert,
+        Select,
+        Count,
+        Exists,
+        Delete,
+        Vacuum,
+        Analyze,
+        Describe,
+        Export,
+        Import,
+        Explain,
+    }
+
+
+
+    impl CommandKind {
+
+
+        ///Returns true if the command may change the data or the structure of a database
+        pub fn is_mutation(&self) -> bool {
+            !matches!(self, CommandKind::Select | CommandKind::Count | CommandKind::Exists | CommandKind::Describe | CommandKind::Export | CommandKind::Explain)
+        }
+
+
+    }
+
+
+
+    impl TryFrom<&str> for CommandKind {
+
+
+        type Error = std::io::Error;
+
+
+        fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+            Ok(match value {
+                CREATE => Self::Create,
+                CREATE_AS => Self::CreateAs,
+                DROP => Self::Drop,
+                RENAME => Self::Rename,
+                DROP_COLUMN => Self::DropColumn,
+                INSERT => Self::Insert,
+                SELECT => Self::Select,
+                COUNT => Self::Count,
+                EXISTS => Self::Exists,
+                DELETE => Self::Delete,
+                VACUUM => Self::Vacuum,
+                ANALYZE => Self::Analyze,
+                DESCRIBE => Self::Describe,
+                EXPORT => Self::Export,
+                IMPORT => Self::Import,
+                EXPLAIN => Self::Explain,
+                x => return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not represent a command", x))),
+            })
+        }
+
+
+    }
+
+
+
     #[derive(Debug, Clone)]
     pub struct Query {
         pub plan: HashMap<String, Vec<String>>
@@ -232,13 +380,39 @@ pub mod parsing {
         pub fn from(q: String) -> std::io::Result<Query> {
 
             //Definition of all possible SQL commands
-            let data_type : Symbol = o(vec![w(t("text"), COLUMN_TYPE_KEY, TEXT), w(t("number"), COLUMN_TYPE_KEY, NUMBER)]);
+            let data_type : Symbol = o(vec![w(t("text"), COLUMN_TYPE_KEY, TEXT), w(t("number"), COLUMN_TYPE_KEY, NUMBER), w(t("blob"), COLUMN_TYPE_KEY, BLOB), w(s(vec![t("number"), t("auto_increment")]), COLUMN_TYPE_KEY, AUTO_INCREMENT)]);
+
+            let operator_with_key = |key : &str| -> Symbol {
+                o(vec![
+                    w(t("=="), key, EQUAL), 
+                    w(t("!="), key, NOT_EQUAL), 
+                    w(t("==*"), key, EQUAL_IGNORE_CASE), 
+                    w(t("<"), key, SMALLER), 
+                    w(t("<="), key, SMALLER_EQUAL), 
+                    w(t(">"), key, BIGGER), 
+                    w(t(">="), key, BIGGER_EQUAL)])
+            };
+            let operator : Symbol = operator_with_key(OPERATOR_KEY);
+
+            //Every col adds a not null flag, which is empty if the col may be null, so the flags line
+            //up with the col names
+            let not_null : Symbol = o(vec![w(s(vec![]), NOT_NULL, ""), w(s(vec![t("not"), t("null")]), NOT_NULL, NOT_NULL)]);
+
+            //A check compares a col with a literal, its operator is stored under the same key as
+            //the one of a predicate, which can not appear in the same query
+            let check : Symbol = o(vec![s(vec![]), s(vec![t("check"), t("("), v(CHECK_COL), operator.clone(), v(CHECK_VAL), t(")")])]);
+
+            //Cols without a foreign key add an empty reference table, so the reference tables line
+            //up with the col names
+            let reference : Symbol = o(vec![w(s(vec![]), REFERENCE_TABLE, ""), s(vec![t("references"), v(REFERENCE_TABLE), t("("), v(REFERENCE_COL), t(")")])]);
+
+            let col_type : Symbol = s(vec![data_type, not_null, check, reference]);
 
             let col_data : Symbol = o(vec![
-                s(vec![v(COLUMN_NAME_KEY), data_type.clone()]), 
+                s(vec![v(COLUMN_NAME_KEY), col_type.clone()]), 
                 s(vec![r(
-                        s(vec![v(COLUMN_NAME_KEY), data_type.clone(), t(",")])),
-                        s(vec![v(COLUMN_NAME_KEY), data_type])])]);
+                        s(vec![v(COLUMN_NAME_KEY), col_type.clone(), t(",")])),
+                        s(vec![v(COLUMN_NAME_KEY), col_type])])]);
 
             let create_table : Symbol = w(s(vec![t("create"), t("table"), v(TABLE_NAME_KEY), t("("), col_data, t(")")]), COMMAND_KEY, CREATE);
 
@@ -252,32 +426,156 @@ pub mod parsing {
 
             let insert : Symbol = w(s(vec![t("insert"), t("into"), v(TABLE_NAME_KEY), insert_values]), COMMAND_KEY, INSERT);
 
-            let operator : Symbol = o(vec![
-                w(t("=="), OPERATOR_KEY, EQUAL), 
-                w(t("!="), OPERATOR_KEY, NOT_EQUAL), 
-                w(t("<"), OPERATOR_KEY, SMALLER), 
-                w(t("<="), OPERATOR_KEY, SMALLER_EQUAL), 
-                w(t(">"), OPERATOR_KEY, BIGGER), 
-                w(t(">="), OPERATOR_KEY, BIGGER_EQUAL)]);
+            //A subquery selects one col of another table. Its keys differ from the ones of the outer
+            //query, so both can be stored in the same plan
+            let subquery_predicate : Symbol = o(vec![s(vec![]), s(vec![t("where"), v(SUBQUERY_PREDICATE_COL), operator_with_key(SUBQUERY_OPERATOR), v(SUBQUERY_PREDICATE_VAL)])]);
+            let subquery : Symbol = s(vec![t("("), t("select"), v(SUBQUERY_COL), t("from"), v(SUBQUERY_TABLE), subquery_predicate, t(")")]);
 
-            let predicate : Symbol = o(vec![s(vec![]), s(vec![t("where"), v(PREDICATE_COL), operator.clone(), v(PREDICATE_VAL)])]);
+            //The right side of a comparison is a value or a subquery that returns one value, in
+            //compares with all values a subquery returns
+            let predicate : Symbol = o(vec![
+                s(vec![]), 
+                s(vec![t("where"), v(PREDICATE_COL), operator.clone(), v(PREDICATE_VAL)]),
+                s(vec![t("where"), v(PREDICATE_COL), operator.clone(), subquery.clone()]),
+                s(vec![t("where"), v(PREDICATE_COL), w(t("in"), OPERATOR_KEY, IN), subquery])]);
 
             let columns : Symbol = o(vec![t("*"), v(COLUMN_NAME_KEY), s(vec![r(s(vec![v(COLUMN_NAME_KEY), t(",")])), v(COLUMN_NAME_KEY)])]);
 
-            let select : Symbol = w(s(vec![t("select"), columns, t("from"), v(TABLE_NAME_KEY), predicate.clone()]), COMMAND_KEY, SELECT);
+            let distinct : Symbol = o(vec![s(vec![]), w(t("distinct"), DISTINCT, DISTINCT)]);
+
+            //A select may apply a function to each col. Plain cols get no function, so the
+            //functions line up with the cols
+            let function : Symbol = o(vec![w(t("upper"), FUNCTION_KEY, UPPER), w(t("lower"), FUNCTION_KEY, LOWER), w(t("length"), FUNCTION_KEY, LENGTH)]);
+            let projection : Symbol = o(vec![s(vec![function, t("("), v(COLUMN_NAME_KEY), t(")")]), w(v(COLUMN_NAME_KEY), FUNCTION_KEY, NO_FUNCTION)]);
+            let projections : Symbol = o(vec![t("*"), s(vec![r(s(vec![projection.clone(), t(",")])), projection])]);
+
+            //Every order col gets a direction, so the directions line up with the cols even if some
+            //are left out
+            let direction : Symbol = o(vec![w(t("asc"), ORDER_DIRECTION, ASCENDING), w(t("desc"), ORDER_DIRECTION, DESCENDING), w(s(vec![]), ORDER_DIRECTION, ASCENDING)]);
+            let order_key : Symbol = s(vec![v(ORDER_COL), direction]);
+            let order : Symbol = o(vec![s(vec![]), s(vec![t("order"), t("by"), r(s(vec![order_key.clone(), t(",")])), order_key])]);
+
+            let select : Symbol = w(s(vec![t("select"), distinct.clone(), projections, t("from"), v(TABLE_NAME_KEY), predicate.clone(), order]), COMMAND_KEY, SELECT);
+
+            //Counting all rows of a table can be answered without a scan, so it is its own command
+            //instead of an aggregate of select
+            let count : Symbol = w(s(vec![t("select"), t("count"), t("("), t("*"), t(")"), t("from"), v(TABLE_NAME_KEY), predicate.clone()]), COMMAND_KEY, COUNT);
+
+            //Only tells whether a row matches, so the scan stops at the first one
+            let exists : Symbol = w(s(vec![t("select"), t("exists"), t("from"), v(TABLE_NAME_KEY), predicate.clone()]), COMMAND_KEY, EXISTS);
+
+            //The new table is named like the target of a rename, the table name belongs to the select
+            let create_as : Symbol = w(s(vec![t("create"), t("table"), v(NEW_TABLE_NAME_KEY), t("as"), t("select"), distinct, columns, t("from"), v(TABLE_NAME_KEY), predicate.clone()]), COMMAND_KEY, CREATE_AS);
 
             let delete : Symbol = w(s(vec![t("delete"), t("from"), v(TABLE_NAME_KEY), predicate.clone()]), COMMAND_KEY, DELETE);
 
-            let query : Symbol = s(vec![o(vec![create_table, drop_table, insert, select, delete]), t(";")]);
+            let rename_table : Symbol = w(s(vec![t("rename"), t("table"), v(TABLE_NAME_KEY), t("to"), v(NEW_TABLE_NAME_KEY)]), COMMAND_KEY, RENAME);
+
+            let drop_column : Symbol = w(s(vec![t("alter"), t("table"), v(TABLE_NAME_KEY), t("drop"), t("column"), v(COLUMN_NAME_KEY)]), COMMAND_KEY, DROP_COLUMN);
+
+            let vacuum : Symbol = w(s(vec![t("vacuum"), v(TABLE_NAME_KEY)]), COMMAND_KEY, VACUUM);
+
+            let analyze : Symbol = w(s(vec![t("analyze"), v(TABLE_NAME_KEY)]), COMMAND_KEY, ANALYZE);
+
+            let describe : Symbol = w(s(vec![t("describe"), v(TABLE_NAME_KEY)]), COMMAND_KEY, DESCRIBE);
+
+            let export : Symbol = w(s(vec![t("export"), v(TABLE_NAME_KEY), t("to"), v(FILE_PATH_KEY)]), COMMAND_KEY, EXPORT);
+
+            //Rows that can not be imported abort the import unless they should be skipped
+            let skip_invalid : Symbol = o(vec![s(vec![]), w(s(vec![t("skip"), t("invalid")]), SKIP_INVALID, SKIP_INVALID)]);
 
-            //Split query string to create input for bnf solver
-            let regex = Regex::new(r"\w+|[();,*]|>=|>|==|!=|<|<=").unwrap();
-            let mut input : Vec<String> = regex.find_iter(&q.to_lowercase()).map(|x| {x.as_str()}).map(|x| {x.to_string()}).collect();
+            let import : Symbol = w(s(vec![t("import"), v(FILE_PATH_KEY), t("into"), v(TABLE_NAME_KEY), skip_invalid]), COMMAND_KEY, IMPORT);
+
+            //Explain wraps any statement, which keeps its own command so it can be described
+            //instead of executed
+            let explain : Symbol = o(vec![s(vec![]), w(t("explain"), EXPLAIN, EXPLAIN)]);
+
+            let query : Symbol = s(vec![explain, o(vec![create_table, create_as, drop_table, rename_table, drop_column, insert, count, exists, select, delete, vacuum, analyze, describe, export, import]), t(";")]);
+
+            //Split query string to create input for bnf solver. Negative numbers and qualified
+            //names like users.id are kept as one token. Placeholders are numbered in the order they
+            //appear, e.g. ?0, ?1, so they can be told apart after solving. Text in single quotes,
+            //like a file path, is one token that keeps its case and loses its quotes. The case
+            //insensitive ==* comes before == and *, so it is not split up
+            let regex = Regex::new(r"'[^']*'|-\d+|\w+(\.\w+)?|==\*|[();,*?]|>=|>|==|!=|<|<=").unwrap();
+            let mut placeholder_count = 0;
+            let mut input : Vec<String> = regex.find_iter(&q).map(|x| {x.as_str()}).map(|x| {
+                if x == PLACEHOLDER {
+                    placeholder_count += 1;
+                    return format!("{}{}", PLACEHOLDER, placeholder_count - 1);
+                }
+                if x.len() >= 2 && x.starts_with('\'') && x.ends_with('\'') {
+                    return x[1..x.len() - 1].to_string();
+                }
+                x.to_lowercase()
+            }).collect();
             input.reverse();
 
             //Solve
-            let plan = bnf::solve(vec![query], input).map_err(|e|{Error::new(ErrorKind::InvalidInput, e.0.to_string())});
-            return Ok(Query {plan: plan?});
+            let plan = bnf::solve(vec![query], input).map_err(|e|{
+                debug!("failed to parse query: {}", e.0);
+                ErrorCode::Parse.error(ErrorKind::InvalidInput, e.0.to_string())
+            })?;
+
+            //Placeholders can only stand for values, not for names or keywords
+            for (key, values) in plan.iter() {
+                if !VALUE_KEYS.contains(&key.as_str()) && values.iter().any(|v| v.starts_with(PLACEHOLDER)) {
+                    return Err(ErrorCode::Parse.error(ErrorKind::InvalidInput, "placeholders are only allowed in place of values"));
+                }
+            }
+            debug!("parsed query into plan {:?}", plan);
+            Ok(Query {plan})
+        }
+
+
+        ///Parses a string of statements separated by semicolons in the order they appear. If there
+        ///are several, an error names the statement it belongs to
+        pub fn batch(q : String) -> Result<Vec<Query>> {
+            let statements = split_statements(&q);
+            if statements.len() <= 1 {
+                return Ok(vec![Query::from(q)?]);
+            }
+            statements.into_iter().enumerate().map(|(i, statement)| Query::from(statement).map_err(|e| in_statement(i, e))).collect()
+        }
+
+
+        ///Returns the number of placeholders that have to be bound before the query can be
+        ///executed
+        pub fn placeholder_count(&self) -> usize {
+            VALUE_KEYS.iter().filter_map(|key| self.plan.get(*key)).flatten().filter(|v| v.starts_with(PLACEHOLDER)).count()
+        }
+
+
+        ///Returns a copy of the query with the placeholders replaced by params, the first param
+        ///replaces the first placeholder of the query string and so on. The params are not
+        ///parsed, so they can contain any character.
+        pub fn bind(&self, params : Vec<String>) -> Result<Query> {
+            if params.len() != self.placeholder_count() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("query has {} placeholders but {} params were given", self.placeholder_count(), params.len())));
+            }
+            let mut plan = self.plan.clone();
+            for key in VALUE_KEYS {
+                if let Some(values) = plan.get_mut(key) {
+                    for value in values.iter_mut() {
+                        if let Some(index) = value.strip_prefix(PLACEHOLDER) {
+                            let index : usize = index.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid placeholder"))?;
+                            *value = params.get(index).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid placeholder"))?.clone();
+                        }
+                    }
+                }
+            }
+            Ok(Query {plan})
+        }
+
+
+        ///Returns the kind of command this query represents. An explained query is an explain, no
+        ///matter which statement it wraps
+        pub fn command(&self) -> Result<CommandKind> {
+            if self.plan.contains_key(EXPLAIN) {
+                return Ok(CommandKind::Explain);
+            }
+            let command = self.plan.get(COMMAND_KEY).ok_or_else(||{Error::new(ErrorKind::InvalidInput, "query was not valid")})?.first().ok_or_else(||{Error::new(ErrorKind::InvalidInput, "command was empty")})?;
+            CommandKind::try_from(command.as_str())
         }
 
 
@@ -287,6 +585,67 @@ pub mod parsing {
 
 
 
+    //Parsed statements by query string along with the query strings from the least to the most
+    //recently used
+    type CachedQueries = (HashMap<String, Vec<Query>>, VecDeque<String>);
+
+
+
+    ///Keeps the parsed statements of the most recently used query strings, so repeated queries
+    ///skip parsing. Queries that do not parse are not kept
+    pub struct QueryCache {
+        capacity : usize,
+        entries : Mutex<CachedQueries>,
+    }
+
+
+
+    impl QueryCache {
+
+
+        ///Creates a cache that keeps up to capacity query strings, a capacity of 0 disables it
+        pub fn new(capacity : usize) -> QueryCache {
+            QueryCache{capacity, entries: Mutex::new((HashMap::new(), VecDeque::new()))}
+        }
+
+
+        ///Like Query::batch, but returns the statements of a cached query string without parsing
+        ///it again. The least recently used query string is evicted once the cache is full
+        pub fn batch(&self, q : String) -> Result<Vec<Query>> {
+            if self.capacity == 0 {
+                return Query::batch(q);
+            }
+            let mut entries = recover(self.entries.lock(), "query cache");
+            let (queries, order) = &mut *entries;
+            if let Some(cached) = queries.get(&q) {
+                let cached = cached.clone();
+                order.retain(|key| *key != q);
+                order.push_back(q);
+                return Ok(cached);
+            }
+
+            //Parsing may take a while, so other queries are not held up meanwhile
+            drop(entries);
+            let parsed = Query::batch(q.clone())?;
+            let mut entries = recover(self.entries.lock(), "query cache");
+            let (queries, order) = &mut *entries;
+            if queries.insert(q.clone(), parsed.clone()).is_some() {
+                order.retain(|key| *key != q);
+            }
+            order.push_back(q);
+            while order.len() > self.capacity {
+                if let Some(evicted) = order.pop_front() {
+                    queries.remove(&evicted);
+                }
+            }
+            Ok(parsed)
+        }
+
+
+    }
+
+
+
     #[cfg(test)]
     mod test {
 
@@ -301,6 +660,37 @@ pub mod parsing {
         }
 
 
+        #[test]
+        fn test_valid_create_table_not_null() {
+            let result = Query::from("CREATE TABLE test (id NUMBER AUTO_INCREMENT NOT NULL, name TEXT NOT NULL, age NUMBER);".to_string()).unwrap();
+            assert_eq!(result.plan.get(COLUMN_TYPE_KEY).map(|types| types.len()), Some(3));
+
+            //Cols are listed in reverse
+            assert_eq!(result.plan.get(NOT_NULL), Some(&vec!["".to_string(), NOT_NULL.to_string(), NOT_NULL.to_string()]));
+        }
+
+
+        #[test]
+        fn test_valid_create_table_check() {
+            let result = Query::from("CREATE TABLE test (age NUMBER CHECK (age >= 0), name TEXT NOT NULL CHECK (name != bob));".to_string()).unwrap();
+            assert_eq!(result.plan.get(CHECK_COL).map(|cols| cols.len()), Some(2));
+            assert_eq!(result.plan.get(OPERATOR_KEY).map(|operators| operators.len()), Some(2));
+            assert!(Query::from("CREATE TABLE test (age NUMBER CHECK (age));".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_create_table_references() {
+            let result = Query::from("CREATE TABLE pets (name TEXT, owner_id NUMBER REFERENCES users(id));".to_string()).unwrap();
+            let tables = result.plan.get(REFERENCE_TABLE).unwrap();
+            let names = result.plan.get(COLUMN_NAME_KEY).unwrap();
+            assert_eq!(tables.len(), names.len(), "every col should have a reference table");
+            let index = names.iter().position(|n| n == "owner_id").unwrap();
+            assert_eq!(tables[index], "users");
+            assert_eq!(result.plan.get(REFERENCE_COL), Some(&vec!["id".to_string()]));
+        }
+
+
         #[test]
         fn test_valid_insert_with_columns() {
             let result = Query::from("INSERT INTO test (col1, col2) VALUES (1, 2);".to_string());
@@ -308,6 +698,13 @@ pub mod parsing {
         }
 
 
+        #[test]
+        fn test_valid_insert_negative_number() {
+            let result = Query::from("INSERT INTO test (col1, col2) VALUES (-1, 2);".to_string()).unwrap();
+            assert!(result.plan.get(COLUMN_VALUE_KEY).unwrap().contains(&"-1".to_string()), "negative numbers should be one token");
+        }
+
+
         #[test]
         fn test_valid_select_with_columns() {
             let result = Query::from("SELECT col1, col2 FROM users WHERE age >= 25;".to_string());
@@ -315,6 +712,43 @@ pub mod parsing {
         }
 
 
+        #[test]
+        fn test_valid_select_distinct() {
+            let result = Query::from("SELECT DISTINCT col1 FROM users;".to_string()).unwrap();
+            assert_eq!(result.plan.get(DISTINCT), Some(&vec![DISTINCT.to_string()]));
+            assert_eq!(result.plan.get(COLUMN_NAME_KEY), Some(&vec!["col1".to_string()]));
+            let result = Query::from("SELECT col1 FROM users;".to_string()).unwrap();
+            assert!(!result.plan.contains_key(DISTINCT));
+        }
+
+
+        #[test]
+        fn test_valid_select_qualified() {
+            let result = Query::from("SELECT users.id, name FROM users WHERE users.age >= 25;".to_string()).unwrap();
+            assert_eq!(result.plan.get(COLUMN_NAME_KEY).map(|c| c.contains(&"users.id".to_string())), Some(true));
+            assert_eq!(result.plan.get(PREDICATE_COL), Some(&vec!["users.age".to_string()]));
+        }
+
+
+        #[test]
+        fn test_valid_export() {
+            let result = Query::from("EXPORT Users TO '/tmp/My Users.csv';".to_string()).unwrap();
+            assert_eq!(result.plan.get(TABLE_NAME_KEY), Some(&vec!["users".to_string()]));
+            assert_eq!(result.plan.get(FILE_PATH_KEY), Some(&vec!["/tmp/My Users.csv".to_string()]), "quoted text should keep its case and spaces");
+            assert!(Query::from("EXPORT users;".to_string()).is_err(), "export without a path should return an error");
+        }
+
+
+        #[test]
+        fn test_valid_import() {
+            let result = Query::from("IMPORT 'Data.csv' INTO users;".to_string()).unwrap();
+            assert_eq!(result.plan.get(FILE_PATH_KEY), Some(&vec!["Data.csv".to_string()]));
+            assert!(!result.plan.contains_key(SKIP_INVALID));
+            let result = Query::from("IMPORT 'Data.csv' INTO users SKIP INVALID;".to_string()).unwrap();
+            assert!(result.plan.contains_key(SKIP_INVALID));
+        }
+
+
         #[test]
         fn test_invalid_insert_multiple_values() {
             let result = Query::from("INSERT INTO test VALUES (1, 2, 3) VALUES (4, 5, 6);".to_string());
@@ -399,6 +833,229 @@ pub mod parsing {
         }
 
 
+        #[test]
+        fn test_valid_vacuum() {
+            let result = Query::from("VACUUM users;".to_string());
+            assert!(result.is_ok(), "Valid vacuum query should not return an error");
+            assert_eq!(result.unwrap().plan.get(COMMAND_KEY), Some(&vec![VACUUM.to_string()]));
+        }
+
+
+        #[test]
+        fn test_invalid_vacuum_no_table() {
+            let result = Query::from("VACUUM;".to_string());
+            assert!(result.is_err(), "Vacuum query missing table name should return an error");
+        }
+
+
+        #[test]
+        fn test_command_kind() {
+            let statements = vec![
+                ("CREATE TABLE test (hallo TEXT);", CommandKind::Create),
+                ("CREATE TABLE copy AS SELECT * FROM test;", CommandKind::CreateAs),
+                ("DROP TABLE test;", CommandKind::Drop),
+                ("RENAME TABLE test TO other;", CommandKind::Rename),
+                ("ALTER TABLE test DROP COLUMN hallo;", CommandKind::DropColumn),
+                ("INSERT INTO test VALUES (1);", CommandKind::Insert),
+                ("SELECT * FROM test;", CommandKind::Select),
+                ("SELECT COUNT(*) FROM test;", CommandKind::Count),
+                ("SELECT EXISTS FROM test;", CommandKind::Exists),
+                ("DELETE FROM test;", CommandKind::Delete),
+                ("VACUUM test;", CommandKind::Vacuum),
+                ("ANALYZE test;", CommandKind::Analyze),
+                ("DESCRIBE test;", CommandKind::Describe),
+                ("EXPORT test TO 'test.csv';", CommandKind::Export),
+                ("IMPORT 'test.csv' INTO test;", CommandKind::Import),
+                ("EXPLAIN DELETE FROM test;", CommandKind::Explain),
+            ];
+            for (statement, kind) in statements {
+                let command = Query::from(statement.to_string()).unwrap().command().unwrap();
+                assert_eq!(command, kind, "{} was mapped to the wrong command kind", statement);
+                assert_eq!(command.is_mutation(), !matches!(kind, CommandKind::Select | CommandKind::Count | CommandKind::Exists | CommandKind::Describe | CommandKind::Export | CommandKind::Explain));
+            }
+        }
+
+
+        #[test]
+        fn test_valid_explain() {
+            let result = Query::from("EXPLAIN SELECT name FROM users WHERE age > 18;".to_string()).unwrap();
+            assert_eq!(result.command().unwrap(), CommandKind::Explain);
+            assert_eq!(result.plan.get(COMMAND_KEY), Some(&vec![SELECT.to_string()]), "the wrapped statement should keep its command");
+            assert_eq!(result.plan.get(PREDICATE_COL), Some(&vec!["age".to_string()]));
+            assert!(!Query::from("SELECT * FROM users;".to_string()).unwrap().plan.contains_key(EXPLAIN));
+            assert!(Query::from("EXPLAIN;".to_string()).is_err());
+            assert!(Query::from("EXPLAIN EXPLAIN SELECT * FROM users;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_equal_ignore_case() {
+            let result = Query::from("SELECT * FROM users WHERE name ==* 'Bob';".to_string()).unwrap();
+            assert_eq!(result.plan.get(OPERATOR_KEY), Some(&vec![EQUAL_IGNORE_CASE.to_string()]));
+            assert_eq!(result.plan.get(PREDICATE_VAL), Some(&vec!["Bob".to_string()]));
+            let result = Query::from("SELECT * FROM users WHERE name==*bob;".to_string()).unwrap();
+            assert_eq!(result.plan.get(OPERATOR_KEY), Some(&vec![EQUAL_IGNORE_CASE.to_string()]));
+            let result = Query::from("SELECT * FROM users WHERE name == 'Bob';".to_string()).unwrap();
+            assert_eq!(result.plan.get(OPERATOR_KEY), Some(&vec![EQUAL.to_string()]));
+        }
+
+
+        #[test]
+        fn test_valid_count() {
+            let result = Query::from("SELECT COUNT(*) FROM users WHERE age > 18;".to_string()).unwrap();
+            assert_eq!(result.plan.get(COMMAND_KEY), Some(&vec![COUNT.to_string()]));
+            assert_eq!(result.plan.get(TABLE_NAME_KEY), Some(&vec!["users".to_string()]));
+            assert_eq!(result.plan.get(PREDICATE_COL), Some(&vec!["age".to_string()]));
+            assert!(!result.plan.contains_key(COLUMN_NAME_KEY));
+            let result = Query::from("SELECT count ( * ) FROM users;".to_string()).unwrap();
+            assert_eq!(result.command().unwrap(), CommandKind::Count);
+            assert!(Query::from("SELECT COUNT(name) FROM users;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_exists() {
+            let result = Query::from("SELECT EXISTS FROM users WHERE age > 18;".to_string()).unwrap();
+            assert_eq!(result.command().unwrap(), CommandKind::Exists);
+            assert_eq!(result.plan.get(TABLE_NAME_KEY), Some(&vec!["users".to_string()]));
+            assert_eq!(result.plan.get(PREDICATE_COL), Some(&vec!["age".to_string()]));
+            assert!(!result.plan.contains_key(COLUMN_NAME_KEY));
+            assert!(Query::from("SELECT EXISTS name FROM users;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_order_by() {
+            let result = Query::from("SELECT * FROM users WHERE age > 18 ORDER BY age DESC, name;".to_string()).unwrap();
+            assert_eq!(result.plan.get(PREDICATE_COL), Some(&vec!["age".to_string()]));
+
+            //Like all lists the keys are stored in reverse order
+            assert_eq!(result.plan.get(ORDER_COL), Some(&vec!["name".to_string(), "age".to_string()]));
+            assert_eq!(result.plan.get(ORDER_DIRECTION), Some(&vec![ASCENDING.to_string(), DESCENDING.to_string()]));
+            let result = Query::from("SELECT name FROM users ORDER BY users.age asc;".to_string()).unwrap();
+            assert_eq!(result.plan.get(ORDER_COL), Some(&vec!["users.age".to_string()]));
+            assert_eq!(result.plan.get(ORDER_DIRECTION), Some(&vec![ASCENDING.to_string()]));
+            assert!(!Query::from("SELECT * FROM users;".to_string()).unwrap().plan.contains_key(ORDER_COL));
+            assert!(Query::from("SELECT * FROM users ORDER BY;".to_string()).is_err());
+            assert!(Query::from("SELECT * FROM users ORDER BY age DESC ASC;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_select_functions() {
+            let result = Query::from("SELECT UPPER(name), id, length(users.name) FROM users;".to_string()).unwrap();
+            assert_eq!(result.plan.get(COLUMN_NAME_KEY), Some(&vec!["users.name".to_string(), "id".to_string(), "name".to_string()]));
+            assert_eq!(result.plan.get(FUNCTION_KEY), Some(&vec![LENGTH.to_string(), NO_FUNCTION.to_string(), UPPER.to_string()]));
+
+            //Cols may be named like a function
+            let result = Query::from("SELECT length, lower(length) FROM users;".to_string()).unwrap();
+            assert_eq!(result.plan.get(COLUMN_NAME_KEY), Some(&vec!["length".to_string(), "length".to_string()]));
+            assert_eq!(result.plan.get(FUNCTION_KEY), Some(&vec![LOWER.to_string(), NO_FUNCTION.to_string()]));
+            assert!(!Query::from("SELECT * FROM users;".to_string()).unwrap().plan.contains_key(FUNCTION_KEY));
+            assert!(Query::from("SELECT trim(name) FROM users;".to_string()).is_err());
+            assert!(Query::from("SELECT upper(name, id) FROM users;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_create_table_as_select() {
+            let result = Query::from("CREATE TABLE adults AS SELECT DISTINCT name, age FROM users WHERE age >= 18;".to_string()).unwrap();
+            assert_eq!(result.plan.get(NEW_TABLE_NAME_KEY), Some(&vec!["adults".to_string()]));
+            assert_eq!(result.plan.get(TABLE_NAME_KEY), Some(&vec!["users".to_string()]));
+            assert_eq!(result.plan.get(COLUMN_NAME_KEY).map(|cols| cols.len()), Some(2));
+            assert!(result.plan.contains_key(DISTINCT));
+            assert!(Query::from("CREATE TABLE adults AS users;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_valid_subquery() {
+            let result = Query::from("SELECT * FROM users WHERE id IN (SELECT owner_id FROM orders WHERE total > ?);".to_string()).unwrap();
+            assert_eq!(result.plan.get(OPERATOR_KEY), Some(&vec![IN.to_string()]));
+            assert_eq!(result.plan.get(SUBQUERY_TABLE), Some(&vec!["orders".to_string()]));
+            assert_eq!(result.plan.get(SUBQUERY_COL), Some(&vec!["owner_id".to_string()]));
+            assert_eq!(result.plan.get(SUBQUERY_OPERATOR), Some(&vec![BIGGER.to_string()]));
+            assert_eq!(result.placeholder_count(), 1);
+            let result = Query::from("DELETE FROM users WHERE age < (SELECT age FROM users WHERE id == 1);".to_string()).unwrap();
+            assert_eq!(result.plan.get(OPERATOR_KEY), Some(&vec![SMALLER.to_string()]));
+            assert!(!result.plan.contains_key(PREDICATE_VAL));
+            assert!(Query::from("SELECT * FROM users WHERE id IN (SELECT a, b FROM orders);".to_string()).is_err(), "subqueries select one col");
+            assert!(Query::from("SELECT * FROM users WHERE id IN 1;".to_string()).is_err());
+        }
+
+
+        #[test]
+        fn test_split_statements() {
+            let script = "CREATE TABLE t (a TEXT);\nINSERT INTO t VALUES ('x;y');\n;\n  SELECT * FROM t";
+            let statements = split_statements(script);
+            assert_eq!(statements, vec!["CREATE TABLE t (a TEXT);", "INSERT INTO t VALUES ('x;y');", "SELECT * FROM t"]);
+            assert!(split_statements("  \n").is_empty());
+        }
+
+
+        #[test]
+        fn test_batch() {
+            let queries = Query::batch("CREATE TABLE t (a NUMBER); INSERT INTO t VALUES (1);\nSELECT * FROM t;".to_string()).unwrap();
+            let commands : Vec<CommandKind> = queries.iter().map(|query| query.command().unwrap()).collect();
+            assert_eq!(commands, vec![CommandKind::Create, CommandKind::Insert, CommandKind::Select]);
+            assert_eq!(Query::batch("SELECT * FROM t;".to_string()).unwrap().len(), 1);
+            let error = Query::batch("SELECT * FROM t; SELEC * FROM t;".to_string()).unwrap_err();
+            assert!(error.to_string().starts_with("statement 2: "), "{}", error);
+            assert_eq!(ErrorCode::of(&error), ErrorCode::Parse);
+            assert!(Query::batch("SELECT * FROM t; SELECT * FROM t".to_string()).is_err(), "every statement needs a semicolon");
+            assert!(Query::batch(String::new()).is_err());
+        }
+
+
+        #[test]
+        fn test_query_cache() {
+            let cache = QueryCache::new(2);
+            let first = "SELECT * FROM t;".to_string();
+            let second = "DELETE FROM t;".to_string();
+            let third = "INSERT INTO t VALUES (1);".to_string();
+            assert_eq!(cache.batch(first.clone()).unwrap()[0].command().unwrap(), CommandKind::Select);
+            cache.batch(second.clone()).unwrap();
+
+            //Using the first query again makes the second one the least recently used
+            assert_eq!(cache.batch(first.clone()).unwrap()[0].command().unwrap(), CommandKind::Select);
+            cache.batch(third.clone()).unwrap();
+            {
+                let (queries, order) = &*cache.entries.lock().unwrap();
+                assert_eq!(order, &VecDeque::from(vec![first.clone(), third.clone()]));
+                assert!(!queries.contains_key(&second));
+                assert_eq!(queries.len(), 2);
+            }
+
+            //Invalid queries fail every time and are not kept
+            assert!(cache.batch("SELEC * FROM t;".to_string()).is_err());
+            assert!(QueryCache::new(0).batch(first).is_ok());
+        }
+
+
+        #[test]
+        fn test_bind_placeholders() {
+            let query = Query::from("INSERT INTO test (a, b) VALUES (?, ?);".to_string()).unwrap();
+            assert_eq!(query.placeholder_count(), 2);
+            let bound = query.bind(vec!["Hello, World!".to_string(), "2".to_string()]).unwrap();
+            assert_eq!(bound.placeholder_count(), 0);
+            let mut values = bound.plan.get(COLUMN_VALUE_KEY).unwrap().clone();
+            values.sort();
+            assert_eq!(values, vec!["2".to_string(), "Hello, World!".to_string()], "params should be inserted unchanged");
+            assert!(query.bind(vec!["1".to_string()]).is_err(), "binding too few params should return an error");
+
+            let query = Query::from("SELECT * FROM test WHERE a == ?;".to_string()).unwrap();
+            let bound = query.bind(vec!["x y".to_string()]).unwrap();
+            assert_eq!(bound.plan.get(PREDICATE_VAL), Some(&vec!["x y".to_string()]));
+        }
+
+
+        #[test]
+        fn test_invalid_placeholder_position() {
+            let result = Query::from("SELECT * FROM ? WHERE a == 1;".to_string());
+            assert!(result.is_err(), "Placeholder in place of a table name should return an error");
+        }
+
+
         #[test]
         fn test_invalid_delete_where_condition_incomplete() {
             let result = Query::from("DELETE FROM users WHERE age = ;".to_string());
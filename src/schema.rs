@@ -2,15 +2,38 @@
 
 
 
-use std::{env, fs::File, io::Result, path::PathBuf, io::{Write, Error, ErrorKind}, collections::hash_map::HashMap, sync::Mutex};
-use rand::{Rng, thread_rng};
+use std::{env, fs::File, io::Result, path::{Path, PathBuf}, io::{Write, Error, ErrorKind}, collections::hash_map::HashMap, sync::Mutex};
+use rand::{Rng, thread_rng, distributions::Alphanumeric};
+use sha2::{Sha256, Digest};
 use dotenv::dotenv;
-use crate::storage::{table_management::{Row, Type, Predicate, Operator, Value, TableHandler, simple::SimpleTableHandler}, file_management::*};
+use crate::error::recover;
+use crate::storage::{table_management::{Row, Type, Predicate, Operator, Value, TableHandler, TableStatistics, ColumnStatistics, simple::SimpleTableHandler}, page_management::{DEFAULT_PAGE_SIZE, simple::SimplePageHandler}, file_management::*};
+
+
+
+//Col data of every table by table name
+type TableData = HashMap<String, Vec<(Type, String)>>;
+
+
+
+///Returns the path of the metadata file called name of the database at db_path. The leading dot
+///keeps it apart from the files of the tables, whose names can not contain a dot
+fn metadata_path(db_path : &Path, name : &str) -> PathBuf {
+    db_path.join(format!(".{}.hive", name))
+}
 
 
 
 pub struct TableSchemaHandler {
-    table_handler: Box<dyn TableHandler>
+    table_handler: Box<dyn TableHandler>,
+
+    //Cols that were declared not null, kept apart from schema.hive so schemas written before the
+    //flag existed can still be read
+    not_null_handler: Box<dyn TableHandler>,
+
+    //Parsed content of schema.hive so reads do not scan it. It is filled by the first read and
+    //cleared by every write, the lock is held while writing so no read caches a stale schema
+    cache: Mutex<Option<TableData>>,
 }
 
 
@@ -20,6 +43,17 @@ impl TableSchemaHandler {
     ///Creates an instance of a TableSchemaHandler. Takes the path of the corresponding database as an
     ///argument.
     pub fn new(db_path: &PathBuf) -> Result<TableSchemaHandler> {
+        TableSchemaHandler::with_page_size(db_path, DEFAULT_PAGE_SIZE)
+    }
+
+    ///Works like new but a newly created schema uses pages of page_size bytes. The page size of
+    ///the schema is the page size of the whole database.
+    pub fn with_page_size(db_path: &Path, page_size : usize) -> Result<TableSchemaHandler> {
+        TableSchemaHandler::with_storage(db_path, page_size, Storage::Disk)
+    }
+
+    ///Works like with_page_size but keeps the schema where storage decides, e.g. in memory
+    pub fn with_storage(db_path: &Path, page_size : usize, storage : Storage) -> Result<TableSchemaHandler> {
 
         //Create table at: 
         let path = db_path.join("schema.hive");
@@ -30,49 +64,40 @@ impl TableSchemaHandler {
         //Col_type -> represents the type of a col as a number that can be decoded by the table management module.
         //Col_id -> this stores the index of a col inside a table in order to order them, since this is important for the creation of a TableHandler.
         let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Text, "col_name"), (Type::Number, "col_type"), (Type::Number, "col_id")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
-        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(path, col_data)?);
-        return Ok(TableSchemaHandler{table_handler});
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+
+        //The not null flags are stored as the table_id and col_name of every not null col
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Text, "col_name")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let not_null_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(metadata_path(db_path, "not_null"), page_size, storage)?), col_data)?);
+        Ok(TableSchemaHandler{table_handler, not_null_handler, cache: Mutex::new(None)})
+    }
+
+    ///Returns the page size new tables of this database should use
+    pub fn page_size(&self) -> usize {
+        self.table_handler.page_size()
     }
 
     ///Collects data of one table and then returns the cols. Takes the table name that should be
-    ///searched for as an argument.
+    ///searched for as an argument. The cols are taken from the cached schema.
     pub fn get_col_data(&self, table : String) -> Result<Vec<(Type, String)>> {
+        self.with_table_data(|table_data| table_data.get(&table).cloned().unwrap_or_default())
+    }
 
-        //Query the table for rows that match the table name.
-        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table) };
-        let res = self.table_handler.select_row(Some(predicate), None)?;
-
-        //Error check query result.
-        if let Some((mut value, mut cursor)) = res {
-            let mut col_data : Vec<(u64, String, Type)> = vec![];
-            loop {
-                let row = value.clone();
-                match (
-                    self.table_handler.get_col_from_row(row.clone(), "col_id")?,
-                    self.table_handler.get_col_from_row(row.clone(), "col_name")?,
-                    self.table_handler.get_col_from_row(row.clone(), "col_type")?) {
-                    (Value::Number(col_id), Value::Text(col_name), Value::Number(col_type)) => col_data.push((col_id, col_name, Type::try_from(col_type)?)),
-                    _ => return Err(Error::new(ErrorKind::InvalidInput, "unexpected error cols in schema did not have the right type")),
-                }
-                if let Some(r) = self.table_handler.next(&mut cursor)? {
-                    value = r;
-                }else{
-                    break;
-                }
-            }
-
-            //Sort cols by col_id
-            col_data.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
-            let end_res : Vec<(Type, String)> = col_data.into_iter().map(|(_, n, t)| (t, n)).collect();
-            return Ok(end_res)
-        }
-        return Ok(vec![]);
+    ///Locks the cached schema. A poisoned cache is cleared, since it may have been left stale
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, Option<TableData>> {
+        self.cache.lock().unwrap_or_else(|poisoned| {
+            let mut cache = poisoned.into_inner();
+            *cache = None;
+            cache
+        })
     }
 
     
     ///Adds a column to the schema. This column can then be retrieved by get table data or get col
     ///data
     pub fn add_col_data(&self, table : String, col : (Type, String)) -> Result<()> {
+        let mut cache = self.lock_cache();
+        *cache = None;
         let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.clone())};
         let mut index = 0;
         if let Some((mut value, mut cursor)) = self.table_handler.select_row(Some(predicate), None)? {
@@ -88,14 +113,77 @@ impl TableSchemaHandler {
                 }
             }
         }
-        let row : Row = Row{cols: vec![Value::new_text(table.clone()), Value::new_text(col.1.clone()), Value::new_number(col.0.clone().into()), Value::new_number(index as u64)]};
+        let row : Row = Row{cols: vec![Value::new_text(table.clone()), Value::new_text(col.1.clone()), Value::new_number(Into::<u64>::into(col.0.clone()) as i64), Value::new_number(index as i64)]};
         self.table_handler.insert_row(row)?;
         return Ok(());
     }
 
+    ///Removes a column from the schema. The remaining cols are added again, so their indices
+    ///stay without gaps
+    pub fn remove_col_data(&self, table : String, col_name : &str) -> Result<()> {
+        let col_data = self.get_col_data(table.clone())?;
+        if !col_data.iter().any(|(_, n)| n == col_name) {
+            return Err(Error::new(ErrorKind::InvalidInput, "col does not exist in table"));
+        }
+        self.remove_table_data(table.clone())?;
+        for col in col_data.into_iter().filter(|(_, n)| n != col_name) {
+            self.add_col_data(table.clone(), col)?;
+        }
+        let not_null = self.get_not_null(table.clone())?;
+        self.remove_not_null(table.clone())?;
+        for col in not_null.into_iter().filter(|n| n != col_name) {
+            self.add_not_null(table.clone(), col)?;
+        }
+        Ok(())
+    }
+
+    ///Marks a col of a table as not null
+    pub fn add_not_null(&self, table : String, col_name : String) -> Result<()> {
+        let row : Row = Row{cols: vec![Value::new_text(table), Value::new_text(col_name)]};
+        self.not_null_handler.insert_row(row)?;
+        Ok(())
+    }
+
+    ///Returns the names of the cols of a table that were declared not null
+    pub fn get_not_null(&self, table : String) -> Result<Vec<String>> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        let mut not_null : Vec<String> = vec![];
+        let mut next = self.not_null_handler.select_row(Some(predicate), None)?;
+        while let Some((row, mut cursor)) = next {
+            match self.not_null_handler.get_col_from_row(row, "col_name")? {
+                Value::Text(col_name) => not_null.push(col_name),
+                _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in not null flags did not have the right type")),
+            }
+            next = self.not_null_handler.next(&mut cursor)?.map(|r| (r, cursor));
+        }
+        Ok(not_null)
+    }
+
+    ///Removes the not null flags of all cols of a table
+    pub fn remove_not_null(&self, table : String) -> Result<()> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        self.not_null_handler.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
     ///Returns the data of all tables as a map with keys of table names and values containing a vec of
-    ///Columns. 
+    ///Columns. The schema is only scanned if it changed since the last call.
     pub fn get_table_data(&self) -> Result<HashMap<String, Vec<(Type, String)>>> {
+        self.with_table_data(|table_data| table_data.clone())
+    }
+
+    ///Calls f with the data of all tables, which is read from the schema if it is not cached
+    fn with_table_data<T>(&self, f : impl FnOnce(&TableData) -> T) -> Result<T> {
+        let mut cache = self.lock_cache();
+        if cache.is_none() {
+            *cache = Some(self.read_table_data()?);
+        }
+        let table_data = cache.as_ref().ok_or_else(|| Error::other("unexpected error: schema was not cached"))?;
+        Ok(f(table_data))
+    }
+
+    ///Scans the schema for the data of all tables
+    fn read_table_data(&self) -> Result<TableData> {
         let mut table_data : HashMap<String, Vec<(u64, String, Type)>> = HashMap::new();
 
         //Query the table without a predicate and thereby get all cols.
@@ -111,7 +199,7 @@ impl TableSchemaHandler {
                     self.table_handler.get_col_from_row(row.clone(), "col_name")?,
                     self.table_handler.get_col_from_row(row.clone(), "col_type")?) {
                     (Value::Text(table_id), Value::Number(col_id), Value::Text(col_name), Value::Number(col_type)) => {
-                        let col_data : (u64, String, Type) = (col_id, col_name, Type::try_from(col_type)?);
+                        let col_data : (u64, String, Type) = (col_id as u64, col_name, Type::try_from(col_type as u64)?);
 
                         //Insert col into table value or create new key value pair if necessary
                         if let Some(mut existent) = table_data.insert(table_id.clone(), vec![col_data.clone()]) {
@@ -143,20 +231,622 @@ impl TableSchemaHandler {
     
     ///Remove a tables entries from the Schema
     pub fn remove_table_data(&self, table : String) -> Result<()> {
+        let mut cache = self.lock_cache();
+        *cache = None;
         let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table) };
-        return self.table_handler.delete_row(Some(predicate));
+        self.table_handler.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
+
+}
+
+
+
+
+pub struct StatisticsHandler {
+    table_handler: Box<dyn TableHandler>
+}
+
+
+
+impl StatisticsHandler {
+
+    ///Creates an instance of a StatisticsHandler which persists the statistics of the tables of
+    ///the database at db_path.
+    pub fn new(db_path: &Path, page_size : usize, storage : Storage) -> Result<StatisticsHandler> {
+
+        //Create table at:
+        let path = metadata_path(db_path, "statistics");
+
+        //With cols:
+        //Table_id -> represents the table name.
+        //Col_id -> index of the col inside the table.
+        //Row_count -> amount of rows in the table, the same in every row of a table.
+        //Distinct -> amount of distinct values in the col.
+        //Bounded -> 1 if min and max are set, 0 if the table was empty.
+        //Min and max -> smallest and largest value of the col as text.
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Number, "col_id"), (Type::Number, "row_count"), (Type::Number, "distinct"), (Type::Number, "bounded"), (Type::Text, "min"), (Type::Text, "max")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+        Ok(StatisticsHandler{table_handler})
+    }
+
+    ///Returns the persisted statistics of a table or None if it was never analyzed. Takes the
+    ///col data of the table to restore the values of min and max.
+    pub fn get_statistics(&self, table : String, col_data : &[(Type, String)]) -> Result<Option<TableStatistics>> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        let mut statistics = TableStatistics::new(col_data.iter().map(|(_, n)| n.clone()).collect());
+        let mut found = false;
+        if let Some((mut value, mut cursor)) = self.table_handler.select_row(Some(predicate), None)? {
+            loop {
+                match (
+                    self.table_handler.get_col_from_row(value.clone(), "col_id")?,
+                    self.table_handler.get_col_from_row(value.clone(), "row_count")?,
+                    self.table_handler.get_col_from_row(value.clone(), "distinct")?,
+                    self.table_handler.get_col_from_row(value.clone(), "bounded")?,
+                    self.table_handler.get_col_from_row(value.clone(), "min")?,
+                    self.table_handler.get_col_from_row(value.clone(), "max")?) {
+                    (Value::Number(col_id), Value::Number(row_count), Value::Number(distinct), Value::Number(bounded), Value::Text(min), Value::Text(max)) => {
+                        let (col_type, name) = col_data.get(col_id as usize).ok_or_else(|| Error::new(ErrorKind::InvalidData, "statistics refer to a col that does not exist"))?;
+                        let restore = |text : String| -> Result<Value> {
+                            match col_type {
+                                Type::Number => Ok(Value::new_number(text.parse().map_err(|_| Error::new(ErrorKind::InvalidData, "statistics contain an invalid number"))?)),
+                                Type::Text => Ok(Value::new_text(text)),
+                                Type::Blob => Value::new_blob_from_hex(&text),
+                            }
+                        };
+                        let (min, max) = match bounded {
+                            0 => (None, None),
+                            _ => (Some(restore(min)?), Some(restore(max)?)),
+                        };
+                        statistics.row_count = row_count as u64;
+                        statistics.columns[col_id as usize] = ColumnStatistics{name: name.clone(), min, max, distinct: distinct as u64};
+                        found = true;
+                    },
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in statistics did not have the right type")),
+                }
+                if let Some(r) = self.table_handler.next(&mut cursor)? {
+                    value = r;
+                }else{
+                    break;
+                }
+            }
+        }
+        Ok(if found {Some(statistics)} else {None})
+    }
+
+    ///Replaces the persisted statistics of a table
+    pub fn set_statistics(&self, table : String, statistics : &TableStatistics) -> Result<()> {
+        self.remove_statistics(table.clone())?;
+        for (col_id, column) in statistics.columns.iter().enumerate() {
+            let bounded = column.min.is_some() && column.max.is_some();
+            let min = column.min.as_ref().map_or(String::new(), |v| v.to_string());
+            let max = column.max.as_ref().map_or(String::new(), |v| v.to_string());
+            let row : Row = Row{cols: vec![Value::new_text(table.clone()), Value::new_number(col_id as i64), Value::new_number(statistics.row_count as i64), Value::new_number(column.distinct as i64), Value::new_number(bounded as i64), Value::new_text(min), Value::new_text(max)]};
+            self.table_handler.insert_row(row)?;
+        }
+        Ok(())
+    }
+
+    ///Removes the persisted statistics of a table
+    pub fn remove_statistics(&self, table : String) -> Result<()> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        self.table_handler.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
+}
+
+
+
+
+pub struct AutoIncrementHandler {
+    table_handler: Box<dyn TableHandler>,
+
+    //Maps table names to their auto increment col and the next value of it
+    counters: Mutex<HashMap<String, (String, i64)>>,
+}
+
+
+
+impl AutoIncrementHandler {
+
+    ///Creates an instance of an AutoIncrementHandler which persists the counters of the auto
+    ///increment cols of the database at db_path.
+    pub fn new(db_path: &Path, page_size : usize, storage : Storage) -> Result<AutoIncrementHandler> {
+
+        //Create table at:
+        let path = metadata_path(db_path, "auto_increment");
+
+        //With cols:
+        //Table_id -> represents the table name.
+        //Col_name -> the auto increment col of the table.
+        //Next -> the value the next inserted row gets.
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Text, "col_name"), (Type::Number, "next")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+
+        //Load all counters, so inserts do not have to query the table
+        let mut counters : HashMap<String, (String, i64)> = HashMap::new();
+        if let Some((mut value, mut cursor)) = table_handler.select_row(None, None)? {
+            loop {
+                match (
+                    table_handler.get_col_from_row(value.clone(), "table_id")?,
+                    table_handler.get_col_from_row(value.clone(), "col_name")?,
+                    table_handler.get_col_from_row(value.clone(), "next")?) {
+                    (Value::Text(table_id), Value::Text(col_name), Value::Number(next)) => {
+                        counters.insert(table_id, (col_name, next));
+                    },
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in auto increment table did not have the right type")),
+                }
+                if let Some(r) = table_handler.next(&mut cursor)? {
+                    value = r;
+                }else{
+                    break;
+                }
+            }
+        }
+        Ok(AutoIncrementHandler{table_handler, counters: Mutex::new(counters)})
+    }
+
+    ///Registers the auto increment col of a new table, its first value is 1
+    pub fn add_column(&self, table : String, col_name : String) -> Result<()> {
+        let mut counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        self.persist(&table, &col_name, 1)?;
+        counters.insert(table, (col_name, 1));
+        Ok(())
+    }
+
+    ///Returns the auto increment col of a table if it has one
+    pub fn get_column(&self, table : &str) -> Result<Option<String>> {
+        let counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        Ok(counters.get(table).map(|(col_name, _)| col_name.clone()))
+    }
+
+    ///Returns the next value of the auto increment col of a table and advances the counter. The
+    ///counter is persisted first, so a value is never handed out twice
+    pub fn take_value(&self, table : &str) -> Result<i64> {
+        let mut counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        let (col_name, next) = counters.get_mut(table).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "table has no auto increment col"))?;
+        let value = *next;
+        self.persist(table, col_name, value + 1)?;
+        *next = value + 1;
+        Ok(value)
+    }
+
+    ///Moves the counter of a table past a value that was inserted explicitly, so it is not
+    ///handed out later
+    pub fn observe_value(&self, table : &str, value : i64) -> Result<()> {
+        let mut counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        if let Some((col_name, next)) = counters.get_mut(table) {
+            if value >= *next {
+                self.persist(table, col_name, value + 1)?;
+                *next = value + 1;
+            }
+        }
+        Ok(())
+    }
+
+    ///Removes the counter of a table
+    pub fn remove_table(&self, table : String) -> Result<()> {
+        let mut counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.clone())};
+        self.table_handler.delete_row(Some(predicate))?;
+        counters.remove(&table);
+        Ok(())
+    }
+
+    ///Moves the counter of a table to its new name
+    pub fn rename_table(&self, table : &str, new_table : &str) -> Result<()> {
+        let mut counters = self.counters.lock().map_err(|_| Error::other("thread poisoned"))?;
+        if let Some((col_name, next)) = counters.remove(table) {
+            let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.to_string())};
+            self.table_handler.delete_row(Some(predicate))?;
+            self.persist(new_table, &col_name, next)?;
+            counters.insert(new_table.to_string(), (col_name, next));
+        }
+        Ok(())
+    }
+
+    ///Replaces the persisted counter of a table
+    fn persist(&self, table : &str, col_name : &str, next : i64) -> Result<()> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.to_string())};
+        self.table_handler.delete_row(Some(predicate))?;
+        let row : Row = Row{cols: vec![Value::new_text(table.to_string()), Value::new_text(col_name.to_string()), Value::new_number(next)]};
+        self.table_handler.insert_row(row)?;
+        Ok(())
+    }
+
+}
+
+
+
+
+pub struct RowCountHandler {
+    table_handler: Box<dyn TableHandler>,
+
+    //Maps table names to the number of rows they have
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+
+
+impl RowCountHandler {
+
+    ///Creates an instance of a RowCountHandler which persists the row counts of the tables of the
+    ///database at db_path. Tables that were created before rows were counted have no count until
+    ///they are vacuumed
+    pub fn new(db_path: &Path, page_size : usize, storage : Storage) -> Result<RowCountHandler> {
+
+        //Create table at:
+        let path = metadata_path(db_path, "row_count");
+
+        //With cols:
+        //Table_id -> represents the table name.
+        //Rows -> the number of rows of the table.
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Number, "rows")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+
+        //Load all counts, so counting does not have to query the table
+        let mut counts : HashMap<String, u64> = HashMap::new();
+        if let Some((mut value, mut cursor)) = table_handler.select_row(None, None)? {
+            loop {
+                match (table_handler.get_col_from_row(value.clone(), "table_id")?, table_handler.get_col_from_row(value.clone(), "rows")?) {
+                    (Value::Text(table_id), Value::Number(rows)) => {
+                        counts.insert(table_id, rows as u64);
+                    },
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in row count table did not have the right type")),
+                }
+                if let Some(r) = table_handler.next(&mut cursor)? {
+                    value = r;
+                }else{
+                    break;
+                }
+            }
+        }
+        Ok(RowCountHandler{table_handler, counts: Mutex::new(counts)})
+    }
+
+    ///Returns the number of rows of a table if they are counted
+    pub fn get_count(&self, table : &str) -> Result<Option<u64>> {
+        let counts = self.counts.lock().map_err(|_| Error::other("thread poisoned"))?;
+        Ok(counts.get(table).copied())
+    }
+
+    ///Replaces the number of rows of a table, e.g. for a new table or after the rows were counted
+    pub fn set_count(&self, table : &str, count : u64) -> Result<()> {
+        let mut counts = self.counts.lock().map_err(|_| Error::other("thread poisoned"))?;
+        self.persist(table, count)?;
+        counts.insert(table.to_string(), count);
+        Ok(())
+    }
+
+    ///Adds change to the number of rows of a table if they are counted
+    pub fn add_rows(&self, table : &str, change : i64) -> Result<()> {
+        let mut counts = self.counts.lock().map_err(|_| Error::other("thread poisoned"))?;
+        if let Some(count) = counts.get_mut(table) {
+            let new_count = count.saturating_add_signed(change);
+            self.persist(table, new_count)?;
+            *count = new_count;
+        }
+        Ok(())
+    }
+
+    ///Removes the count of a table
+    pub fn remove_table(&self, table : String) -> Result<()> {
+        let mut counts = self.counts.lock().map_err(|_| Error::other("thread poisoned"))?;
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.clone())};
+        self.table_handler.delete_row(Some(predicate))?;
+        counts.remove(&table);
+        Ok(())
+    }
+
+    ///Moves the count of a table to its new name
+    pub fn rename_table(&self, table : &str, new_table : &str) -> Result<()> {
+        let mut counts = self.counts.lock().map_err(|_| Error::other("thread poisoned"))?;
+        if let Some(count) = counts.remove(table) {
+            let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.to_string())};
+            self.table_handler.delete_row(Some(predicate))?;
+            self.persist(new_table, count)?;
+            counts.insert(new_table.to_string(), count);
+        }
+        Ok(())
     }
 
+    ///Replaces the persisted count of a table
+    fn persist(&self, table : &str, count : u64) -> Result<()> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.to_string())};
+        self.table_handler.delete_row(Some(predicate))?;
+        let row : Row = Row{cols: vec![Value::new_text(table.to_string()), Value::new_number(count as i64)]};
+        self.table_handler.insert_row(row)?;
+        Ok(())
+    }
 
 }
 
 
 
 
+pub struct CheckHandler {
+    table_handler: Box<dyn TableHandler>
+}
+
+
+
+impl CheckHandler {
+
+    ///Creates an instance of a CheckHandler which persists the check constraints of the tables of
+    ///the database at db_path.
+    pub fn new(db_path: &Path, page_size : usize, storage : Storage) -> Result<CheckHandler> {
+
+        //Create table at:
+        let path = metadata_path(db_path, "checks");
+
+        //With cols:
+        //Table_id -> represents the table name.
+        //Col_name -> the col the check compares.
+        //Operator -> the comparison as it is named by the query module.
+        //Value -> the value the col is compared to as text.
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Text, "col_name"), (Type::Text, "operator"), (Type::Text, "value")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+        Ok(CheckHandler{table_handler})
+    }
+
+    ///Persists a check of a table
+    pub fn add_check(&self, table : String, check : &Predicate) -> Result<()> {
+        let row : Row = Row{cols: vec![Value::new_text(table), Value::new_text(check.column.clone()), Value::new_text(check.operator.clone().into()), Value::new_text(check.value.to_string())]};
+        self.table_handler.insert_row(row)?;
+        Ok(())
+    }
+
+    ///Returns the col, operator and value as text of every check of a table. The value has to be
+    ///converted with the table handler of the table
+    pub fn get_checks(&self, table : String) -> Result<Vec<(String, Operator, String)>> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        let mut checks : Vec<(String, Operator, String)> = vec![];
+        if let Some((mut value, mut cursor)) = self.table_handler.select_row(Some(predicate), None)? {
+            loop {
+                match (
+                    self.table_handler.get_col_from_row(value.clone(), "col_name")?,
+                    self.table_handler.get_col_from_row(value.clone(), "operator")?,
+                    self.table_handler.get_col_from_row(value.clone(), "value")?) {
+                    (Value::Text(col_name), Value::Text(operator), Value::Text(check_value)) => checks.push((col_name, Operator::try_from(operator)?, check_value)),
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in checks did not have the right type")),
+                }
+                if let Some(r) = self.table_handler.next(&mut cursor)? {
+                    value = r;
+                }else{
+                    break;
+                }
+            }
+        }
+        Ok(checks)
+    }
+
+    ///Removes the checks of a table
+    pub fn remove_checks(&self, table : String) -> Result<()> {
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table)};
+        self.table_handler.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
+}
+
+
+
+
+///Foreign key of a table, the values of col have to exist in ref_col of ref_table
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub table : String,
+    pub col : String,
+    pub ref_table : String,
+    pub ref_col : String,
+}
+
+
+
+pub struct ReferenceHandler {
+    table_handler: Box<dyn TableHandler>,
+
+    //All references of the database, so inserts and deletes do not have to query the table
+    references: Mutex<Vec<Reference>>,
+}
+
+
+
+impl ReferenceHandler {
+
+    ///Creates an instance of a ReferenceHandler which persists the foreign keys of the tables of
+    ///the database at db_path.
+    pub fn new(db_path: &Path, page_size : usize, storage : Storage) -> Result<ReferenceHandler> {
+
+        //Create table at:
+        let path = metadata_path(db_path, "references");
+
+        //With cols:
+        //Table_id -> represents the name of the referencing table.
+        //Col_name -> the referencing col.
+        //Ref_table -> the name of the referenced table.
+        //Ref_col -> the referenced col.
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "table_id"), (Type::Text, "col_name"), (Type::Text, "ref_table"), (Type::Text, "ref_col")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, page_size, storage)?), col_data)?);
+        let mut references : Vec<Reference> = vec![];
+        if let Some((mut value, mut cursor)) = table_handler.select_row(None, None)? {
+            loop {
+                match (
+                    table_handler.get_col_from_row(value.clone(), "table_id")?,
+                    table_handler.get_col_from_row(value.clone(), "col_name")?,
+                    table_handler.get_col_from_row(value.clone(), "ref_table")?,
+                    table_handler.get_col_from_row(value.clone(), "ref_col")?) {
+                    (Value::Text(table), Value::Text(col), Value::Text(ref_table), Value::Text(ref_col)) => references.push(Reference{table, col, ref_table, ref_col}),
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected error cols in references did not have the right type")),
+                }
+                if let Some(r) = table_handler.next(&mut cursor)? {
+                    value = r;
+                }else{
+                    break;
+                }
+            }
+        }
+        Ok(ReferenceHandler{table_handler, references: Mutex::new(references)})
+    }
+
+    ///Persists a foreign key
+    pub fn add_reference(&self, reference : Reference) -> Result<()> {
+        let mut references = self.references.lock().map_err(|_| Error::other("thread poisoned"))?;
+        let row : Row = Row{cols: vec![Value::new_text(reference.table.clone()), Value::new_text(reference.col.clone()), Value::new_text(reference.ref_table.clone()), Value::new_text(reference.ref_col.clone())]};
+        self.table_handler.insert_row(row)?;
+        references.push(reference);
+        Ok(())
+    }
+
+    ///Returns the foreign keys of a table
+    pub fn get_references(&self, table : &str) -> Result<Vec<Reference>> {
+        let references = self.references.lock().map_err(|_| Error::other("thread poisoned"))?;
+        Ok(references.iter().filter(|r| r.table == table).cloned().collect())
+    }
+
+    ///Returns the foreign keys that point to a table, including the ones of the table itself
+    pub fn get_referencing(&self, table : &str) -> Result<Vec<Reference>> {
+        let references = self.references.lock().map_err(|_| Error::other("thread poisoned"))?;
+        Ok(references.iter().filter(|r| r.ref_table == table).cloned().collect())
+    }
+
+    ///Replaces the name of a table in all foreign keys, the ones of the table and the ones pointing
+    ///to it
+    pub fn rename_table(&self, table : &str, new_table : &str) -> Result<()> {
+        let mut references = self.references.lock().map_err(|_| Error::other("thread poisoned"))?;
+        for col in ["table_id", "ref_table"] {
+            let predicate : Predicate = Predicate{column: col.to_string(), operator: Operator::Equal, value: Value::new_text(table.to_string())};
+            self.table_handler.delete_row(Some(predicate))?;
+        }
+        for reference in references.iter_mut().filter(|r| r.table == table || r.ref_table == table) {
+            if reference.table == table {
+                reference.table = new_table.to_string();
+            }
+            if reference.ref_table == table {
+                reference.ref_table = new_table.to_string();
+            }
+            let row : Row = Row{cols: vec![Value::new_text(reference.table.clone()), Value::new_text(reference.col.clone()), Value::new_text(reference.ref_table.clone()), Value::new_text(reference.ref_col.clone())]};
+            self.table_handler.insert_row(row)?;
+        }
+        Ok(())
+    }
+
+    ///Removes the foreign keys of a table
+    pub fn remove_table(&self, table : String) -> Result<()> {
+        let mut references = self.references.lock().map_err(|_| Error::other("thread poisoned"))?;
+        let predicate : Predicate = Predicate{column: "table_id".to_string(), operator: Operator::Equal, value: Value::new_text(table.clone())};
+        self.table_handler.delete_row(Some(predicate))?;
+        references.retain(|r| r.table != table);
+        Ok(())
+    }
+
+}
+
+
+
+
+//Prefix of hashed keys, keys stored before hashing was introduced do not have it
+const KEY_HASH_PREFIX : &str = "sha256$";
+
+
+
+///Hashes a key with a new random salt. The result looks like sha256$<salt>$<hex hash> so the salt
+///can be read again when a presented key is checked
+fn hash_key(key : &str) -> String {
+    let salt : String = thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect();
+    format!("{}{}${}", KEY_HASH_PREFIX, salt, salted_hash(&salt, key))
+}
+
+
+
+///Returns a random key of printable ascii characters
+fn generate_key() -> String {
+    let mut key = String::new();
+    let mut rng = thread_rng();
+    for _ in 0..32 {
+        key.push(rng.gen_range(0x20..=0x7E).into());
+    }
+    key
+}
+
+
+
+fn salted_hash(salt : &str, key : &str) -> String {
+    hex::encode(Sha256::new().chain_update(salt.as_bytes()).chain_update(key.as_bytes()).finalize())
+}
+
+
+
+///Checks a presented key against a hash created by hash_key
+fn verify_key(key : &str, hash : &str) -> bool {
+    let Some((salt, expected)) = hash.strip_prefix(KEY_HASH_PREFIX).and_then(|rest| rest.split_once('$')) else {
+        return false;
+    };
+    keys_match(&salted_hash(salt, key), expected)
+}
+
+
+
+///Every byte is compared so the time taken does not reveal how much of the keys matched
+fn keys_match(a : &str, b : &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+
+
+///Rights of a connection. Read only admins can only list databases, read only clients can only
+///run queries that do not change the database
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Full,
+    ReadOnly,
+}
+
+
+
+impl Role {
+
+    pub fn parse(role : &str) -> Result<Self> {
+        match role {
+            "full" => Ok(Role::Full),
+            "read_only" => Ok(Role::ReadOnly),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown role: {}", role))),
+        }
+    }
+
+
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Full => "full",
+            Role::ReadOnly => "read_only",
+        }
+    }
+}
+
+
+
+///Keeps the databases with the hashes of their keys and the hash of the admin key. Plain keys are
+///only known to whoever created them, they can not be recovered from the stored hashes
 pub struct DatabaseSchemaHandler {
     table_handler : Box<dyn TableHandler>, 
     databases : Mutex<HashMap<String, String>>,
-    admin_key : String,
+    admin_key : Mutex<String>,
+
+    //Further admins added by label, each with the hash of its key and its role
+    admins_table : Box<dyn TableHandler>,
+    admins : Mutex<HashMap<String, (String, Role)>>,
+
+    //Hashes of the optional read only keys of databases
+    read_keys_table : Box<dyn TableHandler>,
+    read_keys : Mutex<HashMap<String, String>>,
+
+    //Random admin key that only lives in memory, it lets the cli of this process authenticate
+    //without knowing the admin key from the env file
+    session_key : String,
+    env_path : PathBuf,
 }
 
 
@@ -170,14 +860,18 @@ impl DatabaseSchemaHandler {
         //Table containing database_id and database_key is created
         let path = base_path.join("schema.hive");
         let col_data : Vec<(Type, String)> = vec![(Type::Text, "database_id"), (Type::Text, "database_key")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
-        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(path, col_data)?);
+        let table_handler : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, DEFAULT_PAGE_SIZE, Storage::Disk)?), col_data)?);
 
-        //Map containing database name and key is initialized and filled
+        //Map containing database name and key hash is initialized and filled
         let mut databases : HashMap<String, String> = HashMap::new();
+        let mut plain_keys : Vec<String> = vec![];
         if let Some((mut value, mut cursor)) = table_handler.select_row(None, None)? {
             loop {
                 let database_id : String = table_handler.get_col_from_row(value.clone(), "database_id")?.try_into()?;
                 let database_key : String = table_handler.get_col_from_row(value.clone(), "database_key")?.try_into()?;
+                if !database_key.starts_with(KEY_HASH_PREFIX) {
+                    plain_keys.push(database_id.clone());
+                }
                 databases.insert(database_id, database_key);
                 if let Some(new_value) = table_handler.next(&mut cursor)? {
                     value = new_value;
@@ -186,22 +880,65 @@ impl DatabaseSchemaHandler {
                 break;
             }
         }
-        let mut admin_key = String::new();
+
+        //Keys written before they were hashed are replaced by their hash
+        for database_id in plain_keys {
+            let hash = hash_key(&databases[&database_id]);
+            let predicate = Predicate { column: "database_id".to_string(), operator: Operator::Equal, value: Value::new_text(database_id.clone())};
+            table_handler.delete_row(Some(predicate))?;
+            table_handler.insert_row(Row{cols: vec![Value::new_text(database_id.clone()), Value::new_text(hash.clone())]})?;
+            databases.insert(database_id, hash);
+        }
+        let admin_key : String;
         let env_path = base_path.join(".env");
         if !env_path.exists() { 
-            let mut rng = thread_rng();
-            for i in (0..32) {
-                admin_key.push(rng.gen_range(0x20..=0x7E).into()); 
-            }
-            let mut file = create_file(&env_path)?;
 
-            // Write some default content
-            writeln!(file, "ADMIN_KEY=\"{}\"", admin_key)?;
+            //The generated key is not shown, the cli of this process can rotate it to get a key
+            //for remote admins
+            let key : String = generate_key();
+            admin_key = hash_key(&key);
+            let mut file = create_file(&env_path)?;
+            writeln!(file, "ADMIN_KEY_HASH=\"{}\"", admin_key)?;
         }else{
-            dotenv::from_path(env_path).map_err(|e| {Error::new(ErrorKind::NotFound, format!("couldnt load env: {}", e))})?;
-            admin_key = env::var("ADMIN_KEY").map_err(|e| {Error::new(ErrorKind::NotFound, format!("couldnt find admin key in env file: {}", e))})?;
+
+            //The key is read from the file instead of the environment, since variables that are
+            //set already are not overwritten when the file is loaded again after a rotation
+            let content = std::fs::read_to_string(&env_path).map_err(|e| {Error::new(ErrorKind::NotFound, format!("couldnt load env: {}", e))})?;
+            let find_value = |name : &str| content.lines()
+                .find_map(|line| line.trim().strip_prefix(name))
+                .map(|key| key.trim().trim_matches('"').to_string());
+            admin_key = match (find_value("ADMIN_KEY_HASH="), find_value("ADMIN_KEY=")) {
+                (Some(hash), _) => hash,
+
+                //A plain key from before hashing was introduced is replaced by its hash
+                (None, Some(key)) => {
+                    let hash = hash_key(&key);
+                    write_admin_key_hash(&env_path, &hash)?;
+                    hash
+                },
+                (None, None) => return Err(Error::new(ErrorKind::NotFound, "couldnt find admin key in env file")),
+            };
         }
-        return Ok(DatabaseSchemaHandler {table_handler, databases : Mutex::new(databases), admin_key});
+
+        //Table containing the labels, key hashes and roles of further admins is loaded
+        let path = base_path.join("admins.hive");
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "label"), (Type::Text, "key_hash"), (Type::Text, "role")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let admins_table : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, DEFAULT_PAGE_SIZE, Storage::Disk)?), col_data)?);
+        let mut admins : HashMap<String, (String, Role)> = HashMap::new();
+        for row in read_text_rows(admins_table.as_ref(), &["label", "key_hash", "role"])? {
+            admins.insert(row[0].clone(), (row[1].clone(), Role::parse(&row[2])?));
+        }
+
+        //Table containing the hashes of read only keys is loaded
+        let path = base_path.join("read_keys.hive");
+        let col_data : Vec<(Type, String)> = vec![(Type::Text, "database_id"), (Type::Text, "key_hash")].into_iter().map(|(t, n)| (t, n.to_string())).collect();
+        let read_keys_table : Box<dyn TableHandler> = Box::new(SimpleTableHandler::new(Box::new(SimplePageHandler::with_storage(path, DEFAULT_PAGE_SIZE, Storage::Disk)?), col_data)?);
+        let mut read_keys : HashMap<String, String> = HashMap::new();
+        for row in read_text_rows(read_keys_table.as_ref(), &["database_id", "key_hash"])? {
+            read_keys.insert(row[0].clone(), row[1].clone());
+        }
+        let session_key : String = generate_key();
+        Ok(DatabaseSchemaHandler {table_handler, databases : Mutex::new(databases), admin_key: Mutex::new(admin_key), admins_table, admins: Mutex::new(admins), read_keys_table, read_keys: Mutex::new(read_keys), session_key, env_path})
     }
 
 
@@ -209,70 +946,234 @@ impl DatabaseSchemaHandler {
     pub fn add_database(&self, database : String, key : String) -> Result<()> {
 
         //Check if database with this name exists already
-        if let Ok(databases) = self.databases.lock() {
-            if databases.contains_key(&database) {
-                return Err(Error::new(ErrorKind::AlreadyExists, "database does exist already"));
-            }
+        if recover(self.databases.lock(), "databases").contains_key(&database) {
+            return Err(Error::new(ErrorKind::AlreadyExists, "database does exist already"));
         }
 
-        //Database is added to map and table
-        let row : Row = Row{cols: vec![Value::new_text(database.clone()), Value::new_text(key.clone())]};
+        //Database is added to map and table, only the hash of its key is kept
+        let hash = hash_key(&key);
+        let row : Row = Row{cols: vec![Value::new_text(database.clone()), Value::new_text(hash.clone())]};
         self.table_handler.insert_row(row)?;
-        if let Ok(mut databases) = self.databases.lock() {
-            databases.insert(database, key);
-        }
+        recover(self.databases.lock(), "databases").insert(database, hash);
         return Ok(());
     }
 
 
 
     pub fn remove_database(&self, database : String) -> Result<()> {
-        if let Ok(mut databases) = self.databases.lock() {
-            if databases.remove(&database).is_none() {
-                return Err(Error::new(ErrorKind::NotFound, "database does not exist"));
-            }
+        if recover(self.databases.lock(), "databases").remove(&database).is_none() {
+            return Err(Error::new(ErrorKind::NotFound, "database does not exist"));
         }
         let predicate = Predicate { column: "database_id".to_string(), operator: Operator::Equal, value: Value::new_text(database.clone())};
-        self.table_handler.delete_row(Some(predicate))?;
+        self.table_handler.delete_row(Some(predicate.clone()))?;
+
+        //A read only key of the database is removed along with it
+        let mut read_keys = recover(self.read_keys.lock(), "read keys");
+        if read_keys.remove(&database).is_some() {
+            self.read_keys_table.delete_row(Some(predicate))?;
+        }
         return Ok(());
     }
 
 
 
     pub fn get_database_names(&self) -> Result<Vec<String>> {
-        if let Ok(databases) = self.databases.lock() {
-            return Ok(databases.clone().into_keys().collect()); 
+        Ok(recover(self.databases.lock(), "databases").clone().into_keys().collect())
+    }
+
+
+
+    ///Replaces the key of a database in the table and the map
+    pub fn change_key(&self, database : String, key : String) -> Result<()> {
+
+        //The map is locked the whole time so no other change can come in between
+        let mut databases = recover(self.databases.lock(), "databases");
+        if !databases.contains_key(&database) {
+            return Err(Error::new(ErrorKind::NotFound, "database does not exist"));
         }
-        return Err(Error::new(ErrorKind::Other, "thread poisoned"));
+        let hash = hash_key(&key);
+        let predicate = Predicate { column: "database_id".to_string(), operator: Operator::Equal, value: Value::new_text(database.clone())};
+        self.table_handler.delete_row(Some(predicate))?;
+        self.table_handler.insert_row(Row{cols: vec![Value::new_text(database.clone()), Value::new_text(hash.clone())]})?;
+        databases.insert(database, hash);
+        Ok(())
     }
 
 
 
-    pub fn get_database_key(&self, database_name : String) -> Result<Option<String>> {
-        let databases = self.databases.lock().map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
-        return Ok(databases.get(&database_name).cloned());
+    ///Gives a database a read only key, a read only key it had before stops working
+    pub fn set_read_key(&self, database : String, key : String) -> Result<()> {
+        let databases = recover(self.databases.lock(), "databases");
+        if !databases.contains_key(&database) {
+            return Err(Error::new(ErrorKind::NotFound, "database does not exist"));
+        }
+        let mut read_keys = recover(self.read_keys.lock(), "read keys");
+        let hash = hash_key(&key);
+        if read_keys.contains_key(&database) {
+            let predicate = Predicate { column: "database_id".to_string(), operator: Operator::Equal, value: Value::new_text(database.clone())};
+            self.read_keys_table.delete_row(Some(predicate))?;
+        }
+        self.read_keys_table.insert_row(Row{cols: vec![Value::new_text(database.clone()), Value::new_text(hash.clone())]})?;
+        read_keys.insert(database, hash);
+        Ok(())
     }
 
 
 
-    pub fn check_key(&self, database : String, key : String) -> Result<bool> {
-        if let Ok(databases) = self.databases.lock() {
-            return match databases.get(&database) {
-                Some(val) if *val == key => Ok(true),
-                _ => Err(Error::new(ErrorKind::InvalidInput, "wrong key")),
-            }
+    pub fn remove_read_key(&self, database : String) -> Result<()> {
+        let mut read_keys = recover(self.read_keys.lock(), "read keys");
+        if read_keys.remove(&database).is_none() {
+            return Err(Error::new(ErrorKind::NotFound, "database has no read only key"));
+        }
+        let predicate = Predicate { column: "database_id".to_string(), operator: Operator::Equal, value: Value::new_text(database)};
+        self.read_keys_table.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
+
+
+    ///Returns the role a key grants on a database. The admin key opens every database, so admins
+    ///can connect without knowing its key
+    pub fn check_key(&self, database : String, key : String) -> Result<Role> {
+        let databases = recover(self.databases.lock(), "databases");
+        let hash = databases.get(&database).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "wrong key"))?;
+        if verify_key(&key, hash) || self.get_admin_role(key.clone()) == Some(Role::Full) {
+            return Ok(Role::Full);
+        }
+        let read_keys = recover(self.read_keys.lock(), "read keys");
+        match read_keys.get(&database) {
+            Some(hash) if verify_key(&key, hash) => Ok(Role::ReadOnly),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "wrong key")),
         }
-        return Err(Error::new(ErrorKind::Other, "thread poisoned"));
     }
 
 
 
     pub fn check_admin_key(&self, key : String) -> bool {
-        return key == self.admin_key; 
+        self.get_admin_role(key).is_some()
+    }
+
+
+
+    ///Returns the role of the admin the key belongs to or None if it is no admin key. The session
+    ///key and the key from the env file have full rights
+    pub fn get_admin_role(&self, key : String) -> Option<Role> {
+        if keys_match(&key, &self.session_key) {
+            return Some(Role::Full);
+        }
+        if verify_key(&key, &recover(self.admin_key.lock(), "admin key")) {
+            return Some(Role::Full);
+        }
+        let admins = recover(self.admins.lock(), "admins");
+        admins.values().find(|(hash, _)| verify_key(&key, hash)).map(|(_, role)| *role)
+    }
+
+
+
+    ///Adds an admin with a new random key and returns the key. Only its hash is stored
+    pub fn add_admin(&self, label : String, role : Role) -> Result<String> {
+        let mut admins = recover(self.admins.lock(), "admins");
+        if label.is_empty() || label.contains(char::is_whitespace) {
+            return Err(Error::new(ErrorKind::InvalidInput, "label has to be a single word"));
+        }
+        if admins.contains_key(&label) {
+            return Err(Error::new(ErrorKind::AlreadyExists, "admin does exist already"));
+        }
+        let key : String = generate_key();
+        let hash = hash_key(&key);
+        self.admins_table.insert_row(Row{cols: vec![Value::new_text(label.clone()), Value::new_text(hash.clone()), Value::new_text(role.as_str().to_string())]})?;
+        admins.insert(label, (hash, role));
+        Ok(key)
+    }
+
+
+
+    ///Removes an admin so its key is not accepted anymore. Connections that are authenticated
+    ///already stay open
+    pub fn revoke_admin(&self, label : String) -> Result<()> {
+        let mut admins = recover(self.admins.lock(), "admins");
+        if admins.remove(&label).is_none() {
+            return Err(Error::new(ErrorKind::NotFound, "admin does not exist"));
+        }
+        let predicate = Predicate { column: "label".to_string(), operator: Operator::Equal, value: Value::new_text(label)};
+        self.admins_table.delete_row(Some(predicate))?;
+        Ok(())
+    }
+
+
+
+    ///Returns the labels of all added admins with their roles, sorted by label
+    pub fn get_admins(&self) -> Result<Vec<(String, Role)>> {
+        let admins = recover(self.admins.lock(), "admins");
+        let mut result : Vec<(String, Role)> = admins.iter().map(|(label, (_, role))| (label.clone(), *role)).collect();
+        result.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(result)
+    }
+
+
+
+    ///Returns the admin key that is only valid while this process runs
+    pub fn get_session_key(&self) -> String {
+        self.session_key.clone()
+    }
+
+
+
+    ///Replaces the admin key with a new random one in memory and in the env file and returns it.
+    ///Only the hash is stored, so the returned key can not be shown again.
+    ///Admin connections that are authenticated already stay valid
+    pub fn rotate_admin_key(&self) -> Result<String> {
+        let mut admin_key = recover(self.admin_key.lock(), "admin key");
+        let new_key : String = generate_key();
+        let hash = hash_key(&new_key);
+        write_admin_key_hash(&self.env_path, &hash)?;
+        *admin_key = hash;
+        Ok(new_key)
     }
 
 }
 
+
+
+///Returns the values of the given text columns for every row of a table
+fn read_text_rows(table_handler : &dyn TableHandler, cols : &[&str]) -> Result<Vec<Vec<String>>> {
+    let mut rows : Vec<Vec<String>> = vec![];
+    if let Some((mut value, mut cursor)) = table_handler.select_row(None, None)? {
+        loop {
+            let mut row : Vec<String> = vec![];
+            for col in cols {
+                row.push(table_handler.get_col_from_row(value.clone(), col)?.try_into()?);
+            }
+            rows.push(row);
+            if let Some(new_value) = table_handler.next(&mut cursor)? {
+                value = new_value;
+                continue; 
+            }
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+
+
+///Writes the hash of the admin key to the env file and removes a plain admin key if there is one
+fn write_admin_key_hash(env_path : &PathBuf, hash : &str) -> Result<()> {
+
+    //Other lines of the env file are kept, the new file replaces the old one at once so the
+    //key can not get lost halfway through
+    let content = std::fs::read_to_string(env_path)?;
+    let mut lines : Vec<String> = content.lines()
+        .filter(|line| !line.trim_start().starts_with("ADMIN_KEY=") && !line.trim_start().starts_with("ADMIN_KEY_HASH="))
+        .map(|line| line.to_string())
+        .collect();
+    lines.push(format!("ADMIN_KEY_HASH=\"{}\"", hash));
+    let tmp_path = env_path.with_extension("tmp");
+    std::fs::write(&tmp_path, lines.join("\n") + "\n")?;
+    std::fs::rename(&tmp_path, env_path)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
 
@@ -322,6 +1223,88 @@ mod test {
     }
 
 
+#[test]
+    fn table_schema_cache_test() {
+        let db_path = get_test_path().unwrap();
+        delete_file(&db_path.join("schema.hive"));
+        let schema_handler = TableSchemaHandler::new(&db_path).unwrap();
+        schema_handler.add_col_data("t".to_string(), (Type::Text, "a".to_string())).unwrap();
+        assert_eq!(schema_handler.get_col_data("t".to_string()).unwrap(), vec![(Type::Text, "a".to_string())]);
+
+        //Writes have to show up in the next read although the schema was cached
+        schema_handler.add_col_data("t".to_string(), (Type::Number, "b".to_string())).unwrap();
+        assert_eq!(schema_handler.get_col_data("t".to_string()).unwrap().len(), 2);
+        schema_handler.remove_col_data("t".to_string(), "a").unwrap();
+        assert_eq!(schema_handler.get_table_data().unwrap()["t"], vec![(Type::Number, "b".to_string())]);
+        schema_handler.remove_table_data("t".to_string()).unwrap();
+        assert!(schema_handler.get_table_data().unwrap().is_empty());
+    }
+
+
+#[test]
+    fn table_schema_not_null_test() {
+        let db_path = get_test_path().unwrap().join("table_schema_not_null");
+        let _ = delete_dir(&db_path);
+        create_dir(&db_path).unwrap();
+        let schema_handler = TableSchemaHandler::new(&db_path).unwrap();
+        for col in ["a", "b", "c"] {
+            schema_handler.add_col_data("t".to_string(), (Type::Text, col.to_string())).unwrap();
+        }
+        schema_handler.add_not_null("t".to_string(), "a".to_string()).unwrap();
+        schema_handler.add_not_null("t".to_string(), "b".to_string()).unwrap();
+        drop(schema_handler);
+
+        //The flags survive a restart and dropping a col removes only its flag
+        let schema_handler = TableSchemaHandler::new(&db_path).unwrap();
+        assert_eq!(schema_handler.get_not_null("t".to_string()).unwrap(), vec!["a".to_string(), "b".to_string()]);
+        schema_handler.remove_col_data("t".to_string(), "a").unwrap();
+        assert_eq!(schema_handler.get_not_null("t".to_string()).unwrap(), vec!["b".to_string()]);
+        schema_handler.remove_not_null("t".to_string()).unwrap();
+        assert!(schema_handler.get_not_null("t".to_string()).unwrap().is_empty());
+    }
+
+
+#[test]
+    fn statistics_set_and_get_test() {
+        let db_path = get_test_path().unwrap();
+        delete_file(&metadata_path(&db_path, "statistics"));
+        let statistics_handler = StatisticsHandler::new(&db_path, DEFAULT_PAGE_SIZE, Storage::Disk).unwrap();
+        let col_data = vec![(Type::Text, "name".to_string()), (Type::Number, "age".to_string())];
+        assert_eq!(statistics_handler.get_statistics("t".to_string(), &col_data).unwrap(), None);
+        let mut statistics = TableStatistics::new(vec!["name".to_string(), "age".to_string()]);
+        statistics.add_row(&Row{cols: vec![Value::new_text("bob".to_string()), Value::new_number(12)]});
+        statistics.columns[0].distinct = 1;
+        statistics.columns[1].distinct = 1;
+        statistics_handler.set_statistics("t".to_string(), &statistics).unwrap();
+        statistics_handler.set_statistics("t".to_string(), &statistics).unwrap();
+        assert_eq!(statistics_handler.get_statistics("t".to_string(), &col_data).unwrap(), Some(statistics));
+        statistics_handler.remove_statistics("t".to_string()).unwrap();
+        assert_eq!(statistics_handler.get_statistics("t".to_string(), &col_data).unwrap(), None);
+    }
+
+
+#[test]
+    fn auto_increment_test() {
+        let db_path = get_test_path().unwrap();
+        delete_file(&metadata_path(&db_path, "auto_increment"));
+        let handler = AutoIncrementHandler::new(&db_path, DEFAULT_PAGE_SIZE, Storage::Disk).unwrap();
+        handler.add_column("t".to_string(), "id".to_string()).unwrap();
+        assert_eq!(handler.take_value("t").unwrap(), 1);
+        handler.observe_value("t", 5).unwrap();
+        handler.observe_value("t", 2).unwrap();
+        assert_eq!(handler.take_value("t").unwrap(), 6);
+        drop(handler);
+
+        //Counters survive a restart
+        let handler = AutoIncrementHandler::new(&db_path, DEFAULT_PAGE_SIZE, Storage::Disk).unwrap();
+        assert_eq!(handler.get_column("t").unwrap(), Some("id".to_string()));
+        assert_eq!(handler.take_value("t").unwrap(), 7);
+        handler.remove_table("t".to_string()).unwrap();
+        assert_eq!(handler.get_column("t").unwrap(), None);
+        assert!(handler.take_value("t").is_err());
+    }
+
+
 #[test]
     fn database_schema_handler_creation_test() {
         let db_path = get_test_path().unwrap();
@@ -330,6 +1313,31 @@ mod test {
         assert!(schema_handler.is_ok(), "TableSchemaHandler should be created successfully");
     }
 
+#[test]
+    fn rotate_admin_key_test() {
+        let base_path = get_test_path().unwrap().join("rotate_admin_key");
+        create_dir(&base_path).unwrap();
+        delete_file(&base_path.join("schema.hive"));
+        std::fs::write(base_path.join(".env"), "OTHER=\"1\"\nADMIN_KEY=\"old\"\n").unwrap();
+        let schema_handler = DatabaseSchemaHandler::new(base_path.clone()).unwrap();
+        assert!(schema_handler.check_admin_key("old".to_string()));
+
+        //The plain key is replaced by its hash when the env file is loaded
+        let content = std::fs::read_to_string(base_path.join(".env")).unwrap();
+        assert!(!content.contains("\"old\"") && content.contains("ADMIN_KEY_HASH="));
+        let key = schema_handler.rotate_admin_key().unwrap();
+        assert!(schema_handler.check_admin_key(key.clone()));
+        assert!(!schema_handler.check_admin_key("old".to_string()), "the old key should be rejected");
+
+        //The new key is persisted and other variables are kept
+        let content = std::fs::read_to_string(base_path.join(".env")).unwrap();
+        assert!(content.contains("OTHER=\"1\""));
+        assert!(!content.contains(&key), "only the hash of the key should be stored");
+        drop(schema_handler);
+        let schema_handler = DatabaseSchemaHandler::new(base_path).unwrap();
+        assert!(schema_handler.check_admin_key(key));
+    }
+
 #[test]
     fn database_schema_add_and_retrieve_test() {
         let db_path = get_test_path().unwrap();
@@ -364,16 +1372,103 @@ mod test {
 
 
     #[test]
-    fn database_schema_get_key_test() {
-        let db_path = get_test_path().unwrap();
-        delete_file(&db_path.join("schema.hive"));
-        let schema_handler = DatabaseSchemaHandler::new(get_test_path().unwrap()).unwrap();
-        let name : String = "bob".to_string();
-        let key : String = "key".to_string();
-        schema_handler.add_database(name.clone(), key.clone());
-        let result = schema_handler.get_database_key(name);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), Some(key));
+    fn database_schema_hashed_key_test() {
+        let base_path = get_test_path().unwrap().join("hashed_key");
+        create_dir(&base_path).unwrap();
+        delete_file(&base_path.join("schema.hive"));
+        let schema_handler = DatabaseSchemaHandler::new(base_path.clone()).unwrap();
+        schema_handler.add_database("bob".to_string(), "key".to_string()).unwrap();
+        assert_ne!(schema_handler.databases.lock().unwrap()["bob"], "key");
+        assert!(schema_handler.check_key("bob".to_string(), "other".to_string()).is_err());
+
+        //Keys stored in plain text by older versions are hashed when the schema is loaded
+        schema_handler.table_handler.insert_row(Row{cols: vec![Value::new_text("alice".to_string()), Value::new_text("plain".to_string())]}).unwrap();
+        drop(schema_handler);
+        let schema_handler = DatabaseSchemaHandler::new(base_path).unwrap();
+        assert!(schema_handler.databases.lock().unwrap()["alice"].starts_with(KEY_HASH_PREFIX));
+        assert_eq!(schema_handler.check_key("alice".to_string(), "plain".to_string()).unwrap(), Role::Full);
+        assert_eq!(schema_handler.check_key("bob".to_string(), "key".to_string()).unwrap(), Role::Full);
+
+        //The session key is accepted as admin key and opens every database
+        let session_key = schema_handler.get_session_key();
+        assert!(schema_handler.check_admin_key(session_key.clone()));
+        assert_eq!(schema_handler.check_key("bob".to_string(), session_key.clone()).unwrap(), Role::Full);
+        assert!(schema_handler.check_key("carol".to_string(), session_key).is_err());
+    }
+
+
+#[test]
+    fn database_schema_change_key_test() {
+        let base_path = get_test_path().unwrap().join("change_key");
+        create_dir(&base_path).unwrap();
+        delete_file(&base_path.join("schema.hive"));
+        let schema_handler = DatabaseSchemaHandler::new(base_path.clone()).unwrap();
+        schema_handler.add_database("bob".to_string(), "old".to_string()).unwrap();
+        schema_handler.change_key("bob".to_string(), "new".to_string()).unwrap();
+        assert!(schema_handler.check_key("bob".to_string(), "old".to_string()).is_err());
+        assert_eq!(schema_handler.check_key("bob".to_string(), "new".to_string()).unwrap(), Role::Full);
+        assert!(schema_handler.change_key("alice".to_string(), "new".to_string()).is_err());
+
+        //The new key is persisted
+        drop(schema_handler);
+        let schema_handler = DatabaseSchemaHandler::new(base_path).unwrap();
+        assert_eq!(schema_handler.check_key("bob".to_string(), "new".to_string()).unwrap(), Role::Full);
+        assert_eq!(schema_handler.get_database_names().unwrap(), vec!["bob".to_string()]);
+    }
+
+
+#[test]
+    fn admins_test() {
+        let base_path = get_test_path().unwrap().join("admins");
+        create_dir(&base_path).unwrap();
+        delete_file(&base_path.join("schema.hive"));
+        delete_file(&base_path.join("admins.hive"));
+        let schema_handler = DatabaseSchemaHandler::new(base_path.clone()).unwrap();
+        schema_handler.add_database("bob".to_string(), "key".to_string()).unwrap();
+        let full_key = schema_handler.add_admin("alice".to_string(), Role::Full).unwrap();
+        let read_key = schema_handler.add_admin("carol".to_string(), Role::ReadOnly).unwrap();
+        assert!(schema_handler.add_admin("alice".to_string(), Role::ReadOnly).is_err());
+        assert_eq!(schema_handler.get_admin_role(full_key.clone()), Some(Role::Full));
+        assert_eq!(schema_handler.get_admin_role(read_key.clone()), Some(Role::ReadOnly));
+        assert_eq!(schema_handler.get_admin_role("other".to_string()), None);
+
+        //Only full admins can open databases
+        assert_eq!(schema_handler.check_key("bob".to_string(), full_key.clone()).unwrap(), Role::Full);
+        assert!(schema_handler.check_key("bob".to_string(), read_key.clone()).is_err());
+
+        //Admins survive a restart until they are revoked
+        drop(schema_handler);
+        let schema_handler = DatabaseSchemaHandler::new(base_path).unwrap();
+        assert_eq!(schema_handler.get_admins().unwrap(), vec![("alice".to_string(), Role::Full), ("carol".to_string(), Role::ReadOnly)]);
+        schema_handler.revoke_admin("alice".to_string()).unwrap();
+        assert!(!schema_handler.check_admin_key(full_key));
+        assert!(schema_handler.check_admin_key(read_key));
+        assert!(schema_handler.revoke_admin("alice".to_string()).is_err());
+    }
+
+
+#[test]
+    fn read_key_test() {
+        let base_path = get_test_path().unwrap().join("read_key");
+        create_dir(&base_path).unwrap();
+        delete_file(&base_path.join("schema.hive"));
+        delete_file(&base_path.join("read_keys.hive"));
+        let schema_handler = DatabaseSchemaHandler::new(base_path.clone()).unwrap();
+        schema_handler.add_database("bob".to_string(), "key".to_string()).unwrap();
+        assert!(schema_handler.set_read_key("alice".to_string(), "read".to_string()).is_err());
+        schema_handler.set_read_key("bob".to_string(), "old".to_string()).unwrap();
+        schema_handler.set_read_key("bob".to_string(), "read".to_string()).unwrap();
+        assert!(schema_handler.check_key("bob".to_string(), "old".to_string()).is_err());
+        assert_eq!(schema_handler.check_key("bob".to_string(), "read".to_string()).unwrap(), Role::ReadOnly);
+        assert_eq!(schema_handler.check_key("bob".to_string(), "key".to_string()).unwrap(), Role::Full);
+
+        //The read only key survives a restart until it is removed
+        drop(schema_handler);
+        let schema_handler = DatabaseSchemaHandler::new(base_path).unwrap();
+        assert_eq!(schema_handler.check_key("bob".to_string(), "read".to_string()).unwrap(), Role::ReadOnly);
+        schema_handler.remove_read_key("bob".to_string()).unwrap();
+        assert!(schema_handler.check_key("bob".to_string(), "read".to_string()).is_err());
+        assert!(schema_handler.remove_read_key("bob".to_string()).is_err());
     }
 
 
@@ -387,7 +1482,7 @@ mod test {
         schema_handler.add_database(name.clone(), key.clone());
         let result = schema_handler.check_key(name, key);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
+        assert_eq!(result.unwrap(), Role::Full);
     }
 }
 
@@ -1,11 +1,13 @@
 #![allow(unused)]
 
 
-use std::{io::{ErrorKind, Result, Read, Write}, thread, sync::{atomic::AtomicBool, Arc, RwLock, Mutex, Condvar}, collections::HashMap};
-use mio::{Poll, Token, Interest, Events, Waker};
-use mio::net::{TcpListener, TcpStream};
+use std::{io::{Error, ErrorKind, Result, Read, Write, BufReader}, thread::{self, JoinHandle}, sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock, Mutex, Condvar}, collections::{HashMap, HashSet}, env, fs::{File, OpenOptions}, net::{Ipv4Addr, Ipv6Addr, SocketAddr}, os::unix::fs::FileTypeExt, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use mio::{Poll, Registry, Token, Interest, Events, Waker, event::Source};
+use mio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
 use rand::{Rng, thread_rng};
-use crate::{executor::Executor, query::{parsing::Query}, schema::DatabaseSchemaHandler, storage::{file_management::{get_base_path, create_dir, delete_dir}, table_management::{Row, Type}}};
+use log::{debug, error, info, warn};
+use crate::{bubble::Bubble, error::{recover, ErrorCode}, executor::{Executor, QueryResult}, query::{parsing::{Query, QueryCache}}, schema::{DatabaseSchemaHandler, Role}, storage::{file_management::{get_base_path, create_dir, delete_dir}, page_management::DEFAULT_PAGE_SIZE, table_management::{Row, Type, with_cancellation}}};
 
 
 const QUERY_FLAG : u8 = 0x00;
@@ -14,22 +16,461 @@ const NEW_DATABASE_FLAG : u8 = 0x02;
 const GET_KEY_FLAG : u8 = 0x03;
 const TERMINATE_FLAG : u8 = 0x04;
 const DELETE_DATABASE_FLAG : u8 = 0x05;
+const PREPARE_FLAG : u8 = 0x06;
+const EXECUTE_FLAG : u8 = 0x07;
+const CLOSE_CURSOR_FLAG : u8 = 0x08;
+const LIST_DATABASES_FLAG : u8 = 0x09;
+const ROTATE_ADMIN_KEY_FLAG : u8 = 0x0A;
+const PING_FLAG : u8 = 0x0B;
+const CHANGE_KEY_FLAG : u8 = 0x0C;
+const ADD_ADMIN_FLAG : u8 = 0x0D;
+const REVOKE_ADMIN_FLAG : u8 = 0x0E;
+const LIST_ADMINS_FLAG : u8 = 0x0F;
+const SET_READ_KEY_FLAG : u8 = 0x10;
+const REMOVE_READ_KEY_FLAG : u8 = 0x11;
+const STATS_FLAG : u8 = 0x12;
+const LIST_CONNECTIONS_FLAG : u8 = 0x13;
+const KILL_CONNECTION_FLAG : u8 = 0x14;
+const STATUS_FLAG : u8 = 0x15;
 
+//Answer to a ping
+const PONG : &[u8] = b"pong";
 
-#[derive(Clone)]
+//Every message in both directions is framed by its length as u32 in front of it, so a message
+//can span several reads
+const FRAME_HEADER_SIZE : usize = 4;
+
+//Requests beyond this size end the connection, so a client can not make the server buffer
+//arbitrary amounts of data
+const MAX_REQUEST_SIZE : usize = 16 * 1024 * 1024;
+
+//Time a response may take until the socket accepted it, afterwards the response is dropped
+const WRITE_TIMEOUT : Duration = Duration::from_secs(10);
+
+//Result of a query that runs on its own thread along with whether it was abandoned
+type QuerySlot = (Mutex<(Option<Result<QueryResult>>, bool)>, Condvar);
+
+
+#[derive(Clone, Debug)]
 pub enum ConnectionType {
-    Client,
-    Admin,
+
+    //The role decides whether a client may change its database and which admin requests are
+    //allowed
+    Client(Role),
+    Admin(Role),
+}
+
+
+
+///Stream of a connection, either plain TCP, TLS on top of it or a unix socket. All variants can be
+///read from and written to through a shared reference like a TcpStream
+pub enum ServerStream {
+    Plain(TcpStream),
+    Tls(Box<Mutex<StreamOwned<ServerConnection, TcpStream>>>),
+    Unix(UnixStream),
+}
+
+
+
+impl ServerStream {
+
+
+    ///Ends the connection, TLS connections notify the client before
+    fn shutdown(&self) {
+        match self {
+            ServerStream::Plain(stream) => {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            },
+            ServerStream::Tls(stream) => {
+                if let Ok(mut stream) = stream.lock() {
+                    let stream = &mut *stream;
+                    stream.conn.send_close_notify();
+                    let _ = stream.conn.complete_io(&mut stream.sock);
+                    let _ = stream.sock.shutdown(std::net::Shutdown::Both);
+                }
+            },
+            ServerStream::Unix(stream) => {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            },
+        }
+    }
+
+
+    ///Returns the underlying socket, e.g. to deregister it from a Poll
+    fn source(&mut self) -> &mut dyn Source {
+        match self {
+            ServerStream::Plain(stream) => stream,
+            ServerStream::Tls(stream) => &mut stream.get_mut().unwrap_or_else(|e| e.into_inner()).sock,
+            ServerStream::Unix(stream) => stream,
+        }
+    }
+
+
+}
+
+
+
+impl Read for &ServerStream {
+
+
+    fn read(&mut self, buf : &mut [u8]) -> Result<usize> {
+        match self {
+            ServerStream::Plain(stream) => (&*stream).read(buf),
+            ServerStream::Tls(stream) => {
+
+                //Clients that close the socket without notifying are treated like a normal close.
+                //While the handshake is not complete reads return WouldBlock
+                let mut stream = stream.lock().map_err(|_| Error::other("thread poisoned"))?;
+                match stream.read(buf) {
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(0),
+                    result => result,
+                }
+            },
+            ServerStream::Unix(stream) => (&*stream).read(buf),
+        }
+    }
+
+
+}
+
+
+
+impl Write for &ServerStream {
+
+
+    fn write(&mut self, buf : &[u8]) -> Result<usize> {
+        match self {
+            ServerStream::Plain(stream) => (&*stream).write(buf),
+            ServerStream::Tls(stream) => stream.lock().map_err(|_| Error::other("thread poisoned"))?.write(buf),
+            ServerStream::Unix(stream) => (&*stream).write(buf),
+        }
+    }
+
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            ServerStream::Plain(stream) => (&*stream).flush(),
+            ServerStream::Tls(stream) => stream.lock().map_err(|_| Error::other("thread poisoned"))?.flush(),
+            ServerStream::Unix(stream) => (&*stream).flush(),
+        }
+    }
+
+
+}
+
+
+
+///Reads everything the non blocking stream has to offer right now into buffer and takes the
+///complete requests out of it. Incomplete requests stay in buffer until the rest arrives. Returns
+///the requests along with whether the peer closed the connection
+fn read_frames(mut stream : &ServerStream, buffer : &mut Vec<u8>) -> Result<(Vec<Vec<u8>>, bool)> {
+    let mut closed = false;
+    let mut chunk = [0u8; 4096];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => {
+                closed = true;
+                break;
+            },
+            Ok(len) => buffer.extend(&chunk[..len]),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    let mut requests : Vec<Vec<u8>> = vec![];
+    while buffer.len() >= FRAME_HEADER_SIZE {
+        let len = u32::from_le_bytes(buffer[..FRAME_HEADER_SIZE].try_into().expect("frame header has a fixed size")) as usize;
+        if len > MAX_REQUEST_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("request of {} bytes exceeds the limit of {} bytes", len, MAX_REQUEST_SIZE)));
+        }
+        if buffer.len() < FRAME_HEADER_SIZE + len {
+            break;
+        }
+        requests.push(buffer[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len].to_vec());
+        buffer.drain(..FRAME_HEADER_SIZE + len);
+    }
+    Ok((requests, closed))
+}
+
+
+
+///Writes message to the non blocking stream as one frame. Writing is retried while the socket is
+///full, until WRITE_TIMEOUT passed
+fn write_frame(mut stream : &ServerStream, message : &[u8]) -> Result<()> {
+    let len = u32::try_from(message.len()).map_err(|_| Error::new(ErrorKind::InvalidInput, "response is too long for a frame"))?;
+    let mut frame : Vec<u8> = Vec::with_capacity(FRAME_HEADER_SIZE + message.len());
+    frame.extend(len.to_le_bytes());
+    frame.extend(message);
+    let deadline = Instant::now() + WRITE_TIMEOUT;
+    let retry = |e : Error| -> Result<()> {
+        match e.kind() {
+            ErrorKind::WouldBlock if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(1));
+                Ok(())
+            },
+            ErrorKind::Interrupted => Ok(()),
+            _ => Err(e),
+        }
+    };
+    let mut written = 0;
+    while written < frame.len() {
+        match stream.write(&frame[written..]) {
+            Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "connection does not accept the response")),
+            Ok(len) => written += len,
+            Err(e) => retry(e)?,
+        }
+    }
+    while let Err(e) = stream.flush() {
+        retry(e)?;
+    }
+    Ok(())
+}
+
+
+
+///Listener for connections on a socket address or on a unix socket
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+
+
+impl Listener {
+
+
+    ///Returns the underlying socket to register it with a Poll
+    fn source(&mut self) -> &mut dyn Source {
+        match self {
+            Listener::Tcp(listener) => listener,
+            Listener::Unix(listener) => listener,
+        }
+    }
+
+
+}
+
+
+
+///Binds a unix socket at path. A socket file that was left behind by an earlier run is replaced,
+///any other file at path is kept and an error is returned
+fn bind_unix_socket(path : &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+        }
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+
+
+///Parses a comma separated list of socket addresses, IPv6 addresses are written in brackets
+fn parse_addresses(addresses : &str) -> Result<Vec<SocketAddr>> {
+    let parsed = addresses.split(',').map(str::trim).filter(|address| !address.is_empty()).map(|address| {
+        address.parse::<SocketAddr>().map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} is not a socket address, use e.g. 127.0.0.1:4321 or [::1]:4321", address)))
+    }).collect::<Result<Vec<SocketAddr>>>()?;
+    if parsed.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "no address to listen on was given"));
+    }
+    Ok(parsed)
+}
+
+
+
+///Loads the certificate chain and private key the server presents to TLS clients
+fn load_tls_config(cert_path : &Path, key_path : &Path) -> Result<Arc<ServerConfig>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?)).collect::<Result<Vec<_>>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key_path)?))?.ok_or_else(|| Error::new(ErrorKind::InvalidData, "key file did not contain a private key"))?;
+    let config = ServerConfig::builder().with_no_client_auth().with_single_cert(certs, key).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(Arc::new(config))
+}
+
+
+
+///Limits how many requests a connection may send. Every request takes a token and tokens are
+///refilled at a constant rate up to the capacity, which allows short bursts
+struct TokenBucket {
+    rate : f64,
+    capacity : f64,
+    tokens : f64,
+    last_refill : Instant,
+}
+
+
+
+impl TokenBucket {
+
+
+    ///Creates a full bucket that allows rate requests per second
+    fn new(rate : u32) -> Self {
+        TokenBucket{rate: rate as f64, capacity: rate as f64, tokens: rate as f64, last_refill: Instant::now()}
+    }
+
+
+    ///Takes a token if one is left and returns whether the request is allowed
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+
+}
+
+
+
+///Appends a line for every audited action to a file. The mutex keeps the lines of concurrent
+///workers apart
+struct AuditLog {
+    file : Mutex<File>,
+
+    //Mutation queries are only audited if AUDIT_QUERIES is set, since there can be many of them
+    log_queries : bool,
+}
+
+
+
+impl AuditLog {
+
+
+    ///Opens the log at path, the file is created if it does not exist yet
+    fn open(path : &Path, log_queries : bool) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog{file: Mutex::new(file), log_queries})
+    }
+
+
+    ///Appends the action with the current unix time and the token of the connection that caused
+    ///it
+    fn record(&self, token : Token, action : &str) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_err(Error::other)?.as_secs();
+        let line = format!("{} token {} {}\n", timestamp, token.0, action.replace('\n', " "));
+        let mut file = self.file.lock().map_err(|_| Error::other("thread poisoned"))?;
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+
+}
+
+
+
+///An authenticated connection along with the state the server keeps for it
+struct ConnectionState {
+    database : String,
+    connection_type : ConnectionType,
+    stream : Arc<ServerStream>,
+
+    //Time of the last message, used to end idle connections
+    last_activity : Instant,
+
+    //Hashes of the cursors the connection opened, they are closed with the connection
+    cursors : HashSet<Vec<u8>>,
+
+    //Limits the requests of client connections if RATE_LIMIT is set
+    bucket : Option<TokenBucket>,
+
+    //Received bytes of a request that did not arrive completely yet. It is locked while the
+    //requests of the connection are handled, so they are answered in the order they arrived
+    buffer : Arc<Mutex<Vec<u8>>>,
+}
+
+
+
+impl ConnectionState {
+
+
+    fn new(database : String, connection_type : ConnectionType, stream : ServerStream, rate_limit : Option<u32>) -> Self {
+        let bucket = match connection_type {
+            ConnectionType::Client(_) => rate_limit.map(TokenBucket::new),
+            ConnectionType::Admin(_) => None,
+        };
+        ConnectionState{database, connection_type, stream: Arc::new(stream), last_activity: Instant::now(), cursors: HashSet::new(), bucket, buffer: Arc::new(Mutex::new(vec![]))}
+    }
+
+
 }
 
 
 
+///Seconds a client connection may stay silent before it is ended, unless IDLE_TIMEOUT is set in
+///the env file. A timeout of 0 keeps idle connections open
+const DEFAULT_IDLE_TIMEOUT : u64 = 600;
+
+
+///Seconds a cursor may stay unused before it expires, unless CURSOR_TTL is set in the env file. A
+///ttl of 0 keeps cursors until they are exhausted or closed
+const DEFAULT_CURSOR_TTL : u64 = 600;
+
+
+///Number of query strings whose parsed statements are kept, unless QUERY_CACHE_SIZE is set in the
+///env file. A size of 0 parses every query
+const DEFAULT_QUERY_CACHE_SIZE : usize = 256;
+
+
+///Addresses the client and admin listeners bind to, unless LISTEN_ADDRESSES or
+///ADMIN_LISTEN_ADDRESSES are set in the env file as comma separated lists
+const DEFAULT_LISTEN_ADDRESSES : &str = "127.0.0.1:4321";
+const DEFAULT_ADMIN_LISTEN_ADDRESSES : &str = "127.0.0.1:4322";
+
+
+///Milliseconds a query may take before it is logged as slow, unless SLOW_QUERY_THRESHOLD is set in
+///the env file
+const DEFAULT_SLOW_QUERY_THRESHOLD : u64 = 1000;
+
+
+
 pub struct Server {
     executors : RwLock<HashMap<String, Arc<Executor>>>,
     database_schema : DatabaseSchemaHandler,
     work : Mutex<Vec<Option<Arc<Token>>>>,
     condvar : Condvar,
-    connections : Mutex<HashMap<Token, (String, ConnectionType, Arc<TcpStream>)>>,
+    connections : Mutex<HashMap<Token, ConnectionState>>,
+
+    //A listener is bound to every address, connections are handled the same no matter which
+    //listener accepted them
+    addresses : Vec<SocketAddr>,
+    admin_addresses : Vec<SocketAddr>,
+
+    //Local connections may also use unix sockets at UNIX_SOCKET_PATH and ADMIN_UNIX_SOCKET_PATH
+    unix_socket : Option<PathBuf>,
+    admin_unix_socket : Option<PathBuf>,
+
+    //Connections are encrypted if the env file contains TLS_CERT_PATH and TLS_KEY_PATH, otherwise
+    //they use plain TCP which is fine for local use
+    tls_config : Option<Arc<ServerConfig>>,
+    idle_timeout : Option<Duration>,
+    cursor_ttl : Option<Duration>,
+
+    //Requests per second a client connection may send, unlimited if RATE_LIMIT is not set
+    rate_limit : Option<u32>,
+
+    //Time a query may run before it is abandoned, unlimited if QUERY_TIMEOUT is not set
+    query_timeout : Option<Duration>,
+
+    //Queries that take longer are logged as a warning
+    slow_query_threshold : Duration,
+
+    //Parsed statements of recent query strings, shared by all databases since parsing does not
+    //depend on the schema
+    query_cache : QueryCache,
+
+    //Records admin actions and optionally mutation queries in audit.log
+    audit_log : AuditLog,
+
+    //Set when the server shuts down so threads that do not take work end as well
+    stopped : AtomicBool,
+
+    //Time the server was created at, the status reports the uptime from it
+    started : Instant,
 }
 
 
@@ -54,33 +495,128 @@ impl Server {
         let work = Mutex::new(Vec::new());
         let condvar = Condvar::new();
         let connections = Mutex::new(HashMap::new());
-        let mut server = Server{work, database_schema, condvar, executors: RwLock::new(executors), connections};
+        let _ = dotenv::from_path(path.join(".env"));
+        let tls_config = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+            (Ok(cert_path), Ok(key_path)) => Some(load_tls_config(Path::new(&cert_path), Path::new(&key_path)).expect("couldnt load tls certificate and key")),
+            _ => None,
+        };
+        let addresses = parse_addresses(&env::var("LISTEN_ADDRESSES").unwrap_or(DEFAULT_LISTEN_ADDRESSES.to_string())).unwrap_or_else(|e| panic!("LISTEN_ADDRESSES is invalid: {}", e));
+        let admin_addresses = parse_addresses(&env::var("ADMIN_LISTEN_ADDRESSES").unwrap_or(DEFAULT_ADMIN_LISTEN_ADDRESSES.to_string())).unwrap_or_else(|e| panic!("ADMIN_LISTEN_ADDRESSES is invalid: {}", e));
+        let unix_socket = env::var("UNIX_SOCKET_PATH").ok().map(PathBuf::from);
+        let admin_unix_socket = env::var("ADMIN_UNIX_SOCKET_PATH").ok().map(PathBuf::from);
+        let idle_timeout = match env::var("IDLE_TIMEOUT") {
+            Ok(seconds) => seconds.trim().parse::<u64>().expect("IDLE_TIMEOUT has to be a number of seconds"),
+            Err(_) => DEFAULT_IDLE_TIMEOUT,
+        };
+        let idle_timeout = if idle_timeout == 0 { None } else { Some(Duration::from_secs(idle_timeout)) };
+        let cursor_ttl = match env::var("CURSOR_TTL") {
+            Ok(seconds) => seconds.trim().parse::<u64>().expect("CURSOR_TTL has to be a number of seconds"),
+            Err(_) => DEFAULT_CURSOR_TTL,
+        };
+        let cursor_ttl = if cursor_ttl == 0 { None } else { Some(Duration::from_secs(cursor_ttl)) };
+        let rate_limit = env::var("RATE_LIMIT").ok().map(|rate| rate.trim().parse::<u32>().expect("RATE_LIMIT has to be a number of requests per second")).filter(|rate| *rate > 0);
+        let query_timeout = env::var("QUERY_TIMEOUT").ok().map(|seconds| seconds.trim().parse::<u64>().expect("QUERY_TIMEOUT has to be a number of seconds")).filter(|seconds| *seconds > 0).map(Duration::from_secs);
+        let slow_query_threshold = match env::var("SLOW_QUERY_THRESHOLD") {
+            Ok(millis) => Duration::from_millis(millis.trim().parse::<u64>().expect("SLOW_QUERY_THRESHOLD has to be a number of milliseconds")),
+            Err(_) => Duration::from_millis(DEFAULT_SLOW_QUERY_THRESHOLD),
+        };
+        let query_cache_size = match env::var("QUERY_CACHE_SIZE") {
+            Ok(size) => size.trim().parse::<usize>().expect("QUERY_CACHE_SIZE has to be a number of queries"),
+            Err(_) => DEFAULT_QUERY_CACHE_SIZE,
+        };
+        let audit_queries = env::var("AUDIT_QUERIES").is_ok_and(|value| value.trim() == "true");
+        let audit_log = AuditLog::open(&path.join("audit.log"), audit_queries).expect("couldnt open audit log");
+        let mut server = Server{work, database_schema, condvar, executors: RwLock::new(executors), connections, addresses, admin_addresses, unix_socket, admin_unix_socket, tls_config, idle_timeout, cursor_ttl, rate_limit, query_timeout, slow_query_threshold, query_cache: QueryCache::new(query_cache_size), audit_log, stopped: AtomicBool::new(false), started: Instant::now()};
         let server_arc : Arc<Self> = Arc::new(server);
         return server_arc;
     }
 
 
-    const SERVER : Token = Token(0);
-    const ADMIN_SERVER : Token = Token(1);
-    const TERMINATE : Token = Token(2);
+    ///Returns an admin key that is only valid while this process runs, it is used by the cli
+    pub fn get_session_key(&self) -> String {
+        self.database_schema.get_session_key()
+    }
+
+
+    ///Returns the client and admin address the cli connects to, which are the first ones. An
+    ///unspecified ip like 0.0.0.0 is replaced by the loopback address
+    pub fn cli_addresses(&self) -> (SocketAddr, SocketAddr) {
+        let local = |address : &SocketAddr| {
+            let mut address = *address;
+            if address.ip().is_unspecified() {
+                address.set_ip(if address.is_ipv4() { Ipv4Addr::LOCALHOST.into() } else { Ipv6Addr::LOCALHOST.into() });
+            }
+            address
+        };
+        (local(&self.addresses[0]), local(&self.admin_addresses[0]))
+    }
+
+
+    ///Wraps an accepted stream in a TLS session if the server is configured to use TLS
+    fn wrap_stream(&self, stream : TcpStream) -> Result<ServerStream> {
+        Ok(match &self.tls_config {
+            Some(config) => {
+                let connection = ServerConnection::new(config.clone()).map_err(Error::other)?;
+                ServerStream::Tls(Box::new(Mutex::new(StreamOwned::new(connection, stream))))
+            },
+            None => ServerStream::Plain(stream),
+        })
+    }
+
+
+    ///Accepts a connection on listener and registers it with token. TCP connections are encrypted
+    ///if TLS is configured, unix socket connections stay plain since they do not leave the machine
+    fn accept(&self, listener : &Listener, registry : &Registry, token : Token) -> Result<ServerStream> {
+        match listener {
+            Listener::Tcp(listener) => {
+                let (mut stream, _) = listener.accept()?;
+                stream.set_nodelay(true);
+                registry.register(&mut stream, token, Interest::READABLE.add(Interest::WRITABLE))?;
+                self.wrap_stream(stream)
+            },
+            Listener::Unix(listener) => {
+                let (mut stream, _) = listener.accept()?;
+                registry.register(&mut stream, token, Interest::READABLE.add(Interest::WRITABLE))?;
+                Ok(ServerStream::Unix(stream))
+            },
+        }
+    }
+
+
+    const TERMINATE : Token = Token(0);
+
+    //Listeners get the tokens from this one on, connections the tokens after the listeners
+    const FIRST_LISTENER : usize = 1;
 
 
     pub fn start(self: Arc<Self>, num_thread : usize) -> Result<()> {
 
-        //Set up TcpListener for client and admin connections
-        let mut listener :TcpListener = TcpListener::bind("127.0.0.1:4321".parse().unwrap())?;
-        let mut admin_listener : TcpListener = TcpListener::bind("127.0.0.1:4322".parse().unwrap())?;
+        //Set up a listener for every client and admin address and unix socket. The role is only
+        //known after authentication, until then connections get the least rights
+        let mut listeners : Vec<(Listener, String, ConnectionType)> = vec![];
+        for (addresses, unix_socket, connection_type) in [(&self.addresses, &self.unix_socket, ConnectionType::Client(Role::ReadOnly)), (&self.admin_addresses, &self.admin_unix_socket, ConnectionType::Admin(Role::ReadOnly))] {
+            for address in addresses {
+                let listener = TcpListener::bind(*address).map_err(|e| Error::new(e.kind(), format!("failed to listen on {}: {}", address, e)))?;
+                listeners.push((Listener::Tcp(listener), address.to_string(), connection_type.clone()));
+            }
+            if let Some(path) = unix_socket {
+                let listener = bind_unix_socket(path).map_err(|e| Error::new(e.kind(), format!("failed to listen on {}: {}", path.display(), e)))?;
+                listeners.push((Listener::Unix(listener), path.display().to_string(), connection_type.clone()));
+            }
+        }
 
-        //Map with yet unauthorized connections
-        let mut pending : HashMap<Token, (ConnectionType, TcpStream)> = HashMap::new();
+        //Map with yet unauthorized connections and the part of their credentials that arrived
+        let mut pending : HashMap<Token, (ConnectionType, ServerStream, Vec<u8>)> = HashMap::new();
         let mut poll : Poll = Poll::new()?;
 
         //Waker is used to handle a termination event
         let waker : Arc<Waker> = Arc::new(Waker::new(poll.registry(), Self::TERMINATE)?);
         let mut events : Events = Events::with_capacity(128);
-        let mut token_value = 3;
-        poll.registry().register(&mut listener, Self::SERVER, Interest::READABLE)?;
-        poll.registry().register(&mut admin_listener, Self::ADMIN_SERVER, Interest::READABLE)?;
+        let mut token_value = Self::FIRST_LISTENER + listeners.len();
+        for (i, (listener, name, connection_type)) in listeners.iter_mut().enumerate() {
+            poll.registry().register(listener.source(), Token(Self::FIRST_LISTENER + i), Interest::READABLE)?;
+            info!("listening for {:?} connections on {}", connection_type, name);
+        }
 
         //Worker threads get set up
         let mut threads = Vec::new();
@@ -90,64 +626,43 @@ impl Server {
             threads.push(thread::spawn(move || server_clone.handle_client(waker_clone)));
         }
 
+        //The sweeper ends idle connections and expires unused cursors until the server is stopped
+        let sweeper = (self.idle_timeout.is_some() || self.cursor_ttl.is_some()).then(|| {
+            let server_clone : Arc<Server> = Arc::clone(&self);
+            thread::spawn(move || {
+                while !server_clone.stopped.load(Ordering::Acquire) {
+                    thread::sleep(Duration::from_secs(1));
+                    if let Some(timeout) = server_clone.idle_timeout {
+                        server_clone.remove_idle_connections(timeout);
+                    }
+                    if let Some(ttl) = server_clone.cursor_ttl {
+                        server_clone.expire_cursors(ttl);
+                    }
+                }
+            })
+        });
+
         //Handle incoming events
-        loop {
+        'poll: loop {
             poll.poll(&mut events, None)?;
             for event in events.iter() {
                 match event.token() {
-                    Self::TERMINATE => {
-
-                        //Place none as poison pill into the work vec
-                        if let Ok(mut work) = self.work.lock() {
-                            for _ in 0..num_thread {
-                                work.push(None);
-                                self.condvar.notify_one();
-                            }
-                        }
-
-                        //Wait for threads to finish then exit
-                        for thread in threads {
-                            thread.join();
-                        }
-                        std::process::exit(0);
-                    },
-                    Self::SERVER => {
-                        loop {
-
-                            //Accept incoming client connections and place them into the pending
-                            //vec with the client flag
-                            match listener.accept() {
-                                Ok((mut stream, _)) => {
-                                    let token = Token(token_value);
-                                    token_value += 1;
-                                    stream.set_nodelay(true);
-                                    poll.registry().register(&mut stream, token, Interest::READABLE.add(Interest::WRITABLE))?;
-                                    pending.insert(token, (ConnectionType::Client, stream));
-                                },
-                                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
-                                Err(e) => {
-                                    println!("{}",e);
-                                    break;
-                                },
-                            }
-                        }
-                    },
-                    Self::ADMIN_SERVER => {
+                    Self::TERMINATE => break 'poll,
+                    listener_token if (Self::FIRST_LISTENER..Self::FIRST_LISTENER + listeners.len()).contains(&listener_token.0) => {
+                        let (listener, name, connection_type) = &listeners[listener_token.0 - Self::FIRST_LISTENER];
                         loop {
 
-                            //Accept incoming admin connections and place them into the pending vec
-                            //with an admin flag
-                            match admin_listener.accept() {
-                                Ok((mut stream, _)) => {
-                                    let token = Token(token_value);
+                            //Accept incoming connections and place them into the pending map with
+                            //the type of the listener that accepted them
+                            let token = Token(token_value);
+                            match self.accept(listener, poll.registry(), token) {
+                                Ok(stream) => {
                                     token_value += 1;
-                                    stream.set_nodelay(true);
-                                    poll.registry().register(&mut stream, token, Interest::READABLE.add(Interest::WRITABLE))?;
-                                    pending.insert(token, (ConnectionType::Admin, stream));
+                                    pending.insert(token, (connection_type.clone(), stream, vec![]));
                                 },
                                 Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
                                 Err(e) => {
-                                    println!("{}",e);
+                                    error!("failed to accept connection on {}: {}", name, e);
                                     break;
                                 },
                             }
@@ -158,44 +673,45 @@ impl Server {
                         //The first message on each connection should always be a (database and) the
                         //fitting key, otherwise the connection is ended and removed from the
                         //pending vec
-                        let (connection_type, mut stream) = pending.remove(&token).unwrap();
-                        let mut buff = [0u8; 512];
-                        match stream.read(&mut buff) {
-                            Ok(len) => {
-                                if let Ok(credentials) = String::from_utf8(buff[..len].to_vec()) {
+                        let (connection_type, mut stream, mut buffer) = pending.remove(&token).unwrap();
+                        match read_frames(&stream, &mut buffer) {
+                            Ok((requests, closed)) => {
+
+                                //Sometimes an event is registered but the credentials did not
+                                //arrive completely yet. In this case the connection stays pending
+                                //until the rest arrives
+                                let credentials = match requests.into_iter().next() {
+                                    Some(credentials) => credentials,
+                                    None if closed => continue,
+                                    None => {
+                                        pending.insert(token, (connection_type, stream, buffer));
+                                        continue;
+                                    },
+                                };
+                                if let Ok(credentials) = String::from_utf8(credentials) {
                                     match connection_type {
-                                        ConnectionType::Admin => {
+                                        ConnectionType::Admin(_) => {
 
                                             //An admin connection does not require the database
                                             //name
-                                            if self.database_schema.check_admin_key(credentials) {
-                                                stream.write_all(&[0u8; 1]);
-                                                stream.flush();
-                                                if let Ok(mut connections) = self.connections.lock() {
-                                                    let stream_arc = Arc::new(stream);
-                                                    connections.insert(token, (String::new(), connection_type, stream_arc));
-                                                }
+                                            if let Some(role) = self.database_schema.get_admin_role(credentials) {
+                                                write_frame(&stream, &[0u8; 1]);
+                                                recover(self.connections.lock(), "connections").insert(token, ConnectionState::new(String::new(), ConnectionType::Admin(role), stream, self.rate_limit));
                                             } else {
-                                                poll.registry().deregister(&mut stream);
-                                                stream.write_all(&[1u8; 1]);
-                                                stream.flush();
+                                                poll.registry().deregister(stream.source());
+                                                write_frame(&stream, &[1u8; 1]);
                                             }
                                         },
-                                        ConnectionType::Client => {
+                                        ConnectionType::Client(_) => {
                                             if let Some((database, key)) = credentials.split_once(".") {
                                                 match self.database_schema.check_key(database.to_string(), key.to_string()) {
-                                                    Ok(true) => {
-                                                        stream.write_all(&[0u8; 1]);
-                                                        stream.flush();
-                                                        if let Ok(mut connections) = self.connections.lock() {
-                                                            let stream_arc = Arc::new(stream);
-                                                            connections.insert(token, (database.to_string(), connection_type, stream_arc));
-                                                        }
+                                                    Ok(role) => {
+                                                        write_frame(&stream, &[0u8; 1]);
+                                                        recover(self.connections.lock(), "connections").insert(token, ConnectionState::new(database.to_string(), ConnectionType::Client(role), stream, self.rate_limit));
                                                     }
                                                     _ => {
-                                                        poll.registry().deregister(&mut stream);
-                                                        stream.write_all(&[1u8; 1]);
-                                                        stream.flush();
+                                                        poll.registry().deregister(stream.source());
+                                                        write_frame(&stream, &[1u8; 1]);
                                                     },
                                                 }
                                             }
@@ -205,16 +721,8 @@ impl Server {
                                     continue;
                                 }
                             },
-
-                            //Sometimes an event is registered but the connection is not yet ready
-                            //to be read from. In this case the error is caught and the loop is
-                            //continued with the connection
-                            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                                pending.insert(token, (connection_type, stream));
-                                break;
-                            },
                             Err(e) =>{
-                                println!("{}", e);
+                                warn!("failed to authenticate connection: {}", e);
                             }
                         }
                     },
@@ -222,14 +730,54 @@ impl Server {
 
                         //All other incoming messages from connections are passed to the workers
                         //via the work vec
-                        if let Ok(mut work) = self.work.lock() {
-                            work.push(Some(Arc::new(token)));
-                            self.condvar.notify_one();
-                        }
+                        recover(self.work.lock(), "work").push(Some(Arc::new(token)));
+                        self.condvar.notify_one();
                     },
                 }
             }
         }
+
+        //No new connections are accepted while the server shuts down
+        drop(listeners);
+        for path in [&self.unix_socket, &self.admin_unix_socket].into_iter().flatten() {
+            let _ = std::fs::remove_file(path);
+        }
+        drop(pending);
+        self.stop(threads)?;
+        if let Some(sweeper) = sweeper {
+            sweeper.join().map_err(|_| Error::other("sweeper thread panicked"))?;
+        }
+        Ok(())
+    }
+
+
+    ///Lets the workers finish the work that is already queued and joins them. Afterwards all
+    ///connections are ended and the tables of every database are written to disk. May return
+    ///errors!
+    fn stop(&self, workers : Vec<JoinHandle<()>>) -> Result<()> {
+        self.stopped.store(true, Ordering::Release);
+        info!("stopping server");
+
+        //Poison pills are placed at the bottom of the work vec, so workers only take them once
+        //all queued work is done
+        {
+            let mut work = recover(self.work.lock(), "work");
+            for _ in 0..workers.len() {
+                work.insert(0, None);
+            }
+            self.condvar.notify_all();
+        }
+        for worker in workers {
+            worker.join().map_err(|_| Error::other("worker thread panicked"))?;
+        }
+        let tokens : Vec<Token> = recover(self.connections.lock(), "connections").keys().copied().collect();
+        for token in tokens {
+            self.remove_connection(token);
+        }
+        let executors = self.executors.read().map_err(|_| Error::other("thread poisoned"))?;
+        for executor in executors.values() {
+            executor.sync()?;
+        }
         return Ok(());
     }
 
@@ -238,151 +786,510 @@ impl Server {
 
             //continuously wait for new work
             loop {
-                let ((database, connection_type, mut stream), token) : ((String, ConnectionType, Arc<TcpStream>), Token) = match self.work.lock() {
-                    Ok(mut work) => {
-                        while work.is_empty() {
-                            work = self.condvar.wait(work).expect("thread poisoned")
-                        }
-                        match work.pop().expect("unexpected error: work was empty") {
-                            Some(token) => {
-                                if let Ok(mut connections) = self.connections.lock() {
-                                    if let Some(connection) = connections.get_mut(&token) {
-                                        (connection.clone(), *token)
-                                    }else {
-                                        continue 'outer;
-                                    }
-                                }else {
-                                    continue 'outer;
-                                }
-                            },
+                let ((database, connection_type, mut stream, buffer), token) = {
+                    let mut work = recover(self.work.lock(), "work");
+                    while work.is_empty() {
+                        work = recover(self.condvar.wait(work), "work");
+                    }
+                    match work.pop().expect("unexpected error: work was empty") {
+                        Some(token) => {
+                            drop(work);
+                            if let Some(connection) = recover(self.connections.lock(), "connections").get_mut(&token) {
+                                ((connection.database.clone(), connection.connection_type.clone(), connection.stream.clone(), connection.buffer.clone()), *token)
+                            }else {
+                                continue 'outer;
+                            }
+                        },
 
-                            //Poison pill
-                            None => return,
-                        }
-                    },
-                    Err(_) => continue 'outer,
+                        //Poison pill
+                        None => return,
+                    }
                 };
 
-                //Read from connection
-                let mut buff = [0u8; 512];
-                match stream.as_ref().read(&mut buff) {
-                    Ok(0) => {
-                        if let Ok(mut connections) = self.connections.lock() {
-                            connections.remove(&token);
-                        }else{
-                            println!("error, failed to end connection");
+                //Read from connection, a request may arrive in several parts and several requests
+                //may arrive at once
+                let mut buffer = recover(buffer.lock(), "buffer");
+                match read_frames(&stream, &mut buffer) {
+                    Ok((requests, closed)) => {
+                        for req in requests {
+                            self.handle_request(token, database.clone(), connection_type.clone(), req, stream.clone(), &terminate);
                         }
-                    }
-                    Ok(len) => {
-                        let mut req = buff.to_vec();
-                        req.truncate(len);
-
-                        //Check the first byte and the type of connection
-                        match (connection_type, req.remove(0)) {
-                            (ConnectionType::Client, QUERY_FLAG) => {
-                                let q = String::from_utf8_lossy(&req).to_string();
-                                self.query(database, q, stream);
-                            },
-                            (ConnectionType::Client, CURSOR_FLAG) => {
-                                self.next(database, req.to_vec(), stream);
-                            },
-                            (ConnectionType::Admin, NEW_DATABASE_FLAG) => {
-                                self.new_database(String::from_utf8_lossy(&req).to_string(), stream);
-                            },
-                            (ConnectionType::Admin, DELETE_DATABASE_FLAG) => {
-                                self.delete_database(String::from_utf8_lossy(&req).to_string(), stream);
-                            },
-                            (ConnectionType::Admin, GET_KEY_FLAG) => {
-                                self.get_key(String::from_utf8_lossy(&req).to_string(), stream);
-                            },
-                            (ConnectionType::Admin, TERMINATE_FLAG) => {
-                                terminate.wake().expect("failed to terminate");  
-                            },
-                            _ => println!("Invalid flag"),
+                        if closed {
+                            self.remove_connection(token);
                         }
-                    }
+                    },
 
-                    //If a connection was not ready to be read from ignore the work package
-                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                    }
+                    //A request beyond the size limit can not be skipped, so the connection is ended
+                    Err(e) if e.kind() == ErrorKind::InvalidData => {
+                        warn!("ending connection: {}", e);
+                        self.remove_connection(token);
+                    },
                     Err(e) => {
-                        println!("error: {}", e);
+                        error!("failed to read request: {}", e);
                         continue;
                     },
                 }
             }
     }
 
-    fn query(&self, database : String, args: String, mut stream : Arc<TcpStream>) {
-        let mut response : Vec<u8> = vec![];
-        match Query::from(args) {
-            Ok(query) => {
+    ///Answers a single request of a connection
+    fn handle_request(&self, token : Token, database : String, connection_type : ConnectionType, mut req : Vec<u8>, mut stream : Arc<ServerStream>, terminate : &Waker) {
+        if req.is_empty() {
+            warn!("empty request on connection {}", token.0);
+            return;
+        }
+        let mut allowed = true;
+        if let Some(connection) = recover(self.connections.lock(), "connections").get_mut(&token) {
+            connection.last_activity = Instant::now();
+            allowed = connection.bucket.as_mut().is_none_or(|bucket| bucket.try_take());
+        }
+
+        //Requests beyond the rate limit are answered with an error instead of being executed. Pings
+        //are always answered, so a limited client is not mistaken for a dead connection
+        if !allowed && req.first() != Some(&PING_FLAG) {
+            let response = Self::encode_error(&Error::new(ErrorKind::WouldBlock, "rate limited"));
+            write_frame(&stream, &response);
+            return;
+        }
+
+        //Check the first byte and the type of connection
+        match (connection_type, req.remove(0)) {
+            (ConnectionType::Client(role), QUERY_FLAG) => {
+                let q = String::from_utf8_lossy(&req).to_string();
+                self.query(token, database, role, q, stream);
+            },
+            (ConnectionType::Client(_), CURSOR_FLAG) => {
+                self.next(token, database, req.to_vec(), stream);
+            },
+            (ConnectionType::Client(_), CLOSE_CURSOR_FLAG) => {
+                self.close_cursor(token, database, req.to_vec(), stream);
+            },
+            (ConnectionType::Client(role), PREPARE_FLAG) => {
+                let q = String::from_utf8_lossy(&req).to_string();
+                self.prepare(database, role, q, stream);
+            },
+            (ConnectionType::Client(role), EXECUTE_FLAG) => {
+                self.execute_prepared(token, database, role, req.to_vec(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), NEW_DATABASE_FLAG) => {
+                self.audit(token, &format!("new_database {}", String::from_utf8_lossy(&req)));
+                self.new_database(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), DELETE_DATABASE_FLAG) => {
+                self.audit(token, &format!("delete_database {}", String::from_utf8_lossy(&req)));
+                self.delete_database(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), GET_KEY_FLAG) => {
+                self.get_key(stream);
+            },
+            (ConnectionType::Admin(_), LIST_DATABASES_FLAG) => {
+                self.list_databases(stream);
+            },
+            (ConnectionType::Admin(_), STATS_FLAG) => {
+                self.stats(stream);
+            },
+            (ConnectionType::Admin(_), STATUS_FLAG) => {
+                self.status(stream);
+            },
+            (ConnectionType::Admin(Role::Full), LIST_CONNECTIONS_FLAG) => {
+                self.list_connections(stream);
+            },
+            (ConnectionType::Admin(Role::Full), KILL_CONNECTION_FLAG) => {
+                self.audit(token, &format!("kill {}", String::from_utf8_lossy(&req)));
+                self.kill_connection(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), CHANGE_KEY_FLAG) => {
+                self.audit(token, &format!("change_key {}", String::from_utf8_lossy(&req)));
+                self.change_key(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), ROTATE_ADMIN_KEY_FLAG) => {
+                self.audit(token, "rotate_admin_key");
+                self.rotate_admin_key(stream);
+            },
+            (ConnectionType::Admin(Role::Full), ADD_ADMIN_FLAG) => {
+                self.audit(token, &format!("add_admin {}", String::from_utf8_lossy(&req)));
+                self.add_admin(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), REVOKE_ADMIN_FLAG) => {
+                self.audit(token, &format!("revoke_admin {}", String::from_utf8_lossy(&req)));
+                self.revoke_admin(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), LIST_ADMINS_FLAG) => {
+                self.list_admins(stream);
+            },
+            (ConnectionType::Admin(Role::Full), SET_READ_KEY_FLAG) => {
+                self.audit(token, &format!("read_key {}", String::from_utf8_lossy(&req)));
+                self.set_read_key(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (ConnectionType::Admin(Role::Full), REMOVE_READ_KEY_FLAG) => {
+                self.audit(token, &format!("remove_read_key {}", String::from_utf8_lossy(&req)));
+                self.remove_read_key(String::from_utf8_lossy(&req).to_string(), stream);
+            },
+            (_, PING_FLAG) => {
+                self.ping(stream);
+            },
+
+            //Every other admin request needs full rights
+            (ConnectionType::Admin(Role::ReadOnly), _) => {
+                let mut response : Vec<u8> = vec![1];
+                response.extend(b"read only admins can only list databases, their statistics and the server status");
+                write_frame(&stream, &response);
+            },
+            (ConnectionType::Admin(Role::Full), TERMINATE_FLAG) => {
+                self.audit(token, "terminate");
+                terminate.wake().expect("failed to terminate");  
+            },
+            (connection_type, flag) => warn!("invalid flag {:#04x} for {:?} connection", flag, connection_type),
+        }
+    }
+
+    ///Ends a connection and closes the cursors it left open. The connection is taken out of the map
+    ///under the lock, so only one caller ends it even if a worker and an admin race. Returns whether
+    ///the connection existed
+    fn remove_connection(&self, token : Token) -> bool {
+        let connection = recover(self.connections.lock(), "connections").remove(&token);
+        let removed = connection.is_some();
+        if let Some(connection) = connection {
+            connection.stream.shutdown();
+            if let Ok(executors) = self.executors.read() {
+                if let Some(executor) = executors.get(&connection.database) {
+                    for hash in connection.cursors {
+                        let _ = executor.close_cursor(hash);
+                    }
+                }
+            }
+        }
+        removed
+    }
+
+
+    ///Ends the client connections that did not send a message within the timeout and returns how
+    ///many were ended. Admin connections are kept since the CLI stays connected while it waits
+    ///for input
+    fn remove_idle_connections(&self, timeout : Duration) -> usize {
+        let idle : Vec<Token> = recover(self.connections.lock(), "connections").iter()
+            .filter(|(_, c)| matches!(c.connection_type, ConnectionType::Client(_)) && c.last_activity.elapsed() >= timeout)
+            .map(|(token, _)| *token)
+            .collect();
+        for token in &idle {
+            self.remove_connection(*token);
+        }
+        idle.len()
+    }
+
+
+    ///Expires the cursors of all databases that were not used within the ttl and returns how many
+    ///expired. The connections keep the hashes, they are forgotten once the client reads them or
+    ///disconnects
+    fn expire_cursors(&self, ttl : Duration) -> usize {
+        let expired : usize = recover(self.executors.read(), "executors").values().map(|executor| executor.expire_cursors(ttl)).sum();
+        if expired > 0 {
+            info!("expired {} unused cursors", expired);
+        }
+        expired
+    }
+
+
+    ///Remembers a cursor a connection opened, so it can be closed when the connection ends
+    fn track_cursor(&self, token : Token, result : &Result<QueryResult>) {
+        if let Ok(QueryResult{cursor: Some((hash, _)), ..}) = result {
+            if let Some(connection) = recover(self.connections.lock(), "connections").get_mut(&token) {
+                connection.cursors.insert(hash.clone());
+            }
+        }
+    }
+
+
+    ///Forgets a cursor that was exhausted, closed or failed
+    fn untrack_cursor(&self, token : Token, hash : &Vec<u8>) {
+        if let Some(connection) = recover(self.connections.lock(), "connections").get_mut(&token) {
+            connection.cursors.remove(hash);
+        }
+    }
+
+
+    fn query(&self, token : Token, database : String, role : Role, args: String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        debug!("parsing query on {}: {}", database, args);
+        let text = args.clone();
+        match self.query_cache.batch(args).and_then(|queries| queries.iter().try_for_each(|query| Self::check_permission(role, query)).map(|_| queries)) {
+            Ok(queries) => {
+                if self.audit_log.log_queries && queries.iter().any(|query| query.command().is_ok_and(|command| command.is_mutation())) {
+                    self.audit(token, &format!("query on {}: {}", database, text.trim()));
+                }
                 if let Ok(executors) = self.executors.read() {
 
                     //Choose right executor for the connection
-                    if let Some(executor) = executors.get(&database) {
+                    if let Some(executor) = executors.get(&database).cloned() {
+                        drop(executors);
 
                         //Execute query
-                        match executor.execute(query) {
-                            Ok(Some((hash, row))) => {
-                                response.push(0);
-                                response.extend(hash);
-                                response.extend(Self::encode_row(row));
-                            },
-                            Ok(None) => {
-                                response.push(1);
-                                response.extend(b"successful".to_vec());
-                            },
-                            Err(e) => {
-                                response.push(2);
-                                response.extend(e.to_string().into_bytes());
-                            },
-                        }
+                        let start = Instant::now();
+                        let result = self.run_query(executor, move |executor| executor.execute_batch(queries));
+                        self.log_slow_query(&database, &text, start.elapsed());
+                        self.track_cursor(token, &result);
+                        response.extend(Self::encode_result(result));
                     } else {
 
                         //Couldn't read from executors
-                        response.push(2);
-                        response.extend("unexpected server error".as_bytes());
+                        response.extend(Self::encode_error(&Error::other("unexpected server error")));
                     }
                 }
             },
             Err(e) => {
-                response.push(2);
-                response.extend(e.to_string().into_bytes());
+                response.extend(Self::encode_error(&e));
             },
         }
 
         //Send response
-        stream.as_ref().write_all(&response);
-        stream.as_ref().flush();
+        write_frame(&stream, &response);
+    }
+
+
+    ///Returns an error if the query would change the database but the connection is read only. It
+    ///is checked before the query reaches the executor
+    fn check_permission(role : Role, query : &Query) -> Result<()> {
+        if role == Role::ReadOnly && query.command()?.is_mutation() {
+            return Err(Error::new(ErrorKind::PermissionDenied, "connection is read only"));
+        }
+        Ok(())
+    }
+
+
+    ///Parses a query and stores it in the executor. The response contains the hash that points to
+    ///the prepared query
+    fn prepare(&self, database : String, role : Role, args: String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        debug!("preparing query on {}: {}", database, args);
+        let result = Query::from(args).and_then(|query| {
+            Self::check_permission(role, &query)?;
+            let executors = self.executors.read().map_err(|_| Error::other("unexpected server error"))?;
+            let executor = executors.get(&database).ok_or_else(|| Error::other("unexpected server error"))?;
+            executor.prepare(query)
+        });
+        match result {
+            Ok(hash) => {
+                response.push(0);
+                response.extend(hash);
+            },
+            Err(e) => {
+                response.extend(Self::encode_error(&e));
+            },
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Executes a prepared query. Args are the hash of the query followed by the params which are
+    ///encoded like the columns of a row
+    fn execute_prepared(&self, token : Token, database : String, role : Role, args: Vec<u8>, mut stream : Arc<ServerStream>) {
+        let result = Self::decode_params(&args).and_then(|(hash, params)| {
+            let executors = self.executors.read().map_err(|_| Error::other("unexpected server error"))?;
+            let executor = executors.get(&database).cloned().ok_or_else(|| Error::other("unexpected server error"))?;
+            drop(executors);
+
+            //Prepared queries are shared by all connections to a database, so the query behind
+            //the hash is checked again
+            let prepared = executor.get_prepared(&hash)?;
+            Self::check_permission(role, &prepared)?;
+            if self.audit_log.log_queries && prepared.command()?.is_mutation() {
+                self.audit(token, &format!("prepared query {} on {} with params {:?}", hex::encode(&hash), database, params));
+            }
+            self.run_query(executor, move |executor| executor.execute_prepared(hash, params))
+        });
+        self.track_cursor(token, &result);
+        write_frame(&stream, &Self::encode_result(result));
+    }
+
+
+    ///Runs a query on the executor. If QUERY_TIMEOUT is set the query runs on its own thread and
+    ///is abandoned with a "query timed out" error once the timeout passes. The abandoned query is
+    ///cancelled, so its table scans stop at the next page. Until transactions land, a timed out
+    ///mutation may leave partial side effects, like the rows it already deleted
+    fn run_query(&self, executor : Arc<Executor>, run : impl FnOnce(&Executor) -> Result<QueryResult> + Send + 'static) -> Result<QueryResult> {
+        let timeout = match self.query_timeout {
+            Some(timeout) => timeout,
+            None => return run(&executor),
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        //The slot holds the result once the query finished and whether it was abandoned, so the
+        //result of a query that finishes after the timeout is never lost
+        let slot : Arc<QuerySlot> = Arc::new((Mutex::new((None, false)), Condvar::new()));
+        let thread_slot = slot.clone();
+        let thread_cancel = cancel.clone();
+        thread::spawn(move || {
+            let result = with_cancellation(thread_cancel, || run(&executor));
+            let (lock, condvar) = &*thread_slot;
+            let mut slot = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if slot.1 {
+
+                //Nobody reads the cursor of an abandoned query, so it is closed right away
+                if let Ok(QueryResult{cursor: Some((hash, _)), ..}) = result {
+                    let _ = executor.close_cursor(hash);
+                }
+                return;
+            }
+            slot.0 = Some(result);
+            condvar.notify_all();
+        });
+        let (lock, condvar) = &*slot;
+        let guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (mut guard, _) = condvar.wait_timeout_while(guard, timeout, |slot| slot.0.is_none()).unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(result) = guard.0.take() {
+            return result;
+        }
+        guard.1 = true;
+        cancel.store(true, Ordering::SeqCst);
+        Err(ErrorCode::Timeout.error(ErrorKind::TimedOut, "query timed out"))
+    }
+
+
+    ///Records an action in the audit log. A failed write is logged but does not stop the action
+    fn audit(&self, token : Token, action : &str) {
+        if let Err(e) = self.audit_log.record(token, action) {
+            error!("failed to write audit log: {}", e);
+        }
+    }
+
+
+    ///Logs a warning if a query took at least as long as the slow query threshold. Returns whether
+    ///the query was slow
+    fn log_slow_query(&self, database : &str, query : &str, elapsed : Duration) -> bool {
+        if elapsed < self.slow_query_threshold {
+            return false;
+        }
+        warn!("slow query on {} took {:?}: {}", database, elapsed, query.trim());
+        true
+    }
+
+
+    ///Splits the args of an execute request into the hash and the params as strings
+    fn decode_params(args : &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, "params were encoded incorrectly");
+        let hash = args.get(..16).ok_or_else(invalid)?.to_vec();
+        let mut params : Vec<String> = vec![];
+        let mut index = 16;
+        while index < args.len() {
+            let len = u64::from_le_bytes(args.get(index..(index + 8)).ok_or_else(invalid)?.try_into().map_err(|_| invalid())?) as usize;
+            index += 8;
+            let type_id = u64::from_le_bytes(args.get(index..(index + 8)).ok_or_else(invalid)?.try_into().map_err(|_| invalid())?);
+            index += 8;
+            let bytes = args.get(index..index.checked_add(len).ok_or_else(invalid)?).ok_or_else(invalid)?;
+            index += len;
+            let param = match type_id {
+                0 => i64::from_le_bytes(bytes.try_into().map_err(|_| invalid())?).to_string(),
+                1 => String::from_utf8_lossy(bytes).to_string(),
+
+                //Blobs are bound as hex, which is how they are written in queries
+                2 => hex::encode(bytes),
+                _ => return Err(Error::new(ErrorKind::InvalidInput, "type id did not correspond to any type")),
+            };
+            params.push(param);
+        }
+        Ok((hash, params))
+    }
+
+
+    ///Encodes the result of an executed query with the status in the first byte
+    fn encode_result(result : Result<QueryResult>) -> Vec<u8> {
+        let mut response : Vec<u8> = vec![];
+        match result {
+            Ok(QueryResult{cursor: Some((hash, row)), warnings, columns, ..}) => {
+                response.push(0);
+                response.extend(Self::encode_strings(warnings));
+                response.extend(Self::encode_strings(columns));
+                response.extend(hash);
+                response.extend(Self::encode_row(row));
+            },
+            Ok(QueryResult{cursor: None, warnings, message, affected, inserted_id, ..}) => {
+                response.push(1);
+                response.extend(Self::encode_strings(warnings));
+
+                //A flag byte tells whether the number of affected rows follows and whether the
+                //id of an inserted row follows after it
+                match (affected, inserted_id) {
+                    (Some(affected), Some(inserted_id)) => {
+                        response.push(2);
+                        response.extend(affected.to_le_bytes());
+                        response.extend(inserted_id.to_le_bytes());
+                    },
+                    (Some(affected), None) => {
+                        response.push(1);
+                        response.extend(affected.to_le_bytes());
+                    },
+                    (None, _) => response.push(0),
+                }
+                if let Some(message) = message {
+                    response.extend(message.into_bytes());
+                }
+            },
+            Err(e) => {
+                response.extend(Self::encode_error(&e));
+            },
+        }
+        response
     }
 
 
-    fn next(&self, database : String, args: Vec<u8>, mut stream : Arc<TcpStream>) {
+    ///Encodes an error as status 2 followed by its code and message
+    fn encode_error(error : &Error) -> Vec<u8> {
+        let mut response : Vec<u8> = vec![2, ErrorCode::of(error) as u8];
+        response.extend(error.to_string().into_bytes());
+        response
+    }
+
+
+    fn next(&self, token : Token, database : String, args: Vec<u8>, mut stream : Arc<ServerStream>) {
         let mut response : Vec<u8> = vec![];
         if let Ok(executors) = self.executors.read() {
             if let Some(executor) = executors.get(&database) {
 
                 //Args are the hash that points to the right cursor so they can be directly passed
                 //to the next function
-                match executor.next(args) {
+                match executor.next(args.clone()) {
                     Ok(Some(row)) => {
                         response.push(0);
                         response.extend(Self::encode_row(row));
                     },
                     Ok(None) => {
+                        self.untrack_cursor(token, &args);
                         response.push(1);
                         response.extend(b"successful".to_vec());
                     },
                     Err(e) => {
-                        response.push(2);
-                        response.extend(e.to_string().into_bytes());
+
+                        //Failed cursors are removed by the executor
+                        self.untrack_cursor(token, &args);
+                        response.extend(Self::encode_error(&e));
                     }
                 }
             }
         }
-        stream.as_ref().write_all(&response);
-        stream.as_ref().flush();
+        write_frame(&stream, &response);
+    }
+
+
+    ///Closes the cursor the hash in args points to. The response contains the number of cursors
+    ///that are still open in the database
+    fn close_cursor(&self, token : Token, database : String, args: Vec<u8>, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        self.untrack_cursor(token, &args);
+        let result = self.executors.read().map_err(|_| Error::other("unexpected server error")).and_then(|executors| {
+            let executor = executors.get(&database).ok_or_else(|| Error::other("unexpected server error"))?;
+            executor.close_cursor(args)?;
+            executor.cursor_count()
+        });
+        match result {
+            Ok(count) => {
+                response.push(1);
+                response.extend((count as u64).to_le_bytes());
+            },
+            Err(e) => {
+                response.extend(Self::encode_error(&e));
+            },
+        }
+        write_frame(&stream, &response);
     }
 
 
@@ -401,29 +1308,49 @@ impl Server {
     }
 
 
-    fn new_database(&self, args: String, mut stream : Arc<TcpStream>) {
+    ///Encodes strings like warnings or col names as their count followed by the length and bytes
+    ///of every string
+    fn encode_strings(strings : Vec<String>) -> Vec<u8> {
+        let mut result : Vec<u8> = vec![];
+        result.extend((strings.len() as u64).to_le_bytes());
+        for string in strings {
+            result.extend((string.len() as u64).to_le_bytes());
+            result.extend(string.into_bytes());
+        }
+        result
+    }
+
+
+    fn new_database(&self, args: String, mut stream : Arc<ServerStream>) {
         let mut response : Vec<u8> = vec![];
         if let Ok(base_path) = get_base_path() {
 
-            //Args consist only of the database name
+            //Args consist of the database name and optionally the page size separated by a space
+            let (args, page_size) = match args.split_once(' ') {
+                Some((name, size)) => match size.trim().parse::<usize>() {
+                    Ok(size) => (name.to_string(), size),
+                    Err(_) => {
+                        response.push(0);
+                        response.extend(b"page size has to be a number");
+                        write_frame(&stream, &response);
+                        return;
+                    },
+                },
+                None => (args, DEFAULT_PAGE_SIZE),
+            };
             let path = base_path.join(args.clone());
 
             //The directory for the executor has to be created first
             create_dir(&path); 
-            match Executor::new(path) {
+            match Executor::with_page_size(path, page_size) {
                 Ok(executor) => {
-                    let mut key = String::new();
-                    let mut rng = thread_rng();
-                    for i in (0..32) {
-                        key.push(rng.gen_range(0x20..=0x7E).into()); 
-                    }
+                    let key = Self::generate_key();
                     if !self.database_schema.add_database(args.clone(), key.clone()).is_ok() {
 
                         //Send error to client and abort
                         response.push(0);
                         response.extend(b"failed to add database to schema");
-                        stream.as_ref().write_all(&response);
-                        stream.as_ref().flush();
+                        write_frame(&stream, &response);
                         return;
                     }
 
@@ -441,13 +1368,12 @@ impl Server {
                     response.extend(e.to_string().as_bytes());
                 },
             }
-            stream.as_ref().write_all(&response);
-            stream.as_ref().flush();
+            write_frame(&stream, &response);
         }
     }
 
 
-    fn delete_database(&self, args: String, mut stream : Arc<TcpStream>) {
+    fn delete_database(&self, args: String, mut stream : Arc<ServerStream>) {
         let mut response : Vec<u8> = vec![];
         if let Ok(base_path) = get_base_path() {
             match self.database_schema.remove_database(args.clone()) {
@@ -462,34 +1388,843 @@ impl Server {
                     response.extend(e.to_string().as_bytes());
                 },
             }
-            stream.as_ref().write_all(&response);
-            stream.as_ref().flush();
+            write_frame(&stream, &response);
+        }
+    }
+
+
+    ///Returns a random key of printable ascii characters
+    fn generate_key() -> String {
+        let mut key = String::new();
+        let mut rng = thread_rng();
+        for i in (0..32) {
+            key.push(rng.gen_range(0x20..=0x7E).into()); 
+        }
+        key
+    }
+
+
+    ///Gives a database a new random key and responds with it. Connections that authenticated with
+    ///the old key stay open, new connections need the new key
+    fn change_key(&self, args : String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        let key = Self::generate_key();
+        match self.database_schema.change_key(args, key.clone()) {
+            Ok(()) => {
+                response.push(0);
+                response.extend(key.as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to change key: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Keys are only stored as hashes, so older clients asking for one get an error instead
+    fn get_key(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![1];
+        response.extend(b"database keys are stored hashed and can not be shown again");
+        write_frame(&stream, &response);
+    }
+
+
+    ///Replaces the admin key and responds with the new one
+    fn rotate_admin_key(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.database_schema.rotate_admin_key() {
+            Ok(key) => {
+                response.push(0);
+                response.extend(key.as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to rotate admin key: ");
+                response.extend(e.to_string().as_bytes());
+            }
         }
+        write_frame(&stream, &response);
     }
 
 
-    fn get_key(&self, args : String, mut stream : Arc<TcpStream>) {
+    ///Adds an admin and responds with its key. Args consist of the label and optionally the role
+    ///separated by a space, admins get full rights by default
+    fn add_admin(&self, args : String, mut stream : Arc<ServerStream>) {
         let mut response : Vec<u8> = vec![];
-        match self.database_schema.get_database_key(args) {
-            Ok(Some(key)) => {
+        let (label, role) = match args.split_once(' ') {
+            Some((label, role)) => (label.to_string(), Role::parse(role.trim())),
+            None => (args, Ok(Role::Full)),
+        };
+        match role.and_then(|role| self.database_schema.add_admin(label, role)) {
+            Ok(key) => {
                 response.push(0);
                 response.extend(key.as_bytes());
             },
-            Ok(None) => {
+            Err(e) => {
                 response.push(1);
-                response.extend(b"database does not exist");
+                response.extend(b"failed to add admin: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Gives a database a new read only key and responds with it
+    fn set_read_key(&self, args : String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        let key = Self::generate_key();
+        match self.database_schema.set_read_key(args, key.clone()) {
+            Ok(()) => {
+                response.push(0);
+                response.extend(key.as_bytes());
             },
             Err(e) => {
                 response.push(1);
-                response.extend(b"failed to get database key");
+                response.extend(b"failed to set read only key: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    fn remove_read_key(&self, args : String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.database_schema.remove_read_key(args) {
+            Ok(()) => response.push(0),
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to remove read only key: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    fn revoke_admin(&self, label : String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.database_schema.revoke_admin(label) {
+            Ok(()) => response.push(0),
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to revoke admin: ");
                 response.extend(e.to_string().as_bytes());
             }
         }
-        stream.as_ref().write_all(&response);
-        stream.as_ref().flush();
+        write_frame(&stream, &response);
+    }
+
+
+    ///Responds with the labels and roles of all added admins, one admin per line
+    fn list_admins(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.database_schema.get_admins() {
+            Ok(admins) => {
+                response.push(0);
+                let lines : Vec<String> = admins.into_iter().map(|(label, role)| format!("{} {}", label, role.as_str())).collect();
+                response.extend(lines.join("\n").as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to list admins: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Answers a ping so clients can check that the connection is alive
+    fn ping(&self, mut stream : Arc<ServerStream>) {
+        write_frame(&stream, PONG);
+    }
+
+
+    ///Responds with the sorted names of all databases separated by newlines
+    fn list_databases(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.database_schema.get_database_names() {
+            Ok(mut names) => {
+                names.sort();
+                response.push(0);
+                response.extend(names.join("\n").as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to list databases: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Responds with a bubble that shows the size on disk, the number of tables and the row count
+    ///of every table for each database
+    fn stats(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.render_stats() {
+            Ok(stats) => {
+                response.push(0);
+                response.extend(stats.as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to collect statistics: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Responds with ok and the uptime along with the number of databases, connections and open
+    ///cursors. Every lock is only held for counting, so it can be polled frequently
+    fn status(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.render_status() {
+            Ok(status) => {
+                response.push(0);
+                response.extend(status.as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to collect status: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Responds with a bubble that shows the token, database, type, idle time and number of open
+    ///cursors of every authenticated connection
+    fn list_connections(&self, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match self.render_connections() {
+            Ok(connections) => {
+                response.push(0);
+                response.extend(connections.as_bytes());
+            },
+            Err(e) => {
+                response.push(1);
+                response.extend(b"failed to list connections: ");
+                response.extend(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Ends the connection with the token given in args, its socket is shut down and its cursors
+    ///are closed. A worker that is reading from it at the same time only sees a closed stream
+    fn kill_connection(&self, args : String, mut stream : Arc<ServerStream>) {
+        let mut response : Vec<u8> = vec![];
+        match args.trim().parse::<usize>() {
+            Ok(token) if self.remove_connection(Token(token)) => {
+                response.push(0);
+            },
+            Ok(token) => {
+                response.push(1);
+                response.extend(format!("there is no connection with token {}", token).as_bytes());
+            },
+            Err(_) => {
+                response.push(1);
+                response.extend(b"token has to be a number");
+            },
+        }
+        write_frame(&stream, &response);
+    }
+
+
+    ///Renders the status as lines of name and value below ok
+    fn render_status(&self) -> Result<String> {
+        let executors : Vec<Arc<Executor>> = recover(self.executors.read(), "executors").values().cloned().collect();
+        let connections = recover(self.connections.lock(), "connections").len();
+        let mut cursors = 0;
+        for executor in &executors {
+            cursors += executor.cursor_count()?;
+        }
+        Ok(format!("ok\nuptime: {}s\ndatabases: {}\nconnections: {}\ncursors: {}\n", self.started.elapsed().as_secs(), executors.len(), connections, cursors))
+    }
+
+
+    ///Renders a snapshot of the connections. The lock is only held while the snapshot is taken,
+    ///so the response is written without blocking other workers
+    fn render_connections(&self) -> Result<String> {
+        let mut snapshot : Vec<(usize, String, String, u64, usize)> = {
+            let connections = recover(self.connections.lock(), "connections");
+            connections.iter().map(|(token, connection)| {
+                let connection_type = match connection.connection_type {
+                    ConnectionType::Client(role) => format!("client {}", role.as_str()),
+                    ConnectionType::Admin(role) => format!("admin {}", role.as_str()),
+                };
+                (token.0, connection.database.clone(), connection_type, connection.last_activity.elapsed().as_secs(), connection.cursors.len())
+            }).collect()
+        };
+        snapshot.sort();
+        let mut bubble = Bubble::dynamic();
+        bubble.add_line(vec!["token".to_string(), "database".to_string(), "type".to_string(), "idle".to_string(), "cursors".to_string()]);
+        bubble.add_divider();
+        for (token, database, connection_type, idle, cursors) in snapshot {
+            bubble.add_line(vec![token.to_string(), database, connection_type, format!("{}s", idle), cursors.to_string()]);
+        }
+        Ok(bubble.to_string())
+    }
+
+
+    ///Renders the statistics of all databases, each database is followed by its tables
+    fn render_stats(&self) -> Result<String> {
+        let mut executors : Vec<(String, Arc<Executor>)> = self.executors.read().map_err(|_| Error::other("thread poisoned"))?.iter().map(|(name, executor)| (name.clone(), executor.clone())).collect();
+        executors.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut bubble = Bubble::dynamic();
+        bubble.add_line(vec!["database".to_string(), "table".to_string(), "rows".to_string(), "size".to_string()]);
+        for (name, executor) in executors {
+            let (size, tables) = executor.stats()?;
+            let rows : u64 = tables.iter().map(|(_, rows)| rows).sum();
+            bubble.add_divider();
+            bubble.add_line(vec![name, format!("{} tables", tables.len()), rows.to_string(), format!("{} bytes", size)]);
+            for (table, rows) in tables {
+                bubble.add_line(vec![String::new(), table, rows.to_string(), String::new()]);
+            }
+        }
+        Ok(bubble.to_string())
     }
 
 
 }
 
 
+
+#[cfg(test)]
+mod test {
+
+
+    use super::*;
+    use crate::storage::file_management::get_test_path;
+    use std::net::{TcpListener as StdTcpListener, TcpStream as StdTcpStream};
+    use rust_client::read_frame;
+
+
+
+    ///Creates a server working in its own test directory with an empty database called db
+    fn test_server(name : &str) -> Server {
+        let path = get_test_path().unwrap().join(name);
+        let _ = delete_dir(&path);
+        create_dir(&path.join("db")).unwrap();
+        let database_schema = DatabaseSchemaHandler::new(path.clone()).unwrap();
+        let executor = Executor::new(path.join("db")).unwrap();
+        let executors = RwLock::new(HashMap::from([("db".to_string(), Arc::new(executor))]));
+        let audit_log = AuditLog::open(&path.join("audit.log"), true).unwrap();
+        Server{executors, database_schema, work: Mutex::new(vec![]), condvar: Condvar::new(), connections: Mutex::new(HashMap::new()), addresses: vec!["127.0.0.1:4321".parse().unwrap()], admin_addresses: vec!["[::]:4322".parse().unwrap()], unix_socket: None, admin_unix_socket: None, tls_config: None, idle_timeout: None, cursor_ttl: None, rate_limit: None, query_timeout: None, slow_query_threshold: Duration::from_millis(DEFAULT_SLOW_QUERY_THRESHOLD), query_cache: QueryCache::new(DEFAULT_QUERY_CACHE_SIZE), audit_log, stopped: AtomicBool::new(false), started: Instant::now()}
+    }
+
+
+
+    ///Returns a connected pair of a client stream and the non blocking server side of it
+    fn stream_pair() -> (StdTcpStream, ServerStream) {
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        (client, ServerStream::Plain(TcpStream::from_std(stream)))
+    }
+
+
+
+    ///Reads from a non blocking stream until a whole request arrived or the peer closed the
+    ///connection, like the poll loop would
+    fn read_blocking(stream : &ServerStream, buffer : &mut Vec<u8>) -> Result<(Vec<Vec<u8>>, bool)> {
+        for _ in 0..500 {
+            let (requests, closed) = read_frames(stream, buffer)?;
+            if !requests.is_empty() || closed {
+                return Ok((requests, closed));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Err(Error::new(ErrorKind::TimedOut, "no data arrived"))
+    }
+
+
+
+    #[test]
+    fn idle_connection_test() {
+        let server = test_server("idle_connection");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1);".to_string()).unwrap()).unwrap();
+
+        //An idle client with an open cursor and an idle admin
+        let (mut client, stream) = stream_pair();
+        let (_admin, admin_stream) = stream_pair();
+        let mut connection = ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None);
+        connection.last_activity -= Duration::from_secs(10);
+        let mut admin_connection = ConnectionState::new(String::new(), ConnectionType::Admin(Role::Full), admin_stream, None);
+        admin_connection.last_activity -= Duration::from_secs(10);
+        server.connections.lock().unwrap().insert(Token(3), connection);
+        server.connections.lock().unwrap().insert(Token(4), admin_connection);
+        let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap());
+        server.track_cursor(Token(3), &result);
+        assert_eq!(executor.cursor_count().unwrap(), 1);
+
+        //Only the client is removed, its cursor is closed and its socket is shut down
+        assert_eq!(server.remove_idle_connections(Duration::from_secs(60)), 0);
+        assert_eq!(server.remove_idle_connections(Duration::from_secs(5)), 1);
+        assert!(server.connections.lock().unwrap().contains_key(&Token(4)), "admin connections should be kept");
+        assert_eq!(executor.cursor_count().unwrap(), 0);
+        let mut buff = [0u8; 1];
+        assert_eq!(client.read(&mut buff).unwrap(), 0);
+    }
+
+
+
+    #[test]
+    fn stop_test() {
+        let server = Arc::new(test_server("stop"));
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        let poll = Poll::new().unwrap();
+        let waker = Arc::new(Waker::new(poll.registry(), Server::TERMINATE).unwrap());
+        let workers : Vec<JoinHandle<()>> = (0..3).map(|_| {
+            let server_clone = server.clone();
+            let waker_clone = waker.clone();
+            thread::spawn(move || server_clone.handle_client(waker_clone))
+        }).collect();
+
+        //A connected client with an open cursor is ended by the shutdown
+        let (mut client, stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None));
+        let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap());
+        server.track_cursor(Token(3), &result);
+
+        //Stop returns once all workers have been joined
+        server.stop(workers).unwrap();
+        assert!(server.stopped.load(Ordering::Acquire));
+        assert!(server.connections.lock().unwrap().is_empty());
+        assert!(server.work.lock().unwrap().is_empty(), "every worker should have taken its poison pill");
+        assert_eq!(executor.cursor_count().unwrap(), 0);
+        let mut buff = [0u8; 1];
+        assert_eq!(client.read(&mut buff).unwrap(), 0);
+    }
+
+
+
+    #[test]
+    fn column_names_test() {
+        let server = test_server("column_names");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER, b TEXT);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1, x);".to_string()).unwrap()).unwrap();
+
+        //The names follow the warnings and come before the hash of the cursor
+        let response = Server::encode_result(executor.execute(Query::from("SELECT b FROM t;".to_string()).unwrap()));
+        let mut expected : Vec<u8> = vec![0];
+        expected.extend(0u64.to_le_bytes());
+        expected.extend(1u64.to_le_bytes());
+        expected.extend(1u64.to_le_bytes());
+        expected.extend(b"b");
+        assert_eq!(&response[..expected.len()], &expected[..]);
+        assert_eq!(response.len(), expected.len() + 16 + 8 + 8 + 1, "the hash and the row should follow the names");
+    }
+
+
+
+    #[test]
+    fn ping_test() {
+        let server = test_server("ping");
+        let (mut client, stream) = stream_pair();
+        server.ping(Arc::new(stream));
+        assert_eq!(read_frame(&mut client).unwrap(), PONG);
+    }
+
+
+
+    #[test]
+    fn parse_addresses_test() {
+        let addresses = parse_addresses(" 127.0.0.1:4321, [::1]:4321,").unwrap();
+        assert_eq!(addresses, vec!["127.0.0.1:4321".parse::<SocketAddr>().unwrap(), "[::1]:4321".parse::<SocketAddr>().unwrap()]);
+        assert!(addresses[1].is_ipv6());
+        let error = parse_addresses("127.0.0.1:4321,localhost").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().starts_with("localhost is not a socket address"), "{}", error);
+        assert!(parse_addresses("::1").is_err(), "IPv6 addresses need brackets and a port");
+        assert!(parse_addresses(" , ").is_err());
+    }
+
+
+
+    #[test]
+    fn cli_addresses_test() {
+        let server = test_server("cli_addresses");
+        let (address, admin_address) = server.cli_addresses();
+        assert_eq!(address, "127.0.0.1:4321".parse::<SocketAddr>().unwrap());
+        assert_eq!(admin_address, "[::1]:4322".parse::<SocketAddr>().unwrap(), "an unspecified address should be reached via loopback");
+    }
+
+
+
+    #[test]
+    fn unix_socket_test() {
+        let server = test_server("unix_socket");
+        let path = get_test_path().unwrap().join("unix_socket").join("d-bee.sock");
+
+        //Other files are not replaced by the socket
+        std::fs::write(&path, b"data").unwrap();
+        assert_eq!(bind_unix_socket(&path).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        std::fs::remove_file(&path).unwrap();
+
+        //A socket that was left behind is replaced
+        drop(bind_unix_socket(&path).unwrap());
+        let listener = Listener::Unix(bind_unix_socket(&path).unwrap());
+        let mut client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        let poll = Poll::new().unwrap();
+        let stream = loop {
+            match server.accept(&listener, poll.registry(), Token(3)) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                result => break result.unwrap(),
+            }
+        };
+        assert!(matches!(stream, ServerStream::Unix(_)), "unix socket connections should not use TLS");
+        server.ping(Arc::new(stream));
+        assert_eq!(read_frame(&mut client).unwrap(), PONG);
+    }
+
+
+
+    #[test]
+    fn slow_query_test() {
+        let mut server = test_server("slow_query");
+        assert!(!server.log_slow_query("db", "SELECT * FROM t;", Duration::from_millis(10)), "normal queries should not be logged");
+        server.slow_query_threshold = Duration::from_millis(5);
+        assert!(server.log_slow_query("db", "SELECT * FROM t;", Duration::from_millis(10)));
+        assert!(server.log_slow_query("db", "SELECT * FROM t;", Duration::from_millis(5)));
+    }
+
+
+
+    #[test]
+    fn decode_params_test() {
+        let mut args : Vec<u8> = vec![0u8; 16];
+        args.extend(8u64.to_le_bytes());
+        args.extend(0u64.to_le_bytes());
+        args.extend(5i64.to_le_bytes());
+        let (hash, params) = Server::decode_params(&args).unwrap();
+        assert_eq!(hash, vec![0u8; 16]);
+        assert_eq!(params, vec!["5".to_string()]);
+        for len in [8, 20, 30, args.len() - 1] {
+            assert!(Server::decode_params(&args[..len]).is_err(), "params truncated to {} bytes should return an error", len);
+        }
+        let mut huge_len : Vec<u8> = vec![0u8; 16];
+        huge_len.extend(u64::MAX.to_le_bytes());
+        huge_len.extend(1u64.to_le_bytes());
+        assert!(Server::decode_params(&huge_len).is_err(), "a length past the end should return an error");
+    }
+
+
+
+    #[test]
+    fn error_code_test() {
+        let server = test_server("error_code");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER CHECK (a > 0));".to_string()).unwrap()).unwrap();
+        let statements = [
+            ("SELECT * FROM u;", ErrorCode::TableNotFound),
+            ("INSERT INTO t VALUES (x);", ErrorCode::TypeMismatch),
+            ("INSERT INTO t VALUES (-1);", ErrorCode::ConstraintViolation),
+            ("INSERT INTO t (b) VALUES (1);", ErrorCode::InvalidInput),
+        ];
+        for (statement, code) in statements {
+            let response = Server::encode_result(executor.execute(Query::from(statement.to_string()).unwrap()));
+            assert_eq!(response[..2], [2, code as u8], "{} was sent with the wrong code", statement);
+        }
+        let response = Server::encode_error(&Query::from("SELEC * FROM t;".to_string()).err().unwrap());
+        assert_eq!(response[..2], [2, ErrorCode::Parse as u8]);
+    }
+
+
+
+    #[test]
+    fn query_timeout_test() {
+        let mut server = test_server("query_timeout");
+        server.query_timeout = Some(Duration::from_millis(50));
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1);".to_string()).unwrap()).unwrap();
+
+        //A query that finishes in time is answered normally
+        let result = server.run_query(executor.clone(), |executor| executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()));
+        let (hash, _) = result.unwrap().cursor.unwrap();
+        executor.close_cursor(hash).unwrap();
+
+        //A slow query is abandoned and the cursor it opens afterwards is closed
+        let result = server.run_query(executor.clone(), |executor| {
+            thread::sleep(Duration::from_millis(200));
+            executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap())
+        });
+        let error = result.err().unwrap();
+        assert_eq!(error.to_string(), "query timed out");
+        assert_eq!(Server::encode_error(&error)[..2], [2, ErrorCode::Timeout as u8]);
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(executor.cursor_count().unwrap(), 0);
+
+        //Scans of a cancelled query stop
+        let result = with_cancellation(Arc::new(AtomicBool::new(true)), || executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()));
+        assert_eq!(result.err().unwrap().kind(), ErrorKind::TimedOut);
+        assert!(executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).is_ok(), "the flag should only apply while the cancelled query runs");
+    }
+
+
+
+    #[test]
+    fn token_bucket_test() {
+        let mut bucket = TokenBucket::new(20);
+        for _ in 0..20 {
+            assert!(bucket.try_take());
+        }
+        assert!(!bucket.try_take(), "requests beyond the burst should be limited");
+
+        //Tokens are refilled over time
+        thread::sleep(Duration::from_millis(120));
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+
+        //Admins are never limited
+        let (_client, stream) = stream_pair();
+        assert!(ConnectionState::new(String::new(), ConnectionType::Admin(Role::Full), stream, Some(1)).bucket.is_none());
+    }
+
+
+
+    #[test]
+    fn read_only_permission_test() {
+        for (statement, allowed) in [("SELECT * FROM t;", true), ("DESCRIBE t;", true), ("EXPLAIN DELETE FROM t;", true), ("INSERT INTO t VALUES (1);", false), ("DELETE FROM t;", false), ("CREATE TABLE u (a NUMBER);", false), ("DROP TABLE t;", false)] {
+            let query = Query::from(statement.to_string()).unwrap();
+            assert!(Server::check_permission(Role::Full, &query).is_ok());
+            let result = Server::check_permission(Role::ReadOnly, &query);
+            assert_eq!(result.is_ok(), allowed, "{}", statement);
+            if let Err(e) = result {
+                assert_eq!(ErrorCode::of(&e), ErrorCode::PermissionDenied);
+            }
+        }
+    }
+
+
+
+    #[test]
+    fn stats_test() {
+        let server = test_server("stats");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1);".to_string()).unwrap()).unwrap();
+        let (mut client, stream) = stream_pair();
+        server.stats(Arc::new(stream));
+        let response = read_frame(&mut client).unwrap();
+        assert_eq!(response[0], 0);
+        let stats = String::from_utf8_lossy(&response[1..]).to_string();
+        let lines : Vec<&str> = stats.lines().collect();
+        assert!(lines[1].contains("database") && lines[1].contains("rows") && lines[1].contains("size"), "{}", stats);
+        assert!(lines[3].starts_with("|db") && lines[3].contains("1 tables") && lines[3].contains("bytes"), "{}", stats);
+        assert!(lines[4].contains("|t") && lines[4].contains("|1"), "{}", stats);
+    }
+
+
+
+    #[test]
+    fn status_test() {
+        let server = test_server("status");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap()).unwrap();
+        let (_client, stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None));
+        let (mut admin, admin_stream) = stream_pair();
+        server.status(Arc::new(admin_stream));
+        let response = read_frame(&mut admin).unwrap();
+        assert_eq!(response[0], 0);
+        let status = String::from_utf8_lossy(&response[1..]).to_string();
+        let lines : Vec<&str> = status.lines().collect();
+        assert_eq!(lines[0], "ok");
+        assert!(lines[1].starts_with("uptime: ") && lines[1].ends_with('s'), "{}", status);
+        assert_eq!(&lines[2..], ["databases: 1", "connections: 1", "cursors: 1"]);
+    }
+
+
+
+    #[test]
+    fn list_connections_test() {
+        let server = test_server("list_connections");
+        let (_client, stream) = stream_pair();
+        let mut connection = ConnectionState::new("db".to_string(), ConnectionType::Client(Role::ReadOnly), stream, None);
+        connection.cursors.insert(vec![0u8; 16]);
+        server.connections.lock().unwrap().insert(Token(4), connection);
+        let (_admin, admin_stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new(String::new(), ConnectionType::Admin(Role::Full), admin_stream, None));
+        let (mut client, stream) = stream_pair();
+        server.list_connections(Arc::new(stream));
+        let response = read_frame(&mut client).unwrap();
+        assert_eq!(response[0], 0);
+        let connections = String::from_utf8_lossy(&response[1..]).to_string();
+        let lines : Vec<&str> = connections.lines().collect();
+        assert_eq!(lines.len(), 6, "{}", connections);
+        assert!(lines[3].starts_with("|3") && lines[3].contains("admin full"), "{}", connections);
+        assert!(lines[4].starts_with("|4") && lines[4].contains("|db") && lines[4].contains("client read_only") && lines[4].contains("|0s") && lines[4].ends_with("|1      |"), "{}", connections);
+    }
+
+
+
+    #[test]
+    fn kill_connection_test() {
+        let server = test_server("kill_connection");
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        executor.execute(Query::from("INSERT INTO t VALUES (1);".to_string()).unwrap()).unwrap();
+        let (mut client, stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None));
+        let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap());
+        server.track_cursor(Token(3), &result);
+        let mut buff = [0u8; 64];
+        for (args, status) in [("3", 0), ("3", 1), ("foo", 1)] {
+            let (mut admin, admin_stream) = stream_pair();
+            server.kill_connection(args.to_string(), Arc::new(admin_stream));
+            assert_eq!(read_frame(&mut admin).unwrap()[0], status, "kill {}", args);
+        }
+        assert!(server.connections.lock().unwrap().is_empty());
+        assert_eq!(executor.cursor_count().unwrap(), 0);
+        assert_eq!(client.read(&mut buff).unwrap(), 0);
+    }
+
+
+
+    #[test]
+    fn poisoned_lock_test() {
+        let server = Arc::new(test_server("poisoned_lock"));
+        let executor = server.executors.read().unwrap()["db"].clone();
+        executor.execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        let (mut client, stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None));
+
+        //A worker that panics while holding the lock should not stop connections from being ended
+        let server_clone = server.clone();
+        let _ = thread::spawn(move || {
+            let _connections = server_clone.connections.lock().unwrap();
+            panic!("poison the connections lock");
+        }).join();
+        assert!(server.connections.is_poisoned());
+        let result = executor.execute(Query::from("SELECT * FROM t;".to_string()).unwrap());
+        server.track_cursor(Token(3), &result);
+        assert!(server.remove_connection(Token(3)));
+        assert_eq!(executor.cursor_count().unwrap(), 0);
+        let mut buff = [0u8; 1];
+        assert_eq!(client.read(&mut buff).unwrap(), 0);
+    }
+
+
+
+    #[test]
+    fn audit_log_test() {
+        let server = test_server("audit_log");
+        server.executors.read().unwrap()["db"].execute(Query::from("CREATE TABLE t (a NUMBER);".to_string()).unwrap()).unwrap();
+        let (mut client, stream) = stream_pair();
+        server.query(Token(5), "db".to_string(), Role::Full, "INSERT INTO t VALUES (1);".to_string(), Arc::new(stream));
+        assert!(!read_frame(&mut client).unwrap().is_empty());
+        let (mut client, stream) = stream_pair();
+        server.query(Token(5), "db".to_string(), Role::Full, "SELECT * FROM t;".to_string(), Arc::new(stream));
+        assert!(!read_frame(&mut client).unwrap().is_empty());
+        server.audit(Token(6), "delete_database other");
+        let path = get_test_path().unwrap().join("audit_log").join("audit.log");
+        let log = std::fs::read_to_string(path).unwrap();
+        let lines : Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2, "reading queries should not be audited");
+        assert!(lines[0].ends_with("token 5 query on db: INSERT INTO t VALUES (1);"), "{}", lines[0]);
+        assert!(lines[1].ends_with("token 6 delete_database other"), "{}", lines[1]);
+        assert!(lines[1].split(' ').next().unwrap().parse::<u64>().is_ok(), "lines should start with a timestamp");
+    }
+
+
+
+    #[test]
+    fn tls_stream_test() {
+        let dir = get_test_path().unwrap();
+        let cert_path = dir.join("tls_stream_cert.pem");
+        let key_path = dir.join("tls_stream_key.pem");
+        let certified = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+        let config = load_tls_config(&cert_path, &key_path).unwrap();
+
+        //The client trusts the self signed certificate
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let client = thread::spawn(move || {
+            let mut stream = rust_client::Stream::connect(&address, Some(&cert_path)).unwrap();
+            stream.send(&[b'a'; 5000]).unwrap();
+            assert_eq!(stream.receive().unwrap(), vec![b'b'; 5000]);
+            stream.shutdown().unwrap();
+        });
+
+        //The server side is non blocking like the streams accepted by mio
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let connection = ServerConnection::new(config).unwrap();
+        let stream = ServerStream::Tls(Box::new(Mutex::new(StreamOwned::new(connection, TcpStream::from_std(stream)))));
+        //Messages larger than a TLS record arrive as a whole
+        let mut buffer : Vec<u8> = vec![];
+        let (requests, _) = read_blocking(&stream, &mut buffer).unwrap();
+        assert_eq!(requests, vec![vec![b'a'; 5000]]);
+        write_frame(&stream, &[b'b'; 5000]).unwrap();
+        client.join().unwrap();
+        assert_eq!(read_blocking(&stream, &mut buffer).unwrap(), (vec![], true), "a closed connection should read as empty");
+    }
+
+
+
+    #[test]
+    fn large_request_test() {
+        let server = Arc::new(test_server("large_request"));
+        server.executors.read().unwrap()["db"].execute(Query::from("CREATE TABLE t (a TEXT);".to_string()).unwrap()).unwrap();
+        let (mut client, stream) = stream_pair();
+        server.connections.lock().unwrap().insert(Token(3), ConnectionState::new("db".to_string(), ConnectionType::Client(Role::Full), stream, None));
+        let poll = Poll::new().unwrap();
+        let terminate = Arc::new(Waker::new(poll.registry(), Token(0)).unwrap());
+        let worker = thread::spawn({
+            let server = server.clone();
+            move || server.handle_client(terminate)
+        });
+
+        //Every part of a request is passed to a worker on its own, like the poll loop does for
+        //every readable event
+        let send = |client : &mut StdTcpStream, message : &[u8]| {
+            let mut frame : Vec<u8> = vec![];
+            rust_client::write_frame(&mut frame, message).unwrap();
+            for chunk in frame.chunks(500) {
+                client.write_all(chunk).unwrap();
+                thread::sleep(Duration::from_millis(5));
+                server.work.lock().unwrap().push(Some(Arc::new(Token(3))));
+                server.condvar.notify_one();
+            }
+        };
+        let text = "x".repeat(3000);
+        let mut query = vec![QUERY_FLAG];
+        query.extend(format!("INSERT INTO t VALUES ({});", text).as_bytes());
+        send(&mut client, &query);
+        assert_eq!(read_frame(&mut client).unwrap()[0], 1);
+        let mut query = vec![QUERY_FLAG];
+        query.extend(b"SELECT * FROM t;");
+        send(&mut client, &query);
+        let response = read_frame(&mut client).unwrap();
+        assert!(response.len() > 3000, "the row should arrive in one response");
+        assert!(response.ends_with(text.as_bytes()));
+
+        server.work.lock().unwrap().insert(0, None);
+        server.condvar.notify_all();
+        worker.join().unwrap();
+    }
+
+
+}
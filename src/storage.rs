@@ -4,15 +4,41 @@ pub mod file_management {
 
 
 
-    use std::{sync::{Mutex, Condvar}, collections::HashSet, fs::{self, create_dir_all, metadata, remove_dir_all, remove_file, File, OpenOptions}, os::unix::prelude::*, io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write}, path::PathBuf};
+    use std::{env, ffi::OsString, sync::{Mutex, Condvar, RwLock}, fs::{self, create_dir_all, metadata, remove_dir_all, remove_file, rename as rename_file, File, OpenOptions}, os::unix::prelude::*, io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write}, path::PathBuf, ptr};
     use dirs::home_dir;
     use libc::{pwrite, pread};
+    use memmap2::MmapMut;
+    use aes::Aes256;
+    use ctr::{Ctr128BE, cipher::{KeyIvInit, StreamCipher, StreamCipherSeek}};
+    use sha2::{Sha256, Digest};
+    use rand::RngCore;
 
 
 
-    ///Returns working directory of this project
+    ///Environment variable that overrides the working directory, e.g. to run isolated instances
+    pub const HOME_VAR : &str = "DBEE_HOME";
+
+
+
+    ///Returns working directory of this project, which is DBEE_HOME if it is set and ~/.d-bee
+    ///otherwise. The directory is created if it is missing
     pub fn get_base_path() -> Result<PathBuf> {
-        return Ok(home_dir().ok_or_else(||{Error::new(ErrorKind::NotFound, "home directory could not be found")})?.join(".d-bee"));
+        resolve_base_path(env::var_os(HOME_VAR))
+    }
+
+
+
+    ///Returns the working directory for the value of DBEE_HOME and makes sure it is a directory
+    fn resolve_base_path(home : Option<OsString>) -> Result<PathBuf> {
+        let path = match home {
+            Some(home) if !home.is_empty() => PathBuf::from(home),
+            _ => home_dir().ok_or_else(||{Error::new(ErrorKind::NotFound, "home directory could not be found")})?.join(".d-bee"),
+        };
+        if path.exists() && !path.is_dir() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} is not a directory", path.display())));
+        }
+        create_dir_all(&path)?;
+        Ok(path)
     }
 
 
@@ -57,6 +83,13 @@ pub mod file_management {
 
 
 
+    ///Move the file at from to to
+    pub fn rename(from : &PathBuf, to : &PathBuf) -> Result<()> {
+        rename_file(from, to)
+    }
+
+
+
     ///Returns the size of a File
     pub fn get_size(path : &PathBuf) -> Result<u64> {
         return Ok(metadata(path)?.len());
@@ -75,6 +108,125 @@ pub mod file_management {
         ///Writes data to a file at position <at>, may return an error
         fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()>;
 
+        ///Shrinks or extends the file to <size> bytes, may return an error
+        fn truncate(&self, size : usize) -> Result<()>;
+
+        ///Flushes written data to the storage device, so it survives a crash or power loss. May
+        ///return an error
+        fn sync(&self) -> Result<()>;
+
+        ///Returns the length of the file in bytes, may return an error
+        fn size(&self) -> Result<usize>;
+
+    }
+
+
+
+    ///Decides where the files of a database are kept. Files kept in memory are lost as soon as
+    ///their handler is dropped
+    #[derive(Clone, Copy, Debug, PartialEq, Default)]
+    pub enum Storage {
+        #[default]
+        Disk,
+        Memory,
+    }
+
+
+
+    impl Storage {
+
+
+        ///Returns a handler for the file at path. Files on disk are created if they do not exist,
+        ///files in memory always start empty and only use the path as their name
+        pub fn open(&self, path : &PathBuf) -> Result<Box<dyn FileHandler>> {
+            Ok(match self {
+                Storage::Disk => {
+                    if !path.is_file() {
+                        create_file(path)?;
+                    }
+                    Box::new(SimpleFileHandler::new(path.clone())?)
+                },
+                Storage::Memory => Box::new(InMemoryFileHandler::new(path.clone())),
+            })
+        }
+
+
+    }
+
+
+
+    ///Excludes overlapping accesses to byte ranges of a file. Shared accesses like reads may
+    ///overlap each other, an exclusive access like a write waits until no overlapping access is
+    ///in progress and blocks every overlapping access until it is released. Accesses to
+    ///separate ranges never wait for each other
+    struct RangeLock {
+        cond : Condvar,
+
+        //Start, end and whether the access is exclusive, for every access that is in progress
+        ranges : Mutex<Vec<(usize, usize, bool)>>,
+    }
+
+
+
+    ///Releases its range of a RangeLock when dropped, so the range is released on error paths as
+    ///well
+    struct RangeGuard<'a> {
+        lock : &'a RangeLock,
+        range : (usize, usize, bool),
+    }
+
+
+
+    impl RangeLock {
+
+
+        fn new() -> RangeLock {
+            RangeLock {cond: Condvar::new(), ranges: Mutex::new(Vec::new())}
+        }
+
+
+        ///Waits until length bytes starting at at can be accessed and registers the access until
+        ///the returned guard is dropped. May return errors!
+        fn lock(&self, at : usize, length : usize, exclusive : bool) -> Result<RangeGuard<'_>> {
+            let range = (at, at.saturating_add(length), exclusive);
+            let mut ranges = self.ranges.lock().map_err(|_| Error::other("Thread poisoned"))?;
+            while ranges.iter().any(|(start, end, other_exclusive)| (exclusive || *other_exclusive) && *start < range.1 && range.0 < *end) {
+                ranges = self.cond.wait(ranges).map_err(|_| Error::other("Thread poisoned"))?;
+            }
+            ranges.push(range);
+            Ok(RangeGuard {lock: self, range})
+        }
+
+
+        ///Waits until no access is in progress and excludes all others until the guard is
+        ///dropped, e.g. while the file changes its size. May return errors!
+        fn lock_all(&self) -> Result<RangeGuard<'_>> {
+            self.lock(0, usize::MAX, true)
+        }
+
+
+    }
+
+
+
+    impl Drop for RangeGuard<'_> {
+
+
+        fn drop(&mut self) {
+
+            //The guard has to release its range even if another thread panicked while holding
+            //the mutex, otherwise waiting accesses would never continue
+            let mut ranges = match self.lock.ranges.lock() {
+                Ok(ranges) => ranges,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            if let Some(index) = ranges.iter().position(|range| *range == self.range) {
+                ranges.swap_remove(index);
+            }
+            self.lock.cond.notify_all();
+        }
+
+
     }
 
 
@@ -84,8 +236,7 @@ pub mod file_management {
         file : File,
         fd : i32,
         path : PathBuf,
-        cond : Condvar,
-        accesses : Mutex<HashSet<(usize, usize)>>
+        accesses : RangeLock
 
     }
 
@@ -100,9 +251,8 @@ pub mod file_management {
             }
             let file = OpenOptions::new().write(true).read(true).open(&path)?;
             let fd = file.as_raw_fd();
-            let cond = Condvar::new();
-            let accesses = Mutex::new(HashSet::new());
-            return Ok(SimpleFileHandler {file, fd, path, cond, accesses});
+            let accesses = RangeLock::new();
+            Ok(SimpleFileHandler {file, fd, path, accesses})
         }
 
 
@@ -119,18 +269,27 @@ pub mod file_management {
 
 
         fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
-            {
-                let mut accesses = self.accesses.lock().map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
-                while accesses.iter().any(|(start, len)| *start < at + length && at < start + len){
-                    accesses = self.cond.wait(accesses).map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
-                }
-            }
+            let _guard = self.accesses.lock(at, length, false)?;
+
+            //Pread may return fewer bytes than requested, so it is repeated until the buffer is
+            //full. Bytes beyond the end of the file are left as zeros
             let mut buffer = vec![0; length];
-            let res = unsafe {
-                pread(self.fd, buffer.as_mut_ptr() as *mut _, length, at as _)
-            };
-            if res == -1 {
-                return Err(Error::last_os_error());
+            let mut done : usize = 0;
+            while done < length {
+                let res = unsafe {
+                    pread(self.fd, buffer.as_mut_ptr().add(done) as *mut _, length - done, (at + done) as _)
+                };
+                if res == -1 {
+                    let error = Error::last_os_error();
+                    if error.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(error);
+                }
+                if res == 0 {
+                    break;
+                }
+                done += res as usize;
             }
             return Ok(buffer);
         }
@@ -138,25 +297,48 @@ pub mod file_management {
 
         fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
             let data_len = data.len();
-            {
-                let mut accesses = self.accesses.lock().map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
-                while accesses.iter().any(|(start, length)| *start < at + data_len && at < start + length){
-                    accesses = self.cond.wait(accesses).map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
+            let _guard = self.accesses.lock(at, data_len, true)?;
+
+            //Pwrite may write fewer bytes than passed, so it is repeated until all data is written
+            
+            (|| -> Result<()> {
+                let mut done : usize = 0;
+                while done < data_len {
+                    let res = unsafe {
+                        pwrite(self.fd, data.as_ptr().add(done) as *const _, data_len - done, (at + done) as _)
+                    };
+                    if res == -1 {
+                        let error = Error::last_os_error();
+                        if error.kind() == ErrorKind::Interrupted {
+                            continue;
+                        }
+                        return Err(error);
+                    }
+                    if res == 0 {
+                        return Err(Error::new(ErrorKind::WriteZero, "file did not accept more bytes"));
+                    }
+                    done += res as usize;
                 }
-                accesses.insert((at, data_len)); 
-            }
-            let res = unsafe {
-                pwrite(self.fd, data.as_ptr() as *const _, data_len, at as _)
-            };
-            {
-                let mut accesses = self.accesses.lock().map_err(|_| Error::new(ErrorKind::Other, "Thread poisoned"))?;
-                accesses.remove(&(at, data_len)); 
-                self.cond.notify_all();
-            }
-            if res == -1 {
-                return Err(Error::last_os_error());
-            }
-            return Ok(());
+                Ok(())
+            })()
+        }
+
+
+        fn truncate(&self, size : usize) -> Result<()> {
+
+            //Wait till no other access is in progress since the file content changes as a whole
+            let _guard = self.accesses.lock_all()?;
+            self.file.set_len(size as u64)
+        }
+
+
+        fn sync(&self) -> Result<()> {
+            self.file.sync_data()
+        }
+
+
+        fn size(&self) -> Result<usize> {
+            Ok(self.file.metadata()?.len() as usize)
         }
 
 
@@ -164,753 +346,2906 @@ pub mod file_management {
 
 
 
-#[cfg(test)]
-    mod tests {
+    ///Keeps a file in a Vec instead of on disk, e.g. for tests or caches that do not have to
+    ///survive a restart. Writes take the lock exclusively, so unlike the handlers of real files no
+    ///tracking of overlapping accesses is needed
+    pub struct InMemoryFileHandler {
 
+        path : PathBuf,
+        data : RwLock<Vec<u8>>,
 
+    }
 
-        use std::sync::{Arc};
-        use std::thread;
-        use super::*;
 
 
+    impl InMemoryFileHandler {
 
-        #[test]
-        //Test if directories can be created and deleted without errors
-        fn create_and_delete_directory_test() {
-            let dir_path = get_test_path().unwrap().join("test_dir");
-            create_dir(&dir_path).unwrap();
-            assert!(dir_path.is_dir(), "Directory was not created");
-            delete_dir(&dir_path).unwrap();
-            assert!(!dir_path.exists(), "Directory was not deleted");
+
+        ///Creates an empty file, the path is only used to name it in errors
+        pub fn new(path : PathBuf) -> InMemoryFileHandler {
+            InMemoryFileHandler {path, data: RwLock::new(vec![])}
         }
 
 
+    }
 
-        #[test]
-        //Test if files can be created and deleted without errors
-        fn create_and_delete_file_test() {
-            create_dir(&get_test_path().unwrap());
-            let file_path = get_test_path().unwrap().join("create_and_delete_file.test");
-            create_file(&file_path).unwrap();
-            assert!(file_path.is_file(), "File was not created");
-            delete_file(&file_path).unwrap();
-            assert!(!file_path.exists(), "File was not deleted");
-        }
 
 
+    impl FileHandler for InMemoryFileHandler {
 
-        #[test]
-        //Test if data can be written and read from a file without errors and if this data changes
-        //during the process
-        fn write_and_read_test() {
-            create_dir(&get_test_path().unwrap());
-            let file_path = get_test_path().unwrap().join("write_and_read.test");
-            create_file(&file_path).unwrap();
-            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
-            let data: Vec<u8> = b"hello world".to_vec();
-            handler.write_at(0, data.clone()).unwrap();
-            let read_data = handler.read_at(0, data.len()).unwrap();
-            assert_eq!(data, read_data, "Data read does not match data written");
-            delete_file(&file_path).unwrap();
+
+        fn get_path(&self) -> &PathBuf {
+            &self.path
         }
 
 
+        fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
 
-        #[test]
-        //Test if SimpleFileHandler returns an error when an invalid path is passed to the new
-        //function
-        fn file_not_found_test() {
-            let invalid_path = get_test_path().unwrap().join("nonexistent_file.test");
-            let result = SimpleFileHandler::new(invalid_path.clone());
-            assert!(result.is_err(), "Expected error when initializing handler with non-existent file");
+            //Bytes beyond the end of the file are returned as zeros like pread leaves them
+            let mut buffer = vec![0; length];
+            let data = self.data.read().map_err(|_| Error::other("Thread poisoned"))?;
+            if at < data.len() {
+                let available = length.min(data.len() - at);
+                buffer[..available].copy_from_slice(&data[at..at + available]);
+            }
+            Ok(buffer)
         }
 
 
+        fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
 
-        #[test]
-        //Test if read_at returns a string with the right length and value
-        fn read_partial_data_test() {
-            create_dir(&get_test_path().unwrap());
-            let file_path = get_test_path().unwrap().join("read_partial_data.test");
-            create_file(&file_path).unwrap();
-            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
-            let data: Vec<u8> = b"hello world".to_vec();
-            handler.write_at(0, data.clone()).unwrap();
-            let read_data = handler.read_at(0, 5).unwrap(); // Read only "hello"
-            assert_eq!(read_data, b"hello", "Partial read does not match expected data");
-            delete_file(&file_path).unwrap();
+            //The file grows if the write extends past the end, the gap is filled with zeros
+            let mut file = self.data.write().map_err(|_| Error::other("Thread poisoned"))?;
+            if file.len() < at + data.len() {
+                file.resize(at + data.len(), 0);
+            }
+            file[at..at + data.len()].copy_from_slice(&data);
+            Ok(())
         }
 
 
-
-        #[test]
-        //Test if data that is written and read beyond end of file is still correct
-        fn write_beyond_eof_test() {
-            let file_path = get_test_path().unwrap().join("write_beyond_eof.test");
-            create_file(&file_path).unwrap();
-            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
-            let data: Vec<u8> = b"beyond eof".to_vec();
-            handler.write_at(100, data.clone()).unwrap();
-            let read_data = handler.read_at(100, data.len()).unwrap();
-            assert_eq!(read_data, data, "Data written beyond EOF does not match expected data");
-            delete_file(&file_path).unwrap();
+        fn truncate(&self, size : usize) -> Result<()> {
+            let mut data = self.data.write().map_err(|_| Error::other("Thread poisoned"))?;
+            data.resize(size, 0);
+            data.shrink_to_fit();
+            Ok(())
         }
 
 
-        #[test]
-        fn parallel_writes_test() {
-            let file_path = get_test_path().unwrap().join("parallel_writes.test");
-            create_file(&file_path).unwrap();
-            let handler: Arc<dyn FileHandler> = Arc::new(SimpleFileHandler::new(file_path.clone()).unwrap());
-            for _ in 0..1000 {
-                let data1 = b"AAAA".to_vec();
-                let data2 = b"BBBB".to_vec();
-                let handler_clone1 = Arc::clone(&handler);
-                let handler_clone2 = Arc::clone(&handler);
-                let thread1 = thread::spawn(move || {
-                    handler_clone1.write_at(0, data1).unwrap();
-                });
-                let thread2 = thread::spawn(move || {
-                    handler_clone2.write_at(2, data2).unwrap();  // Overlaps partially with first write
-                });
-                thread1.join().unwrap();
-                thread2.join().unwrap();
-                let result = handler.read_at(0, 6).unwrap();
-                assert!(result == b"AAAABB" || result == b"AABBBB", "Writes did not synchronize properly!");
-            }
-            delete_file(&file_path).unwrap();
+        fn sync(&self) -> Result<()> {
+            Ok(())
         }
 
 
+        fn size(&self) -> Result<usize> {
+            Ok(self.data.read().map_err(|_| Error::other("Thread poisoned"))?.len())
+        }
+
 
     }
 
 
 
-}
+    ///Mapped region of a file along with a pointer to its start. The pointer is taken once when
+    ///the file is mapped, so accesses do not create references to the shared memory.
+    struct Mapping {
+        map : MmapMut,
+        ptr : *mut u8,
+        len : usize,
+    }
 
 
 
-pub mod page_management {
+    impl Mapping {
 
 
+        ///Maps the whole file, returns None for empty files since they can not be mapped
+        fn new(file : &File) -> Result<Option<Mapping>> {
+            let len = file.metadata()?.len() as usize;
+            if len == 0 {
+                return Ok(None);
+            }
+            let mut map = unsafe { MmapMut::map_mut(file)? };
+            let ptr = map.as_mut_ptr();
+            Ok(Some(Mapping {map, ptr, len}))
+        }
 
-    use std::{
-        io::{Error, ErrorKind, Result}, 
-        path::PathBuf,
-        fmt::{self, Display, Formatter}
-    };
 
+    }
 
-    use super::file_management::{
-        self, 
-        FileHandler, 
-        SimpleFileHandler
-    };
 
 
-    use crate::bubble::Bubble;
+    pub struct MmapFileHandler {
 
+        file : File,
+        path : PathBuf,
 
+        //Reads and writes hold the read lock while copying, the write lock is only taken to
+        //remap the file when it grows or shrinks
+        mapping : RwLock<Option<Mapping>>,
+        accesses : RangeLock
 
-    const PAGE_SIZE : usize = 4096;
-    const HEAD_SIZE : usize = 8;
+    }
 
 
 
-    pub trait PageHandler: Sync + Send {
+    //Overlapping accesses to the mapped memory are excluded through accesses and the mapping is
+    //only replaced while no access holds the lock
+    unsafe impl Send for MmapFileHandler {}
+    unsafe impl Sync for MmapFileHandler {}
 
-        ///Takes the number of bytes of the data that should be stored in a page. Returns the
-        ///header of the first header that can fit the data. If no page is allocated that has
-        ///enough space left None is returned. May also return errors!
-        fn find_fitting_page(&self, size : usize) -> Result<Option<PageHeader>>;
 
-        ///Takes a page id and checks if the page with the id is allocated. If so the page header
-        ///of that page is returned, otherwise None. May return errors!
-        fn is_page(&self, id : usize) -> Result<Option<PageHeader>>;
 
-        ///Allocate a new page and returns its page header. May return errors!
-        fn alloc_page(&self) -> Result<PageHeader>;
+    impl MmapFileHandler {
 
-        ///Takes a page header of the page that should be deallocated. It then gets deallocated and
-        ///has to be allocated again before use. May return errors!
-        fn dealloc_page(&self, page : PageHeader) -> Result<()>;
 
-        ///Takes a page header of the page that should be read. The page bytes are then returned.
-        ///May return errors!
-        fn read_page(&self, page : &PageHeader) -> Result<Vec<u8>>;
+        pub fn new(path : PathBuf) -> Result<MmapFileHandler> {
+            if !path.is_file() {
+                return Err(Error::new(ErrorKind::NotFound, "the path passed is not a file or does not have right permissions"));
+            }
+            let file = OpenOptions::new().write(true).read(true).open(&path)?;
+            let mapping = RwLock::new(Mapping::new(&file)?);
+            let accesses = RangeLock::new();
+            Ok(MmapFileHandler {file, path, mapping, accesses})
+        }
 
-        ///Takes a page header of the page that should be written to the data and the size. The
-        ///size is used for the find_fitting_page method and does not necessarily have to be the
-        ///length of data. May return errors!
-        fn write_page(&self, page : PageHeader, data : Vec<u8>, size : usize) -> Result<()>;
 
-        ///Takes a callback function f that gets executed for every allocated page bytes. When the
-        ///callback returns true the iteration stops. Errors returned by the callback are passed
-        ///through this function. Errors by this method can be returned as well!
-        fn iterate_pages<'a>(&self, f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()>; 
+        ///Sets the length of the file and maps it again
+        fn resize(&self, mapping : &mut Option<Mapping>, size : usize) -> Result<()> {
+
+            //The old mapping has to be removed before the file shrinks
+            *mapping = None;
+            self.file.set_len(size as u64)?;
+            *mapping = Mapping::new(&self.file)?;
+            Ok(())
+        }
 
-        ///Works the same as iterate_pages but takes a page header additionally. The pages get
-        ///iterated starting (inclusive) from the page corresponding to the header. May return
-        ///errors!
-        fn iterate_pages_from<'a>(&self, start : PageHeader, f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()>; 
 
     }
 
 
 
+    impl FileHandler for MmapFileHandler {
 
-#[derive(Clone, Debug)]
-    pub struct  PageHeader {
 
-        ///Used to calculate the page start
-        pub id : usize,
+        fn get_path(&self) -> &PathBuf {
+            &self.path
+        }
 
-        ///Used for the find fitting_page_method
-        pub used : usize,
 
-        next : Option<usize>,
-        header_page_id : Option<usize>,
-        header_offset : Option<usize>,
-        previous_page_id : Option<usize>,
-    }
+        fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
+            let _guard = self.accesses.lock(at, length, false)?;
+
+            //Bytes beyond the end of the file are returned as zeros like pread leaves them
+            let mut buffer = vec![0; length];
+            let mapping = self.mapping.read().map_err(|_| Error::other("Thread poisoned"))?;
+            if let Some(mapping) = mapping.as_ref() {
+                if at < mapping.len {
+                    let available = length.min(mapping.len - at);
+                    unsafe {
+                        ptr::copy_nonoverlapping(mapping.ptr.add(at), buffer.as_mut_ptr(), available);
+                    }
+                }
+            }
+            Ok(buffer)
+        }
 
 
+        fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+            let data_len = data.len();
+            let _guard = self.accesses.lock(at, data_len, true)?;
+            let res = (|| -> Result<()> {
+
+                //Grow the file and the mapping if the write extends past the end
+                if self.mapping.read().map_err(|_| Error::other("Thread poisoned"))?.as_ref().map_or(0, |m| m.len) < at + data_len {
+                    let mut mapping = self.mapping.write().map_err(|_| Error::other("Thread poisoned"))?;
+                    let len = mapping.as_ref().map_or(0, |m| m.len);
+                    if len < at + data_len {
+                        self.resize(&mut mapping, at + data_len)?;
+                    }
+                }
+                let mapping = self.mapping.read().map_err(|_| Error::other("Thread poisoned"))?;
+                let mapping = mapping.as_ref().ok_or_else(|| Error::other("unexpected error: file was not mapped"))?;
+                if mapping.len < at + data_len {
+                    return Err(Error::other("unexpected error: mapping is too small"));
+                }
+                unsafe {
+                    ptr::copy_nonoverlapping(data.as_ptr(), mapping.ptr.add(at), data_len);
+                }
+                Ok(())
+            })();
+            res
+        }
 
-    impl PageHeader {
 
+        fn truncate(&self, size : usize) -> Result<()> {
 
-        fn new(id : usize, next : Option<usize>, used : usize, header_page_id : Option<usize>, header_offset : Option<usize>, previous_page_id : Option<usize>) -> PageHeader {
-            return PageHeader{id, used,next, header_page_id, header_offset, previous_page_id};
+            //Wait till no other access is in progress since the file content changes as a whole
+            let _guard = self.accesses.lock_all()?;
+            let mut mapping = self.mapping.write().map_err(|_| Error::other("Thread poisoned"))?;
+            self.resize(&mut mapping, size)
         }
 
 
-        fn get_size() -> usize {
-            return 24;
+        fn sync(&self) -> Result<()> {
+
+            //Writes only reach the mapped memory, so the mapping is flushed before the file
+            let mapping = self.mapping.read().map_err(|_| Error::other("Thread poisoned"))?;
+            if let Some(mapping) = mapping.as_ref() {
+                mapping.map.flush()?;
+            }
+            self.file.sync_data()
         }
-        
+
+
+        fn size(&self) -> Result<usize> {
+            Ok(self.file.metadata()?.len() as usize)
+        }
+
 
     }
 
 
 
-    pub mod simple {
+    //+------------+------------+------------------+
+    //| nonce      | key check  | sectors          |
+    //+------------+------------+------------------+
+    //| [u8; 16]   | [u8; 16]   | Vec<u8>          |
+    //+------------+------------+------------------+
+    //| random per | hash of    | encrypted data   |
+    //| file, part | the file   | of the wrapped   |
+    //| of the key | key        | handler          |
+    //+------------+------------+------------------+
+    const ENCRYPTION_HEAD_SIZE : usize = 32;
 
+    //+------------+------------------------+
+    //| iv         | data                   |
+    //+------------+------------------------+
+    //| [u8; 16]   | [u8; SECTOR_SIZE]      |
+    //+------------+------------------------+
+    //| random per | encrypted bytes, only  |
+    //| write      | the last sector may be |
+    //|            | shorter                |
+    //+------------+------------------------+
+    const SECTOR_SIZE : usize = 4096;
+    const SECTOR_IV_SIZE : usize = 16;
 
 
-        use super::*;
 
+    ///Wraps a FileHandler and encrypts everything written through it with AES-256-CTR. The data is
+    ///split into sectors of SECTOR_SIZE bytes, each stored behind the iv it was encrypted with.
+    ///Every write encrypts the sectors it touches again under a new random iv, so rewriting data
+    ///at the same offset never reuses a key stream. Data keeps its logical position and can be
+    ///read and written at any offset. The key of a file is derived from the key passed and a
+    ///random nonce stored in front of the sectors, so files sharing a key use different keys.
+    pub struct EncryptedFileHandler {
 
+        file_handler : Box<dyn FileHandler>,
+        key : [u8; 32],
 
-        pub struct SimplePageHandler {
-            file_handler : Box<dyn FileHandler>
-        }
+        //Held for reading while sectors are read and for writing while they are rewritten, so no
+        //read sees an iv with the data of another write
+        lock : RwLock<()>,
 
-        
-        //+------------+-------------+-------------+
-        //| id         | next        | used        |
-        //+------------+-------------+-------------+
-        //| usize      | usize       | usize       |
-        //+------------+-------------+-------------+
-        //| id of      | if there    | this is     |
-        //| associated | is overflow | used for    |
-        //| page       | this is the | page alloc  |
-        //|            | id of the   | and fitting |
-        //|            | next page   | page search |
-        //+------------+-------------+-------------+
-        
-/*
-        +----+--------------------------------------------------+
-        |head|next free page at: 2                              | head of free list points to the first free page 
-        +----+--------------------------------------------------+
-        |0   |id: 0, used: 96, next 5                           | header page contains headers of other pages 
-        |0   |id: 1, used: 0, next none                         |
-        |0   |id: 3, used: 0, next none                         |
-        |0   |id: 4, used: 0, next none                         |
-        +----+--------------------------------------------------+
-        |1   |..................................................| header page of this page is 0
-        +----+--------------------------------------------------+
-        |2   |6                                                 | this page is not allocated anymore and page 6 is the next in free list
-        +----+--------------------------------------------------+
-        |3   |..................................................| header page of this page is 0
-        +----+--------------------------------------------------+
-        |4   |..................................................| header page of this page is 0
-        +----+--------------------------------------------------+
-        |5   |id: 5, used: 72, next none                        | next page of page 0 
-        |5   |id: 7, used: 0, next none                         |
-        |5   |id: 8, used: 0, next none                         |
-        +----+--------------------------------------------------+
-        |6   |9                                                 | this page is not allocated anymore and page 9 is the next free page 
-        +----+--------------------------------------------------+
-        |7   |..................................................| header page is 5
-        +----+--------------------------------------------------+
-        |8   |..................................................| header page is 5
-        +----+--------------------------------------------------+
-        |9   |                                                  | 
-        +----+--------------------------------------------------+
+    }
 
 
-*/
-        impl TryFrom<Vec<u8>> for PageHeader {
 
+    impl EncryptedFileHandler {
 
-            type Error = std::io::Error;
 
+        ///Takes the handler of the file that should be encrypted and the key it is encrypted with.
+        ///Returns a PermissionDenied error if the file was encrypted with a different key.
+        pub fn new(file_handler : Box<dyn FileHandler>, key : &str) -> Result<EncryptedFileHandler> {
+            let head = file_handler.read_at(0, ENCRYPTION_HEAD_SIZE)?;
 
-            fn try_from(value: Vec<u8>) -> std::result::Result<Self, Self::Error> {
-                let id = usize::from_le_bytes(value[0..8].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for id"))?);
-                let next = usize::from_le_bytes(value[8..16].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for next"))?);
-                let used = usize::from_le_bytes(value[16..24].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for used"))?);
-                return Ok(PageHeader {id, used, next: if next == 0 {None} else {Some(next)}, header_page_id: None, header_offset: None, previous_page_id: None});
+            //A file without head is new and gets a random nonce
+            let nonce : Vec<u8> = if head.iter().all(|b| *b == 0) {
+                let mut nonce = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut nonce);
+                nonce.to_vec()
+            }else{
+                head[0..16].to_vec()
+            };
+            let key : [u8; 32] = Sha256::new().chain_update(&nonce).chain_update(key.as_bytes()).finalize().into();
+
+            //Files that were encrypted before the data was split into sectors used the counter
+            //of the offset as iv and a key check without the sectors tag
+            let key_check : Vec<u8> = Sha256::new().chain_update(b"sectors").chain_update(key).finalize()[0..16].to_vec();
+            let legacy_key_check : Vec<u8> = Sha256::digest(key)[0..16].to_vec();
+            if head.iter().all(|b| *b == 0) {
+                let mut head = nonce;
+                head.extend(key_check);
+                file_handler.write_at(0, head)?;
+            }else if head[16..32] == legacy_key_check[..] {
+                return Err(Error::new(ErrorKind::InvalidData, format!("file {} was encrypted without sectors, which is no longer supported", file_handler.get_path().display())));
+            }else if head[16..32] != key_check[..] {
+                return Err(Error::new(ErrorKind::PermissionDenied, format!("file {} was encrypted with a different key", file_handler.get_path().display())));
             }
+            Ok(EncryptedFileHandler {file_handler, key, lock: RwLock::new(())})
+        }
 
 
+        ///Encrypts or decrypts data of a sector with its iv, both are the same for CTR
+        fn apply_key_stream(&self, iv : &[u8], data : &mut [u8]) {
+            let mut cipher = Ctr128BE::<Aes256>::new(&self.key.into(), iv.into());
+            cipher.apply_keystream(data);
         }
 
 
+        ///Returns where sector index starts in the wrapped file
+        fn sector_start(index : usize) -> usize {
+            ENCRYPTION_HEAD_SIZE + index * (SECTOR_IV_SIZE + SECTOR_SIZE)
+        }
+
 
-        impl Into<Vec<u8>> for PageHeader {
+        ///Reads and decrypts the data of sector index. A sector beyond the end of the file is
+        ///empty and the last sector may be shorter than SECTOR_SIZE
+        fn read_sector(&self, index : usize) -> Result<Vec<u8>> {
+            let start = EncryptedFileHandler::sector_start(index);
+            let stored_len = self.file_handler.size()?.saturating_sub(start).min(SECTOR_IV_SIZE + SECTOR_SIZE);
+            if stored_len <= SECTOR_IV_SIZE {
+                return Ok(vec![]);
+            }
+            let mut sector = self.file_handler.read_at(start, stored_len)?;
+            let mut data = sector.split_off(SECTOR_IV_SIZE);
+            self.apply_key_stream(&sector, &mut data);
+            Ok(data)
+        }
 
 
-            fn into(self) -> Vec<u8> {
-                let mut buffer = Vec::new();
-                buffer.extend(&self.id.to_le_bytes());
-                buffer.extend(&self.next.unwrap_or(0).to_le_bytes());
-                buffer.extend(&self.used.to_le_bytes());
-                return buffer;
+        ///Encrypts data under a new random iv and stores it as sector index
+        fn write_sector(&self, index : usize, mut data : Vec<u8>) -> Result<()> {
+            let mut sector = vec![0u8; SECTOR_IV_SIZE];
+            rand::thread_rng().fill_bytes(&mut sector);
+            self.apply_key_stream(&sector, &mut data);
+            sector.extend(data);
+            self.file_handler.write_at(EncryptedFileHandler::sector_start(index), sector)
+        }
+
+
+        ///Writes data at offset at, the caller has to hold the lock for writing
+        fn write_data(&self, at : usize, data : Vec<u8>) -> Result<()> {
+
+            //A gap between the end of the file and at is written as encrypted zeros, so it reads
+            //as zeros
+            let size = EncryptedFileHandler::data_size(self.file_handler.size()?);
+            let (at, data) = if at > size {
+                let mut filled = vec![0u8; at - size];
+                filled.extend(data);
+                (size, filled)
+            }else{
+                (at, data)
+            };
+
+            //Every touched sector is encrypted again as a whole
+            let mut position = at;
+            while position < at + data.len() {
+                let index = position / SECTOR_SIZE;
+                let offset = position % SECTOR_SIZE;
+                let len = (SECTOR_SIZE - offset).min(at + data.len() - position);
+                let mut sector = self.read_sector(index)?;
+                if sector.len() < offset + len {
+                    sector.resize(offset + len, 0);
+                }
+                sector[offset..(offset + len)].copy_from_slice(&data[(position - at)..(position - at + len)]);
+                self.write_sector(index, sector)?;
+                position += len;
             }
+            Ok(())
+        }
 
 
+        ///Returns the number of data bytes the wrapped file of size bytes holds
+        fn data_size(size : usize) -> usize {
+            let sectors = size.saturating_sub(ENCRYPTION_HEAD_SIZE);
+            let rest = sectors % (SECTOR_IV_SIZE + SECTOR_SIZE);
+            sectors / (SECTOR_IV_SIZE + SECTOR_SIZE) * SECTOR_SIZE + rest.saturating_sub(SECTOR_IV_SIZE)
         }
 
 
+    }
 
-        impl Display for PageHeader {
 
 
-            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-                return write!(f, "id: {}, used: {}, next {}", self.id, self.used, self.next.map_or("none".to_string(), |n| n.to_string()));
-            }
+    impl FileHandler for EncryptedFileHandler {
 
 
+        fn get_path(&self) -> &PathBuf {
+            self.file_handler.get_path()
         }
 
 
+        fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
+            let _lock = self.lock.read().map_err(|_| Error::other("thread poisoned"))?;
+
+            //Bytes beyond the end of the file were never written and are returned as zeros
+            let mut data = vec![0u8; length];
+            let mut position = at;
+            while position < at + length {
+                let index = position / SECTOR_SIZE;
+                let offset = position % SECTOR_SIZE;
+                let len = (SECTOR_SIZE - offset).min(at + length - position);
+                let sector = self.read_sector(index)?;
+                if offset >= sector.len() {
+                    break;
+                }
+                let available = len.min(sector.len() - offset);
+                data[(position - at)..(position - at + available)].copy_from_slice(&sector[offset..(offset + available)]);
+                position += len;
+            }
+            Ok(data)
+        }
+
 
-        impl PageHeader {
+        fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+            let _lock = self.lock.write().map_err(|_| Error::other("thread poisoned"))?;
+            self.write_data(at, data)
+        }
 
 
-            fn get_first() -> PageHeader {
-                return PageHeader{ header_page_id: Some(0), previous_page_id: Some(0), header_offset: Some(PageHeader::get_size()), id: 0, used: 0, next: None  }
+        fn truncate(&self, size : usize) -> Result<()> {
+            let _lock = self.lock.write().map_err(|_| Error::other("thread poisoned"))?;
+
+            //Growing the file writes encrypted zeros, so the new bytes read as zeros
+            let current = EncryptedFileHandler::data_size(self.file_handler.size()?);
+            if size > current {
+                return self.write_data(current, vec![0u8; size - current]);
             }
 
+            //Cutting off the end of a sector keeps the rest of it readable with the same iv
+            let rest = size % SECTOR_SIZE;
+            let stored_rest = if rest == 0 { 0 } else { SECTOR_IV_SIZE + rest };
+            self.file_handler.truncate(EncryptedFileHandler::sector_start(size / SECTOR_SIZE) + stored_rest)
+        }
+
 
+        fn sync(&self) -> Result<()> {
+            self.file_handler.sync()
         }
 
 
+        fn size(&self) -> Result<usize> {
+            Ok(EncryptedFileHandler::data_size(self.file_handler.size()?))
+        }
 
-        impl SimplePageHandler {
 
+    }
 
-            pub fn new(page_path : PathBuf) -> Result<SimplePageHandler> {
-                file_management::create_file(&page_path);                        
-                let file_handler = Box::new(SimpleFileHandler::new(page_path)?);
-                let page_handler = SimplePageHandler { file_handler };
-                if file_management::get_size(page_handler.file_handler.get_path())? < 32 { 
-                    page_handler.file_handler.write_at(0, 1_usize.to_le_bytes().to_vec());
-                    let first_header = PageHeader::new(0, None, PageHeader::get_size(), None, None, None);
-                    page_handler.file_handler.write_at(8, first_header.into());
-                }
-                return Ok(page_handler);
-            }
 
 
-            fn push_free(&self, id : usize) -> Result<()> {
-                //Load previous first free page id
-                let next_bytes : Vec<u8> = self.file_handler.read_at(0, 8)?;
-                //Update first free page id
-                self.file_handler.write_at(0, id.to_le_bytes().to_vec())?;
-                //Set next free page id of the new id to the previous first
-                self.file_handler.write_at(SimplePageHandler::calculate_page_start(id), next_bytes)?;
-                return Ok(());
-            }
+#[cfg(test)]
+    mod tests {
 
 
-            fn pop_free(&self) -> Result<usize> {
-                //Load the first free page id 
-                let first_page : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8)?.try_into().map_err(|_|{Error::new(ErrorKind::UnexpectedEof, "not enough bytes for first page")})?);
-                //Load the next free page id from the first free page
-                let second_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(first_page), 8)?;
-                //Check if the second free page is the tail of the free list
-                if second_page_bytes != vec![0, 0, 0, 0, 0, 0, 0, 0] {
-                //If it is not set the first free page to the second page
-                    self.file_handler.write_at(0, second_page_bytes)?;
-                }else{
-                //Otherwise increment first page id by one since it has to be first free page all
-                //time
-                    self.file_handler.write_at(0, (first_page + 1).to_le_bytes().to_vec())?;
-                }
-                return Ok(first_page);
-            }
 
+        use std::sync::{Arc};
+        use std::thread;
+        use super::*;
 
-            fn calculate_page_start(id : usize) -> usize {
-                return id * PAGE_SIZE + HEAD_SIZE;  
-            }
 
 
-            ///Iterates over_all headers starting from the header passed to the function, once until true is returned from f
-            fn iterate_headers_from<F>(&self, header : PageHeader, mut f : F) -> Result<()> where F : FnMut(PageHeader) -> Result<bool> {
-                let mut current_page_id : usize = header.header_page_id.ok_or_else(|| {Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id")})?;
-                let mut previous_page_id = header.previous_page_id.ok_or_else(|| {Error::new(ErrorKind::InvalidInput, "header did not contain previous")})?;
-                let mut  initial_header_offset : usize = header.header_offset.ok_or_else(||{Error::new(ErrorKind::InvalidInput, "header did not contain offset")})?;
+        #[test]
+        //Test if the working directory is taken from DBEE_HOME and created when it is missing
+        fn resolve_base_path_test() {
+            let home = get_test_path().unwrap().join("home");
+            let _ = delete_dir(&home);
+            assert_eq!(resolve_base_path(Some(home.clone().into_os_string())).unwrap(), home);
+            assert!(home.is_dir(), "Directory was not created");
+            assert!(resolve_base_path(Some(OsString::new())).unwrap().ends_with(".d-bee"), "An empty value should fall back to the default");
+            let file_path = home.join("file.test");
+            create_file(&file_path).unwrap();
+            assert_eq!(resolve_base_path(Some(file_path.into_os_string())).unwrap_err().kind(), ErrorKind::InvalidInput);
+            delete_dir(&home).unwrap();
+        }
 
-                //Loop till the current header does not have a next_page_id
-                loop {
 
-                    //Load current header page and extract the own header in order to find the
-                    //next_page_id and the number of headers stored in the page
-                    let current_header_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(current_page_id), PAGE_SIZE)?;
-                    let own_header = PageHeader::try_from(current_header_page_bytes[0..PageHeader::get_size()].to_vec())?;
 
-                    //Loop through all headers in the header page
-                    for current_header_offset in (initial_header_offset..own_header.used).step_by(PageHeader::get_size()) {
+        #[test]
+        //Test if directories can be created and deleted without errors
+        fn create_and_delete_directory_test() {
+            let dir_path = get_test_path().unwrap().join("test_dir");
+            create_dir(&dir_path).unwrap();
+            assert!(dir_path.is_dir(), "Directory was not created");
+            delete_dir(&dir_path).unwrap();
+            assert!(!dir_path.exists(), "Directory was not deleted");
+        }
 
-                        //For every header set the correct header values and execute f
-                        if let Some(header_bytes) = current_header_page_bytes.get(current_header_offset..current_header_offset + PageHeader::get_size()) {
-                            let mut current_header = PageHeader::try_from(header_bytes.to_vec())?;
-                            current_header.header_page_id = Some(current_page_id);
-                            current_header.header_offset = Some(current_header_offset);
-                            current_header.previous_page_id = Some(previous_page_id);
-                            if f(current_header)? {
-                                return Ok(());
-                            }
-                        }else{
-                            break;
-                        }
-                    }
-                    if let Some(next_page_id) = own_header.next {
-                        previous_page_id = current_page_id;
-                        current_page_id = next_page_id;
-                    }else{
-                        break;
-                    }
 
-                    //Reset initial_offset since the offset from the header passed to the function
-                    //should only be used in the first header_page
-                    initial_header_offset = PageHeader::get_size();
-                }
-                return Ok(());
-            }
+
+        #[test]
+        //Test if files can be created and deleted without errors
+        fn create_and_delete_file_test() {
+            create_dir(&get_test_path().unwrap());
+            let file_path = get_test_path().unwrap().join("create_and_delete_file.test");
+            create_file(&file_path).unwrap();
+            assert!(file_path.is_file(), "File was not created");
+            delete_file(&file_path).unwrap();
+            assert!(!file_path.exists(), "File was not deleted");
+        }
+
 
 
+        #[test]
+        //Test if data can be written and read from a file without errors and if this data changes
+        //during the process
+        fn write_and_read_test() {
+            create_dir(&get_test_path().unwrap());
+            let file_path = get_test_path().unwrap().join("write_and_read.test");
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            let data: Vec<u8> = b"hello world".to_vec();
+            handler.write_at(0, data.clone()).unwrap();
+            let read_data = handler.read_at(0, data.len()).unwrap();
+            assert_eq!(data, read_data, "Data read does not match data written");
+            delete_file(&file_path).unwrap();
         }
-        
 
 
-        #[cfg(test)]
-        impl Display for SimplePageHandler {
-            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-                let width = 50;
-                let mut bubble = Bubble::new(vec![4, width]);
-                let first_page : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8).unwrap().try_into().unwrap());
-                bubble.add_line(vec!["head".to_string(), format!("next free page at: {}", first_page.to_string())]);
-                'outer:
-                    for i in 0..10 {
-                        let mut j : usize = 0;
-                        bubble.add_divider();
-                        //Check if page is a header page and if so show headers
-                        loop{
-                            let header_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(j), PAGE_SIZE).unwrap();
-                            let page_header = PageHeader::try_from(header_page_bytes[0..PageHeader::get_size()].to_vec()).unwrap();
-                            if page_header.id == i {
-                                for n in (0..page_header.used).step_by(PageHeader::get_size()) {
-                                    let m :usize = n + PageHeader::get_size();
-                                    if let Some(header_bytes) = header_page_bytes.get(n..m) {
-                                        let mut header = PageHeader::try_from(header_bytes.to_vec()).unwrap();
-                                        bubble.add_line(vec![i.to_string(), header.to_string()]);
-                                    }
-                                }
-                                continue 'outer;
-                            }
-                            if let Some(next) = page_header.next {
-                                j = next;
-                            }else{
-                                break;
-                            }
-                        }
-                        //Check if page is in the free list
-                        j = usize::from_le_bytes(self.file_handler.read_at(0, 8).unwrap().try_into().unwrap());
-                        loop {
-                            let next : usize = usize::from_le_bytes(self.file_handler.read_at(SimplePageHandler::calculate_page_start(j), 8).unwrap().try_into().unwrap());
-                            if next == 0 {
-                                break;
-                            }
-                            if j == i {
-                                bubble.add_line(vec![i.to_string(), next.to_string()]);
-                                continue 'outer;
-                            }
-                            j = next;
-                        }
-                        //Write used space
-                        let mut allocated = false;
-                        self.iterate_headers_from(PageHeader{ header_page_id: Some(0), previous_page_id: Some(0), header_offset: Some(PageHeader::get_size()), id: 0, used: 0, next: None  },|h| {
-                            if i == h.id {
-                                let space = h.used * width / PAGE_SIZE;
-                                let mut space_representation = String::new();
-                                for _ in 0..space {
-                                    space_representation.push_str("#");
-                                }
-                                for _ in space..width {
-                                    space_representation.push_str(".");
+
+        #[test]
+        //Test if SimpleFileHandler returns an error when an invalid path is passed to the new
+        //function
+        fn file_not_found_test() {
+            let invalid_path = get_test_path().unwrap().join("nonexistent_file.test");
+            let result = SimpleFileHandler::new(invalid_path.clone());
+            assert!(result.is_err(), "Expected error when initializing handler with non-existent file");
+        }
+
+
+
+        #[test]
+        //Test if read_at returns a string with the right length and value
+        fn read_partial_data_test() {
+            create_dir(&get_test_path().unwrap());
+            let file_path = get_test_path().unwrap().join("read_partial_data.test");
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            let data: Vec<u8> = b"hello world".to_vec();
+            handler.write_at(0, data.clone()).unwrap();
+            let read_data = handler.read_at(0, 5).unwrap(); // Read only "hello"
+            assert_eq!(read_data, b"hello", "Partial read does not match expected data");
+            delete_file(&file_path).unwrap();
+        }
+
+
+
+        #[test]
+        //Test if large buffers are written and read completely and if reads crossing the end of
+        //the file are filled with zeros
+        fn large_read_write_test() {
+            let file_path = get_test_path().unwrap().join("large_read_write.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            let data: Vec<u8> = (0..(16 << 20)).map(|i| (i % 251) as u8).collect();
+            handler.write_at(3, data.clone()).unwrap();
+            assert_eq!(handler.read_at(3, data.len()).unwrap(), data, "Large read does not match written data");
+            let tail = handler.read_at(data.len() - 2, 10).unwrap();
+            assert_eq!(tail[..5], data[data.len() - 5..], "Read crossing the end of the file lost data");
+            assert_eq!(tail[5..], [0; 5], "Bytes beyond the end of the file should be zeros");
+            delete_file(&file_path).unwrap();
+        }
+
+
+
+        #[test]
+        //Test if the in memory handler grows, truncates and fills gaps like a file
+        fn in_memory_test() {
+            let handler = InMemoryFileHandler::new(PathBuf::from("memory"));
+            handler.write_at(100, b"beyond eof".to_vec()).unwrap();
+            assert_eq!(handler.size().unwrap(), 110, "File did not grow with the write");
+            assert_eq!(handler.read_at(98, 4).unwrap(), b"\0\0be");
+            handler.truncate(102).unwrap();
+            assert_eq!(handler.read_at(100, 4).unwrap(), b"be\0\0");
+        }
+
+
+
+        #[test]
+        //Test if data that is written and read beyond end of file is still correct
+        fn write_beyond_eof_test() {
+            let file_path = get_test_path().unwrap().join("write_beyond_eof.test");
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            let data: Vec<u8> = b"beyond eof".to_vec();
+            handler.write_at(100, data.clone()).unwrap();
+            let read_data = handler.read_at(100, data.len()).unwrap();
+            assert_eq!(read_data, data, "Data written beyond EOF does not match expected data");
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        //Test if truncate shrinks a file to the given size
+        fn truncate_test() {
+            let file_path = get_test_path().unwrap().join("truncate.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            handler.write_at(0, b"hello world".to_vec()).unwrap();
+            handler.truncate(5).unwrap();
+            assert_eq!(get_size(&file_path).unwrap(), 5, "File was not truncated");
+            assert_eq!(handler.read_at(0, 5).unwrap(), b"hello", "Truncate changed the remaining data");
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        //Test if the memory mapped handler reads what was written, also beyond the end of the
+        //file and after reopening
+        fn mmap_write_and_read_test() {
+            let file_path = get_test_path().unwrap().join("mmap_write_and_read.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(MmapFileHandler::new(file_path.clone()).unwrap());
+            assert_eq!(handler.read_at(0, 4).unwrap(), vec![0; 4], "Empty file should read as zeros");
+            handler.write_at(0, b"hello world".to_vec()).unwrap();
+            handler.write_at(100, b"beyond eof".to_vec()).unwrap();
+            assert_eq!(get_size(&file_path).unwrap(), 110, "File did not grow with the write");
+            assert_eq!(handler.read_at(0, 5).unwrap(), b"hello");
+            assert_eq!(handler.read_at(100, 10).unwrap(), b"beyond eof", "Data written beyond EOF does not match expected data");
+            handler.truncate(5).unwrap();
+            assert_eq!(get_size(&file_path).unwrap(), 5, "File was not truncated");
+            assert_eq!(handler.read_at(0, 8).unwrap(), b"hello\0\0\0");
+            drop(handler);
+            let handler = SimpleFileHandler::new(file_path.clone()).unwrap();
+            assert_eq!(handler.read_at(0, 5).unwrap(), b"hello", "Data was not written to the file");
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        //Test if data written through the encrypted handler is stored encrypted and can only be
+        //read back with the right key
+        fn encrypted_write_and_read_test() {
+            let file_path = get_test_path().unwrap().join("encrypted_write_and_read.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler: Box<dyn FileHandler> = Box::new(EncryptedFileHandler::new(Box::new(SimpleFileHandler::new(file_path.clone()).unwrap()), "key").unwrap());
+            let data: Vec<u8> = b"hello world".to_vec();
+            handler.write_at(3, data.clone()).unwrap();
+            assert_eq!(handler.read_at(5, 5).unwrap(), b"llo w", "Partial read does not match expected data");
+            assert_eq!(handler.read_at(3, 16).unwrap(), b"hello world\0\0\0\0\0", "Bytes beyond EOF should read as zeros");
+            drop(handler);
+
+            //The file does not contain the plain data
+            let raw = SimpleFileHandler::new(file_path.clone()).unwrap().read_at(0, get_size(&file_path).unwrap() as usize).unwrap();
+            assert!(!raw.windows(data.len()).any(|w| w == data), "Data was stored unencrypted");
+
+            //Reopening works with the same key only
+            let handler = EncryptedFileHandler::new(Box::new(SimpleFileHandler::new(file_path.clone()).unwrap()), "key").unwrap();
+            assert_eq!(handler.read_at(3, data.len()).unwrap(), data);
+            let result = EncryptedFileHandler::new(Box::new(SimpleFileHandler::new(file_path.clone()).unwrap()), "other key");
+            assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::PermissionDenied));
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        //Test if rewriting data at the same offset uses a new key stream and if writes spanning
+        //sectors, gaps and truncation keep the data readable
+        fn encrypted_rewrite_test() {
+            let file_path = get_test_path().unwrap().join("encrypted_rewrite.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler = EncryptedFileHandler::new(Box::new(SimpleFileHandler::new(file_path.clone()).unwrap()), "key").unwrap();
+            let raw = SimpleFileHandler::new(file_path.clone()).unwrap();
+            handler.write_at(0, vec![1u8; 64]).unwrap();
+            let first = raw.read_at(ENCRYPTION_HEAD_SIZE, SECTOR_IV_SIZE + 64).unwrap();
+            handler.write_at(0, vec![2u8; 64]).unwrap();
+            let second = raw.read_at(ENCRYPTION_HEAD_SIZE, SECTOR_IV_SIZE + 64).unwrap();
+
+            //Xoring both ciphertexts would reveal the xor of both plaintexts if the key stream was
+            //reused
+            let xor : Vec<u8> = first[SECTOR_IV_SIZE..].iter().zip(second[SECTOR_IV_SIZE..].iter()).map(|(a, b)| a ^ b).collect();
+            assert_ne!(first[..SECTOR_IV_SIZE], second[..SECTOR_IV_SIZE], "Every write should use a new iv");
+            assert_ne!(xor, vec![3u8; 64], "The key stream was reused");
+            assert_eq!(handler.read_at(0, 64).unwrap(), vec![2u8; 64]);
+
+            //A write spanning two sectors behind a gap
+            let data : Vec<u8> = (0..100).collect();
+            handler.write_at(SECTOR_SIZE - 50, data.clone()).unwrap();
+            assert_eq!(handler.size().unwrap(), SECTOR_SIZE + 50);
+            assert_eq!(handler.read_at(SECTOR_SIZE - 50, 100).unwrap(), data);
+            assert_eq!(handler.read_at(64, 10).unwrap(), vec![0u8; 10], "The gap should read as zeros");
+
+            //Truncating cuts the data and growing adds zeros
+            handler.truncate(SECTOR_SIZE + 10).unwrap();
+            assert_eq!(handler.size().unwrap(), SECTOR_SIZE + 10);
+            handler.truncate(SECTOR_SIZE + 20).unwrap();
+            assert_eq!(handler.read_at(SECTOR_SIZE, 20).unwrap(), [&data[50..60], &[0u8; 10]].concat());
+            drop(handler);
+            let handler = EncryptedFileHandler::new(Box::new(SimpleFileHandler::new(file_path.clone()).unwrap()), "key").unwrap();
+            assert_eq!(handler.read_at(SECTOR_SIZE - 50, 60).unwrap(), data[..60]);
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        fn mmap_parallel_writes_test() {
+            let file_path = get_test_path().unwrap().join("mmap_parallel_writes.test");
+            let _ = delete_file(&file_path);
+            create_file(&file_path).unwrap();
+            let handler: Arc<dyn FileHandler> = Arc::new(MmapFileHandler::new(file_path.clone()).unwrap());
+            for i in 0..200 {
+                let handler_clone1 = Arc::clone(&handler);
+                let handler_clone2 = Arc::clone(&handler);
+                let thread1 = thread::spawn(move || {
+                    handler_clone1.write_at(0, b"AAAA".to_vec()).unwrap();
+                });
+                let thread2 = thread::spawn(move || {
+                    handler_clone2.write_at(2 + i * 8, b"BBBB".to_vec()).unwrap();  // Grows the file while the other write runs
+                });
+                thread1.join().unwrap();
+                thread2.join().unwrap();
+                assert_eq!(handler.read_at(2 + i * 8, 4).unwrap(), b"BBBB");
+            }
+            delete_file(&file_path).unwrap();
+        }
+
+
+        #[test]
+        //Reads and writes of overlapping ranges run concurrently. Writes always cover whole
+        //chunks, so a read sees every chunk either completely before or after a write
+        fn range_lock_stress_test() {
+            const CHUNK : usize = 512;
+            let file_path = get_test_path().unwrap().join("range_lock_stress.test");
+            create_file(&file_path).unwrap();
+            let handlers: Vec<Arc<dyn FileHandler>> = vec![
+                Arc::new(SimpleFileHandler::new(file_path.clone()).unwrap()),
+                Arc::new(MmapFileHandler::new(file_path.clone()).unwrap()),
+            ];
+            for handler in handlers {
+                handler.write_at(0, vec![0; CHUNK * 4]).unwrap();
+                let mut threads = Vec::new();
+                for i in 0..8 {
+                    let handler_clone = Arc::clone(&handler);
+                    threads.push(thread::spawn(move || {
+                        for j in 0..200 {
+
+                            //Ranges of two chunks starting at chunk 0, 1 or 2 overlap each other
+                            let at = ((i + j) % 3) * CHUNK;
+                            if i % 2 == 0 {
+                                handler_clone.write_at(at, vec![(i * 10 + j % 10) as u8; CHUNK * 2]).unwrap();
+                            } else {
+                                let data = handler_clone.read_at(at, CHUNK * 2).unwrap();
+                                for chunk in data.chunks(CHUNK) {
+                                    assert!(chunk.iter().all(|b| *b == chunk[0]), "read saw a partial write");
                                 }
-                                bubble.add_line(vec![i.to_string(), space_representation]);
-                                allocated = true;
-                                return Ok(true);
                             }
-                            return Ok(false);
-                        });
-                        if !allocated {
-                            bubble.add_line(vec![i.to_string(), "".to_string()]);
                         }
-                    }
-                write!(f, "{}", bubble)
+                    }));
+                }
+                for thread in threads {
+                    thread.join().unwrap();
+                }
             }
+            delete_file(&file_path).unwrap();
         }
 
 
+        #[test]
+        fn parallel_writes_test() {
+            let file_path = get_test_path().unwrap().join("parallel_writes.test");
+            create_file(&file_path).unwrap();
+            let handler: Arc<dyn FileHandler> = Arc::new(SimpleFileHandler::new(file_path.clone()).unwrap());
+            for _ in 0..1000 {
+                let data1 = b"AAAA".to_vec();
+                let data2 = b"BBBB".to_vec();
+                let handler_clone1 = Arc::clone(&handler);
+                let handler_clone2 = Arc::clone(&handler);
+                let thread1 = thread::spawn(move || {
+                    handler_clone1.write_at(0, data1).unwrap();
+                });
+                let thread2 = thread::spawn(move || {
+                    handler_clone2.write_at(2, data2).unwrap();  // Overlaps partially with first write
+                });
+                thread1.join().unwrap();
+                thread2.join().unwrap();
+                let result = handler.read_at(0, 6).unwrap();
+                assert!(result == b"AAAABB" || result == b"AABBBB", "Writes did not synchronize properly!");
+            }
+            delete_file(&file_path).unwrap();
+        }
+
 
-        impl PageHandler for SimplePageHandler {
-            
 
-            fn find_fitting_page(&self, size : usize) -> Result<Option<PageHeader>> {
-                let mut header : Option<PageHeader> = None;
-                let callback = |current_header:PageHeader| {
+    }
 
-                    //Set header to current header and exit iteration if page fits data of size
-                    if PAGE_SIZE - current_header.used >= size {
-                        header = Some(current_header);
-                        return Ok(true);
-                    }
-                    return Ok(false);
-                };
-                self.iterate_headers_from(PageHeader::get_first(), callback)?;
-                return Ok(header);
-            }
 
 
-            fn is_page(&self, id : usize) -> Result<Option<PageHeader>> {
-                let mut header : Option<PageHeader> = None;
-                let callback = |current_header : PageHeader| {
-                    if current_header.id == id {
-                        header = Some(current_header);
-                        return Ok(true);
-                    }
-                    return Ok(false);
-                };
-                self.iterate_headers_from(PageHeader::get_first(), callback)?;
-                return Ok(header);
+}
+
+
+
+pub mod page_management {
+
+
+
+    use std::{
+        io::{Error, ErrorKind, Result}, 
+        path::PathBuf,
+        fmt::{self, Display, Formatter}
+    };
+
+
+    use super::file_management::{
+        self, 
+        FileHandler, 
+        SimpleFileHandler,
+        MmapFileHandler,
+        EncryptedFileHandler,
+        InMemoryFileHandler,
+        Storage
+    };
+
+
+    use crate::bubble::Bubble;
+    use log::debug;
+
+
+
+    pub const DEFAULT_PAGE_SIZE : usize = 4096;
+
+    //Number of pages a page handler keeps in memory unless configured otherwise
+    pub const DEFAULT_CACHE_SIZE : usize = 64;
+
+    //The head of a page file holds the first free page id, the magic and version of the format,
+    //the page size and flags
+    const HEAD_SIZE : usize = 32;
+    const HEAD_MAGIC : &[u8; 4] = b"dbee";
+    const HEAD_VERSION : u32 = 1;
+
+    //Set in the flags of the head if the pages of the file carry checksums
+    const FLAG_CHECKSUMS : u64 = 1;
+
+    //Files written before the head was versioned only hold the first free page id in their head
+    //and use pages of 4096 bytes. The bytes the magic is stored in belong to the id of the first
+    //header page in them, which is always 0
+    const LEGACY_HEAD_SIZE : usize = 8;
+    const LEGACY_PAGE_SIZE : usize = 4096;
+    const CHECKSUM_SIZE : usize = 4;
+
+
+
+    ///Calculates the CRC32 (IEEE) checksum of data
+    fn crc32(data : &[u8]) -> u32 {
+        let mut crc : u32 = 0xFFFFFFFF;
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (!(crc & 1)).wrapping_add(1);
+                crc = (crc >> 1) ^ (0xEDB88320 & mask);
             }
+        }
+        !crc
+    }
 
 
 
-            fn alloc_page(&self) -> Result<PageHeader> {
-                let mut current_header_page_id : usize = 0;
-                let mut new_page_id = self.pop_free()?;
-                loop {
-                    let mut current_header_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(current_header_page_id), PAGE_SIZE)?;
-                    let mut own_header = PageHeader::try_from(current_header_page_bytes[0..PageHeader::get_size()].to_vec())?;
-                    if PAGE_SIZE - own_header.used > PageHeader::get_size() {
-                        //Add new header to the header page
-                        let new_header = PageHeader::new(new_page_id, None, 0, Some(own_header.id), Some(own_header.used), None);
-                        let new_header_bytes : Vec<u8> = new_header.clone().into();
-                        current_header_page_bytes[own_header.used..own_header.used + PageHeader::get_size()].copy_from_slice(&new_header_bytes);
-                        //Increase used value
-                        own_header.used += PageHeader::get_size();
-                        current_header_page_bytes[..PageHeader::get_size()].copy_from_slice(&Into::<Vec<u8>>::into(own_header)); 
-                        self.file_handler.write_at(SimplePageHandler::calculate_page_start(current_header_page_id), current_header_page_bytes)?;
-                        return Ok(new_header);
-                    }
-                    if let Some(next_header_page_id) = own_header.next {
-                        //In case one header page did not have enough space for another header and
-                        //another one exists already the loop gets repeated with the next header page
-                        current_header_page_id = next_header_page_id;     
-                    }else{
-                        //In case one page is full and no next was created a new one is appended to the
-                        //previous page.
-                        own_header.next = Some(new_page_id);
-                        let own_header_bytes : Vec<u8> = own_header.clone().into();
-                        current_header_page_bytes[..PageHeader::get_size()].copy_from_slice(&own_header_bytes); 
-                        self.file_handler.write_at(SimplePageHandler::calculate_page_start(current_header_page_id), current_header_page_bytes);
-                        let new_own_header = PageHeader::new(new_page_id, None, PageHeader::get_size(), None, None, Some(own_header.id));
-                        self.file_handler.write_at(SimplePageHandler::calculate_page_start(new_page_id), new_own_header.into());
-                        current_header_page_id = new_page_id;
-                        new_page_id = self.pop_free()?;
+    pub trait PageHandler: Sync + Send {
+
+        ///Takes the number of bytes of the data that should be stored in a page. Returns the
+        ///header of the first header that can fit the data. If no page is allocated that has
+        ///enough space left None is returned. May also return errors!
+        fn find_fitting_page(&self, size : usize) -> Result<Option<PageHeader>>;
+
+        ///Takes a page id and checks if the page with the id is allocated. If so the page header
+        ///of that page is returned, otherwise None. May return errors!
+        fn is_page(&self, id : usize) -> Result<Option<PageHeader>>;
+
+        ///Allocate a new page and returns its page header. May return errors!
+        fn alloc_page(&self) -> Result<PageHeader>;
+
+        ///Takes a page header of the page that should be deallocated. It then gets deallocated and
+        ///has to be allocated again before use. May return errors!
+        fn dealloc_page(&self, page : PageHeader) -> Result<()>;
+
+        ///Takes a page header of the page that should be read. The page bytes are then returned.
+        ///May return errors!
+        fn read_page(&self, page : &PageHeader) -> Result<Vec<u8>>;
+
+        ///Takes a page header of the page that should be written to the data and the size. The
+        ///size is used for the find_fitting_page method and does not necessarily have to be the
+        ///length of data. May return errors!
+        fn write_page(&self, page : PageHeader, data : Vec<u8>, size : usize) -> Result<()>;
+
+        ///Takes a callback function f that gets executed for every allocated page bytes. When the
+        ///callback returns true the iteration stops. Errors returned by the callback are passed
+        ///through this function. Errors by this method can be returned as well!
+        fn iterate_pages<'a>(&self, f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()>; 
+
+        ///Works the same as iterate_pages but takes a page header additionally. The pages get
+        ///iterated starting (inclusive) from the page corresponding to the header. May return
+        ///errors!
+        fn iterate_pages_from<'a>(&self, start : PageHeader, f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()>; 
+
+        ///Moves the allocated pages into the free pages at the front of the file, empties the
+        ///free list and truncates the file afterwards. Pages are copied before anything points to
+        ///the copies, so a compaction that stops early leaves a readable file. Previously
+        ///returned page headers become invalid. May return errors!
+        fn compact(&self) -> Result<()>;
+
+        ///Truncates the file behind the last allocated page if the pages at the end of the file
+        ///are free and returns the number of released pages. Only trailing free space is
+        ///reclaimed, free pages between allocated ones stay in the file until it is compacted.
+        ///May return errors!
+        fn shrink(&self) -> Result<usize>;
+
+        ///Writes all pages to the storage device, e.g. before the database is shut down. May
+        ///return errors!
+        fn sync(&self) -> Result<()>;
+
+        ///Returns the size of the pages in bytes
+        fn page_size(&self) -> usize;
+
+        ///Returns the number of bytes of a page that can be used for data, which is the length
+        ///of the pages returned by read_page
+        fn page_capacity(&self) -> usize;
+
+        ///Takes a page header and the id of the page that should follow it, e.g. to chain
+        ///overflow pages. The header is updated as well. May return errors!
+        fn set_next(&self, page : &mut PageHeader, next : Option<usize>) -> Result<()>;
+
+    }
+
+
+
+
+#[derive(Clone, Debug)]
+    pub struct  PageHeader {
+
+        ///Used to calculate the page start
+        pub id : usize,
+
+        ///Used for the find fitting_page_method
+        pub used : usize,
+
+        next : Option<usize>,
+        header_page_id : Option<usize>,
+        header_offset : Option<usize>,
+        previous_page_id : Option<usize>,
+    }
+
+
+
+    impl PageHeader {
+
+
+        fn new(id : usize, next : Option<usize>, used : usize, header_page_id : Option<usize>, header_offset : Option<usize>, previous_page_id : Option<usize>) -> PageHeader {
+            return PageHeader{id, used,next, header_page_id, header_offset, previous_page_id};
+        }
+
+
+        fn get_size() -> usize {
+            return 24;
+        }
+
+
+        ///Returns the id of the page that follows this page, if any
+        pub fn get_next(&self) -> Option<usize> {
+            self.next
+        }
+        
+
+    }
+
+
+
+    pub mod simple {
+
+
+
+        use super::*;
+        use std::{collections::{HashSet, HashMap, BTreeMap, BTreeSet}, sync::Mutex};
+
+
+
+        ///Hits and misses of the page cache since the page handler was created
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct CacheStatistics {
+            pub hits : u64,
+            pub misses : u64,
+        }
+
+
+
+        ///Least recently used cache of the bytes of whole pages, keyed by page id
+        struct PageCache {
+            capacity : usize,
+            pages : HashMap<usize, (Vec<u8>, u64)>,
+
+            //Maps the time of the last use to the page id, the first entry is evicted first
+            order : BTreeMap<u64, usize>,
+            time : u64,
+
+            //Incremented on every invalidation, so pages read from the file while an invalidation
+            //happened are not cached with stale content
+            generation : u64,
+            statistics : CacheStatistics,
+        }
+
+
+
+        impl PageCache {
+
+
+            fn new(capacity : usize) -> Self {
+                PageCache{capacity, pages: HashMap::new(), order: BTreeMap::new(), time: 0, generation: 0, statistics: CacheStatistics{hits: 0, misses: 0}}
+            }
+
+
+            fn get(&mut self, id : usize) -> Option<Vec<u8>> {
+                self.time += 1;
+                match self.pages.get_mut(&id) {
+                    Some((page, last_use)) => {
+                        self.order.remove(last_use);
+                        *last_use = self.time;
+                        self.order.insert(self.time, id);
+                        self.statistics.hits += 1;
+                        Some(page.clone())
+                    },
+                    None => {
+                        self.statistics.misses += 1;
+                        None
+                    },
+                }
+            }
+
+
+            fn insert(&mut self, id : usize, page : Vec<u8>) {
+                self.remove(id);
+                if self.capacity == 0 {
+                    return;
+                }
+                while self.pages.len() >= self.capacity {
+                    if let Some((_, evicted)) = self.order.pop_first() {
+                        self.pages.remove(&evicted);
+                    }
+                }
+                self.time += 1;
+                self.order.insert(self.time, id);
+                self.pages.insert(id, (page, self.time));
+            }
+
+
+            fn remove(&mut self, id : usize) {
+                if let Some((_, last_use)) = self.pages.remove(&id) {
+                    self.order.remove(&last_use);
+                }
+            }
+
+
+            fn clear(&mut self) {
+                self.pages.clear();
+                self.order.clear();
+            }
+
+
+            fn resize(&mut self, capacity : usize) {
+                self.capacity = capacity;
+                while self.pages.len() > self.capacity {
+                    if let Some((_, evicted)) = self.order.pop_first() {
+                        self.pages.remove(&evicted);
+                    }
+                }
+            }
+
+
+        }
+
+
+
+        ///Free bytes of every allocated page, ordered by them so a page with enough space for some
+        ///data is found without walking the headers
+        #[derive(Default)]
+        struct FreeSpaceMap {
+            pages : BTreeSet<(usize, usize)>,
+            free : HashMap<usize, usize>,
+        }
+
+
+
+        impl FreeSpaceMap {
+
+
+            fn set(&mut self, id : usize, free : usize) {
+                if let Some(previous) = self.free.insert(id, free) {
+                    self.pages.remove(&(previous, id));
+                }
+                self.pages.insert((free, id));
+            }
+
+
+            fn remove(&mut self, id : usize) {
+                if let Some(previous) = self.free.remove(&id) {
+                    self.pages.remove(&(previous, id));
+                }
+            }
+
+
+            ///Returns the page with the least free space that is still at least size bytes
+            fn find(&self, size : usize) -> Option<usize> {
+                self.pages.range((size, 0)..).next().map(|(_, id)| *id)
+            }
+
+
+        }
+
+
+
+        //Position of a page header by page id, see SimplePageHandler::header_index
+        type HeaderIndex = HashMap<usize, (usize, usize, usize)>;
+
+
+
+        pub struct SimplePageHandler {
+            file_handler : Box<dyn FileHandler>,
+
+            ///If set the last CHECKSUM_SIZE bytes of every data page hold a CRC32 of the rest of
+            ///the page, stored in the head of the file
+            checksums : bool,
+
+            ///Size of every page in bytes, stored in the head of the file
+            page_size : usize,
+
+            ///Size of the head of the file, which is smaller for files in the legacy layout
+            head_size : usize,
+
+            ///If set every written page is synced to the storage device before write_page returns.
+            ///Off by default since it makes writes much slower
+            durable : bool,
+
+            ///Recently read pages, every write to the file invalidates the pages it touches
+            cache : Mutex<PageCache>,
+
+            ///Maps the id of every allocated page to the id of its header page, the offset of its
+            ///header in there and the id of the header page before. None while it was not built
+            header_index : Mutex<Option<HeaderIndex>>,
+
+            ///Free bytes of every allocated page, built together with the header index
+            free_space : Mutex<Option<FreeSpaceMap>>,
+        }
+
+        
+        //+------------+-------------+-------------+
+        //| id         | next        | used        |
+        //+------------+-------------+-------------+
+        //| usize      | usize       | usize       |
+        //+------------+-------------+-------------+
+        //| id of      | if there    | this is     |
+        //| associated | is overflow | used for    |
+        //| page       | this is the | page alloc  |
+        //|            | id of the   | and fitting |
+        //|            | next page   | page search |
+        //+------------+-------------+-------------+
+        
+/*
+        +----+--------------------------------------------------+
+        |head|next free page at: 2                              | head of free list points to the first free page 
+        +----+--------------------------------------------------+
+        |0   |id: 0, used: 96, next 5                           | header page contains headers of other pages 
+        |0   |id: 1, used: 0, next none                         |
+        |0   |id: 3, used: 0, next none                         |
+        |0   |id: 4, used: 0, next none                         |
+        +----+--------------------------------------------------+
+        |1   |..................................................| header page of this page is 0
+        +----+--------------------------------------------------+
+        |2   |6                                                 | this page is not allocated anymore and page 6 is the next in free list
+        +----+--------------------------------------------------+
+        |3   |..................................................| header page of this page is 0
+        +----+--------------------------------------------------+
+        |4   |..................................................| header page of this page is 0
+        +----+--------------------------------------------------+
+        |5   |id: 5, used: 72, next none                        | next page of page 0 
+        |5   |id: 7, used: 0, next none                         |
+        |5   |id: 8, used: 0, next none                         |
+        +----+--------------------------------------------------+
+        |6   |9                                                 | this page is not allocated anymore and page 9 is the next free page 
+        +----+--------------------------------------------------+
+        |7   |..................................................| header page is 5
+        +----+--------------------------------------------------+
+        |8   |..................................................| header page is 5
+        +----+--------------------------------------------------+
+        |9   |                                                  | 
+        +----+--------------------------------------------------+
+
+
+*/
+        impl TryFrom<Vec<u8>> for PageHeader {
+
+
+            type Error = std::io::Error;
+
+
+            fn try_from(value: Vec<u8>) -> std::result::Result<Self, Self::Error> {
+                if value.len() < PageHeader::get_size() {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "not enough bytes for page header"));
+                }
+                let id = usize::from_le_bytes(value[0..8].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for id"))?);
+                let next = usize::from_le_bytes(value[8..16].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for next"))?);
+                let used = usize::from_le_bytes(value[16..24].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for used"))?);
+                return Ok(PageHeader {id, used, next: if next == 0 {None} else {Some(next)}, header_page_id: None, header_offset: None, previous_page_id: None});
+            }
+
+
+        }
+
+
+
+        impl Into<Vec<u8>> for PageHeader {
+
+
+            fn into(self) -> Vec<u8> {
+                let mut buffer = Vec::new();
+                buffer.extend(&self.id.to_le_bytes());
+                buffer.extend(&self.next.unwrap_or(0).to_le_bytes());
+                buffer.extend(&self.used.to_le_bytes());
+                return buffer;
+            }
+
+
+        }
+
+
+
+        impl Display for PageHeader {
+
+
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                return write!(f, "id: {}, used: {}, next {}", self.id, self.used, self.next.map_or("none".to_string(), |n| n.to_string()));
+            }
+
+
+        }
+
+
+
+        impl PageHeader {
+
+
+            fn get_first() -> PageHeader {
+                return PageHeader{ header_page_id: Some(0), previous_page_id: Some(0), header_offset: Some(PageHeader::get_size()), id: 0, used: 0, next: None  }
+            }
+
+
+        }
+
+
+
+        impl SimplePageHandler {
+
+
+            ///Opens or creates the page file at page_path. Checksums only decide whether a new file
+            ///is created with them, existing files keep the setting stored in their head. If an
+            ///encryption key is passed the file is encrypted with a key derived from it.
+            pub fn new(page_path : PathBuf, checksums : bool, encryption_key : Option<&str>) -> Result<SimplePageHandler> {
+                SimplePageHandler::with_page_size(page_path, DEFAULT_PAGE_SIZE, checksums, encryption_key)
+            }
+
+
+            ///Works like new but creates the file with pages of page_size bytes. Existing files
+            ///keep the page size stored in their head.
+            pub fn with_page_size(page_path : PathBuf, page_size : usize, checksums : bool, encryption_key : Option<&str>) -> Result<SimplePageHandler> {
+                SimplePageHandler::with_durability(page_path, page_size, checksums, encryption_key, false)
+            }
+
+
+            ///Works like with_page_size but if durable is set every write_page syncs the file
+            ///before it returns, so written pages survive a power loss at the cost of latency.
+            pub fn with_durability(page_path : PathBuf, page_size : usize, checksums : bool, encryption_key : Option<&str>, durable : bool) -> Result<SimplePageHandler> {
+
+                //The file may exist already, other errors surface when it is opened
+                if let Err(e) = file_management::create_file(&page_path) {
+                    if e.kind() != ErrorKind::AlreadyExists {
+                        return Err(e);
+                    }
+                }
+                let mut file_handler : Box<dyn FileHandler> = Box::new(SimpleFileHandler::new(page_path)?);
+                if let Some(key) = encryption_key {
+                    file_handler = Box::new(EncryptedFileHandler::new(file_handler, key)?);
+                }
+                SimplePageHandler::with_file_handler(file_handler, page_size, checksums, durable)
+            }
+
+
+            ///Opens or creates the page file at page_path where storage decides, without syncing.
+            ///New files are created with checksums. This is the page handler tables use by default.
+            pub fn with_storage(page_path : PathBuf, page_size : usize, storage : Storage) -> Result<SimplePageHandler> {
+                SimplePageHandler::with_file_handler(storage.open(&page_path)?, page_size, true, false)
+            }
+
+
+            ///Works like with_durability but uses the file handler passed to access the page file,
+            ///e.g. a MmapFileHandler for read heavy workloads or an InMemoryFileHandler. The file
+            ///has to exist already.
+            pub fn with_file_handler(file_handler : Box<dyn FileHandler>, page_size : usize, checksums : bool, durable : bool) -> Result<SimplePageHandler> {
+                let mut page_handler = SimplePageHandler { file_handler, checksums, page_size, head_size: HEAD_SIZE, durable, cache: Mutex::new(PageCache::new(DEFAULT_CACHE_SIZE)), header_index: Mutex::new(None), free_space: Mutex::new(None) };
+                let size = page_handler.file_handler.size()?;
+                let versioned = page_handler.file_handler.read_at(8, HEAD_MAGIC.len())? == HEAD_MAGIC;
+
+                //Files too small to hold the first header page hold nothing and are initialized
+                //again, which also finishes an initialization that was interrupted
+                if size < LEGACY_HEAD_SIZE + PageHeader::get_size() || (versioned && size < HEAD_SIZE + PageHeader::get_size()) {
+                    SimplePageHandler::validate_page_size(page_size, checksums)?;
+                    page_handler.initialize()?;
+                }else{
+                    let corrupted = |message : String| Error::new(ErrorKind::InvalidData, format!("page file {} is corrupted: {}", page_handler.file_handler.get_path().display(), message));
+                    if versioned {
+                        let version = u32::from_le_bytes(page_handler.file_handler.read_at(12, 4)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for the version"))?);
+                        if version > HEAD_VERSION {
+                            return Err(Error::new(ErrorKind::InvalidData, format!("page file {} has version {} but only versions up to {} are supported", page_handler.file_handler.get_path().display(), version, HEAD_VERSION)));
+                        }
+                        page_handler.page_size = usize::from_le_bytes(page_handler.file_handler.read_at(16, 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for page size"))?);
+                        let flags = u64::from_le_bytes(page_handler.file_handler.read_at(24, 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for flags"))?);
+                        page_handler.checksums = flags & FLAG_CHECKSUMS != 0;
+                    }else{
+                        //Checksums did not exist yet when files had the legacy layout
+                        page_handler.head_size = LEGACY_HEAD_SIZE;
+                        page_handler.page_size = LEGACY_PAGE_SIZE;
+                        page_handler.checksums = false;
+                    }
+                    SimplePageHandler::validate_page_size(page_handler.page_size, page_handler.checksums).map_err(|e| corrupted(e.to_string()))?;
+                    page_handler.validate()?;
+                }
+                page_handler.build_indexes()?;
+                return Ok(page_handler);
+            }
+
+
+            ///Checks that a page size is a power of two and big enough for a header page with at
+            ///least one header
+            fn validate_page_size(page_size : usize, checksums : bool) -> Result<()> {
+                let minimum = 2 * PageHeader::get_size() + if checksums {CHECKSUM_SIZE} else {0};
+                if !page_size.is_power_of_two() || page_size < minimum {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("page size {} has to be a power of two and at least {}", page_size, minimum)));
+                }
+                Ok(())
+            }
+
+
+            ///Checks that the free list and the chain of header pages are consistent. This is run
+            ///when an existing file is opened, since an abrupt shutdown can leave pages half
+            ///written. Returns an InvalidData error describing the first inconsistency found.
+            fn validate(&self) -> Result<()> {
+                let corrupted = |message : String| Error::new(ErrorKind::InvalidData, format!("page file {} is corrupted: {}", self.file_handler.get_path().display(), message));
+
+                //Ids whose page would start beyond the addressable range can only come from garbage
+                let max_id = (usize::MAX - self.head_size) / self.page_size - 1;
+
+                //Walk the free list, the tail of the list is the first page that has no next
+                let mut free_pages : HashSet<usize> = HashSet::new();
+                let mut current_free : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8)?.try_into().map_err(|_| corrupted("not enough bytes for the free list head".to_string()))?);
+                loop {
+                    if current_free == 0 {
+                        return Err(corrupted("the free list points to the first header page".to_string()));
+                    }
+                    if current_free > max_id {
+                        return Err(corrupted(format!("the free list points to page {} which is out of range", current_free)));
+                    }
+                    if !free_pages.insert(current_free) {
+                        return Err(corrupted(format!("the free list contains a cycle at page {}", current_free)));
+                    }
+                    let next_free : usize = usize::from_le_bytes(self.file_handler.read_at(self.calculate_page_start(current_free), 8)?.try_into().map_err(|_| corrupted(format!("not enough bytes for the free list entry of page {}", current_free)))?);
+                    if next_free == 0 {
+                        break;
+                    }
+                    current_free = next_free;
+                }
+
+                //Walk the header pages and check every header stored in them
+                let mut seen_pages : HashSet<usize> = HashSet::new();
+                let mut current_header_page_id : usize = 0;
+                loop {
+                    if !seen_pages.insert(current_header_page_id) {
+                        return Err(corrupted(format!("page {} is referenced more than once", current_header_page_id)));
+                    }
+                    let header_page_bytes = self.read_page_bytes(current_header_page_id)?;
+                    let own_header = PageHeader::try_from(header_page_bytes[0..PageHeader::get_size()].to_vec())?;
+                    if own_header.id != current_header_page_id {
+                        return Err(corrupted(format!("header page {} is stored with id {}", current_header_page_id, own_header.id)));
+                    }
+                    if own_header.used > self.page_size || own_header.used < PageHeader::get_size() || own_header.used % PageHeader::get_size() != 0 {
+                        return Err(corrupted(format!("header page {} has an invalid used value of {}", current_header_page_id, own_header.used)));
+                    }
+                    for offset in (PageHeader::get_size()..own_header.used).step_by(PageHeader::get_size()) {
+                        let header = PageHeader::try_from(header_page_bytes[offset..offset + PageHeader::get_size()].to_vec())?;
+                        if header.used > self.page_size {
+                            return Err(corrupted(format!("page {} has a used value of {} which exceeds the page size", header.id, header.used)));
+                        }
+                        if free_pages.contains(&header.id) {
+                            return Err(corrupted(format!("page {} is allocated but also part of the free list", header.id)));
+                        }
+                        if let Some(next) = header.next {
+                            if free_pages.contains(&next) {
+                                return Err(corrupted(format!("page {} points to page {} which is part of the free list", header.id, next)));
+                            }
+                        }
+                        if !seen_pages.insert(header.id) {
+                            return Err(corrupted(format!("page {} is referenced more than once", header.id)));
+                        }
+                    }
+                    match own_header.next {
+                        Some(next) if free_pages.contains(&next) => return Err(corrupted(format!("header page {} points to page {} which is part of the free list", current_header_page_id, next))),
+                        Some(next) if next > max_id => return Err(corrupted(format!("header page {} points to page {} which is out of range", current_header_page_id, next))),
+                        Some(next) => current_header_page_id = next,
+                        None => break,
+                    }
+                }
+                Ok(())
+            }
+
+
+            ///Scans all headers and replaces the header index and the free space map with their
+            ///positions and free bytes
+            fn build_indexes(&self) -> Result<()> {
+                let mut index : HashMap<usize, (usize, usize, usize)> = HashMap::new();
+                let mut free_space = FreeSpaceMap::default();
+                self.iterate_headers_from(PageHeader::get_first(), |header| {
+                    if let (Some(header_page_id), Some(header_offset), Some(previous_page_id)) = (header.header_page_id, header.header_offset, header.previous_page_id) {
+                        index.insert(header.id, (header_page_id, header_offset, previous_page_id));
+                    }
+                    free_space.set(header.id, self.page_capacity().saturating_sub(header.used));
+                    Ok(false)
+                })?;
+                *self.header_index.lock().map_err(|_| Error::other("thread poisoned"))? = Some(index);
+                *self.free_space.lock().map_err(|_| Error::other("thread poisoned"))? = Some(free_space);
+                Ok(())
+            }
+
+
+            ///Finds the first page with at least size free bytes by walking all headers. This is
+            ///what find_fitting_page falls back to while the free space map is not built
+            fn scan_for_fitting_page(&self, size : usize) -> Result<Option<PageHeader>> {
+                let mut header : Option<PageHeader> = None;
+                let callback = |current_header:PageHeader| {
+
+                    //Set header to current header and exit iteration if page fits data of size
+                    if self.page_capacity().saturating_sub(current_header.used) >= size {
+                        header = Some(current_header);
+                        return Ok(true);
+                    }
+                    Ok(false)
+                };
+                self.iterate_headers_from(PageHeader::get_first(), callback)?;
+                Ok(header)
+            }
+
+
+            ///Updates the free bytes of a page in the free space map, None removes the page
+            fn set_free_space(&self, id : usize, free : Option<usize>) -> Result<()> {
+                if let Some(free_space) = self.free_space.lock().map_err(|_| Error::other("thread poisoned"))?.as_mut() {
+                    match free {
+                        Some(free) => free_space.set(id, free),
+                        None => free_space.remove(id),
+                    }
+                }
+                Ok(())
+            }
+
+
+            ///Finds the header of a page by walking all headers. This is what is_page falls back
+            ///to while the header index is not built
+            fn scan_for_page(&self, id : usize) -> Result<Option<PageHeader>> {
+                let mut header : Option<PageHeader> = None;
+                let callback = |current_header : PageHeader| {
+                    if current_header.id == id {
+                        header = Some(current_header);
+                        return Ok(true);
+                    }
+                    Ok(false)
+                };
+                self.iterate_headers_from(PageHeader::get_first(), callback)?;
+                Ok(header)
+            }
+
+
+            ///Writes the head and the first header page of an empty page file
+            fn initialize(&self) -> Result<()> {
+                let mut head : Vec<u8> = vec![];
+                head.extend(1_usize.to_le_bytes());
+                head.extend(HEAD_MAGIC);
+                head.extend(HEAD_VERSION.to_le_bytes());
+                head.extend(self.page_size.to_le_bytes());
+                head.extend((if self.checksums {FLAG_CHECKSUMS} else {0}).to_le_bytes());
+                self.write_at(0, head)?;
+                let first_header = PageHeader::new(0, None, PageHeader::get_size(), None, None, None);
+                self.write_at(HEAD_SIZE, first_header.into())?;
+                Ok(())
+            }
+
+
+            ///Writes a page header back to the position in its header page
+            fn write_header(&self, header : &PageHeader) -> Result<()> {
+                let header_page_id = header.header_page_id.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id"))?;
+                let header_offset = header.header_offset.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain offset"))?;
+                self.write_at(self.calculate_page_start(header_page_id) + header_offset, header.clone().into())
+            }
+
+
+            fn push_free(&self, id : usize) -> Result<()> {
+                //Load previous first free page id
+                let next_bytes : Vec<u8> = self.file_handler.read_at(0, 8)?;
+                //Update first free page id
+                self.write_at(0, id.to_le_bytes().to_vec())?;
+                //Set next free page id of the new id to the previous first
+                self.write_at(self.calculate_page_start(id), next_bytes)?;
+                return Ok(());
+            }
+
+
+            ///Removes the header of a single page and adds the page to the free list, pages it
+            ///points to are not freed
+            fn free_page(&self, page_header : PageHeader) -> Result<()> {
+                let header_page_id = page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "header did not contain header_page_id")})?;
+                let mut header_page_bytes : Vec<u8> = self.read_page_bytes(header_page_id)?;
+                //Remove header from header page_header
+                let header_offset : usize = page_header.header_offset.ok_or(ErrorKind::InvalidInput)?;
+                header_page_bytes.drain(header_offset..(header_offset + PageHeader::get_size())); 
+                //Decrease used value
+                let mut own_header = PageHeader::try_from(header_page_bytes[..PageHeader::get_size()].to_vec())?;
+                own_header.used -= PageHeader::get_size();
+                //If a header page_header is empty it gets removed
+                let header_page_id = page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "page header did not contain a header_page_id")})?;
+                let removed_header_page = own_header.used <= PageHeader::get_size() && header_page_id != 0;
+                if removed_header_page {
+                    let previous_page_id = page_header.previous_page_id.ok_or_else(|| {Error::new(ErrorKind::NotFound, "header did not contain previous_page_id")})?;
+                    let previous_page_bytes = self.read_page_bytes(previous_page_id)?;
+                    let mut previous_page_header = PageHeader::try_from(previous_page_bytes[..PageHeader::get_size()].to_vec())?;
+                    previous_page_header.next = own_header.next;
+                    self.write_at(self.calculate_page_start(previous_page_id), previous_page_header.into())?;
+                }else{
+                    header_page_bytes[..PageHeader::get_size()].copy_from_slice(&Into::<Vec<u8>>::into(own_header)); 
+                    self.write_at(self.calculate_page_start(header_page_id), header_page_bytes)?;
+                }
+
+                //The headers after the removed one moved to the front of their header page. If the
+                //header page itself was removed the header page before it precedes the next one
+                if let Some(index) = self.header_index.lock().map_err(|_| Error::other("thread poisoned"))?.as_mut() {
+                    index.remove(&page_header.id);
+                    let previous_page_id = page_header.previous_page_id.unwrap_or(0);
+                    for (current_header_page_id, current_offset, current_previous_page_id) in index.values_mut() {
+                        if *current_header_page_id == header_page_id && *current_offset > header_offset {
+                            *current_offset -= PageHeader::get_size();
+                        }
+                        if removed_header_page && *current_previous_page_id == header_page_id {
+                            *current_previous_page_id = previous_page_id;
+                        }
+                    }
+                }
+
+                self.set_free_space(page_header.id, None)?;
+
+                //Add page_header to free list
+                self.push_free(page_header.id)?;
+                Ok(())
+            }
+
+
+            fn pop_free(&self) -> Result<usize> {
+                //Load the first free page id 
+                let first_page : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8)?.try_into().map_err(|_|{Error::new(ErrorKind::UnexpectedEof, "not enough bytes for first page")})?);
+                //Load the next free page id from the first free page
+                let second_page_bytes = self.file_handler.read_at(self.calculate_page_start(first_page), 8)?;
+                //Check if the second free page is the tail of the free list
+                if second_page_bytes != vec![0, 0, 0, 0, 0, 0, 0, 0] {
+                //If it is not set the first free page to the second page
+                    self.write_at(0, second_page_bytes)?;
+                }else{
+                //Otherwise increment first page id by one since it has to be first free page all
+                //time
+                    self.write_at(0, (first_page + 1).to_le_bytes().to_vec())?;
+                }
+                return Ok(first_page);
+            }
+
+
+            ///Sets the number of pages kept in memory, 0 disables the cache
+            pub fn set_cache_size(&self, size : usize) -> Result<()> {
+                let mut cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                cache.resize(size);
+                Ok(())
+            }
+
+
+            ///Returns how often pages were found in the cache and how often they had to be read
+            pub fn cache_statistics(&self) -> Result<CacheStatistics> {
+                let cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                Ok(cache.statistics.clone())
+            }
+
+
+            ///Returns the bytes of a whole page, from the cache if possible
+            fn read_page_bytes(&self, id : usize) -> Result<Vec<u8>> {
+                let generation = {
+                    let mut cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                    if let Some(page) = cache.get(id) {
+                        return Ok(page);
+                    }
+                    cache.generation
+                };
+                let page = self.file_handler.read_at(self.calculate_page_start(id), self.page_size)?;
+                let mut cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                if cache.generation == generation {
+                    cache.insert(id, page.clone());
+                }
+                Ok(page)
+            }
+
+
+            ///Writes to the file and invalidates the cached pages the data overlaps with
+            fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+                let end = at + data.len();
+                self.file_handler.write_at(at, data)?;
+                if end > self.head_size && end > at {
+                    let first = at.saturating_sub(self.head_size) / self.page_size;
+                    let last = (end - 1 - self.head_size) / self.page_size;
+                    let mut cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                    cache.generation += 1;
+                    for id in first..=last {
+                        cache.remove(id);
+                    }
+                }
+                Ok(())
+            }
+
+
+            ///Truncates the file and empties the cache
+            fn truncate(&self, size : usize) -> Result<()> {
+                self.file_handler.truncate(size)?;
+                let mut cache = self.cache.lock().map_err(|_| Error::other("thread poisoned"))?;
+                cache.generation += 1;
+                cache.clear();
+                Ok(())
+            }
+
+
+            fn calculate_page_start(&self, id : usize) -> usize {
+                id * self.page_size + self.head_size
+            }
+
+
+            ///Iterates over_all headers starting from the header passed to the function, once until true is returned from f
+            fn iterate_headers_from<F>(&self, header : PageHeader, mut f : F) -> Result<()> where F : FnMut(PageHeader) -> Result<bool> {
+                let mut current_page_id : usize = header.header_page_id.ok_or_else(|| {Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id")})?;
+                let mut previous_page_id = header.previous_page_id.ok_or_else(|| {Error::new(ErrorKind::InvalidInput, "header did not contain previous")})?;
+                let mut  initial_header_offset : usize = header.header_offset.ok_or_else(||{Error::new(ErrorKind::InvalidInput, "header did not contain offset")})?;
+
+                //Loop till the current header does not have a next_page_id
+                loop {
+
+                    //Load current header page and extract the own header in order to find the
+                    //next_page_id and the number of headers stored in the page
+                    let current_header_page_bytes = self.read_page_bytes(current_page_id)?;
+                    let own_header = PageHeader::try_from(current_header_page_bytes[0..PageHeader::get_size()].to_vec())?;
+
+                    //Loop through all headers in the header page
+                    for current_header_offset in (initial_header_offset..own_header.used).step_by(PageHeader::get_size()) {
+
+                        //For every header set the correct header values and execute f
+                        if let Some(header_bytes) = current_header_page_bytes.get(current_header_offset..current_header_offset + PageHeader::get_size()) {
+                            let mut current_header = PageHeader::try_from(header_bytes.to_vec())?;
+                            current_header.header_page_id = Some(current_page_id);
+                            current_header.header_offset = Some(current_header_offset);
+                            current_header.previous_page_id = Some(previous_page_id);
+                            if f(current_header)? {
+                                return Ok(());
+                            }
+                        }else{
+                            break;
+                        }
+                    }
+                    if let Some(next_page_id) = own_header.next {
+                        previous_page_id = current_page_id;
+                        current_page_id = next_page_id;
+                    }else{
+                        break;
+                    }
+
+                    //Reset initial_offset since the offset from the header passed to the function
+                    //should only be used in the first header_page
+                    initial_header_offset = PageHeader::get_size();
+                }
+                return Ok(());
+            }
+
+
+        }
+        
+
+
+        #[cfg(test)]
+        impl Display for SimplePageHandler {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                let width = 50;
+                let mut bubble = Bubble::new(vec![4, width]);
+                let first_page : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8).unwrap().try_into().unwrap());
+                bubble.add_line(vec!["head".to_string(), format!("next free page at: {}", first_page.to_string())]);
+                'outer:
+                    for i in 0..10 {
+                        let mut j : usize = 0;
+                        bubble.add_divider();
+                        //Check if page is a header page and if so show headers
+                        loop{
+                            let header_page_bytes = self.file_handler.read_at(self.calculate_page_start(j), self.page_size).unwrap();
+                            let page_header = PageHeader::try_from(header_page_bytes[0..PageHeader::get_size()].to_vec()).unwrap();
+                            if page_header.id == i {
+                                for n in (0..page_header.used).step_by(PageHeader::get_size()) {
+                                    let m :usize = n + PageHeader::get_size();
+                                    if let Some(header_bytes) = header_page_bytes.get(n..m) {
+                                        let mut header = PageHeader::try_from(header_bytes.to_vec()).unwrap();
+                                        bubble.add_line(vec![i.to_string(), header.to_string()]);
+                                    }
+                                }
+                                continue 'outer;
+                            }
+                            if let Some(next) = page_header.next {
+                                j = next;
+                            }else{
+                                break;
+                            }
+                        }
+                        //Check if page is in the free list
+                        j = usize::from_le_bytes(self.file_handler.read_at(0, 8).unwrap().try_into().unwrap());
+                        loop {
+                            let next : usize = usize::from_le_bytes(self.file_handler.read_at(self.calculate_page_start(j), 8).unwrap().try_into().unwrap());
+                            if next == 0 {
+                                break;
+                            }
+                            if j == i {
+                                bubble.add_line(vec![i.to_string(), next.to_string()]);
+                                continue 'outer;
+                            }
+                            j = next;
+                        }
+                        //Write used space
+                        let mut allocated = false;
+                        self.iterate_headers_from(PageHeader{ header_page_id: Some(0), previous_page_id: Some(0), header_offset: Some(PageHeader::get_size()), id: 0, used: 0, next: None  },|h| {
+                            if i == h.id {
+                                let space = h.used * width / self.page_size;
+                                let mut space_representation = String::new();
+                                for _ in 0..space {
+                                    space_representation.push_str("#");
+                                }
+                                for _ in space..width {
+                                    space_representation.push_str(".");
+                                }
+                                bubble.add_line(vec![i.to_string(), space_representation]);
+                                allocated = true;
+                                return Ok(true);
+                            }
+                            return Ok(false);
+                        });
+                        if !allocated {
+                            bubble.add_line(vec![i.to_string(), "".to_string()]);
+                        }
+                    }
+                write!(f, "{}", bubble)
+            }
+        }
+
+
+
+        impl PageHandler for SimplePageHandler {
+            
+
+            fn find_fitting_page(&self, size : usize) -> Result<Option<PageHeader>> {
+                if cfg!(feature = "scan-fitting-page") {
+                    return self.scan_for_fitting_page(size);
+                }
+                loop {
+                    let id = match self.free_space.lock().map_err(|_| Error::other("thread poisoned"))?.as_ref() {
+                        Some(free_space) => free_space.find(size),
+                        None => return self.scan_for_fitting_page(size),
+                    };
+                    let id = match id {
+                        Some(id) => id,
+                        None => return Ok(None),
+                    };
+
+                    //The header is the source of truth, a page the map got wrong is corrected and
+                    //the search is repeated
+                    match self.is_page(id)? {
+                        Some(header) if self.page_capacity().saturating_sub(header.used) >= size => return Ok(Some(header)),
+                        Some(header) => self.set_free_space(id, Some(self.page_capacity().saturating_sub(header.used)))?,
+                        None => self.set_free_space(id, None)?,
+                    }
+                }
+            }
+
+
+            fn is_page(&self, id : usize) -> Result<Option<PageHeader>> {
+                let position = match self.header_index.lock().map_err(|_| Error::other("thread poisoned"))?.as_ref() {
+                    Some(index) => index.get(&id).cloned(),
+                    None => return self.scan_for_page(id),
+                };
+                let (header_page_id, header_offset, previous_page_id) = match position {
+                    Some(position) => position,
+                    None => return Ok(None),
+                };
+
+                //The used value and the next page change without the index, so they are read from
+                //the header page. If the header there is not the one expected the index is rebuilt
+                let header_page_bytes = self.read_page_bytes(header_page_id)?;
+                if let Some(header_bytes) = header_page_bytes.get(header_offset..header_offset + PageHeader::get_size()) {
+                    let mut header = PageHeader::try_from(header_bytes.to_vec())?;
+                    if header.id == id {
+                        header.header_page_id = Some(header_page_id);
+                        header.header_offset = Some(header_offset);
+                        header.previous_page_id = Some(previous_page_id);
+                        return Ok(Some(header));
+                    }
+                }
+                self.build_indexes()?;
+                self.scan_for_page(id)
+            }
+
+
+
+            fn alloc_page(&self) -> Result<PageHeader> {
+                let mut current_header_page_id : usize = 0;
+                let mut previous_header_page_id : usize = 0;
+                let mut new_page_id = self.pop_free()?;
+                loop {
+                    let mut current_header_page_bytes = self.read_page_bytes(current_header_page_id)?;
+                    let mut own_header = PageHeader::try_from(current_header_page_bytes[0..PageHeader::get_size()].to_vec())?;
+                    if self.page_size - own_header.used > PageHeader::get_size() {
+                        //Clear the page, a page taken from the free list still holds its old data
+                        //and the free list pointer, which would fail the checksum
+                        self.write_at(self.calculate_page_start(new_page_id), vec![0; self.page_size])?;
+                        //Add new header to the header page
+                        let header_offset = own_header.used;
+                        let new_header = PageHeader::new(new_page_id, None, 0, Some(own_header.id), Some(header_offset), None);
+                        let new_header_bytes : Vec<u8> = new_header.clone().into();
+                        current_header_page_bytes[own_header.used..own_header.used + PageHeader::get_size()].copy_from_slice(&new_header_bytes);
+                        //Increase used value
+                        own_header.used += PageHeader::get_size();
+                        current_header_page_bytes[..PageHeader::get_size()].copy_from_slice(&Into::<Vec<u8>>::into(own_header)); 
+                        self.write_at(self.calculate_page_start(current_header_page_id), current_header_page_bytes)?;
+                        if let Some(index) = self.header_index.lock().map_err(|_| Error::other("thread poisoned"))?.as_mut() {
+                            index.insert(new_page_id, (current_header_page_id, header_offset, previous_header_page_id));
+                        }
+                        self.set_free_space(new_page_id, Some(self.page_capacity()))?;
+                        debug!("allocated page {} with its header on header page {}", new_page_id, current_header_page_id);
+                        return Ok(new_header);
+                    }
+                    if let Some(next_header_page_id) = own_header.next {
+                        //In case one header page did not have enough space for another header and
+                        //another one exists already the loop gets repeated with the next header page
+                        previous_header_page_id = current_header_page_id;
+                        current_header_page_id = next_header_page_id;     
+                    }else{
+                        //In case one page is full and no next was created a new one is appended to the
+                        //previous page.
+                        own_header.next = Some(new_page_id);
+                        let own_header_bytes : Vec<u8> = own_header.clone().into();
+                        current_header_page_bytes[..PageHeader::get_size()].copy_from_slice(&own_header_bytes); 
+                        self.write_at(self.calculate_page_start(current_header_page_id), current_header_page_bytes)?;
+                        let new_own_header = PageHeader::new(new_page_id, None, PageHeader::get_size(), None, None, Some(own_header.id));
+                        self.write_at(self.calculate_page_start(new_page_id), new_own_header.into())?;
+                        debug!("appended header page {} to header page {}", new_page_id, current_header_page_id);
+                        previous_header_page_id = current_header_page_id;
+                        current_header_page_id = new_page_id;
+                        new_page_id = self.pop_free()?;
+                    }
+                }
+                return Err(Error::new(ErrorKind::Other, "unexpected error"));
+            }
+
+
+            fn dealloc_page(&self, page_header : PageHeader) -> Result<()> {
+
+                //The whole overflow chain is collected before anything is freed. Freeing a page
+                //moves the headers behind it, so every page is looked up again right before it is
+                //freed instead of using a header that may be stale by then
+                let mut ids : Vec<usize> = vec![page_header.id];
+                let mut next = page_header.next;
+                while let Some(next_id) = next {
+                    if ids.contains(&next_id) {
+                        return Err(Error::new(ErrorKind::InvalidData, format!("overflow chain of page {} contains a cycle", page_header.id)));
+                    }
+                    let next_header = self.is_page(next_id)?.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("page {} points to page {} which is not allocated", page_header.id, next_id)))?;
+                    next = next_header.next;
+                    ids.push(next_id);
+                }
+                self.free_page(page_header)?;
+                for id in ids.iter().copied().skip(1) {
+                    let header = self.is_page(id)?.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("page {} was freed twice", id)))?;
+                    self.free_page(header)?;
+                }
+                debug!("freed pages {:?}", ids);
+                return Ok(());
+            }
+
+
+            fn read_page(&self, page_header : &PageHeader) -> Result<Vec<u8>> {
+                let mut page = self.read_page_bytes(page_header.id)?;
+                if !self.checksums {
+                    return Ok(page);
+                }
+
+                //Split off the checksum and compare it to the content. Pages that were allocated but
+                //never written consist of zeros only and are accepted as well
+                let stored_checksum = u32::from_le_bytes(page[self.page_capacity()..].try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for checksum"))?);
+                page.truncate(self.page_capacity());
+                if stored_checksum != crc32(&page) && !(stored_checksum == 0 && page.iter().all(|b| *b == 0)) {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("checksum mismatch in page {}", page_header.id)));
+                }
+                Ok(page)
+            }
+
+
+            fn write_page(&self, page_header : PageHeader, mut data : Vec<u8>, size : usize) -> Result<()> {
+                //Check if data fits into one page
+                if data.len() > self.page_capacity() {
+                    return Err(Error::new(ErrorKind::ArgumentListTooLong, "data is to big to write into one page"));
+                }
+                if self.checksums {
+                    data.resize(self.page_capacity(), 0);
+                    let checksum = crc32(&data);
+                    data.extend(checksum.to_le_bytes());
+                }
+                //Load all data required to change the content of a page
+                let header_page_id = page_header.header_page_id.ok_or(ErrorKind::InvalidInput)?;
+                let mut header_page_bytes = self.read_page_bytes(header_page_id)?;
+                let header_offset : usize = page_header.header_offset.ok_or_else(|| {Error::new(ErrorKind::NotFound, "header did not have a header_offset")})?;
+                let header_bytes = header_page_bytes.get(header_offset..(header_offset + PageHeader::get_size())).ok_or_else(|| {Error::new(ErrorKind::Other, "unexpected error")})?;
+                let mut own_header = PageHeader::try_from(header_bytes.to_vec())?;
+                //Check if the page header passed has the same id as the header loaded from storage
+                if own_header.id == page_header.id {
+                    //Update size and write back header with new size as well as the page itself
+                    own_header.used = size;
+                    header_page_bytes[header_offset..(header_offset + PageHeader::get_size())].copy_from_slice(&Into::<Vec<u8>>::into(own_header));
+                    self.write_at(self.calculate_page_start(page_header.id), data)?;
+                    self.write_at(self.calculate_page_start(page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "page header did not contain a header_page_id")})?), header_page_bytes)?;
+                    self.set_free_space(page_header.id, Some(self.page_capacity().saturating_sub(size)))?;
+                    if self.durable {
+                        self.file_handler.sync()?;
+                    }
+                    return Ok(());
+                }
+                //Can only be returned if header did not have the same values as the header it
+                //referred to in storage
+                return Err(Error::new(ErrorKind::InvalidInput, "wrong header type"));
+            }
+
+
+            fn iterate_pages<'a>(&self, mut f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()> {
+                self.iterate_headers_from(PageHeader::get_first(),|h| {
+                    return f(h.clone(), self.read_page(&h)?);
+                }, )?;
+                return Ok(());
+            }
+
+
+            fn iterate_pages_from<'a>(&self, start : PageHeader, mut f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()> {
+                self.iterate_headers_from(start,|h| {
+                    return f(h.clone(), self.read_page(&h)?);
+                }, )?;
+                return Ok(());
+            }
+
+
+            fn compact(&self) -> Result<()> {
+
+                //Collect the header pages along with the header page before them and the headers
+                //of all data pages. Every other page is free or was leaked by a crash
+                let mut header_pages : Vec<(usize, usize)> = vec![];
+                let mut current : Option<usize> = Some(0);
+                let mut previous : usize = 0;
+                while let Some(id) = current {
+                    header_pages.push((id, previous));
+                    previous = id;
+                    current = PageHeader::try_from(self.read_page_bytes(id)?[0..PageHeader::get_size()].to_vec())?.next;
+                }
+                let mut headers : Vec<PageHeader> = vec![];
+                self.iterate_headers_from(PageHeader::get_first(), |h| {
+                    headers.push(h);
+                    Ok(false)
+                })?;
+
+                //Pages at or behind the number of used pages are moved into the gaps before it
+                let used : HashSet<usize> = header_pages.iter().map(|(id, _)| *id).chain(headers.iter().map(|h| h.id)).collect();
+                let end = used.len();
+                let mut gaps = (0..end).filter(|id| !used.contains(id));
+                let mut moves : HashMap<usize, usize> = HashMap::new();
+                let mut moved : Vec<usize> = used.iter().copied().filter(|id| *id >= end).collect();
+                moved.sort_unstable();
+                for id in moved {
+                    moves.insert(id, gaps.next().ok_or_else(|| Error::other("no free page to move a page to"))?);
+                }
+
+                //Nothing is truncated before every page was moved, so the file stays readable if
+                //compaction stops early. The free list is emptied first since the free pages are
+                //overwritten, the pages that were free are leaked until compaction finishes
+                let mut tail : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for the free list head"))?);
+                loop {
+                    let next : usize = usize::from_le_bytes(self.file_handler.read_at(self.calculate_page_start(tail), 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for a free list entry"))?);
+                    if next == 0 {
+                        break;
+                    }
+                    tail = next;
+                }
+                self.write_at(0, tail.to_le_bytes().to_vec())?;
+                *self.header_index.lock().map_err(|_| Error::other("thread poisoned"))? = None;
+                *self.free_space.lock().map_err(|_| Error::other("thread poisoned"))? = None;
+
+                //A header page is copied with its new id and becomes used once the header page
+                //before it points to the copy. The first header page is never moved
+                let new_id = |id : usize| moves.get(&id).copied().unwrap_or(id);
+                for (id, previous) in &header_pages {
+                    if let Some(target) = moves.get(id) {
+                        let mut bytes = self.read_page_bytes(*id)?;
+                        bytes[0..8].copy_from_slice(&target.to_le_bytes());
+                        self.write_at(self.calculate_page_start(*target), bytes)?;
+                        self.write_at(self.calculate_page_start(new_id(*previous)) + 8, target.to_le_bytes().to_vec())?;
+                    }
+                }
+
+                //A data page is copied and then its header as well as the header of the page
+                //pointing to it get the new id. Both are written at once if they share a header page
+                let mut pointing : HashMap<usize, &PageHeader> = HashMap::new();
+                for header in &headers {
+                    if let Some(next) = header.next {
+                        pointing.insert(next, header);
+                    }
+                }
+                for header in &headers {
+                    if let Some(target) = moves.get(&header.id) {
+                        self.write_at(self.calculate_page_start(*target), self.read_page_bytes(header.id)?)?;
+                        let mut changes : HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+                        let header_page_id = new_id(header.header_page_id.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id"))?);
+                        changes.entry(header_page_id).or_default().push((header.header_offset.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain offset"))?, *target));
+                        if let Some(before) = pointing.get(&header.id) {
+                            let header_page_id = new_id(before.header_page_id.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id"))?);
+                            changes.entry(header_page_id).or_default().push((before.header_offset.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain offset"))? + 8, *target));
+                        }
+                        for (header_page_id, changes) in changes {
+                            let mut bytes = self.read_page_bytes(header_page_id)?;
+                            for (at, id) in changes {
+                                bytes[at..at + 8].copy_from_slice(&id.to_le_bytes());
+                            }
+                            self.write_at(self.calculate_page_start(header_page_id), bytes)?;
+                        }
+                    }
+                }
+
+                //The moved pages have to be on disk before their old copies are cut off. The
+                //free list is set last, the page it points to has to be behind the end of the file
+                self.file_handler.sync()?;
+                self.truncate(self.calculate_page_start(end))?;
+                self.write_at(0, end.to_le_bytes().to_vec())?;
+                self.file_handler.sync()?;
+                self.build_indexes()
+            }
+
+
+            fn shrink(&self) -> Result<usize> {
+
+                //Walk the free list, its last entry is the first page that was never allocated
+                let mut free_pages : Vec<usize> = vec![];
+                let mut current : usize = usize::from_le_bytes(self.file_handler.read_at(0, 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for the free list head"))?);
+                loop {
+                    let next : usize = usize::from_le_bytes(self.file_handler.read_at(self.calculate_page_start(current), 8)?.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for a free list entry"))?);
+                    if next == 0 {
+                        break;
+                    }
+                    free_pages.push(current);
+                    current = next;
+                }
+
+                //Move the end of the file back over the free pages before it. Page 0 is the first
+                //header page and is never free
+                let free : HashSet<usize> = free_pages.iter().cloned().collect();
+                let mut end = current;
+                while end > 1 && free.contains(&(end - 1)) {
+                    end -= 1;
+                }
+                if end == current {
+                    return Ok(0);
+                }
+
+                //Link the remaining free pages in their previous order, ending with the new end
+                let remaining : Vec<usize> = free_pages.into_iter().filter(|id| *id < end).collect();
+                self.write_at(0, remaining.first().unwrap_or(&end).to_le_bytes().to_vec())?;
+                for (index, id) in remaining.iter().enumerate() {
+                    self.write_at(self.calculate_page_start(*id), remaining.get(index + 1).unwrap_or(&end).to_le_bytes().to_vec())?;
+                }
+                self.truncate(self.calculate_page_start(end))?;
+                Ok(current - end)
+            }
+
+
+            fn sync(&self) -> Result<()> {
+                self.file_handler.sync()
+            }
+
+
+            fn page_size(&self) -> usize {
+                self.page_size
+            }
+
+
+            fn page_capacity(&self) -> usize {
+                if self.checksums {
+                    return self.page_size - CHECKSUM_SIZE;
+                }
+                self.page_size
+            }
+
+
+            fn set_next(&self, page_header : &mut PageHeader, next : Option<usize>) -> Result<()> {
+                let header_page_id = page_header.header_page_id.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain header_page_id"))?;
+                let header_offset = page_header.header_offset.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "header did not contain offset"))?;
+
+                //Load the stored header so the used value written last is kept
+                let header_bytes = self.file_handler.read_at(self.calculate_page_start(header_page_id) + header_offset, PageHeader::get_size())?;
+                let mut own_header = PageHeader::try_from(header_bytes)?;
+                if own_header.id != page_header.id {
+                    return Err(Error::new(ErrorKind::InvalidInput, "wrong header type"));
+                }
+                own_header.next = next;
+                self.write_at(self.calculate_page_start(header_page_id) + header_offset, own_header.into())?;
+                page_header.next = next;
+                Ok(())
+            }
+
+
+        }
+
+
+
+        #[cfg(test)]
+        mod test {
+
+
+
+            use super::*;
+            use std::sync::Arc;
+
+
+
+            #[test]
+            fn read_write_test() {
+                let path = file_management::get_test_path().unwrap().join("read_write.test");
+                file_management::delete_file(&path);
+                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path, true, None).unwrap());
+                let data = b"Hello, Page!".to_vec();
+                handler.write_page(handler.alloc_page().unwrap(), data.clone(), data.len()).unwrap();
+                let mut read_data = handler.read_page(&handler.is_page(1).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            #[test]
+            fn mmap_file_handler_test() {
+                let path = file_management::get_test_path().unwrap().join("mmap_page_handler.test");
+                let _ = file_management::delete_file(&path);
+                file_management::create_file(&path).unwrap();
+                let handler = SimplePageHandler::with_file_handler(Box::new(MmapFileHandler::new(path.clone()).unwrap()), DEFAULT_PAGE_SIZE, true, false).unwrap();
+                let data = b"Hello, Page!".to_vec();
+                let pages : Vec<PageHeader> = (0..3).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[1].clone(), data.clone(), data.len()).unwrap();
+                handler.dealloc_page(handler.is_page(pages[0].id).unwrap().unwrap()).unwrap();
+                drop(handler);
+
+                //The file written through the mapping can be read with the default handler
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                let mut read_data = handler.read_page(&handler.is_page(pages[1].id).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            ///Passes everything to a SimpleFileHandler and counts the syncs
+            struct SyncCountingFileHandler {
+                file_handler : SimpleFileHandler,
+                syncs : Arc<Mutex<usize>>,
+            }
+
+
+
+            impl FileHandler for SyncCountingFileHandler {
+
+
+                fn get_path(&self) -> &PathBuf {
+                    self.file_handler.get_path()
+                }
+
+
+                fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
+                    self.file_handler.read_at(at, length)
+                }
+
+
+                fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+                    self.file_handler.write_at(at, data)
+                }
+
+
+                fn truncate(&self, size : usize) -> Result<()> {
+                    self.file_handler.truncate(size)
+                }
+
+
+                fn sync(&self) -> Result<()> {
+                    *self.syncs.lock().unwrap() += 1;
+                    self.file_handler.sync()
+                }
+
+
+                fn size(&self) -> Result<usize> {
+                    self.file_handler.size()
+                }
+
+
+            }
+
+
+
+            #[test]
+            fn durability_test() {
+                for durable in [false, true] {
+                    let path = file_management::get_test_path().unwrap().join(format!("durability_{}.test", durable));
+                    let _ = file_management::delete_file(&path);
+                    file_management::create_file(&path).unwrap();
+                    let syncs = Arc::new(Mutex::new(0));
+                    let file_handler = SyncCountingFileHandler{file_handler: SimpleFileHandler::new(path.clone()).unwrap(), syncs: syncs.clone()};
+                    let handler = SimplePageHandler::with_file_handler(Box::new(file_handler), DEFAULT_PAGE_SIZE, true, durable).unwrap();
+                    let data = b"Hello, Page!".to_vec();
+                    for _ in 0..3 {
+                        handler.write_page(handler.alloc_page().unwrap(), data.clone(), data.len()).unwrap();
+                    }
+                    assert_eq!(*syncs.lock().unwrap(), if durable {3} else {0}, "only durable handlers should sync every written page");
+                }
+                let path = file_management::get_test_path().unwrap().join("durability.test");
+                let _ = file_management::delete_file(&path);
+                let handler = SimplePageHandler::with_durability(path, DEFAULT_PAGE_SIZE, true, Some("key"), true).unwrap();
+                let data = b"Hello, Page!".to_vec();
+                handler.write_page(handler.alloc_page().unwrap(), data.clone(), data.len()).unwrap();
+                let mut read_data = handler.read_page(&handler.is_page(1).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            #[test]
+            fn in_memory_page_handler_test() {
+                let handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), DEFAULT_PAGE_SIZE, true, false).unwrap();
+                let data = b"Hello, Page!".to_vec();
+                let pages : Vec<PageHeader> = (0..3).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[1].clone(), data.clone(), data.len()).unwrap();
+                handler.dealloc_page(handler.is_page(pages[2].id).unwrap().unwrap()).unwrap();
+                assert_eq!(handler.shrink().unwrap(), 1);
+                let mut read_data = handler.read_page(&handler.is_page(pages[1].id).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            #[test]
+            fn shrink_test() {
+                let path = file_management::get_test_path().unwrap().join("shrink.test");
+                let _ = file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let data = b"Hello, Page!".to_vec();
+                let pages : Vec<PageHeader> = (0..5).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[2].clone(), data.clone(), data.len()).unwrap();
+                for index in [1, 4, 3] {
+                    handler.dealloc_page(handler.is_page(pages[index].id).unwrap().unwrap()).unwrap();
+                }
+                assert_eq!(handler.shrink().unwrap(), 2, "the two free pages at the end should be released");
+                assert_eq!(file_management::get_size(&path).unwrap() as usize, handler.calculate_page_start(pages[3].id));
+                assert_eq!(handler.shrink().unwrap(), 0, "free pages between allocated ones should stay");
+
+                //The free page in the middle is used first, then the file grows again
+                assert_eq!(handler.alloc_page().unwrap().id, pages[1].id);
+                assert_eq!(handler.alloc_page().unwrap().id, pages[3].id);
+                drop(handler);
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                let mut read_data = handler.read_page(&handler.is_page(pages[2].id).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            #[test]
+            fn encrypted_page_handler_test() {
+                let path = file_management::get_test_path().unwrap().join("encrypted_page_handler.test");
+                let _ = file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, Some("key")).unwrap();
+                let data = b"Hello, Page!".to_vec();
+                let pages : Vec<PageHeader> = (0..3).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[2].clone(), data.clone(), data.len()).unwrap();
+                handler.dealloc_page(handler.is_page(pages[0].id).unwrap().unwrap()).unwrap();
+                handler.alloc_page().unwrap();
+                drop(handler);
+
+                //The file can only be opened again with the same key
+                assert!(SimplePageHandler::new(path.clone(), true, None).is_err(), "an encrypted file should not be readable without key");
+                assert!(SimplePageHandler::new(path.clone(), true, Some("other key")).is_err(), "an encrypted file should not be readable with another key");
+                let handler = SimplePageHandler::new(path, true, Some("key")).unwrap();
+                let mut read_data = handler.read_page(&handler.is_page(pages[2].id).unwrap().unwrap()).unwrap();
+                read_data.truncate(data.len());
+                assert_eq!(data, read_data);
+            }
+
+
+
+            #[test]
+            fn page_cache_test() {
+                let path = file_management::get_test_path().unwrap().join("page_cache.test");
+                let _ = file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), vec![1; 8], 8).unwrap();
+                let before = handler.cache_statistics().unwrap();
+                handler.read_page(&page).unwrap();
+                handler.read_page(&page).unwrap();
+                let after = handler.cache_statistics().unwrap();
+                assert_eq!(after.misses - before.misses, 1, "only the first read should go to the file");
+                assert_eq!(after.hits - before.hits, 1);
+
+                //Writes invalidate the cached page
+                handler.write_page(page.clone(), vec![2; 8], 8).unwrap();
+                assert_eq!(handler.read_page(&page).unwrap()[..8], vec![2; 8]);
+                handler.dealloc_page(handler.is_page(page.id).unwrap().unwrap()).unwrap();
+                assert!(handler.is_page(page.id).unwrap().is_none(), "deallocated page should not be served from the cache");
+
+                //Without cache every read is a miss
+                handler.set_cache_size(0).unwrap();
+                let before = handler.cache_statistics().unwrap();
+                handler.find_fitting_page(8).unwrap();
+                handler.find_fitting_page(8).unwrap();
+                assert_eq!(handler.cache_statistics().unwrap().hits, before.hits);
+            }
+
+
+
+            #[test]
+            fn header_index_test() {
+                let path = file_management::get_test_path().unwrap().join("header_index.test");
+                file_management::delete_file(&path);
+
+                //Small pages so the headers span several header pages
+                let handler = SimplePageHandler::with_page_size(path.clone(), 128, true, None).unwrap();
+                let pages : Vec<PageHeader> = (0..20).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[15].clone(), vec![1; 8], 8).unwrap();
+
+                //Eight pages in a row cover a whole header page, which is removed with them
+                for page in pages[4..12].iter().chain([&pages[13]]) {
+                    handler.dealloc_page(handler.is_page(page.id).unwrap().unwrap()).unwrap();
+                }
+                handler.alloc_page().unwrap();
+
+                //Every lookup through the index has to match a scan of the headers
+                for page in &pages {
+                    let position = |header : Option<PageHeader>| header.map(|h| (h.id, h.used, h.next, h.header_page_id, h.header_offset, h.previous_page_id));
+                    assert_eq!(position(handler.is_page(page.id).unwrap()), position(handler.scan_for_page(page.id).unwrap()), "page {} was looked up wrong", page.id);
+                }
+                assert_eq!(handler.is_page(pages[15].id).unwrap().unwrap().used, 8);
+                drop(handler);
+                let handler = SimplePageHandler::with_page_size(path, 128, true, None).unwrap();
+                assert!(handler.is_page(pages[15].id).unwrap().is_some(), "the index should be built when the file is opened");
+            }
+
+
+
+            #[test]
+            fn free_space_map_test() {
+                let path = file_management::get_test_path().unwrap().join("free_space_map.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let capacity = handler.page_capacity();
+                let pages : Vec<PageHeader> = (0..3).map(|_| handler.alloc_page().unwrap()).collect();
+                handler.write_page(pages[0].clone(), vec![1; 8], capacity - 100).unwrap();
+                handler.write_page(pages[1].clone(), vec![1; 8], capacity - 50).unwrap();
+                handler.write_page(pages[2].clone(), vec![1; 8], capacity).unwrap();
+                assert!(handler.find_fitting_page(200).unwrap().is_none());
+                assert_eq!(handler.find_fitting_page(80).unwrap().unwrap().id, pages[0].id);
+                handler.dealloc_page(handler.is_page(pages[0].id).unwrap().unwrap()).unwrap();
+                assert!(handler.find_fitting_page(80).unwrap().is_none(), "deallocated pages should not be found");
+                assert_eq!(handler.find_fitting_page(40).unwrap().unwrap().id, pages[1].id);
+
+                //The map is built from the headers when the file is opened again
+                drop(handler);
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                assert_eq!(handler.find_fitting_page(40).unwrap().unwrap().id, pages[1].id);
+                assert!(handler.find_fitting_page(51).unwrap().is_none());
+            }
+
+
+
+            ///Compares is_page with the scan it replaced, run it with cargo test -- --ignored
+            #[test]
+            #[ignore]
+            fn is_page_benchmark() {
+                let path = file_management::get_test_path().unwrap().join("is_page_benchmark.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                let ids : Vec<usize> = (0..5000).map(|_| handler.alloc_page().unwrap().id).collect();
+                let start = std::time::Instant::now();
+                for id in ids.iter().step_by(50) {
+                    handler.scan_for_page(*id).unwrap().unwrap();
+                }
+                let scan = start.elapsed();
+                let start = std::time::Instant::now();
+                for id in ids.iter().step_by(50) {
+                    handler.is_page(*id).unwrap().unwrap();
+                }
+                let index = start.elapsed();
+                println!("100 lookups in 5000 pages: scan {:?}, index {:?}", scan, index);
+                assert!(index < scan);
+            }
+
+
+
+            #[test]
+            fn page_cache_eviction_test() {
+                let mut cache = PageCache::new(2);
+                cache.insert(1, vec![1]);
+                cache.insert(2, vec![2]);
+                assert_eq!(cache.get(1), Some(vec![1]));
+
+                //Page 2 was used least recently and gets evicted
+                cache.insert(3, vec![3]);
+                assert_eq!(cache.get(2), None);
+                assert_eq!(cache.get(1), Some(vec![1]));
+                assert_eq!(cache.get(3), Some(vec![3]));
+                cache.resize(1);
+                assert_eq!(cache.get(1), None);
+                assert_eq!(cache.statistics, CacheStatistics{hits: 3, misses: 2});
+            }
+
+
+
+            #[test]
+            fn find_fitting_page_test() {
+                let path = file_management::get_test_path().unwrap().join("find_fitting_page.test");
+                file_management::delete_file(&path);
+                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path, true, None).unwrap());
+                let page1 = handler.alloc_page().unwrap();
+                let page2 = handler.alloc_page().unwrap();
+                handler.write_page(page1, vec![0; DEFAULT_PAGE_SIZE - 10], DEFAULT_PAGE_SIZE - 10).unwrap();
+                let fitting_page = handler.find_fitting_page(20).unwrap();
+                assert_eq!(page2.id, fitting_page.unwrap().id);
+            }
+
+
+
+            #[test]
+            fn dont_find_fitting_page_test() {
+                let path = file_management::get_test_path().unwrap().join("dont_find_fitting_page.test");
+                file_management::delete_file(&path);
+                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path, true, None).unwrap());
+                let page1 = handler.alloc_page().unwrap();
+                handler.write_page(page1, vec![0; DEFAULT_PAGE_SIZE - 10], DEFAULT_PAGE_SIZE - 10).unwrap();
+                let fitting_page = handler.find_fitting_page(90).unwrap();
+                assert!(matches!(fitting_page, None), "expected none but found some");
+            }
+
+
+
+            #[test]
+            fn invalid_dealloc_test() {
+                let path = file_management::get_test_path().unwrap().join("invalid_dealloc.test");
+                file_management::delete_file(&path);
+                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path.clone(), true, None).unwrap());
+                let result = handler.dealloc_page(PageHeader::new(999, None, 0, None, None, None));
+                assert!(result.is_err(), "Expected error when deallocating non-existent page");
+            }
+
+
+
+            #[test]
+            fn free_list_integrity_test() {
+                let path = file_management::get_test_path().unwrap().join("free_list_integrity.test");
+                file_management::delete_file(&path);
+                let handler = Box::new(SimplePageHandler::new(path.clone(), true, None).unwrap());
+                let page1 = handler.alloc_page().unwrap();
+                let page2 = handler.alloc_page().unwrap();
+                let id1 = page1.id;
+                let id2 = page2.id;
+                handler.dealloc_page(page1).unwrap();
+                handler.dealloc_page(page2).unwrap();
+                let page3 = handler.alloc_page().unwrap();
+                assert_eq!(page3.id, id2); // Reuse from free list
+                let page4 = handler.alloc_page().unwrap();
+                assert_eq!(page4.id, id1); // Reuse from free list
+            }
+
+
+
+            #[test]
+            fn dealloc_overflow_chain_test() {
+                let path = file_management::get_test_path().unwrap().join("dealloc_overflow_chain.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let mut pages : Vec<PageHeader> = (0..3).map(|_| handler.alloc_page().unwrap()).collect();
+                let ids : Vec<usize> = pages.iter().map(|page| page.id).collect();
+
+                //The chain points backwards, so freeing a later page moves the header of the
+                //first one
+                handler.set_next(&mut pages[1], Some(ids[0])).unwrap();
+                handler.set_next(&mut pages[2], Some(ids[1])).unwrap();
+                let kept = handler.alloc_page().unwrap();
+                handler.dealloc_page(pages[2].clone()).unwrap();
+                for id in &ids {
+                    assert!(handler.is_page(*id).unwrap().is_none(), "page {} should be freed", id);
+                }
+                assert!(handler.is_page(kept.id).unwrap().is_some());
+
+                //All pages of the chain are on the free list and the file is still consistent
+                let mut reused : Vec<usize> = (0..3).map(|_| handler.alloc_page().unwrap().id).collect();
+                reused.sort();
+                assert_eq!(reused, ids);
+                drop(handler);
+                assert!(SimplePageHandler::new(path, true, None).is_ok());
+            }
+
+
+
+            #[test]
+            fn reopen_valid_file_test() {
+                let path = file_management::get_test_path().unwrap().join("reopen_valid_file.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page1 = handler.alloc_page().unwrap();
+                handler.alloc_page().unwrap();
+                handler.dealloc_page(page1).unwrap();
+                drop(handler);
+                assert!(SimplePageHandler::new(path, true, None).is_ok(), "a consistent file should pass validation");
+            }
+
+
+
+            #[test]
+            fn legacy_layout_test() {
+                let path = file_management::get_test_path().unwrap().join("legacy_layout.test");
+                file_management::delete_file(&path);
+
+                //Write a file the way it was written before the head was versioned, with page 1
+                //allocated and holding five bytes
+                file_management::create_file(&path).unwrap();
+                let file_handler = SimpleFileHandler::new(path.clone()).unwrap();
+                file_handler.write_at(0, 2_usize.to_le_bytes().to_vec()).unwrap();
+                let header_page : Vec<u8> = [PageHeader::new(0, None, 2 * PageHeader::get_size(), None, None, None), PageHeader::new(1, None, 5, None, None, None)].into_iter().flat_map(Into::<Vec<u8>>::into).collect();
+                file_handler.write_at(LEGACY_HEAD_SIZE, header_page).unwrap();
+                file_handler.write_at(LEGACY_HEAD_SIZE + LEGACY_PAGE_SIZE, b"hello".to_vec()).unwrap();
+                drop(file_handler);
+
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page1 = handler.is_page(1).unwrap().unwrap();
+                assert_eq!(&handler.read_page(&page1).unwrap()[..5], b"hello");
+                let page2 = handler.alloc_page().unwrap();
+                assert_eq!(page2.id, 2);
+                handler.write_page(page2, b"world".to_vec(), 5).unwrap();
+                drop(handler);
+
+                //The file keeps its layout, so the second page starts right after the first one
+                assert_eq!(SimpleFileHandler::new(path.clone()).unwrap().read_at(LEGACY_HEAD_SIZE + 2 * LEGACY_PAGE_SIZE, 5).unwrap(), b"world".to_vec());
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                assert_eq!(&handler.read_page(&handler.is_page(1).unwrap().unwrap()).unwrap()[..5], b"hello");
+                assert_eq!(&handler.read_page(&handler.is_page(2).unwrap().unwrap()).unwrap()[..5], b"world");
+            }
+
+
+
+            #[test]
+            fn unsupported_version_test() {
+                let path = file_management::get_test_path().unwrap().join("unsupported_version.test");
+                file_management::delete_file(&path);
+                drop(SimplePageHandler::new(path.clone(), true, None).unwrap());
+                SimpleFileHandler::new(path.clone()).unwrap().write_at(12, (HEAD_VERSION + 1).to_le_bytes().to_vec()).unwrap();
+                let result = SimplePageHandler::new(path, true, None);
+                assert!(matches!(result, Err(ref e) if e.kind() == ErrorKind::InvalidData), "expected an InvalidData error for a newer version");
+            }
+
+
+
+            #[test]
+            fn detect_corrupted_used_test() {
+                let path = file_management::get_test_path().unwrap().join("detect_corrupted_used.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page1 = handler.alloc_page().unwrap();
+                drop(handler);
+
+                //Overwrite the used value of the page header with a value beyond the page size
+                let file_handler = SimpleFileHandler::new(path.clone()).unwrap();
+                file_handler.write_at(HEAD_SIZE + page1.header_offset.unwrap() + 16, (DEFAULT_PAGE_SIZE + 1).to_le_bytes().to_vec()).unwrap();
+                let result = SimplePageHandler::new(path, true, None);
+                assert!(matches!(result, Err(ref e) if e.kind() == ErrorKind::InvalidData), "expected an InvalidData error for a used value beyond the page size");
+            }
+
+
+
+            #[test]
+            fn detect_next_into_free_list_test() {
+                let path = file_management::get_test_path().unwrap().join("detect_next_into_free_list.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page1 = handler.alloc_page().unwrap();
+                let page2 = handler.alloc_page().unwrap();
+                handler.dealloc_page(page2.clone()).unwrap();
+                drop(handler);
+
+                //Let the header of page1 point to the deallocated page2
+                let file_handler = SimpleFileHandler::new(path.clone()).unwrap();
+                file_handler.write_at(HEAD_SIZE + page1.header_offset.unwrap() + 8, page2.id.to_le_bytes().to_vec()).unwrap();
+                let result = SimplePageHandler::new(path, true, None);
+                assert!(matches!(result, Err(ref e) if e.kind() == ErrorKind::InvalidData), "expected an InvalidData error for a next pointer into the free list");
+            }
+
+
+
+            #[test]
+            fn compact_test() {
+                let path = file_management::get_test_path().unwrap().join("compact.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page1 = handler.alloc_page().unwrap();
+                let page2 = handler.alloc_page().unwrap();
+                let page3 = handler.alloc_page().unwrap();
+                handler.write_page(page1.clone(), vec![1; DEFAULT_PAGE_SIZE - CHECKSUM_SIZE], 10).unwrap();
+                handler.write_page(page2.clone(), vec![2; DEFAULT_PAGE_SIZE - CHECKSUM_SIZE], 20).unwrap();
+                handler.write_page(page3.clone(), vec![3; DEFAULT_PAGE_SIZE - CHECKSUM_SIZE], 30).unwrap();
+                handler.dealloc_page(handler.is_page(page1.id).unwrap().unwrap()).unwrap();
+                handler.dealloc_page(handler.is_page(page2.id).unwrap().unwrap()).unwrap();
+                let size_before = file_management::get_size(&path).unwrap();
+                handler.compact().unwrap();
+                assert!(file_management::get_size(&path).unwrap() < size_before, "compact should shrink the file");
+
+                //The remaining page is moved to the first free position and keeps its data
+                let compacted = handler.is_page(1).unwrap().expect("page should have been moved to id 1");
+                assert_eq!(compacted.used, 30);
+                assert_eq!(handler.read_page(&compacted).unwrap(), vec![3; DEFAULT_PAGE_SIZE - CHECKSUM_SIZE]);
+                assert!(handler.is_page(page3.id).unwrap().is_none());
+                assert_eq!(handler.alloc_page().unwrap().id, 2, "free list should continue after the compacted pages");
+                drop(handler);
+                assert!(SimplePageHandler::new(path, true, None).is_ok(), "compacted file should pass validation");
+            }
+
+
+
+            ///Passes everything to a SimpleFileHandler but fails every write and truncate after the
+            ///first limit ones, like a process that is killed in the middle of an operation
+            struct InterruptedFileHandler {
+                file_handler : SimpleFileHandler,
+                limit : usize,
+                writes : Mutex<usize>,
+            }
+
+
+
+            impl InterruptedFileHandler {
+
+
+                fn count_write(&self) -> Result<()> {
+                    let mut writes = self.writes.lock().unwrap();
+                    if *writes >= self.limit {
+                        return Err(Error::other("interrupted"));
                     }
+                    *writes += 1;
+                    Ok(())
                 }
-                return Err(Error::new(ErrorKind::Other, "unexpected error"));
+
+
             }
 
 
-            fn dealloc_page(&self, page_header : PageHeader) -> Result<()> {
-                if let Some(next_page_header_id) = page_header.next {
-                    self.dealloc_page(self.is_page(next_page_header_id)?.ok_or(ErrorKind::InvalidInput)?);
+
+            impl FileHandler for InterruptedFileHandler {
+
+
+                fn get_path(&self) -> &PathBuf {
+                    self.file_handler.get_path()
                 }
-                let header_page_id = page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "header did not contain header_page_id")})?;
-                let mut header_page_bytes : Vec<u8> = self.file_handler.read_at(SimplePageHandler::calculate_page_start(header_page_id), PAGE_SIZE)?;
-                //Remove header from header page_header
-                let header_offset : usize = page_header.header_offset.ok_or(ErrorKind::InvalidInput)?;
-                header_page_bytes.drain(header_offset..(header_offset + PageHeader::get_size())); 
-                //Decrease used value
-                let mut own_header = PageHeader::try_from(header_page_bytes[..PageHeader::get_size()].to_vec())?;
-                own_header.used -= PageHeader::get_size();
-                //If a header page_header is empty it gets removed
-                let header_page_id = page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "page header did not contain a header_page_id")})?;
-                if own_header.used <= PageHeader::get_size() && header_page_id != 0 {
-                    let previous_page_id = page_header.previous_page_id.ok_or_else(|| {Error::new(ErrorKind::NotFound, "header did not contain previous_page_id")})?;
-                    let previous_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(previous_page_id), PAGE_SIZE)?;
-                    let mut previous_page_header = PageHeader::try_from(previous_page_bytes[..PageHeader::get_size()].to_vec())?;
-                    previous_page_header.next = own_header.next;
-                    self.file_handler.write_at(SimplePageHandler::calculate_page_start(previous_page_id), previous_page_header.into());
-                }else{
-                    header_page_bytes[..PageHeader::get_size()].copy_from_slice(&Into::<Vec<u8>>::into(own_header)); 
-                    self.file_handler.write_at(SimplePageHandler::calculate_page_start(header_page_id), header_page_bytes)?;
+
+
+                fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
+                    self.file_handler.read_at(at, length)
                 }
-                //Add page_header to free list
-                self.push_free(page_header.id);
-                return Ok(());
-            }
 
 
-            fn read_page(&self, page_header : &PageHeader) -> Result<Vec<u8>> {
-                return self.file_handler.read_at(SimplePageHandler::calculate_page_start(page_header.id), PAGE_SIZE);
-                return Err(Error::new(ErrorKind::InvalidInput, "wrong header type"));
-            }
+                fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+                    self.count_write()?;
+                    self.file_handler.write_at(at, data)
+                }
 
 
-            fn write_page(&self, page_header : PageHeader, data : Vec<u8>, size : usize) -> Result<()> {
-                //Check if data fits into one page
-                if data.len() > PAGE_SIZE {
-                    return Err(Error::new(ErrorKind::ArgumentListTooLong, "data is to big to write into one page"));
+                fn truncate(&self, size : usize) -> Result<()> {
+                    self.count_write()?;
+                    self.file_handler.truncate(size)
                 }
-                //Load all data required to change the content of a page
-                let header_page_id = page_header.header_page_id.ok_or(ErrorKind::InvalidInput)?;
-                let mut header_page_bytes = self.file_handler.read_at(SimplePageHandler::calculate_page_start(header_page_id), PAGE_SIZE)?;
-                let header_offset : usize = page_header.header_offset.ok_or_else(|| {Error::new(ErrorKind::NotFound, "header did not have a header_offset")})?;
-                let header_bytes = header_page_bytes.get(header_offset..(header_offset + PageHeader::get_size())).ok_or_else(|| {Error::new(ErrorKind::Other, "unexpected error")})?;
-                let mut own_header = PageHeader::try_from(header_bytes.to_vec())?;
-                //Check if the page header passed has the same id as the header loaded from storage
-                if own_header.id == page_header.id {
-                    //Update size and write back header with new size as well as the page itself
-                    own_header.used = size;
-                    header_page_bytes[header_offset..(header_offset + PageHeader::get_size())].copy_from_slice(&Into::<Vec<u8>>::into(own_header));
-                    self.file_handler.write_at(SimplePageHandler::calculate_page_start(page_header.id), data)?;
-                    self.file_handler.write_at(SimplePageHandler::calculate_page_start(page_header.header_page_id.ok_or_else(||{Error::new(ErrorKind::NotFound, "page header did not contain a header_page_id")})?), header_page_bytes)?;
-                    return Ok(());
+
+
+                fn sync(&self) -> Result<()> {
+                    self.file_handler.sync()
                 }
-                //Can only be returned if header did not have the same values as the header it
-                //referred to in storage
-                return Err(Error::new(ErrorKind::InvalidInput, "wrong header type"));
-            }
 
 
-            fn iterate_pages<'a>(&self, mut f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()> {
-                self.iterate_headers_from(PageHeader::get_first(),|h| {
-                    return f(h.clone(), self.read_page(&h)?);
-                }, )?;
-                return Ok(());
-            }
+                fn size(&self) -> Result<usize> {
+                    self.file_handler.size()
+                }
 
 
-            fn iterate_pages_from<'a>(&self, start : PageHeader, mut f : Box<dyn FnMut(PageHeader, Vec<u8>) -> Result<bool> + 'a>) -> Result<()> {
-                self.iterate_headers_from(start,|h| {
-                    return f(h.clone(), self.read_page(&h)?);
-                }, )?;
-                return Ok(());
             }
 
 
-        }
 
+            #[test]
+            fn compact_interrupted_test() {
+                let path = file_management::get_test_path().unwrap().join("compact_interrupted.test");
+                let capacity = DEFAULT_PAGE_SIZE - CHECKSUM_SIZE;
+                for limit in 0.. {
+                    let _ = file_management::delete_file(&path);
+                    let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                    let mut pages : Vec<PageHeader> = (1..=6).map(|i| {
+                        let page = handler.alloc_page().unwrap();
+                        handler.write_page(page.clone(), vec![i; capacity], 10).unwrap();
+                        page
+                    }).collect();
+
+                    //Page 3 is followed by 5 and 6, which both have to be moved
+                    let ids : Vec<usize> = pages.iter().map(|page| page.id).collect();
+                    handler.set_next(&mut pages[4], Some(ids[5])).unwrap();
+                    handler.set_next(&mut pages[2], Some(ids[4])).unwrap();
+                    handler.dealloc_page(pages[0].clone()).unwrap();
+                    handler.dealloc_page(handler.is_page(pages[1].id).unwrap().unwrap()).unwrap();
+                    drop(handler);
+
+                    let file_handler = InterruptedFileHandler{file_handler: SimpleFileHandler::new(path.clone()).unwrap(), limit, writes: Mutex::new(0)};
+                    let handler = SimplePageHandler::with_file_handler(Box::new(file_handler), DEFAULT_PAGE_SIZE, true, false).unwrap();
+                    let result = handler.compact();
+                    drop(handler);
+
+                    //Wherever compaction stopped the file holds every page exactly once
+                    let handler = SimplePageHandler::new(path.clone(), true, None).unwrap_or_else(|e| panic!("file should be valid after {} writes: {}", limit, e));
+                    let mut content : HashMap<u8, Option<usize>> = HashMap::new();
+                    let mut ids : HashMap<usize, u8> = HashMap::new();
+                    handler.iterate_pages(Box::new(|header, data| {
+                        assert!(content.insert(data[0], header.get_next()).is_none(), "page {} is there twice", data[0]);
+                        ids.insert(header.id, data[0]);
+                        Ok(false)
+                    })).unwrap();
+                    let mut values : Vec<u8> = content.keys().copied().collect();
+                    values.sort();
+                    assert_eq!(values, vec![3, 4, 5, 6], "pages after {} writes", limit);
+                    assert_eq!(content[&3].and_then(|next| ids.get(&next)), Some(&5), "page 3 should point to page 5 after {} writes", limit);
+                    assert_eq!(content[&5].and_then(|next| ids.get(&next)), Some(&6), "page 5 should point to page 6 after {} writes", limit);
+                    if result.is_ok() {
+                        assert_eq!(file_management::get_size(&path).unwrap(), (HEAD_SIZE + 5 * DEFAULT_PAGE_SIZE) as u64);
+                        break;
+                    }
+                }
+            }
 
 
-        #[cfg(test)]
-        mod test {
+
+            #[test]
+            fn dealloc_chain_test() {
+                let path = file_management::get_test_path().unwrap().join("dealloc_chain.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let mut head = handler.alloc_page().unwrap();
+                let tail = handler.alloc_page().unwrap();
+                let other = handler.alloc_page().unwrap();
+                handler.write_page(tail.clone(), vec![1; 8], 10).unwrap();
+                handler.set_next(&mut head, Some(tail.id)).unwrap();
+                assert_eq!(head.get_next(), Some(tail.id));
+                assert_eq!(handler.is_page(tail.id).unwrap().unwrap().used, 10, "linking should not change the used value of the next page");
+
+                //Deallocating the head frees the whole chain
+                handler.dealloc_page(handler.is_page(head.id).unwrap().unwrap()).unwrap();
+                assert!(handler.is_page(head.id).unwrap().is_none());
+                assert!(handler.is_page(tail.id).unwrap().is_none());
+                assert!(handler.is_page(other.id).unwrap().is_some());
+                drop(handler);
+                assert!(SimplePageHandler::new(path, true, None).is_ok(), "file should pass validation");
+            }
 
 
 
-            use super::*;
+            #[test]
+            fn checksum_mismatch_test() {
+                let path = file_management::get_test_path().unwrap().join("checksum_mismatch.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), b"Hello, Page!".to_vec(), 12).unwrap();
+                assert!(handler.read_page(&page).is_ok(), "an intact page should pass the checksum");
+
+                //Flip a byte of the page content behind the handlers back, the cache would hide it
+                handler.set_cache_size(0).unwrap();
+                handler.file_handler.write_at(handler.calculate_page_start(page.id), b"J".to_vec()).unwrap();
+                let result = handler.read_page(&page);
+                assert!(matches!(result, Err(ref e) if e.kind() == ErrorKind::InvalidData), "expected an InvalidData error for a corrupted page");
+            }
 
 
 
             #[test]
-            fn read_write_test() {
-                let path = file_management::get_test_path().unwrap().join("read_write.test");
+            fn without_checksum_test() {
+                let path = file_management::get_test_path().unwrap().join("without_checksum.test");
                 file_management::delete_file(&path);
-                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path).unwrap());
-                let data = b"Hello, Page!".to_vec();
-                handler.write_page(handler.alloc_page().unwrap(), data.clone(), data.len()).unwrap();
-                let mut read_data = handler.read_page(&handler.is_page(1).unwrap().unwrap()).unwrap();
-                read_data.truncate(data.len());
-                assert_eq!(data, read_data);
+                let handler = SimplePageHandler::new(path.clone(), false, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), vec![7; DEFAULT_PAGE_SIZE], DEFAULT_PAGE_SIZE).unwrap();
+                assert_eq!(handler.read_page(&page).unwrap(), vec![7; DEFAULT_PAGE_SIZE], "without checksums the whole page is usable");
             }
 
 
 
             #[test]
-            fn find_fitting_page_test() {
-                let path = file_management::get_test_path().unwrap().join("find_fitting_page.test");
+            fn stored_checksum_flag_test() {
+                let path = file_management::get_test_path().unwrap().join("stored_checksum_flag.test");
                 file_management::delete_file(&path);
-                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path).unwrap());
-                let page1 = handler.alloc_page().unwrap();
-                let page2 = handler.alloc_page().unwrap();
-                handler.write_page(page1, vec![0; PAGE_SIZE - 10], PAGE_SIZE - 10).unwrap();
-                let fitting_page = handler.find_fitting_page(20).unwrap();
-                assert_eq!(page2.id, fitting_page.unwrap().id);
+                let handler = SimplePageHandler::new(path.clone(), false, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), vec![7; DEFAULT_PAGE_SIZE], DEFAULT_PAGE_SIZE).unwrap();
+                drop(handler);
+
+                //A file written without checksums stays without them when it is opened with them
+                let handler = SimplePageHandler::with_storage(path.clone(), DEFAULT_PAGE_SIZE, Storage::Disk).unwrap();
+                assert_eq!(handler.read_page(&page).unwrap(), vec![7; DEFAULT_PAGE_SIZE]);
+                drop(handler);
+
+                //And a file written with checksums keeps verifying them when it is opened without
+                let path = file_management::get_test_path().unwrap().join("stored_checksum_flag_on.test");
+                file_management::delete_file(&path);
+                let handler = SimplePageHandler::new(path.clone(), true, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), b"Hello, Page!".to_vec(), 12).unwrap();
+                drop(handler);
+                let handler = SimplePageHandler::new(path, false, None).unwrap();
+                assert_eq!(handler.page_capacity(), DEFAULT_PAGE_SIZE - CHECKSUM_SIZE);
+                handler.set_cache_size(0).unwrap();
+                handler.file_handler.write_at(handler.calculate_page_start(page.id), b"J".to_vec()).unwrap();
+                assert!(matches!(handler.read_page(&page), Err(ref e) if e.kind() == ErrorKind::InvalidData), "expected an InvalidData error for a corrupted page");
             }
 
 
 
             #[test]
-            fn dont_find_fitting_page_test() {
-                let path = file_management::get_test_path().unwrap().join("dont_find_fitting_page.test");
+            fn custom_page_size_test() {
+                let path = file_management::get_test_path().unwrap().join("custom_page_size.test");
                 file_management::delete_file(&path);
-                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path).unwrap());
-                let page1 = handler.alloc_page().unwrap();
-                handler.write_page(page1, vec![0; PAGE_SIZE - 10], PAGE_SIZE - 10).unwrap();
-                let fitting_page = handler.find_fitting_page(90).unwrap();
-                assert!(matches!(fitting_page, None), "expected none but found some");
+                let handler = SimplePageHandler::with_page_size(path.clone(), 8192, true, None).unwrap();
+                let page = handler.alloc_page().unwrap();
+                handler.write_page(page.clone(), vec![5; 8192 - CHECKSUM_SIZE], 8000).unwrap();
+                assert!(handler.find_fitting_page(500).unwrap().is_none());
+                drop(handler);
+
+                //Reopening uses the stored size instead of the requested one
+                let handler = SimplePageHandler::new(path, true, None).unwrap();
+                assert_eq!(handler.page_size(), 8192);
+                assert_eq!(handler.read_page(&handler.is_page(page.id).unwrap().unwrap()).unwrap(), vec![5; 8192 - CHECKSUM_SIZE]);
             }
 
 
 
             #[test]
-            fn invalid_dealloc_test() {
-                let path = file_management::get_test_path().unwrap().join("invalid_dealloc.test");
+            fn invalid_page_size_test() {
+                let path = file_management::get_test_path().unwrap().join("invalid_page_size.test");
                 file_management::delete_file(&path);
-                let handler: Box<dyn PageHandler> = Box::new(SimplePageHandler::new(path.clone()).unwrap());
-                let result = handler.dealloc_page(PageHeader::new(999, None, 0, None, None, None));
-                assert!(result.is_err(), "Expected error when deallocating non-existent page");
+                assert!(SimplePageHandler::with_page_size(path.clone(), 5000, true, None).is_err(), "page size has to be a power of two");
+                file_management::delete_file(&path);
+                assert!(SimplePageHandler::with_page_size(path.clone(), 32, true, None).is_err(), "page size has to fit a header page");
             }
 
 
 
             #[test]
-            fn free_list_integrity_test() {
-                let path = file_management::get_test_path().unwrap().join("free_list_integrity.test");
-                file_management::delete_file(&path);
-                let handler = Box::new(SimplePageHandler::new(path.clone()).unwrap());
-                let page1 = handler.alloc_page().unwrap();
-                let page2 = handler.alloc_page().unwrap();
-                let id1 = page1.id;
-                let id2 = page2.id;
-                handler.dealloc_page(page1).unwrap();
-                handler.dealloc_page(page2).unwrap();
-                let page3 = handler.alloc_page().unwrap();
-                assert_eq!(page3.id, id2); // Reuse from free list
-                let page4 = handler.alloc_page().unwrap();
-                assert_eq!(page4.id, id1); // Reuse from free list
+            fn crc32_test() {
+                assert_eq!(crc32(b"123456789"), 0xCBF43926);
             }
 
 
@@ -937,13 +3272,15 @@ pub mod table_management {
 
 
 
-    use super::{file_management, page_management::{PageHandler, PageHeader, simple::{SimplePageHandler}}};
+    use super::{file_management::{self, Storage}, page_management::{PageHandler, PageHeader, DEFAULT_PAGE_SIZE, simple::{SimplePageHandler}}};
+    use crate::error::ErrorCode;
 
 
     use std::{
         collections::HashSet,
         io::{self, Error, ErrorKind, Result},
         path::PathBuf,
+        sync::{RwLock, Arc, atomic::{AtomicBool, AtomicU64, Ordering}},
         cell::RefCell,
         fmt::{self, Display, Formatter}
     };
@@ -953,11 +3290,52 @@ pub mod table_management {
 
 
 
+    thread_local! {
+        //Cancellation flag of the query running on this thread, see with_cancellation
+        static CANCELLATION : RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+    }
+
+
+
+    ///Runs f with cancel as the cancellation flag of the current thread. Table scans started by f
+    ///check the flag in their page callbacks and fail with a TimedOut error once it is set, so a
+    ///query that was abandoned stops at the next page instead of scanning the whole table
+    pub fn with_cancellation<T>(cancel : Arc<AtomicBool>, f : impl FnOnce() -> T) -> T {
+        let previous = CANCELLATION.with(|cancellation| cancellation.replace(Some(cancel)));
+        let result = f();
+        CANCELLATION.with(|cancellation| cancellation.replace(previous));
+        result
+    }
+
+
+
+    ///Returns an error if the query running on this thread was cancelled
+    fn check_cancelled() -> Result<()> {
+        let cancelled = CANCELLATION.with(|cancellation| cancellation.borrow().as_ref().is_some_and(|cancel| cancel.load(Ordering::SeqCst)));
+        if cancelled {
+            return Err(ErrorCode::Timeout.error(ErrorKind::TimedOut, "query timed out"));
+        }
+        Ok(())
+    }
+
+
+
+    ///Returns an error naming both counts if a row is given a different number of values than it
+    ///names cols, or than the table has cols if it names none
+    pub fn check_value_count(expected : usize, given : usize) -> Result<()> {
+        if expected != given {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("expected {} values but got {}", expected, given)));
+        }
+        Ok(())
+    }
+
+
+
     pub trait TableHandler: Sync + Send {
 
         ///Creates a row from cols and their names. They can be in the wrong order as long as val x
         ///in col_values has the same index as its corresponding name in col_names. Invalid names
-        ///result in an error.
+        ///and a number of values that does not match the cols result in an error.
         fn cols_to_row(&self, cols_names : Option<Vec<String>>, col_values : Vec<String>) -> Result<Row>;
         
         ///Takes a row object and a col name and then Returns the value on the corresponding place
@@ -972,19 +3350,55 @@ pub mod table_management {
         ///method may return errors!
         fn insert_row(&self, row : Row) -> Result<()>;
 
+        ///Inserts many rows at once. Rows are added to a page in memory until it is full, so each
+        ///page is written once instead of once per row. If a row violates a check none of the
+        ///rows are inserted. May return errors!
+        fn insert_rows(&self, rows : Vec<Row>) -> Result<()>;
+
         ///This method takes a predicate and returns a cursor which holds one value to a row and a
         ///reference to the next cursor which fulfill the predicates claims. In case no row does so
-        ///None is returned. Errors may be returned!
+        ///None is returned. The predicate is checked against the whole row, cols only select the
+        ///cols of the returned rows, also of those next returns. Errors may be returned!
         fn select_row(&self, predicate : Option<Predicate>, cols : Option<Vec<String>>) -> Result<Option<(Row, Cursor)>>;
 
         ///This method takes a predicate and removes all rows that fulfill the predicates claims
-        ///from the table this handler works in. May fail and return an error!
-        fn delete_row(&self, predicate : Option<Predicate>) -> Result<()>;
+        ///from the table this handler works in. Returns the number of removed rows. May fail and
+        ///return an error!
+        fn delete_row(&self, predicate : Option<Predicate>) -> Result<usize>;
 
         ///Takes a cursor and updates it to point at the next row. If a next row was found this
-        ///method returns true. Otherwise false is returned. Errors may be thrown!!
+        ///method returns true. Otherwise false is returned. Fails if the table was modified
+        ///since the cursor was created. Errors may be thrown!!
         fn next(&self, cursor : &mut Cursor) -> Result<Option<Row>>;
 
+        ///Frees pages that do not contain any rows and rewrites the remaining pages contiguously
+        ///so the table file shrinks. Cursors of this table become invalid. May return errors!
+        fn compact(&self) -> Result<()>;
+
+        ///Returns the size of the pages of this table in bytes
+        fn page_size(&self) -> usize;
+
+        ///Returns the number of cols of the table
+        fn col_count(&self) -> usize;
+
+        ///Writes the table to the storage device. May return errors!
+        fn sync(&self) -> Result<()>;
+
+        ///Scans the whole table and returns exact statistics about its rows. May return errors!
+        fn analyze(&self) -> Result<TableStatistics>;
+
+        ///Returns how many rows fulfill the predicate, or how many rows the table has if there is
+        ///none. Without a predicate no rows are built. May return errors!
+        fn count_rows(&self, predicate : Option<Predicate>) -> Result<u64>;
+
+        ///Adds a check every inserted row has to fulfill. Fails if the col of the check does not
+        ///exist or has a different type than its value
+        fn add_check(&self, check : Predicate) -> Result<()>;
+
+        ///Marks a col as not null, every inserted row has to give it a value. Fails if the col
+        ///does not exist
+        fn add_not_null(&self, col_name : String) -> Result<()>;
+
     }
 
 
@@ -993,14 +3407,18 @@ pub mod table_management {
     pub enum Type {
         Text,
         Number,
+        Blob,
     }
 
 
 
+    ///Numbers are stored as 8 little endian bytes in two's complement, so numbers written
+    ///before they were signed are read back unchanged as long as they are below 2^63
 #[derive(Clone, Debug)]
     pub enum Value {
         Text(String),
-        Number(u64),
+        Number(i64),
+        Blob(Vec<u8>),
     }
 
 
@@ -1020,6 +3438,12 @@ pub mod table_management {
         LessOrEqual,
         Bigger,
         BiggerOrEqual,
+
+        //Like equal but ignores the case of text, other types can not be compared this way
+        EqualIgnoreCase,
+
+        //Matches if the col equals one of the values, the value of the predicate is not compared
+        In(Vec<Value>),
     }
 
 
@@ -1032,6 +3456,26 @@ pub mod table_management {
     }
 
 
+#[derive(Clone, Debug, PartialEq)]
+    pub struct ColumnStatistics {
+        pub name : String,
+        pub min : Option<Value>,
+        pub max : Option<Value>,
+
+        ///Number of distinct values, only exact after a full scan
+        pub distinct : u64,
+    }
+
+
+
+#[derive(Clone, Debug, PartialEq)]
+    pub struct TableStatistics {
+        pub row_count : u64,
+        pub columns : Vec<ColumnStatistics>,
+    }
+
+
+
 #[derive(Debug)]
     pub struct Cursor {
         header : PageHeader,
@@ -1039,6 +3483,9 @@ pub mod table_management {
         data_offset : usize,
         predicate : Option<Predicate>,
         cols : Option<Vec<String>>,
+
+        //Generation of the table when the cursor was created
+        generation : u64,
     }
 
 
@@ -1053,6 +3500,7 @@ pub mod table_management {
             Ok(match value {
                 0 => Self::Number,
                 1 => Self::Text,
+                2 => Self::Blob,
                 x => return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not represent a type", x))),
             })
         }
@@ -1072,6 +3520,7 @@ pub mod table_management {
             Ok(match value.as_str() {
                 "text" => Self::Text, 
                 "number" => Self::Number,
+                "blob" => Self::Blob,
                 x => return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not represent a type", x))),
             })
         }
@@ -1081,6 +3530,22 @@ pub mod table_management {
 
 
 
+    impl Display for Type {
+
+
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self {
+                Type::Text => write!(f, "text"),
+                Type::Number => write!(f, "number"),
+                Type::Blob => write!(f, "blob"),
+            }
+        }
+
+
+    }
+
+
+
     impl Into<u64> for Type {
 
 
@@ -1088,6 +3553,7 @@ pub mod table_management {
             match self {
                 Type::Number => 0,
                 Type::Text => 1,
+                Type::Blob => 2,
             }
         }
 
@@ -1120,18 +3586,29 @@ pub mod table_management {
         }
 
 
-        pub fn new_number(value : u64) -> Self {
+        pub fn new_number(value : i64) -> Self {
             return Self::Number(value);
         }
 
 
+        pub fn new_blob(value : Vec<u8>) -> Self {
+            Self::Blob(value)
+        }
+
+
+        ///Blobs are written as hex in queries, as the query text can not carry arbitrary bytes
+        pub fn new_blob_from_hex(value : &str) -> Result<Self> {
+            Ok(Self::Blob(hex::decode(value).map_err(|_| ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, "could not convert string to blob, expected hex"))?))
+        }
+
+
         pub fn new_text_from_bytes(value : Vec<u8>) -> Result<Self> {
             return Ok(Self::Text(String::from_utf8(value).map_err(|_| Error::new(ErrorKind::InvalidInput, "couldnt convert bytes to string"))?));
         }
         
 
         pub fn new_number_from_bytes(value : Vec<u8>) -> Result<Self> {
-            return Ok(Self::Number(u64::from_le_bytes(value.try_into().map_err(|_| Error::new(ErrorKind::InvalidInput, "couldnt convert bytes to string"))?)));
+            Ok(Self::Number(i64::from_le_bytes(value.try_into().map_err(|_| Error::new(ErrorKind::InvalidInput, "couldnt convert bytes to string"))?)))
         }
 
 
@@ -1146,6 +3623,7 @@ pub mod table_management {
             match self { 
                 Self::Text(val) => {val.as_bytes().to_vec()},
                 Self::Number(val) => {val.to_le_bytes().to_vec()},
+                Self::Blob(val) => val,
             }
         }
 
@@ -1161,7 +3639,7 @@ pub mod table_management {
             match self {
                 Self::Text(_) => Type::Text,
                 Self::Number(_) => Type::Number,
-                
+                Self::Blob(_) => Type::Blob,
             }
         }
 
@@ -1176,48 +3654,171 @@ pub mod table_management {
             match self {
                 Self::Text(val) => Ok(val),
                 Self::Number(_) => Err(Error::new(ErrorKind::InvalidInput, "could not convert number to String")), 
+                Self::Blob(_) => Err(Error::new(ErrorKind::InvalidInput, "could not convert blob to String")),
+            }
+        }
+
+    }
+
+
+    impl TryInto<i64> for Value {
+        type Error = std::io::Error;
+        fn try_into(self) -> std::result::Result<i64, Self::Error> {
+            match self {
+                Self::Text(_) => Err(Error::new(ErrorKind::InvalidInput, "could not convert text to i64")), 
+                Self::Blob(_) => Err(Error::new(ErrorKind::InvalidInput, "could not convert blob to i64")),
+                Self::Number(val) => Ok(val),
+            }
+        }
+
+    }
+
+
+
+    impl Display for Value {
+
+
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self { 
+                Self::Text(val) => write!(f, "{}", val),
+                Self::Number(val) => write!(f, "{}", val),
+                Self::Blob(val) => write!(f, "{}", hex::encode(val)),
+            }
+        }
+
+
+    }
+
+    impl PartialEq for Value {
+        fn eq(&self, other: &Self) -> bool {
+            match (self, other) {
+                (Self::Text(v1), Self::Text(v2)) => v1 == v2,
+                (Self::Number(v1), Self::Number(v2)) => v1 == v2,
+                (Self::Blob(v1), Self::Blob(v2)) => v1 == v2,
+                _ => false,
+            }
+        }
+    }
+
+
+
+    ///Text is ordered lexically, numbers numerically and blobs by their bytes. Values of different
+    ///types are not comparable. This is the only place values are ordered, so a float type would
+    ///return None for NaN here
+    impl PartialOrd for Value {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            match (self, other) {
+                (Self::Text(v1), Self::Text(v2)) => v1.partial_cmp(v2),
+                (Self::Number(v1), Self::Number(v2)) => v1.partial_cmp(v2),
+                (Self::Blob(v1), Self::Blob(v2)) => v1.partial_cmp(v2),
+                _ => None,
+            }
+        }
+    }
+
+
+
+    impl TableStatistics {
+
+
+        ///Creates statistics of an empty table with the given columns
+        pub fn new(col_names : Vec<String>) -> Self {
+            let columns = col_names.into_iter().map(|name| ColumnStatistics{name, min: None, max: None, distinct: 0}).collect();
+            TableStatistics{row_count: 0, columns}
+        }
+
+
+        ///Updates row count, min and max with a row that was added to the table. Distinct counts
+        ///can not be maintained this way and are left untouched.
+        pub fn add_row(&mut self, row : &Row) {
+            self.row_count += 1;
+            for (column, value) in self.columns.iter_mut().zip(row.cols.iter()) {
+                if column.min.as_ref().is_none_or(|min| value < min) {
+                    column.min = Some(value.clone());
+                }
+                if column.max.as_ref().is_none_or(|max| max < value) {
+                    column.max = Some(value.clone());
+                }
+            }
+        }
+
+
+    }
+
+
+
+    impl Display for TableStatistics {
+
+
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "rows: {}", self.row_count)?;
+            for column in &self.columns {
+                let min = column.min.as_ref().map_or("none".to_string(), |v| v.to_string());
+                let max = column.max.as_ref().map_or("none".to_string(), |v| v.to_string());
+                write!(f, "; {}: min {}, max {}, distinct {}", column.name, min, max, column.distinct)?;
             }
+            Ok(())
         }
 
-    }
 
+    }
+
+
+
+   impl From<Operator> for String {
 
-    impl TryInto<u64> for Value {
-        type Error = std::io::Error;
-        fn try_into(self) -> std::result::Result<u64, Self::Error> {
-            match self {
-                Self::Text(_) => Err(Error::new(ErrorKind::InvalidInput, "could not convert text to u64")), 
-                Self::Number(val) => Ok(val),
-            }
+
+        fn from(val: Operator) -> Self {
+            match val {
+                Operator::Equal => "equal",
+                Operator::NotEqual => "not_equal",
+                Operator::Less => "less",
+                Operator::LessOrEqual => "less_equal",
+                Operator::Bigger => "bigger",
+                Operator::BiggerOrEqual => "bigger_equal",
+                Operator::EqualIgnoreCase => "equal_ignore_case",
+                Operator::In(_) => "in",
+            }.to_string()
         }
 
-    }
+
+   }
 
 
 
-#[cfg(test)]
-    impl Display for Value {
+   impl Display for Operator {
 
 
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-            match self { 
-                Self::Text(val) => write!(f, "{}", val),
-                Self::Number(val) => write!(f, "{}", val),
+            match self {
+                Self::Equal => write!(f, "=="),
+                Self::NotEqual => write!(f, "!="),
+                Self::Less => write!(f, "<"),
+                Self::LessOrEqual => write!(f, "<="),
+                Self::Bigger => write!(f, ">"),
+                Self::BiggerOrEqual => write!(f, ">="),
+                Self::EqualIgnoreCase => write!(f, "==*"),
+                Self::In(values) => write!(f, "in ({})", values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(", ")),
             }
         }
 
 
-    }
+   }
 
-    impl PartialEq for Value {
-        fn eq(&self, other: &Self) -> bool {
-            match (self, other) {
-                (Self::Text(v1), Self::Text(v2)) => v1 == v2,
-                (Self::Number(v1), Self::Number(v2)) => v1 == v2,
-                _ => false,
+
+
+   impl Display for Predicate {
+
+
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            if let Operator::In(_) = self.operator {
+                return write!(f, "{} {}", self.column, self.operator);
             }
+            write!(f, "{} {} {}", self.column, self.operator, self.value)
         }
-    }
+
+
+   }
 
 
 
@@ -1234,6 +3835,7 @@ pub mod table_management {
                 "less_equal" => Self::LessOrEqual,
                 "bigger" => Self::Bigger,
                 "bigger_equal" => Self::BiggerOrEqual,
+                "equal_ignore_case" => Self::EqualIgnoreCase,
                 x => return Err(Error::new(ErrorKind::InvalidInput, format!("{} does not represent a operator", x))),
             })
         }
@@ -1262,14 +3864,39 @@ pub mod table_management {
         use super::*;
  
 
-        //Bytes should always be >= log_2(PAGE_SIZE)
+        //Bytes should always be >= log_2(page size)
         type OffsetType = u16;
 
 
+        //+-----------------+------------+------------+------------+
+        //| overflow marker | chunk_size | chunk      |            |
+        //+-----------------+------------+------------+------------+
+        //| OffsetType      | OffsetType | Vec<u8>    |            |
+        //+-----------------+------------+------------+------------+
+        //| OffsetType::MAX | number of  | next bytes | free space |
+        //| in place of the | bytes in   | of the row |            |
+        //| row_count       | the chunk  |            |            |
+        //+-----------------+------------+------------+------------+
+        //
+        //Rows that do not fit into one page are stored in a page of their own, which holds the
+        //first chunk of the row like a normal page with one row. The remaining bytes are split
+        //across overflow pages linked through the next field of the page headers.
+        const OVERFLOW_MARKER : OffsetType = OffsetType::MAX;
+
+
 
         pub struct SimpleTableHandler {
             page_handler : Box<dyn PageHandler>,
             col_data : Vec<(Type, String)>,
+
+            //Predicates every inserted row has to fulfill
+            checks : RwLock<Vec<Predicate>>,
+
+            //Cols that were declared not null
+            not_null : RwLock<Vec<String>>,
+
+            //Changes whenever rows are moved, so cursors can tell the table was modified
+            generation : AtomicU64,
         }
  
 
@@ -1315,11 +3942,13 @@ pub mod table_management {
             let mut last_col_offset = col_types.len() * offset_size;
             let mut row = Row {cols : Vec::new()};
             for (index, col) in col_types.iter().enumerate() {
-                let col_offset = OffsetType::from_le_bytes(bytes[(index * offset_size)..((index + 1) * offset_size)].try_into().map_err(|_|{Error::new(ErrorKind::UnexpectedEof, "not enough bytes for col_offset")})?) as usize;
-                let col_bytes : Vec<u8> = bytes[last_col_offset..col_offset].into();
+                let offset_bytes = bytes.get((index * offset_size)..((index + 1) * offset_size)).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for col_offset"))?;
+                let col_offset = OffsetType::from_le_bytes(offset_bytes.try_into().map_err(|_|{Error::new(ErrorKind::UnexpectedEof, "not enough bytes for col_offset")})?) as usize;
+                let col_bytes : Vec<u8> = bytes.get(last_col_offset..col_offset).ok_or_else(|| Error::new(ErrorKind::InvalidData, "col offsets point outside of the row"))?.into();
                 let val : Value = match col {
                     Type::Number => Value::new_number_from_bytes(col_bytes)?,
                     Type::Text => Value::new_text_from_bytes(col_bytes)?,
+                    Type::Blob => Value::new_blob(col_bytes),
                 };
                 row.cols.push(val);
                 last_col_offset = col_offset as usize;
@@ -1332,34 +3961,148 @@ pub mod table_management {
 
 
 
+        ///Generations are unique across all tables, so cursors of a table that was replaced by a
+        ///new handler with the same name are invalid as well
+        static GENERATIONS : AtomicU64 = AtomicU64::new(0);
+
+        fn next_generation() -> u64 {
+            GENERATIONS.fetch_add(1, Ordering::SeqCst)
+        }
+
+
         impl SimpleTableHandler {
 
 
-           pub fn new(table_path : PathBuf, col_data: Vec<(Type, String)>) -> Result<SimpleTableHandler> {
-                let page_handler = Box::new(SimplePageHandler::new(table_path)?);
-                return Ok(SimpleTableHandler {page_handler, col_data});
+           ///Creates a table that stores its rows in the pages of page_handler, so the table does
+           ///not depend on how or where the pages are kept. Usually this is a SimplePageHandler
+           ///created with with_storage.
+           pub fn new(page_handler : Box<dyn PageHandler>, col_data: Vec<(Type, String)>) -> Result<SimpleTableHandler> {
+
+                //Offsets inside a page have to be representable by OffsetType
+                if page_handler.page_size() > OffsetType::MAX as usize {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("page size {} is too big for a table", page_handler.page_size())));
+                }
+                Ok(SimpleTableHandler {page_handler, col_data, checks: RwLock::new(vec![]), not_null: RwLock::new(vec![]), generation: AtomicU64::new(next_generation())})
             }
 
 
+           ///Reads the offset with index from the start of a page, index 0 holds the number of rows
+           fn read_ptr(page : &[u8], index : usize) -> Result<usize> {
+               let ptr_size = (OffsetType::BITS / 8) as usize;
+               let bytes = page.get((index * ptr_size)..((index + 1) * ptr_size)).ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for offset"))?;
+               Ok(OffsetType::from_le_bytes(bytes.try_into().map_err(|_| Error::new(ErrorKind::UnexpectedEof, "not enough bytes for offset"))?) as usize)
+           }
+
+
+           ///Returns the bytes of a row, rows are stored from the end of a page. The row starts
+           ///data_offset bytes before the end and ends where the previous row starts
+           fn read_row_bytes(page : &[u8], data_offset : usize, previous_data_offset : usize) -> Result<Vec<u8>> {
+               if data_offset > page.len() || previous_data_offset > data_offset {
+                   return Err(Error::new(ErrorKind::InvalidData, "row offsets point outside of the page"));
+               }
+               Ok(page[(page.len() - data_offset)..(page.len() - previous_data_offset)].to_vec())
+           }
+
+
+           ///Appends the bytes stored in the overflow pages following header to row_bytes
+           fn read_overflow(&self, header : &PageHeader, row_bytes : &mut Vec<u8>) -> Result<()> {
+               let ptr_size = (OffsetType::BITS / 8) as usize;
+               let mut next = header.get_next();
+               while let Some(id) = next {
+                   let overflow_header = self.page_handler.is_page(id)?.ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("overflow page {} is not allocated", id)))?;
+                   let page = self.page_handler.read_page(&overflow_header)?;
+                   if Self::read_ptr(&page, 0)? != OVERFLOW_MARKER as usize {
+                       return Err(Error::new(ErrorKind::InvalidData, format!("page {} is not an overflow page", id)));
+                   }
+                   let chunk_size = Self::read_ptr(&page, 1)?;
+                   row_bytes.extend(page.get((2 * ptr_size)..(2 * ptr_size + chunk_size)).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("overflow page {} has an invalid chunk size", id)))?);
+                   next = overflow_header.get_next();
+               }
+               Ok(())
+           }
+
+
+           ///Adds the bytes of a row to a page that has enough free space for them and their offset
+           fn append_row(page : &mut [u8], row_bytes : &[u8]) -> Result<()> {
+               let ptr_size = (OffsetType::BITS / 8) as usize;
+               let row_size = row_bytes.len();
+               let ptr_count = Self::read_ptr(page, 0)?;
+               let data_offset = Self::read_ptr(page, ptr_count)?;
+               page[0..ptr_size].copy_from_slice(OffsetType::to_le_bytes((ptr_count+1) as OffsetType).as_ref());
+               page[((ptr_count + 1) * ptr_size)..((ptr_count + 2) * ptr_size)].copy_from_slice(OffsetType::to_le_bytes((data_offset + row_size) as OffsetType).as_ref());
+               if page.len() < data_offset + row_size {
+                   return Err(Error::new(ErrorKind::InvalidInput, "page to small for input"));
+               }
+               let start : usize = page.len() - (data_offset + row_size);
+               let end : usize = page.len() - data_offset;
+               page[start..end].copy_from_slice(row_bytes);
+               Ok(())
+           }
+
+
+           ///Stores a row that does not fit into one page in a page of its own followed by a chain
+           ///of overflow pages
+           fn insert_overflowing_row(&self, row_bytes : Vec<u8>) -> Result<()> {
+               let ptr_size = (OffsetType::BITS / 8) as usize;
+               let capacity = self.page_handler.page_capacity();
+               let mut head = self.page_handler.alloc_page()?;
+               let chunks : Vec<&[u8]> = row_bytes.chunks(capacity - 2 * ptr_size).collect();
+
+               //Write the overflow pages from the tail on, so every page is linked to a page that
+               //was written already
+               let mut next : Option<usize> = None;
+               let mut overflow_headers : Vec<PageHeader> = vec![];
+               for _ in 1..chunks.len() {
+                   overflow_headers.push(self.page_handler.alloc_page()?);
+               }
+               for (mut header, chunk) in overflow_headers.into_iter().zip(chunks[1..].iter()).rev() {
+                   let mut page : Vec<u8> = vec![0; capacity];
+                   page[0..ptr_size].copy_from_slice(&OVERFLOW_MARKER.to_le_bytes());
+                   page[ptr_size..(2 * ptr_size)].copy_from_slice(&(chunk.len() as OffsetType).to_le_bytes());
+                   page[(2 * ptr_size)..(2 * ptr_size + chunk.len())].copy_from_slice(chunk);
+                   self.page_handler.write_page(header.clone(), page, capacity)?;
+                   self.page_handler.set_next(&mut header, next)?;
+                   next = Some(header.id);
+               }
+
+               //The head holds the first chunk as its only row and is marked as full
+               let mut page : Vec<u8> = vec![0; capacity];
+               page[0..ptr_size].copy_from_slice(&(1 as OffsetType).to_le_bytes());
+               page[ptr_size..(2 * ptr_size)].copy_from_slice(&(chunks[0].len() as OffsetType).to_le_bytes());
+               page[(capacity - chunks[0].len())..].copy_from_slice(chunks[0]);
+               self.page_handler.write_page(head.clone(), page, capacity)?;
+               self.page_handler.set_next(&mut head, next)?;
+               Ok(())
+           }
+
+
+           ///Marks the table as modified, which invalidates all of its cursors
+           fn modified(&self) {
+               self.generation.store(next_generation(), Ordering::SeqCst);
+           }
+
+
+
            fn row_fulfills(&self, row: &Row, p: &Option<Predicate>) -> Result<bool> {
                if let Some(predicate) = p {
                    let col_index = self.col_data.iter().position(|(t, name)| name == &predicate.column);
                    if let Some(index) = col_index {
                        if let Some(value) = row.cols.get(index) {
-                           let comparison_result = match (&predicate.operator, value, &predicate.value) {
-                               (Operator::Equal, Value::Text(a), Value::Text(b)) => a == b,
-                               (Operator::Equal, Value::Number(a), Value::Number(b)) => a == b,
-                               (Operator::NotEqual, Value::Text(a), Value::Text(b)) => a != b,
-                               (Operator::NotEqual, Value::Number(a), Value::Number(b)) => a != b,
-                               (Operator::Less, Value::Text(a), Value::Text(b)) => a < b,
-                               (Operator::Less, Value::Number(a), Value::Number(b)) => a < b,
-                               (Operator::LessOrEqual, Value::Text(a), Value::Text(b)) => a <= b,
-                               (Operator::LessOrEqual, Value::Number(a), Value::Number(b)) => a <= b,
-                               (Operator::Bigger, Value::Text(a), Value::Text(b)) => a > b,
-                               (Operator::Bigger, Value::Number(a), Value::Number(b)) => a > b,
-                               (Operator::BiggerOrEqual, Value::Text(a), Value::Text(b)) => a >= b,
-                               (Operator::BiggerOrEqual, Value::Number(a), Value::Number(b)) => a >= b,
-                               _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Type mismatch in comparison")),
+                           let blobs = matches!((value, &predicate.value), (Value::Blob(_), Value::Blob(_)));
+                           let ordering = || value.partial_cmp(&predicate.value).ok_or_else(|| ErrorCode::TypeMismatch.error(io::ErrorKind::InvalidInput, "Type mismatch in comparison"));
+                           let comparison_result = match &predicate.operator {
+                               Operator::In(values) => values.contains(value),
+                               Operator::Equal => ordering()?.is_eq(),
+                               Operator::NotEqual => ordering()?.is_ne(),
+                               Operator::EqualIgnoreCase => match (value, &predicate.value) {
+                                   (Value::Text(a), Value::Text(b)) => a.to_lowercase() == b.to_lowercase(),
+                                   _ => return Err(ErrorCode::TypeMismatch.error(io::ErrorKind::InvalidInput, "only text can be compared ignoring case")),
+                               },
+                               _ if blobs => return Err(io::Error::new(io::ErrorKind::InvalidInput, "blobs can only be compared for equality")),
+                               Operator::Less => ordering()?.is_lt(),
+                               Operator::LessOrEqual => ordering()?.is_le(),
+                               Operator::Bigger => ordering()?.is_gt(),
+                               Operator::BiggerOrEqual => ordering()?.is_ge(),
                            };
                            return Ok(comparison_result);
                        } else {
@@ -1378,7 +4121,7 @@ pub mod table_management {
                let col_name_sett: HashSet<_> = col_names.iter().collect();
                let col_data_set: HashSet<_> = self.col_data.iter().map(|(_, n)| n).collect();
                if !col_name_sett.is_subset(&col_data_set) {
-                   return Err(Error::new(ErrorKind::Other, "table does not contain these cols"));
+                   return Err(Error::new(ErrorKind::InvalidInput, "table does not contain these cols"));
                }
                return Ok(());
            }
@@ -1451,16 +4194,23 @@ pub mod table_management {
 
 
             fn cols_to_row(&self, mut col_names_option : Option<Vec<String>>, col_values : Vec<String>) -> Result<Row> {
+                check_value_count(col_names_option.as_ref().map_or(self.col_data.len(), |c| c.len()), col_values.len())?;
                 let col_names : Vec<String> = match col_names_option {
                     Some(c) => {
                         self.validate_cols(c.clone())?;
+
+                        //There are no null values yet, so every col needs a value whether it was declared
+                        //not null or not
+                        if let Some((_, missing)) = self.col_data.iter().find(|(_, n)| !c.contains(n)) {
+                            if self.not_null.read().map_err(|_| Error::other("thread poisoned"))?.contains(missing) {
+                                return Err(ErrorCode::ConstraintViolation.error(ErrorKind::InvalidInput, format!("col {} is not null but no value was given", missing)));
+                            }
+                            return Err(Error::new(ErrorKind::Unsupported, format!("col {} was given no value but null values are not supported", missing)));
+                        }
                         c
                     },
                     None => self.col_data.clone().into_iter().map(|(_, n)| n).collect(),
                 };
-                if col_names.len() != col_values.len() {
-                    return Err(Error::new(ErrorKind::InvalidInput, "amount of values and columns did not match"));
-                }
                 let mut cols : Vec<(String, String)> = col_names.into_iter().zip(col_values.into_iter()).collect();
                 cols.sort_by_key(|(n, _)| self.col_data.iter().position(|(_, s)| s==n));
                 let mut res : Vec<Value> = vec![];
@@ -1468,9 +4218,10 @@ pub mod table_management {
                     let col : Result<Value> = match self.col_data[index].0 {
                         Type::Text => Ok(Value::new_text(value.clone())),
                         Type::Number => {
-                            let number_value : u64 = value.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, "could not convert string to int"))?;
+                            let number_value : i64 = value.parse().map_err(|_| ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, "could not convert string to int"))?;
                             Ok(Value::new_number(number_value))
                         },
+                        Type::Blob => Value::new_blob_from_hex(value),
                     };
                     res.push(col?);
                 }
@@ -1483,66 +4234,110 @@ pub mod table_management {
                 Ok(match col.0 {
                     Type::Text => Value::new_text(value),
                     Type::Number => {
-                        let number_value : u64 = value.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, "could not convert string to int"))?;
+                        let number_value : i64 = value.parse().map_err(|_| ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, "could not convert string to int"))?;
                         Value::new_number(number_value)
                     },
+                    Type::Blob => Value::new_blob_from_hex(&value)?,
                 })
             }
 
 
             fn insert_row(&self, row : Row) -> Result<()> {
-                let mut row_bytes : Vec<u8> = row.into();
-                let row_size = row_bytes.len();
+                self.insert_rows(vec![row])
+            }
+
+
+
+            fn insert_rows(&self, rows : Vec<Row>) -> Result<()> {
+
+                //Checks are evaluated before any page is touched
+                let checks = self.checks.read().map_err(|_| Error::other("thread poisoned"))?;
+                for row in &rows {
+                    for check in checks.iter() {
+                        if !self.row_fulfills(row, &Some(check.clone()))? {
+                            return Err(ErrorCode::ConstraintViolation.error(ErrorKind::InvalidInput, format!("row violates check {}", check)));
+                        }
+                    }
+                }
+                drop(checks);
+                self.modified();
                 let ptr_size = (OffsetType::BITS / 8) as usize;
-                let mut used = 0;
-                let page_header = match self.page_handler.find_fitting_page(row_size + ptr_size)? {
-                    Some(p) => p,
-                    None => {
-                        used += ptr_size;
-                        self.page_handler.alloc_page()?},
-                };
-                used += page_header.used + row_size + ptr_size;
-                let mut page = self.page_handler.read_page(&page_header)?; 
-                let ptr_count = OffsetType::from_le_bytes(page[0..ptr_size].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for ptr_count")})?) as usize;
-                let data_offset = OffsetType::from_le_bytes(page[(ptr_count * ptr_size)..((ptr_count + 1) * ptr_size)].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for data_offset")})?) as usize;
-                page[0..ptr_size].copy_from_slice(&OffsetType::to_le_bytes((ptr_count+1) as OffsetType).to_vec());
-                page[((ptr_count + 1) * ptr_size)..((ptr_count + 2) * ptr_size)].copy_from_slice(&OffsetType::to_le_bytes((data_offset + row_size) as OffsetType).to_vec());
-                if page.len() < data_offset + row_size {
-                    return Err(Error::new(ErrorKind::InvalidInput, "page to small for input"));
-                }
-                let start : usize = page.len() - (data_offset + row_size);
-                let end : usize = page.len() - data_offset;
-                page[start..end].copy_from_slice(&row_bytes);
-                self.page_handler.write_page(page_header.clone(), page, used)?;
+
+                //The page rows are added to along with its used value. It is only written once the
+                //next row does not fit anymore
+                let mut current : Option<(PageHeader, Vec<u8>, usize)> = None;
+                for row in rows {
+                    let row_bytes : Vec<u8> = row.into();
+                    let row_size = row_bytes.len();
+
+                    //Offsets inside a row have to be representable by OffsetType
+                    if row_size > OffsetType::MAX as usize {
+                        return Err(Error::new(ErrorKind::InvalidInput, format!("row of {} bytes is too big for a table", row_size)));
+                    }
+
+                    //Rows that do not even fit into an empty page are split across overflow pages
+                    if row_size + 2 * ptr_size > self.page_handler.page_capacity() {
+                        self.insert_overflowing_row(row_bytes)?;
+                        continue;
+                    }
+                    let fits = matches!(&current, Some((_, _, used)) if self.page_handler.page_capacity().saturating_sub(*used) >= row_size + ptr_size);
+                    if !fits {
+                        if let Some((page_header, page, used)) = current.take() {
+                            self.page_handler.write_page(page_header, page, used)?;
+                        }
+                        let (page_header, used) = match self.page_handler.find_fitting_page(row_size + ptr_size)? {
+                            Some(page_header) => {
+                                let used = page_header.used;
+                                (page_header, used)
+                            },
+                            None => (self.page_handler.alloc_page()?, ptr_size),
+                        };
+                        let page = self.page_handler.read_page(&page_header)?;
+                        current = Some((page_header, page, used));
+                    }
+                    let (_, page, used) = current.as_mut().ok_or_else(|| Error::other("unexpected error: no page to insert into"))?;
+                    SimpleTableHandler::append_row(page, &row_bytes)?;
+                    *used += row_size + ptr_size;
+                }
+                if let Some((page_header, page, used)) = current {
+                    self.page_handler.write_page(page_header, page, used)?;
+                }
                 return Ok(());
             }
 
 
 
-            fn delete_row(&self, predicate : Option<Predicate>) -> Result<()> {
+            fn delete_row(&self, predicate : Option<Predicate>) -> Result<usize> {
                 let col_types : Vec<Type> = self.col_data.iter().map(|x| x.0.clone()).collect();
+                let mut overflowing_rows : Vec<usize> = vec![];
+                let mut deleted : usize = 0;
                 let callback = |header : PageHeader, mut page : Vec<u8>| -> Result<bool> {
+                    check_cancelled()?;
                     let mut new_used = header.used;
                     let ptr_size = (OffsetType::BITS / 8) as usize;
                     //Get pointer count in order to then iterate over all rows in the page. 
-                    let mut ptr_count = OffsetType::from_le_bytes(page[0..ptr_size].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for ptr_count")})?) as usize;
+                    let mut ptr_count = Self::read_ptr(&page, 0)?;
+                    if ptr_count == OVERFLOW_MARKER as usize {
+                        return Ok(false);
+                    }
                     let mut previous_data_offset : usize = 0;
                     //Iterate over all rows in the page
                     let mut ptr_index = 0;
                     while ptr_index < ptr_count {
                         //Get offset of last page
-                        let last_offset_start = (ptr_count)*ptr_size;
-                        let last_offset_end = (ptr_count+1)*ptr_size;
-                        let mut last_offset = OffsetType::from_le_bytes(page[last_offset_start..last_offset_end].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for last_offset")})?) as usize;
+                        let mut last_offset = Self::read_ptr(&page, ptr_count)?;
                         //Get the row
-                        let current_offset_start = (ptr_index + 1) * ptr_size;
-                        let current_offset_end = (ptr_index + 2) * ptr_size;
-                        let data_offset = OffsetType::from_le_bytes(page[current_offset_start..current_offset_end].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for data_offset")})?) as usize;
+                        let data_offset = Self::read_ptr(&page, ptr_index + 1)?;
+                        let mut row_bytes : Vec<u8> = Self::read_row_bytes(&page, data_offset, previous_data_offset)?;
                         let data_start : usize = page.len() - data_offset;
                         let data_end : usize = page.len() - previous_data_offset;
-                        let row_bytes : Vec<u8> = page[data_start..data_end].into();
+                        self.read_overflow(&header, &mut row_bytes)?;
                         let value : Row = Row::try_from((row_bytes, col_types.clone()))?;
                         if self.row_fulfills(&value, &predicate)? {
+                            //Pages of overflowing rows are freed after the iteration
+                            if header.get_next().is_some() {
+                                overflowing_rows.push(header.id);
+                            }
                             //Shift the data left of the deleted row to the right, just over it
                             let row_size = data_end - data_start;
                             let last_data_start = page.len()-last_offset;
@@ -1550,15 +4345,14 @@ pub mod table_management {
                             page[(data_end-remainder_bytes.len())..data_end].copy_from_slice(remainder_bytes);
                             for remaining_index in ptr_index..ptr_count {
                                 //Shift the data_offsets to the left over the deleted data_offset
-                                let start = (remaining_index + 1) * ptr_size;
-                                let end = (remaining_index + 2) * ptr_size;
-                                let mut new_data_offset = OffsetType::from_le_bytes(page[start..end].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for data_offset")})?) as usize;
+                                let mut new_data_offset = Self::read_ptr(&page, remaining_index + 1)?;
                                 new_data_offset -= row_size;
                                 let new_start = remaining_index * ptr_size;
                                 let new_end = (remaining_index+1) * ptr_size;
                                 page[new_start..new_end].copy_from_slice(&OffsetType::to_le_bytes(new_data_offset as OffsetType).to_vec());
                             }
                             //Adjust other variables
+                            deleted += 1;
                             new_used -= (row_size + ptr_size);
                             last_offset += row_size;
                             ptr_count -= 1;
@@ -1570,12 +4364,28 @@ pub mod table_management {
                     if new_used != header.used {
                         //Write back page if it changed
                         page[0..ptr_size].copy_from_slice(&OffsetType::to_le_bytes(ptr_count as OffsetType).to_vec());
-                        self.page_handler.write_page(header.clone(), page, new_used); 
+                        self.page_handler.write_page(header.clone(), page, new_used)?;
                     }
                     return Ok(false);
                 };
                 self.page_handler.iterate_pages(Box::new(callback))?;
-                return Ok(());
+                if deleted > 0 {
+                    self.modified();
+                }
+
+                //Headers are looked up again before each dealloc since deallocating moves the
+                //headers stored after the removed one
+                let freed = !overflowing_rows.is_empty();
+                for id in overflowing_rows {
+                    let header = self.page_handler.is_page(id)?.ok_or_else(|| Error::other("unexpected error: page of overflowing row vanished"))?;
+                    self.page_handler.dealloc_page(header)?;
+                }
+
+                //Freed pages at the end of the file are given back right away
+                if freed {
+                    self.page_handler.shrink()?;
+                }
+                Ok(deleted)
             }
 
 
@@ -1584,22 +4394,22 @@ pub mod table_management {
                 let col_types : Vec<Type> = self.col_data.iter().map(|x| x.0.clone()).collect();
                 let mut result : Option<(Row, Cursor)> = None;
                 let callback = |header : PageHeader, page : Vec<u8>| -> Result<bool> {
-                    let ptr_size = (OffsetType::BITS / 8) as usize;
-                    let ptr_count = OffsetType::from_le_bytes(page[0..ptr_size].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for ptr_count")})?) as usize;
+                    check_cancelled()?;
+                    let ptr_count = Self::read_ptr(&page, 0)?;
+                    if ptr_count == OVERFLOW_MARKER as usize {
+                        return Ok(false);
+                    }
                     let mut last_data_offset : usize = 0;
                     for ptr_index in 0..ptr_count.clone() {
-                        let start = (ptr_index + 1) * ptr_size;
-                        let end = (ptr_index + 2) * ptr_size;
-                        let data_offset = OffsetType::from_le_bytes(page[start..end].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for data_offset")})?) as usize;
-                        let start : usize = page.len() - data_offset;
-                        let end : usize = page.len() - last_data_offset;
-                        let row_bytes : Vec<u8> = page[start..end].into();
+                        let data_offset = Self::read_ptr(&page, ptr_index + 1)?;
+                        let mut row_bytes : Vec<u8> = Self::read_row_bytes(&page, data_offset, last_data_offset)?;
+                        self.read_overflow(&header, &mut row_bytes)?;
                         let mut row : Row = Row::try_from((row_bytes, col_types.clone()))?;
                         if self.row_fulfills(&row, &predicate)? {
                             if let Some(cs) = cols.clone() {
                                 self.filter_row(&mut row, cs)?;
                             }
-                            result = Some((row, Cursor { header, ptr_index: ptr_index+1, data_offset, predicate: predicate.clone(), cols: cols.clone()}));
+                            result = Some((row, Cursor { header, ptr_index: ptr_index+1, data_offset, predicate: predicate.clone(), cols: cols.clone(), generation: self.generation.load(Ordering::SeqCst)}));
                             return Ok(true);
                         }
                         last_data_offset = data_offset;
@@ -1613,6 +4423,12 @@ pub mod table_management {
 
 
             fn next(&self, cursor : &mut Cursor) -> Result<Option<Row>> {
+
+                //The position stored in the cursor may point at other rows or past the data of a page
+                //once rows were moved
+                if cursor.generation != self.generation.load(Ordering::SeqCst) {
+                    return Err(Error::new(ErrorKind::InvalidInput, "cursor was invalidated because its table was modified"));
+                }
                 let col_types : Vec<Type> = self.col_data.iter().map(|x| x.0.clone()).collect();
                 let mut result : Option<Row> = None;
                 let mut found_next = false;
@@ -1620,16 +4436,18 @@ pub mod table_management {
                 let mut initial_last_data_offset = cursor.data_offset;
                 self.page_handler.iterate_pages_from(cursor.header.clone(), Box::new(
                         |header : PageHeader, page : Vec<u8>| -> Result<bool> { 
-                            let ptr_size = (OffsetType::BITS / 8) as usize;
-                            let ptr_count = OffsetType::from_le_bytes(page[0..ptr_size].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for ptr_count")})?) as usize;
+                            check_cancelled()?;
+                            let ptr_count = Self::read_ptr(&page, 0)?;
+                            if ptr_count == OVERFLOW_MARKER as usize {
+                                initial_ptr_index = 0;
+                                initial_last_data_offset = 0;
+                                return Ok(false);
+                            }
                             let mut last_data_offset : usize = initial_last_data_offset;
                             for ptr_index in initial_ptr_index..ptr_count {
-                                let start = (ptr_index + 1) * ptr_size;
-                                let end = (ptr_index + 2) * ptr_size;
-                                let data_offset = OffsetType::from_le_bytes(page[start..end].try_into().map_err(|_| {Error::new(ErrorKind::UnexpectedEof, "not enough bytes for data_offset")})?) as usize;
-                                let start : usize = page.len() - data_offset;
-                                let end : usize = page.len() - last_data_offset;
-                                let row_bytes : Vec<u8> = page[start..end].to_vec();
+                                let data_offset = Self::read_ptr(&page, ptr_index + 1)?;
+                                let mut row_bytes : Vec<u8> = Self::read_row_bytes(&page, data_offset, last_data_offset)?;
+                                self.read_overflow(&header, &mut row_bytes)?;
                                 let mut row : Row = Row::try_from((row_bytes, col_types.clone()))?;
                                 if self.row_fulfills(&row, &cursor.predicate)? {
                                     if let Some(cs) = cursor.cols.clone() {
@@ -1653,6 +4471,118 @@ pub mod table_management {
             }
 
 
+
+            fn compact(&self) -> Result<()> {
+
+                //Collect the ids of all pages without rows
+                let mut empty_pages : Vec<usize> = vec![];
+                self.page_handler.iterate_pages(Box::new(|header : PageHeader, page : Vec<u8>| -> Result<bool> {
+                    let ptr_count = Self::read_ptr(&page, 0)?;
+                    if ptr_count == 0 {
+                        empty_pages.push(header.id);
+                    }
+                    Ok(false)
+                }))?;
+                self.modified();
+
+                //Headers are looked up again before each dealloc since deallocating moves the
+                //headers stored after the removed one
+                for id in empty_pages {
+                    let header = self.page_handler.is_page(id)?.ok_or_else(|| Error::other("unexpected error: empty page vanished"))?;
+                    self.page_handler.dealloc_page(header)?;
+                }
+                self.page_handler.compact()
+            }
+
+
+
+            fn page_size(&self) -> usize {
+                self.page_handler.page_size()
+            }
+
+
+
+            fn col_count(&self) -> usize {
+                self.col_data.len()
+            }
+
+
+
+            fn sync(&self) -> Result<()> {
+                self.page_handler.sync()
+            }
+
+
+
+            fn analyze(&self) -> Result<TableStatistics> {
+                let mut statistics = TableStatistics::new(self.col_data.iter().map(|(_, n)| n.clone()).collect());
+                let mut distinct : Vec<HashSet<Vec<u8>>> = vec![HashSet::new(); self.col_data.len()];
+                if let Some((mut row, mut cursor)) = self.select_row(None, None)? {
+                    loop {
+                        for (index, value) in row.cols.iter().enumerate() {
+                            distinct[index].insert(value.clone().into());
+                        }
+                        statistics.add_row(&row);
+                        match self.next(&mut cursor)? {
+                            Some(r) => row = r,
+                            None => break,
+                        }
+                    }
+                }
+                for (column, values) in statistics.columns.iter_mut().zip(distinct) {
+                    column.distinct = values.len() as u64;
+                }
+                Ok(statistics)
+            }
+
+
+            fn count_rows(&self, predicate : Option<Predicate>) -> Result<u64> {
+                let mut count : u64 = 0;
+
+                //Rows have to be built to evaluate a predicate
+                if predicate.is_some() {
+                    let mut next = self.select_row(predicate, None)?;
+                    while let Some((_, mut cursor)) = next {
+                        count += 1;
+                        next = self.next(&mut cursor)?.map(|row| (row, cursor));
+                    }
+                    return Ok(count);
+                }
+
+                //Otherwise the row count at the start of every page is summed up. Overflow pages
+                //continue a row that is counted in the page it starts in
+                self.page_handler.iterate_pages(Box::new(|_ : PageHeader, page : Vec<u8>| -> Result<bool> {
+                    check_cancelled()?;
+                    let ptr_count = Self::read_ptr(&page, 0)?;
+                    if ptr_count != OVERFLOW_MARKER as usize {
+                        count += ptr_count as u64;
+                    }
+                    Ok(false)
+                }))?;
+                Ok(count)
+            }
+
+
+            fn add_check(&self, check : Predicate) -> Result<()> {
+                let (col_type, _) = self.col_data.iter().find(|(_, n)| *n == check.column).ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("check refers to col {} which does not exist", check.column)))?;
+                if *col_type != Into::<Type>::into(check.value.clone()) {
+                    return Err(ErrorCode::TypeMismatch.error(ErrorKind::InvalidInput, format!("check {} compares values of different types", check)));
+                }
+                let mut checks = self.checks.write().map_err(|_| Error::other("thread poisoned"))?;
+                checks.push(check);
+                Ok(())
+            }
+
+
+            fn add_not_null(&self, col_name : String) -> Result<()> {
+                if !self.col_data.iter().any(|(_, n)| *n == col_name) {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("col {} does not exist", col_name)));
+                }
+                self.not_null.write().map_err(|_| Error::other("thread poisoned"))?.push(col_name);
+                Ok(())
+            }
+
+
         }
 
 
@@ -1668,7 +4598,8 @@ pub mod table_management {
             use super::file_management::{
                 self, 
                 FileHandler, 
-                SimpleFileHandler
+                SimpleFileHandler,
+                InMemoryFileHandler
             };
 
 
@@ -1687,17 +4618,169 @@ pub mod table_management {
                 let table_path = file_management::get_test_path().unwrap().join("simple_table_handler_creation.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
-                let handler_result = simple::SimpleTableHandler::new(table_path, col_data);
+                let handler_result = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data);
                 assert!(handler_result.is_ok());
             }
 
 
+            #[test]
+            fn page_handler_injection_test() {
+
+                //The table works with any page handler, here one that keeps its pages in memory
+                let page_handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), 4096, true, false).unwrap();
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(page_handler), col_data).unwrap();
+                assert_eq!(handler.page_size(), 4096);
+                handler.insert_row(Row{cols: vec![Value::new_text("a".to_string()), Value::new_number(1)]}).unwrap();
+                let (row, _) = handler.select_row(None, None).unwrap().unwrap();
+                assert_eq!(row.cols, vec![Value::new_text("a".to_string()), Value::new_number(1)]);
+            }
+
+
+            #[test]
+            fn insert_rows_test() {
+                let page_handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), 512, true, false).unwrap();
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(page_handler), col_data).unwrap();
+
+                //Rows spread over many small pages, one of them is too big for a page
+                let mut rows : Vec<Row> = (0..300).map(|i| Row{cols: vec![Value::new_text(format!("name {}", i)), Value::new_number(i)]}).collect();
+                rows[150].cols[0] = Value::new_text("x".repeat(2000));
+                handler.insert_rows(rows.clone()).unwrap();
+                let mut found : Vec<Row> = vec![];
+                let mut next = handler.select_row(None, None).unwrap();
+                while let Some((row, mut cursor)) = next {
+                    found.push(row);
+                    next = handler.next(&mut cursor).unwrap().map(|row| (row, cursor));
+                }
+                assert_eq!(found.len(), 300);
+                let mut ages : Vec<Value> = found.iter().map(|row| row.cols[1].clone()).collect();
+                ages.sort_by_key(|age| if let Value::Number(age) = age { *age } else { -1 });
+                assert_eq!(ages, (0..300).map(Value::new_number).collect::<Vec<Value>>());
+                assert!(found.iter().any(|row| row.cols[0] == Value::new_text("x".repeat(2000))));
+
+                //A row that violates a check keeps the whole batch out
+                handler.add_check(Predicate{column: "Age".to_string(), operator: Operator::Bigger, value: Value::new_number(0)}).unwrap();
+                let rows = vec![Row{cols: vec![Value::new_text("a".to_string()), Value::new_number(1)]}, Row{cols: vec![Value::new_text("b".to_string()), Value::new_number(-1)]}];
+                assert!(handler.insert_rows(rows).is_err());
+                assert!(handler.select_row(Some(Predicate{column: "Name".to_string(), operator: Operator::Equal, value: Value::new_text("a".to_string())}), None).unwrap().is_none());
+            }
+
+
+            #[test]
+            fn count_rows_test() {
+                let page_handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), 512, true, false).unwrap();
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(page_handler), col_data).unwrap();
+                assert_eq!(handler.count_rows(None).unwrap(), 0);
+
+                //The overflowing row is counted once although it spans several pages
+                let mut rows : Vec<Row> = (0..100).map(|i| Row{cols: vec![Value::new_text(format!("name {}", i)), Value::new_number(i)]}).collect();
+                rows[50].cols[0] = Value::new_text("x".repeat(2000));
+                handler.insert_rows(rows).unwrap();
+                handler.delete_row(Some(Predicate{column: "Age".to_string(), operator: Operator::BiggerOrEqual, value: Value::new_number(90)})).unwrap();
+                assert_eq!(handler.count_rows(None).unwrap(), 90);
+                assert_eq!(handler.count_rows(Some(Predicate{column: "Age".to_string(), operator: Operator::Less, value: Value::new_number(10)})).unwrap(), 10);
+            }
+
+
+            #[test]
+            fn cursor_invalidation_test() {
+                let page_handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), 512, true, false).unwrap();
+                let col_data : Vec<(Type, String)> = vec![(Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(page_handler), col_data).unwrap();
+                handler.insert_rows((0..10).map(|i| Row{cols: vec![Value::new_number(i)]}).collect()).unwrap();
+
+                //Deleting nothing does not move any rows
+                let (_, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                handler.delete_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Bigger, value: Value::new_number(100)})).unwrap();
+                assert!(handler.next(&mut cursor).unwrap().is_some());
+
+                //But deleting and inserting rows does
+                handler.delete_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Equal, value: Value::new_number(0)})).unwrap();
+                assert!(handler.next(&mut cursor).is_err(), "cursors should be invalid after a delete");
+                let (_, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                handler.insert_row(Row{cols: vec![Value::new_number(10)]}).unwrap();
+                assert!(handler.next(&mut cursor).is_err(), "cursors should be invalid after an insert");
+
+                //Cursors of another handler of the same table do not match either
+                let (_, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                let other_page_handler = SimplePageHandler::with_file_handler(Box::new(InMemoryFileHandler::new(PathBuf::from("memory"))), 512, true, false).unwrap();
+                let other = simple::SimpleTableHandler::new(Box::new(other_page_handler), vec![(Type::Number, "Age".to_string())]).unwrap();
+                assert!(other.next(&mut cursor).is_err());
+            }
+
+
+            ///Works like an InMemoryFileHandler but every write fails once fail is set
+            struct FailingFileHandler {
+                file_handler : InMemoryFileHandler,
+                fail : Arc<AtomicBool>,
+            }
+
+
+
+            impl FileHandler for FailingFileHandler {
+
+
+                fn get_path(&self) -> &PathBuf {
+                    self.file_handler.get_path()
+                }
+
+
+                fn read_at(&self, at : usize, length : usize) -> Result<Vec<u8>> {
+                    self.file_handler.read_at(at, length)
+                }
+
+
+                fn write_at(&self, at : usize, data : Vec<u8>) -> Result<()> {
+                    if self.fail.load(Ordering::SeqCst) {
+                        return Err(Error::new(ErrorKind::StorageFull, "disk is full"));
+                    }
+                    self.file_handler.write_at(at, data)
+                }
+
+
+                fn truncate(&self, size : usize) -> Result<()> {
+                    self.file_handler.truncate(size)
+                }
+
+
+                fn sync(&self) -> Result<()> {
+                    self.file_handler.sync()
+                }
+
+
+                fn size(&self) -> Result<usize> {
+                    self.file_handler.size()
+                }
+
+
+            }
+
+
+            #[test]
+            fn failed_write_test() {
+                let fail = Arc::new(AtomicBool::new(false));
+                let file_handler = FailingFileHandler{file_handler: InMemoryFileHandler::new(PathBuf::from("memory")), fail: fail.clone()};
+                let page_handler = SimplePageHandler::with_file_handler(Box::new(file_handler), 512, true, false).unwrap();
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(page_handler), col_data).unwrap();
+                handler.insert_row(Row{cols: vec![Value::new_text("a".to_string()), Value::new_number(1)]}).unwrap();
+
+                //A delete that can not write back its page has to fail instead of reporting the
+                //row as deleted
+                fail.store(true, Ordering::SeqCst);
+                assert_eq!(handler.delete_row(None).unwrap_err().kind(), ErrorKind::StorageFull);
+                assert!(handler.insert_row(Row{cols: vec![Value::new_text("b".to_string()), Value::new_number(2)]}).is_err());
+            }
+
+
             #[test]
             fn cols_to_row_test() {
                 let table_path = file_management::get_test_path().unwrap().join("cols_to_row.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Text, "Surname".to_string()), (Type::Number, "Age".to_string())];
-                let handler = simple::SimpleTableHandler::new(table_path, col_data).unwrap();
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
 
                 //right order with col_names given
                 let col_names : Vec<String> = vec!["Name".to_string(), "Surname".to_string(), "Age".to_string()];
@@ -1726,6 +4809,19 @@ pub mod table_management {
                 //wrong order without col_names
                 let result = handler.cols_to_row(None, col_values);
                 assert!(result.is_err());
+
+                //missing col
+                let col_names : Vec<String> = vec!["Name".to_string(), "Age".to_string()];
+                let result = handler.cols_to_row(Some(col_names), vec!["tschigerillo".to_string(), "2".to_string()]);
+                assert!(result.unwrap_err().to_string().contains("Surname"), "the error should name the missing col");
+
+                //missing col that was declared not null
+                handler.add_not_null("Surname".to_string()).unwrap();
+                let col_names : Vec<String> = vec!["Name".to_string(), "Age".to_string()];
+                let error = handler.cols_to_row(Some(col_names), vec!["tschigerillo".to_string(), "2".to_string()]).unwrap_err();
+                assert_eq!(ErrorCode::of(&error), ErrorCode::ConstraintViolation);
+                assert!(error.to_string().contains("Surname is not null"), "the error should name the not null col");
+                assert!(handler.add_not_null("Height".to_string()).is_err(), "a missing col can not be not null");
             }
 
 
@@ -1736,7 +4832,7 @@ pub mod table_management {
                 let table_path = file_management::get_test_path().unwrap().join("get_col_from_row.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Text, "Surname".to_string()), (Type::Number, "Age".to_string())];
-                let handler = simple::SimpleTableHandler::new(table_path, col_data).unwrap();
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
 
                 //create row
                 let col_names : Vec<String> = vec!["Name".to_string(), "Surname".to_string(), "Age".to_string()];
@@ -1761,7 +4857,7 @@ pub mod table_management {
                 let table_path = file_management::get_test_path().unwrap().join("get_col_from_row.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Text, "Surname".to_string()), (Type::Number, "Age".to_string())];
-                let handler = simple::SimpleTableHandler::new(table_path, col_data).unwrap();
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
 
                 //Existing column with fitting type text
                 let result = handler.create_value("Surname".to_string(), "bob".to_string());                 
@@ -1799,6 +4895,27 @@ pub mod table_management {
             }
 
 
+            #[test]
+            fn malformed_bytes_test() {
+                let row = Row {
+                    cols: vec![
+                        Value::new_text("text".to_string()),
+                        Value::new_number(123),
+                    ],
+                };
+                let col_types = vec![Type::Text, Type::Number];
+                let row_bytes: Vec<u8> = row.into();
+                for len in [0, 3, row_bytes.len() - 1] {
+                    simple::Row::try_from((row_bytes[..len].to_vec(), col_types.clone())).expect_err("a truncated row should return an error");
+                }
+                let page = vec![0u8; 16];
+                simple::SimpleTableHandler::read_ptr(&page, 100).expect_err("an offset past the page should return an error");
+                simple::SimpleTableHandler::read_row_bytes(&page, 17, 0).expect_err("a row starting before the page should return an error");
+                simple::SimpleTableHandler::read_row_bytes(&page, 4, 8).expect_err("a row ending before it starts should return an error");
+                assert_eq!(simple::SimpleTableHandler::read_row_bytes(&page, 8, 4).unwrap().len(), 4);
+            }
+
+
 
 
 
@@ -1809,7 +4926,7 @@ pub mod table_management {
                 let table_path = file_management::get_test_path().unwrap().join("insert_and_select.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Text, "Surname".to_string()), (Type::Number, "Age".to_string())];
-                let handler = simple::SimpleTableHandler::new(table_path, col_data).unwrap();
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
 
                 //Create rows
                 let row = handler.cols_to_row(None, vec!["tschigerillo".to_string(), "bob".to_string(), "2".to_string()]).unwrap();
@@ -1849,12 +4966,132 @@ pub mod table_management {
                 assert_eq!(cursor.0.cols, row.cols);
             }
 
+            #[test]
+            fn compact_table_test() {
+                let table_path = file_management::get_test_path().unwrap().join("compact_table.test");
+                file_management::delete_file(&table_path);
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path.clone(), true, None).unwrap()), col_data).unwrap();
+
+                //Fill multiple pages and delete most of the rows afterwards
+                for i in 0..1000 {
+                    handler.insert_row(handler.cols_to_row(None, vec![format!("name{}", i), i.to_string()]).unwrap()).unwrap();
+                }
+                assert_eq!(handler.delete_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Less, value: Value::new_number(990)})).unwrap(), 990);
+                let size_before = file_management::get_size(&table_path).unwrap();
+                handler.compact().unwrap();
+                assert!(file_management::get_size(&table_path).unwrap() < size_before, "compact should shrink the table file");
+
+                //All remaining rows can still be selected
+                let (_, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                let mut count = 1;
+                while handler.next(&mut cursor).unwrap().is_some() {
+                    count += 1;
+                }
+                assert_eq!(count, 10);
+            }
+
+
+            #[test]
+            fn overflowing_row_test() {
+                let table_path = file_management::get_test_path().unwrap().join("overflowing_row.test");
+                file_management::delete_file(&table_path);
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path.clone(), true, None).unwrap()), col_data.clone()).unwrap();
+
+                //Insert a text value several pages long between two small rows
+                let long_text : String = (0..3 * DEFAULT_PAGE_SIZE).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+                let rows = vec![
+                    Row{cols: vec![Value::new_text("bob".to_string()), Value::new_number(1)]},
+                    Row{cols: vec![Value::new_text(long_text.clone()), Value::new_number(2)]},
+                    Row{cols: vec![Value::new_text("alice".to_string()), Value::new_number(3)]},
+                ];
+                for row in rows.clone() {
+                    handler.insert_row(row).unwrap();
+                }
+
+                //All rows are read back in full by select and next
+                let (row, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                let mut selected = vec![row];
+                while let Some(row) = handler.next(&mut cursor).unwrap() {
+                    selected.push(row);
+                }
+                assert_eq!(selected.len(), 3);
+                for row in &rows {
+                    assert!(selected.iter().any(|r| r.cols == row.cols), "row should have been selected");
+                }
+                let (row, _) = handler.select_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Equal, value: Value::new_number(2)}), None).unwrap().unwrap();
+                assert_eq!(row.cols[0], Value::new_text(long_text.clone()));
+
+                //The row survives reopening and compaction
+                drop(handler);
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path.clone(), true, None).unwrap()), col_data).unwrap();
+                handler.compact().unwrap();
+                let (row, _) = handler.select_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Equal, value: Value::new_number(2)}), None).unwrap().unwrap();
+                assert_eq!(row.cols[0], Value::new_text(long_text));
+
+                //Deleting the row frees its overflow pages, they are at the end of the compacted
+                //file so it shrinks right away
+                let size_before = file_management::get_size(&table_path).unwrap();
+                assert_eq!(handler.delete_row(Some(Predicate{column: "Age".to_string(), operator: Operator::Equal, value: Value::new_number(2)})).unwrap(), 1);
+                assert!(file_management::get_size(&table_path).unwrap() < size_before, "the overflow pages should have been freed");
+                handler.compact().unwrap();
+                let (_, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                assert!(handler.next(&mut cursor).unwrap().is_some());
+                assert!(handler.next(&mut cursor).unwrap().is_none());
+            }
+
+
+            #[test]
+            fn reuse_overflow_pages_test() {
+                let table_path = file_management::get_test_path().unwrap().join("reuse_overflow_pages.test");
+                file_management::delete_file(&table_path);
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), vec![(Type::Text, "Name".to_string())]).unwrap();
+                handler.insert_row(Row{cols: vec![Value::new_text("a".repeat(3 * DEFAULT_PAGE_SIZE))]}).unwrap();
+                handler.delete_row(None).unwrap();
+
+                //The freed pages are allocated again for the following rows
+                handler.insert_row(Row{cols: vec![Value::new_text("b".to_string())]}).unwrap();
+                handler.insert_row(Row{cols: vec![Value::new_text("c".repeat(2 * DEFAULT_PAGE_SIZE))]}).unwrap();
+                let (row, mut cursor) = handler.select_row(None, None).unwrap().unwrap();
+                assert_eq!(row.cols, vec![Value::new_text("b".to_string())]);
+                assert_eq!(handler.next(&mut cursor).unwrap().unwrap().cols, vec![Value::new_text("c".repeat(2 * DEFAULT_PAGE_SIZE))]);
+            }
+
+
+            #[test]
+            fn too_big_row_test() {
+                let table_path = file_management::get_test_path().unwrap().join("too_big_row.test");
+                file_management::delete_file(&table_path);
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), vec![(Type::Text, "Name".to_string())]).unwrap();
+                let row = Row{cols: vec![Value::new_text("a".repeat(OffsetType::MAX as usize))]};
+                assert_eq!(handler.insert_row(row).unwrap_err().kind(), ErrorKind::InvalidInput);
+            }
+
+
+            #[test]
+            fn analyze_test() {
+                let table_path = file_management::get_test_path().unwrap().join("analyze.test");
+                file_management::delete_file(&table_path);
+                let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string())];
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
+                assert_eq!(handler.analyze().unwrap(), TableStatistics::new(vec!["Name".to_string(), "Age".to_string()]));
+                for (name, age) in [("bob", "30"), ("alice", "12"), ("bob", "50")] {
+                    handler.insert_row(handler.cols_to_row(None, vec![name.to_string(), age.to_string()]).unwrap()).unwrap();
+                }
+                let statistics = handler.analyze().unwrap();
+                assert_eq!(statistics.row_count, 3);
+                assert_eq!(statistics.columns[0], ColumnStatistics{name: "Name".to_string(), min: Some(Value::new_text("alice".to_string())), max: Some(Value::new_text("bob".to_string())), distinct: 2});
+                assert_eq!(statistics.columns[1], ColumnStatistics{name: "Age".to_string(), min: Some(Value::new_number(12)), max: Some(Value::new_number(50)), distinct: 3});
+            }
+
+
             #[test]
             fn insert_delete_select_test() {
                 let table_path = file_management::get_test_path().unwrap().join("simple_table_handler_insert_and_select.test");
                 file_management::delete_file(&table_path);
                 let col_data : Vec<(Type, String)> = vec![(Type::Text, "Name".to_string()), (Type::Number, "Age".to_string()), (Type::Number, "Score".to_string())];
-                let handler = simple::SimpleTableHandler::new(table_path, col_data).unwrap();
+                let handler = simple::SimpleTableHandler::new(Box::new(SimplePageHandler::new(table_path, true, None).unwrap()), col_data).unwrap();
                 let row = Row {
                     cols: vec![
                         Value::new_text("Alice".to_string()),
@@ -1927,6 +5164,16 @@ pub mod table_management {
         }
 
 
+        #[test]
+        fn test_value_ordering() {
+            assert!(Value::new_number(-3) < Value::new_number(2));
+            assert!(Value::new_text("b".to_string()) > Value::new_text("abc".to_string()));
+            assert!(Value::new_blob(vec![1, 2]) < Value::new_blob(vec![1, 3]));
+            assert_eq!(Value::new_number(1).partial_cmp(&Value::new_number(1)), Some(std::cmp::Ordering::Equal));
+            assert_eq!(Value::new_number(1).partial_cmp(&Value::new_text("1".to_string())), None, "values of different types should not be comparable");
+        }
+
+
 
     }
 